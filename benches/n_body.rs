@@ -0,0 +1,38 @@
+//! Compares the cost of a simulation step with the bodies on rails and integrated as an N-body system
+use bevy::app::{App, FixedUpdate};
+use criterion::{criterion_group, criterion_main, Criterion};
+use rust_space_trading::prelude::*;
+
+fn explorer_app(n_body_config: NBodyConfig) -> App {
+    let mut app = App::new();
+    app.add_plugins(
+        ClientPlugin::testing()
+            .with_bodies(BodiesConfig::SmallestBodyType(BodyType::Comet))
+            .with_n_body(n_body_config)
+            .in_mode(ClientMode::Explorer),
+    );
+    app.update();
+    app
+}
+
+fn bench_bodies(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bodies step");
+    let configs = [
+        ("rails", None, 0.),
+        ("n-body exact", Some(BodyType::Comet), 0.),
+        ("n-body barnes-hut", Some(BodyType::Comet), 0.5),
+    ];
+    for (name, smallest, opening_angle) in configs {
+        let mut app = explorer_app(NBodyConfig {
+            bodies: smallest.map(BodiesConfig::SmallestBodyType),
+            opening_angle,
+        });
+        group.bench_function(name, |b| {
+            b.iter(|| app.world_mut().run_schedule(FixedUpdate))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_bodies);
+criterion_main!(benches);
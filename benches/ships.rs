@@ -0,0 +1,114 @@
+//! Scaling of the influence lookups and of a full simulation tick with the number of ships
+use bevy::{
+    app::{App, FixedUpdate},
+    ecs::system::SystemState,
+    math::DVec3,
+    prelude::*,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rust_space_trading::{
+    objects::orbiting_obj::OrbitingObjects,
+    physics::{
+        influence::{BodiesOctree, HillRadius},
+        time::SIMTICKS_PER_TICK,
+    },
+    prelude::*,
+    utils::algebra::circular_orbit_around_body,
+};
+
+const SHIPS_NUMBERS: [usize; 5] = [10, 100, 1_000, 10_000, 100_000];
+
+fn new_app() -> App {
+    let mut app = App::new();
+    app.add_plugins(
+        ClientPlugin::testing()
+            .with_bodies(BodiesConfig::SmallestBodyType(BodyType::Comet))
+            .in_mode(ClientMode::Singleplayer),
+    );
+    app.update();
+    app
+}
+
+/// Ships on circular orbits around random bodies
+fn ships_coordinates(world: &mut World, n: usize) -> Vec<(DVec3, DVec3)> {
+    let mut rng = StdRng::seed_from_u64(0);
+    let bodies: Vec<_> = world
+        .query::<(&Mass, &Position, &Velocity, &BodyInfo)>()
+        .iter(world)
+        .map(|(m, p, v, BodyInfo(data))| (m.0, p.0, v.0, data.radius))
+        .collect();
+    (0..n)
+        .map(|_| {
+            let (mass, pos, speed, radius) = bodies[rng.gen_range(0..bodies.len())];
+            circular_orbit_around_body(radius * rng.gen_range(0.1..2.), mass, pos, speed)
+        })
+        .collect()
+}
+
+fn bench_influence(c: &mut Criterion) {
+    let mut app = new_app();
+    let mut group = c.benchmark_group("influence");
+    for n in SHIPS_NUMBERS {
+        let positions: Vec<_> = ships_coordinates(app.world_mut(), n)
+            .into_iter()
+            .map(|(p, _)| Position(p))
+            .collect();
+        #[allow(clippy::type_complexity)]
+        let mut system_state: SystemState<(
+            Query<(&Position, &HillRadius, &OrbitingObjects)>,
+            Res<BodiesMapping>,
+            Res<BodiesOctree>,
+        )> = SystemState::new(app.world_mut());
+        let (bodies, mapping, octree) = system_state.get(app.world());
+        group.bench_with_input(BenchmarkId::new("tree walk", n), &positions, |b, p| {
+            b.iter(|| {
+                p.iter()
+                    .map(|pos| Influenced::new(pos, &bodies, &mapping, id_from("soleil")))
+                    .collect::<Vec<_>>()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("octree", n), &positions, |b, p| {
+            b.iter(|| {
+                p.iter()
+                    .map(|pos| octree.influence(pos))
+                    .collect::<Vec<_>>()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_tick(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ships tick");
+    group.sample_size(10);
+    for n in SHIPS_NUMBERS {
+        let mut app = new_app();
+        for (i, (spawn_pos, spawn_speed)) in ships_coordinates(app.world_mut(), n)
+            .into_iter()
+            .enumerate()
+        {
+            app.world_mut().send_event(ShipEvent::Create(ShipInfo {
+                id: id_from(&i.to_string()),
+                spawn_pos,
+                spawn_speed,
+            }));
+        }
+        app.update();
+        app.world_mut()
+            .resource_mut::<NextState<GameStage>>()
+            .set(GameStage::Action);
+        app.update();
+        group.bench_function(BenchmarkId::from_parameter(n), |b| {
+            b.iter(|| {
+                for _ in 0..SIMTICKS_PER_TICK {
+                    app.world_mut().run_schedule(FixedUpdate);
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_influence, bench_tick);
+criterion_main!(benches);
@@ -0,0 +1,388 @@
+//! Headless batch simulation, used for mission analysis.
+//!
+//! A [BatchConfig] selects the bodies of the catalog and the ships to simulate along with their trajectories.
+//! The physics then run for the requested duration without any UI, and the state of every ship is sampled
+//! at each server tick to produce ephemerides that can be written as CSV, JSON, OEM or binary. The ephemerides
+//! of bodies can be exported along with the ones of the ships in all formats but JSON.
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use bevy::{app::FixedMain, ecs::system::SystemState, math::DVec3, prelude::*};
+use color_eyre::eyre::{bail, eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ephemeris::{self, bodies_ephemerides, Ephemeris, EphemerisFormat, SampleRange},
+    objects::{
+        bodies::{main_bodies::SUN_ID, BodyID},
+        ships::{
+            trajectory::{load_trajectory, TrajectoryEvent},
+            ShipID,
+        },
+    },
+    physics::time::{GAMETIME_PER_SIMTICK, SIMTICKS_PER_TICK},
+    prelude::*,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    Json,
+    Oem,
+    Binary,
+}
+
+/// A ship to simulate
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatchShip {
+    pub id: ShipID,
+    pub spawn_pos: DVec3,
+    pub spawn_speed: DVec3,
+    /// Body the spawn coordinates are relative to, they are absolute if `None`
+    #[serde(default)]
+    pub relative_to: Option<BodyID>,
+    /// Trajectory file, in the same format as the ones saved by the game
+    #[serde(default)]
+    pub trajectory: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BatchConfig {
+    #[serde(default)]
+    pub bodies: BodiesConfig,
+    pub ships: Vec<BatchShip>,
+    /// Duration of the simulation (in days)
+    pub days: f64,
+    /// Number of ticks between two samples of the ephemerides
+    #[serde(default = "default_sample_every")]
+    pub sample_every: u64,
+    #[serde(default)]
+    pub format: OutputFormat,
+    /// Bodies whose ephemerides are exported too, in all formats but JSON
+    #[serde(default)]
+    pub export_bodies: Vec<BodyID>,
+}
+
+fn default_sample_every() -> u64 {
+    1
+}
+
+impl BatchConfig {
+    pub fn from_toml_file(path: impl AsRef<Path>) -> Result<Self> {
+        let mut buf = String::new();
+        File::open(path)?.read_to_string(&mut buf)?;
+        Ok(toml::from_str(&buf)?)
+    }
+
+    /// Number of ticks simulated, rounded to the nearest so that a duration like 0.35 days is not cut short by
+    /// the floating point division
+    fn ticks(&self) -> u64 {
+        (self.days / GAMETIME_PER_SIMTICK / SIMTICKS_PER_TICK as f64).round() as u64
+    }
+}
+
+/// State of a ship at a given tick
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EphemerisRecord {
+    pub ship: ShipID,
+    pub tick: u64,
+    pub pos: DVec3,
+    pub speed: DVec3,
+    pub main_influencer: Option<BodyID>,
+}
+
+/// Runs the simulation described by the config and returns the sampled ephemerides, sorted by tick then ship
+pub fn run_batch(config: &BatchConfig) -> Result<Vec<EphemerisRecord>> {
+    let mut app = new_app(config);
+
+    let mapping = app.world().resource::<BodiesMapping>().0.clone();
+    for ship in &config.ships {
+        let (origin_pos, origin_speed) = match ship.relative_to {
+            Some(body) => {
+                let e = mapping
+                    .get(&body)
+                    .ok_or_else(|| eyre!("Unknown body {} for ship {}", body, ship.id))?;
+                let world = app.world();
+                (
+                    world.get::<Position>(*e).unwrap().0,
+                    world.get::<Velocity>(*e).unwrap().0,
+                )
+            }
+            None => (DVec3::ZERO, DVec3::ZERO),
+        };
+        app.world_mut().send_event(ShipEvent::Create(ShipInfo {
+            id: ship.id,
+            spawn_pos: ship.spawn_pos + origin_pos,
+            spawn_speed: ship.spawn_speed + origin_speed,
+        }));
+        if let Some(path) = &ship.trajectory {
+            app.world_mut().send_event(TrajectoryEvent::Create {
+                ship: ship.id,
+                trajectory: load_trajectory(path, &mapping, 0)?,
+            });
+        }
+    }
+    app.update();
+    app.world_mut()
+        .resource_mut::<NextState<GameStage>>()
+        .set(GameStage::Action);
+    app.update();
+
+    let mut records = sample(&mut app);
+    for tick in 1..=config.ticks() {
+        for _ in 0..SIMTICKS_PER_TICK {
+            app.world_mut().run_schedule(FixedMain);
+        }
+        app.update();
+        if tick % config.sample_every.max(1) == 0 {
+            records.extend(sample(&mut app));
+        }
+    }
+    Ok(records)
+}
+
+/// Computes the ephemerides of the exported bodies, sampled like the ones of the ships
+pub fn export_bodies(config: &BatchConfig) -> Result<Vec<Ephemeris>> {
+    if config.export_bodies.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut app = new_app(config);
+    #[allow(clippy::type_complexity)]
+    let mut system_state: SystemState<(
+        Query<(&EllipticalOrbit, &BodyInfo)>,
+        Res<BodiesMapping>,
+    )> = SystemState::new(app.world_mut());
+    let (mut bodies, mapping) = system_state.get_mut(app.world_mut());
+    bodies_ephemerides(
+        &config.export_bodies,
+        &mut bodies.as_query_lens(),
+        &mapping.0,
+        id_from(SUN_ID),
+        SampleRange {
+            start: 0,
+            end: config.ticks(),
+            step: config.sample_every,
+        },
+    )
+}
+
+fn new_app(config: &BatchConfig) -> App {
+    let mut app = App::new();
+    app.add_plugins(
+        ClientPlugin::testing()
+            .with_bodies(config.bodies.clone())
+            .deterministic()
+            .in_mode(ClientMode::Singleplayer),
+    );
+    app.update();
+    app
+}
+
+fn sample(app: &mut App) -> Vec<EphemerisRecord> {
+    let world = app.world_mut();
+    let tick = world.resource::<GameTime>().tick();
+    let mut records: Vec<_> = world
+        .query::<(&ShipInfo, &Position, &Velocity, &Influenced)>()
+        .iter(world)
+        .map(|(info, pos, speed, influence)| EphemerisRecord {
+            ship: info.id,
+            tick,
+            pos: pos.0,
+            speed: speed.0,
+            main_influencer: influence
+                .main_influencer
+                .and_then(|e| world.get::<BodyInfo>(e))
+                .map(|BodyInfo(data)| data.id),
+        })
+        .collect();
+    records.sort_by_key(|r| r.ship);
+    records
+}
+
+/// Writes the ephemerides of the ships and bodies in the given format
+pub fn write_ephemerides(
+    records: &[EphemerisRecord],
+    bodies: &[Ephemeris],
+    format: OutputFormat,
+    mut writer: impl Write,
+) -> Result<()> {
+    if !bodies.is_empty() && format == OutputFormat::Json {
+        bail!("Bodies ephemerides can't be written as JSON");
+    }
+    match format {
+        OutputFormat::Json => serde_json::to_writer_pretty(&mut writer, records)?,
+        OutputFormat::Csv => write_csv(records, bodies, writer)?,
+        OutputFormat::Oem | OutputFormat::Binary => {
+            let mut ships: Vec<_> = records.iter().map(|r| r.ship).collect();
+            ships.sort();
+            ships.dedup();
+            // Ships coordinates are absolute, which is relative to the sun
+            let all = SampleRange {
+                start: 0,
+                end: u64::MAX,
+                step: 1,
+            };
+            let ephemerides: Vec<_> = ships
+                .into_iter()
+                .map(|ship| Ephemeris::from_records(ship, id_from(SUN_ID), records, all))
+                .chain(bodies.iter().cloned())
+                .collect();
+            let format = match format {
+                OutputFormat::Oem => EphemerisFormat::Oem,
+                _ => EphemerisFormat::Binary,
+            };
+            ephemeris::write_ephemerides(&ephemerides, format, writer)?
+        }
+    }
+    Ok(())
+}
+
+/// Writes the records of the ships, then the states of the bodies, whose main influencer is left empty
+fn write_csv(
+    records: &[EphemerisRecord],
+    bodies: &[Ephemeris],
+    mut writer: impl Write,
+) -> Result<()> {
+    writeln!(writer, "object,tick,x,y,z,vx,vy,vz,main_influencer")?;
+    let ships = records.iter().map(|r| {
+        let influencer = r.main_influencer.map(|id| id.to_string());
+        (r.ship.to_string(), r.tick, r.pos, r.speed, influencer)
+    });
+    let bodies = bodies.iter().flat_map(|ephemeris| {
+        ephemeris.states.iter().map(|s| {
+            let tick = s.simtick / SIMTICKS_PER_TICK;
+            (ephemeris.object.clone(), tick, s.pos, s.speed, None)
+        })
+    });
+    for (object, tick, pos, speed, influencer) in ships.chain(bodies) {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{}",
+            object,
+            tick,
+            pos.x,
+            pos.y,
+            pos.z,
+            speed.x,
+            speed.y,
+            speed.z,
+            influencer.unwrap_or_default()
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{
+        ephemeris::read_oem,
+        objects::ships::trajectory::{write_trajectory, ManeuverNode, Trajectory},
+    };
+
+    const CONFIG: &str = r#"
+        days = 1.0
+        sample_every = 10
+
+        [bodies]
+        SmallestBodyType = "Moon"
+
+        [[ships]]
+        id = "probe"
+        relative_to = "terre"
+        spawn_pos = [1e5, 0.0, 0.0]
+        spawn_speed = [0.0, 1.7e5, 0.0]
+    "#;
+
+    #[test]
+    fn test_batch() {
+        let mut config: BatchConfig = toml::from_str(CONFIG).unwrap();
+        assert_eq!(config.format, OutputFormat::Csv);
+        let records = run_batch(&config).unwrap();
+        // One sample at the start, then every 10 ticks
+        assert_eq!(records.len(), 11);
+        assert_eq!(records.last().unwrap().tick, 100);
+        assert!(records
+            .iter()
+            .all(|r| r.main_influencer == Some(id_from("terre"))));
+
+        let mut csv = Vec::new();
+        write_ephemerides(&records, &[], OutputFormat::Csv, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 12);
+        assert_eq!(
+            csv.lines().next(),
+            Some("object,tick,x,y,z,vx,vy,vz,main_influencer")
+        );
+        let first = csv.lines().nth(1).unwrap();
+        assert!(first.starts_with("probe,0,") && first.ends_with(",terre"));
+        let mut json = Vec::new();
+        write_ephemerides(&records, &[], OutputFormat::Json, &mut json).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Vec<EphemerisRecord>>(&json).unwrap(),
+            records
+        );
+
+        // Bodies can be exported with the ships in all formats but JSON
+        config.export_bodies = vec![id_from("terre")];
+        let bodies = export_bodies(&config).unwrap();
+        assert!(write_ephemerides(&records, &bodies, OutputFormat::Json, &mut Vec::new()).is_err());
+        let mut csv = Vec::new();
+        write_ephemerides(&records, &bodies, OutputFormat::Csv, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 23);
+        assert!(csv.lines().last().unwrap().starts_with("terre,100,"));
+        let mut oem = Vec::new();
+        write_ephemerides(&records, &bodies, OutputFormat::Oem, &mut oem).unwrap();
+        let segments = read_oem(&oem[..]).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].object, "probe");
+        assert_eq!(segments[1].object, "terre");
+        assert!(segments.iter().all(|s| s.states.len() == 11));
+        assert!((segments[0].states[10].pos - records[10].pos).length() < 1e-6);
+        config.export_bodies.clear();
+
+        // A maneuver node changes the ephemerides after its tick only
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("probe.toml");
+        write_trajectory(
+            &path,
+            &Trajectory {
+                nodes: BTreeMap::from([(
+                    50,
+                    ManeuverNode {
+                        name: "burn".to_owned(),
+                        thrust: DVec3::new(1e3, 0., 0.),
+                        origin: id_from("terre"),
+                    },
+                )]),
+            },
+        )
+        .unwrap();
+        config.ships[0].trajectory = Some(path);
+        let maneuvered = run_batch(&config).unwrap();
+        assert_eq!(maneuvered[..5], records[..5]);
+        assert_ne!(maneuvered[5], records[5]);
+    }
+
+    #[test]
+    fn test_ticks() {
+        let mut config: BatchConfig = toml::from_str(CONFIG).unwrap();
+        assert_eq!(config.ticks(), 100);
+        // 0.35 / 1e-3 is slightly below 350
+        config.days = 0.35;
+        assert_eq!(config.ticks(), 35);
+        config.days = 0.0;
+        assert_eq!(config.ticks(), 0);
+        assert!(export_bodies(&config).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,36 @@
+use std::{env, fs::File, io::stdout};
+
+use color_eyre::eyre::{eyre, Result};
+use rust_space_trading::batch::{
+    export_bodies, run_batch, write_ephemerides, BatchConfig, OutputFormat,
+};
+
+const USAGE: &str = "Usage: batch <config.toml> [-o output] [--format csv|json|oem|binary]";
+
+fn main() -> Result<()> {
+    color_eyre::install()?;
+    let mut args = env::args().skip(1);
+    let mut config = BatchConfig::from_toml_file(args.next().ok_or(eyre!(USAGE))?)?;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "-o" => output = Some(args.next().ok_or(eyre!("Expected output file path"))?),
+            "--format" => {
+                config.format = match args.next().as_deref() {
+                    Some("csv") => OutputFormat::Csv,
+                    Some("json") => OutputFormat::Json,
+                    Some("oem") => OutputFormat::Oem,
+                    Some("binary") => OutputFormat::Binary,
+                    _ => return Err(eyre!("Expected csv, json, oem or binary")),
+                }
+            }
+            _ => return Err(eyre!(USAGE)),
+        }
+    }
+    let records = run_batch(&config)?;
+    let bodies = export_bodies(&config)?;
+    match output {
+        Some(path) => write_ephemerides(&records, &bodies, config.format, File::create(path)?),
+        None => write_ephemerides(&records, &bodies, config.format, stdout().lock()),
+    }
+}
@@ -1,25 +1,67 @@
-use std::env;
+use std::{env, process::exit};
 
 use bevy::app::App;
-use rust_space_trading::{prelude::*, ui::gui::GuiPlugin, utils::args::get_keymap};
+use rust_space_trading::{
+    objects::ships::ghost::GhostImports,
+    prelude::*,
+    replay::{InputJournal, JournalPath, Lockstep, Replay},
+    ui::gui::GuiPlugin,
+    utils::args::{
+        get_ghosts, get_keymap, get_keymap_path, get_record, get_replay, get_script, is_windowless,
+    },
+};
 
 fn main() {
     #[allow(unused_variables)]
     let singleplayer_bodies_config = BodiesConfig::SmallestBodyType(BodyType::Moon);
     #[cfg(feature = "asteroids")]
     let singleplayer_bodies_config = BodiesConfig::SmallestBodyType(BodyType::Comet);
+    let windowless = is_windowless(env::args());
+    let keymap = get_keymap(env::args()).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        exit(1)
+    });
+    let record = get_record(env::args());
+    let replay = get_replay(env::args()).map(|path| {
+        InputJournal::load(path).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            exit(1)
+        })
+    });
+    // Recorded sessions start right in a singleplayer game and advance by one simtick per frame
+    let lockstep = record.is_some() || replay.is_some();
 
-    App::new()
-        .add_plugins((
-            ClientPlugin {
-                singleplayer_bodies_config,
-                ..Default::default()
+    let mut app = App::new();
+    app.add_plugins((
+        ClientPlugin {
+            singleplayer_bodies_config,
+            windowless,
+            deterministic: lockstep,
+            initial_mode: if lockstep {
+                ClientMode::Singleplayer
+            } else {
+                ClientMode::None
             },
-            TuiPlugin {
-                keymap: get_keymap(env::args()).unwrap(),
-                ..Default::default()
-            },
-            GuiPlugin,
-        ))
-        .run();
+            ..Default::default()
+        },
+        TuiPlugin {
+            keymap,
+            keymap_path: get_keymap_path(env::args()),
+            console_script: get_script(env::args()),
+            ..Default::default()
+        },
+    ))
+    .insert_resource(GhostImports(get_ghosts(env::args())))
+    .insert_resource(Lockstep(lockstep));
+    if let Some(path) = record {
+        app.insert_resource(InputJournal::default())
+            .insert_resource(JournalPath(path));
+    }
+    if let Some(journal) = replay {
+        app.insert_resource(Replay::new(journal));
+    }
+    if !windowless {
+        app.add_plugins(GuiPlugin);
+    }
+    app.run();
 }
@@ -10,7 +10,8 @@ use crate::{
     game::GamePlugin,
     network::{ClientChannel, ServerMessage},
     objects::prelude::BodiesConfig,
-    prelude::{GameTime, ToggleTime},
+    prelude::{GameTime, NBodyConfig, ToggleTime},
+    replay::Deterministic,
     utils::ecs::exit_on_error_if_app,
 };
 
@@ -22,8 +23,12 @@ pub mod prelude {
 pub struct ClientPlugin {
     pub network_info: ClientNetworkInfo,
     pub singleplayer_bodies_config: BodiesConfig,
+    pub n_body_config: NBodyConfig,
     pub initial_mode: ClientMode,
     pub testing: bool,
+    pub deterministic: bool,
+    /// Whether the game is played in the terminal only, without the Bevy window
+    pub windowless: bool,
 }
 
 #[derive(Resource)]
@@ -44,12 +49,34 @@ impl ClientPlugin {
         }
     }
 
+    pub fn with_n_body(self, n_body_config: NBodyConfig) -> Self {
+        Self {
+            n_body_config,
+            ..self
+        }
+    }
+
+    /// Only advances the simulation when it is stepped, see [crate::replay]
+    pub fn deterministic(self) -> Self {
+        Self {
+            deterministic: true,
+            ..self
+        }
+    }
+
     pub fn in_mode(self, initial_mode: ClientMode) -> Self {
         Self {
             initial_mode,
             ..self
         }
     }
+
+    pub fn windowless(self) -> Self {
+        Self {
+            windowless: true,
+            ..self
+        }
+    }
 }
 
 impl Plugin for ClientPlugin {
@@ -60,11 +87,14 @@ impl Plugin for ClientPlugin {
         app.add_plugins((
             GamePlugin {
                 testing: self.testing,
+                windowless: self.windowless,
             },
             QuinnetClientPlugin::default(),
         ))
         .insert_resource(self.network_info.clone())
         .insert_resource(self.singleplayer_bodies_config.clone())
+        .insert_resource(self.n_body_config.clone())
+        .insert_resource(Deterministic(self.deterministic))
         .insert_state(self.initial_mode)
         .add_systems(
             OnEnter(ClientMode::Multiplayer),
@@ -0,0 +1,607 @@
+//! Export and import of state histories ("ephemerides") of bodies and ships.
+//!
+//! Ephemerides can be written as CCSDS Orbit Ephemeris Messages (OEM, in KVN text form), as CSV or in a simple
+//! binary format, and read back from OEM and binary. Epochs are converted to calendar dates with simtick 0 at
+//! [EPOCH_J2000_DATE]. The simulation frame is the ecliptic of J2000: OEM states are rotated to EME2000, the
+//! equatorial frame expected by most tools, while CSV and binary states are left in the simulation frame.
+use std::{
+    io::{BufRead, Read, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    ecs::system::QueryLens,
+    math::{DQuat, DVec3},
+    prelude::*,
+    utils::HashMap,
+};
+use color_eyre::eyre::{bail, eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    batch::EphemerisRecord,
+    objects::{bodies::BodyID, prelude::BodyInfo, ships::ShipID},
+    physics::{
+        orbit::EllipticalOrbit,
+        predictions::get_bodies_coordinates,
+        time::{GAMETIME_PER_SIMTICK, SIMTICKS_PER_TICK},
+        SECONDS_PER_DAY,
+    },
+};
+
+/// Calendar date of simtick 0
+pub const EPOCH_J2000_DATE: &str = "2000-01-01T12:00:00.000";
+
+/// Days between the unix epoch and [EPOCH_J2000_DATE]
+const J2000_UNIX_DAYS: f64 = 10957.5;
+
+/// Obliquity of the ecliptic at J2000 (in degrees), the angle between the simulation frame and EME2000
+const J2000_OBLIQUITY: f64 = 23.4392911;
+
+const BINARY_MAGIC: &[u8; 6] = b"S4XEPH";
+const BINARY_VERSION: u8 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EphemerisFormat {
+    Oem,
+    Csv,
+    Binary,
+}
+
+/// Position and velocity (in km and km/d) of an object at a given simtick
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EphemerisState {
+    pub simtick: u64,
+    pub pos: DVec3,
+    pub speed: DVec3,
+}
+
+/// State history of an object, relative to a center body
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ephemeris {
+    pub object: String,
+    pub center: String,
+    /// States sorted by simtick
+    pub states: Vec<EphemerisState>,
+}
+
+/// Ticks at which an ephemeris is sampled, both ends included
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampleRange {
+    pub start: u64,
+    pub end: u64,
+    pub step: u64,
+}
+
+impl SampleRange {
+    pub fn simticks(&self) -> impl Iterator<Item = u64> {
+        (self.start..=self.end)
+            .step_by(self.step.max(1) as usize)
+            .map(|tick| tick * SIMTICKS_PER_TICK)
+    }
+}
+
+/// Computes the ephemerides of the selected bodies on their orbits, relative to the primary body
+pub fn bodies_ephemerides(
+    selected: &[BodyID],
+    bodies: &mut QueryLens<(&EllipticalOrbit, &BodyInfo)>,
+    mapping: &HashMap<BodyID, Entity>,
+    primary: BodyID,
+    range: SampleRange,
+) -> Result<Vec<Ephemeris>> {
+    let entities = selected
+        .iter()
+        .map(|id| mapping.get(id).copied().ok_or(eyre!("Unknown body {}", id)))
+        .collect::<Result<Vec<_>>>()?;
+    let mut ephemerides: Vec<_> = selected
+        .iter()
+        .map(|id| Ephemeris {
+            object: id.to_string(),
+            center: primary.to_string(),
+            states: Vec::new(),
+        })
+        .collect();
+    for simtick in range.simticks() {
+        let coords = get_bodies_coordinates(entities.iter().copied(), bodies, mapping, simtick);
+        for (ephemeris, (pos, speed)) in ephemerides.iter_mut().zip(coords) {
+            ephemeris.states.push(EphemerisState {
+                simtick,
+                pos,
+                speed,
+            });
+        }
+    }
+    Ok(ephemerides)
+}
+
+impl Ephemeris {
+    /// Ephemeris of a ship from the states recorded by a batch simulation
+    pub fn from_records(
+        ship: ShipID,
+        center: BodyID,
+        records: &[EphemerisRecord],
+        range: SampleRange,
+    ) -> Self {
+        let ticks = range.start..=range.end;
+        Self {
+            object: ship.to_string(),
+            center: center.to_string(),
+            states: records
+                .iter()
+                .filter(|r| {
+                    r.ship == ship
+                        && ticks.contains(&r.tick)
+                        && (r.tick - range.start).is_multiple_of(range.step.max(1))
+                })
+                .map(|r| EphemerisState {
+                    simtick: r.tick * SIMTICKS_PER_TICK,
+                    pos: r.pos,
+                    speed: r.speed,
+                })
+                .collect(),
+        }
+    }
+
+    /// State at the given simtick, interpolated between the two closest states and clamped to the ends
+    pub fn state_at(&self, simtick: u64) -> Option<(DVec3, DVec3)> {
+        let i = self.states.partition_point(|s| s.simtick <= simtick);
+        if i == 0 {
+            return self.states.first().map(|s| (s.pos, s.speed));
+        }
+        let (a, b) = match self.states.get(i) {
+            Some(b) => (&self.states[i - 1], b),
+            None => return self.states.last().map(|s| (s.pos, s.speed)),
+        };
+        // Cubic Hermite interpolation, see https://en.wikipedia.org/wiki/Cubic_Hermite_spline
+        let dt = (b.simtick - a.simtick) as f64 * GAMETIME_PER_SIMTICK;
+        let t = (simtick - a.simtick) as f64 / (b.simtick - a.simtick) as f64;
+        let (t2, t3) = (t * t, t * t * t);
+        let pos = (2. * t3 - 3. * t2 + 1.) * a.pos
+            + (t3 - 2. * t2 + t) * dt * a.speed
+            + (-2. * t3 + 3. * t2) * b.pos
+            + (t3 - t2) * dt * b.speed;
+        let speed = ((6. * t2 - 6. * t) * a.pos
+            + (3. * t2 - 4. * t + 1.) * dt * a.speed
+            + (-6. * t2 + 6. * t) * b.pos
+            + (3. * t2 - 2. * t) * dt * b.speed)
+            / dt;
+        Some((pos, speed))
+    }
+}
+
+/// Writes the ephemerides in the given format
+pub fn write_ephemerides(
+    ephemerides: &[Ephemeris],
+    format: EphemerisFormat,
+    writer: impl Write,
+) -> Result<()> {
+    match format {
+        EphemerisFormat::Oem => write_oem(ephemerides, writer),
+        EphemerisFormat::Csv => write_csv(ephemerides, writer),
+        EphemerisFormat::Binary => write_binary(ephemerides, writer),
+    }
+}
+
+fn write_oem(ephemerides: &[Ephemeris], mut writer: impl Write) -> Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64() / SECONDS_PER_DAY;
+    writeln!(writer, "CCSDS_OEM_VERS = 2.0")?;
+    writeln!(writer, "CREATION_DATE = {}", format_date(now))?;
+    writeln!(writer, "ORIGINATOR = SOLAR4X")?;
+    for ephemeris in ephemerides {
+        let (Some(first), Some(last)) = (ephemeris.states.first(), ephemeris.states.last()) else {
+            continue;
+        };
+        writeln!(writer)?;
+        writeln!(writer, "META_START")?;
+        writeln!(writer, "OBJECT_NAME = {}", ephemeris.object)?;
+        writeln!(writer, "OBJECT_ID = {}", ephemeris.object)?;
+        writeln!(writer, "CENTER_NAME = {}", ephemeris.center)?;
+        writeln!(writer, "REF_FRAME = EME2000")?;
+        writeln!(writer, "TIME_SYSTEM = TDB")?;
+        writeln!(writer, "START_TIME = {}", format_epoch(first.simtick))?;
+        writeln!(writer, "STOP_TIME = {}", format_epoch(last.simtick))?;
+        writeln!(writer, "META_STOP")?;
+        writeln!(writer)?;
+        for s in &ephemeris.states {
+            let pos = ecliptic_to_equatorial() * s.pos;
+            // OEM velocities are in km/s
+            let speed = ecliptic_to_equatorial() * s.speed / SECONDS_PER_DAY;
+            writeln!(
+                writer,
+                "{} {:e} {:e} {:e} {:e} {:e} {:e}",
+                format_epoch(s.simtick),
+                pos.x,
+                pos.y,
+                pos.z,
+                speed.x,
+                speed.y,
+                speed.z
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn write_csv(ephemerides: &[Ephemeris], mut writer: impl Write) -> Result<()> {
+    writeln!(writer, "object,center,simtick,x,y,z,vx,vy,vz")?;
+    for ephemeris in ephemerides {
+        for s in &ephemeris.states {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{}",
+                ephemeris.object,
+                ephemeris.center,
+                s.simtick,
+                s.pos.x,
+                s.pos.y,
+                s.pos.z,
+                s.speed.x,
+                s.speed.y,
+                s.speed.z
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Each ephemeris is written as the magic bytes and version, the object and center names prefixed by their
+/// length as a u16, the number of states as a u64, then for each state the simtick as a u64 followed by the
+/// position and velocity as f64, everything in little endian
+fn write_binary(ephemerides: &[Ephemeris], mut writer: impl Write) -> Result<()> {
+    for ephemeris in ephemerides {
+        writer.write_all(BINARY_MAGIC)?;
+        writer.write_all(&[BINARY_VERSION])?;
+        for name in [&ephemeris.object, &ephemeris.center] {
+            writer.write_all(&(name.len() as u16).to_le_bytes())?;
+            writer.write_all(name.as_bytes())?;
+        }
+        writer.write_all(&(ephemeris.states.len() as u64).to_le_bytes())?;
+        for s in &ephemeris.states {
+            writer.write_all(&s.simtick.to_le_bytes())?;
+            for x in s.pos.to_array().into_iter().chain(s.speed.to_array()) {
+                writer.write_all(&x.to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads ephemerides written in the binary format
+pub fn read_binary(mut reader: impl Read) -> Result<Vec<Ephemeris>> {
+    fn read_array<const N: usize>(reader: &mut impl Read) -> Result<[u8; N]> {
+        let mut buf = [0; N];
+        reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+    fn read_name(reader: &mut impl Read) -> Result<String> {
+        let mut buf = vec![0; u16::from_le_bytes(read_array(reader)?) as usize];
+        reader.read_exact(&mut buf)?;
+        Ok(String::from_utf8(buf)?)
+    }
+    fn read_vec(reader: &mut impl Read) -> Result<DVec3> {
+        let mut v = [0.; 3];
+        for x in &mut v {
+            *x = f64::from_le_bytes(read_array(reader)?);
+        }
+        Ok(DVec3::from_array(v))
+    }
+
+    let mut ephemerides = Vec::new();
+    let mut magic = [0; 6];
+    loop {
+        match reader.read_exact(&mut magic) {
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            r => r?,
+        }
+        if &magic != BINARY_MAGIC || read_array::<1>(&mut reader)?[0] != BINARY_VERSION {
+            bail!("Not an ephemeris file");
+        }
+        let object = read_name(&mut reader)?;
+        let center = read_name(&mut reader)?;
+        let n = u64::from_le_bytes(read_array(&mut reader)?);
+        let states = (0..n)
+            .map(|_| {
+                Ok(EphemerisState {
+                    simtick: u64::from_le_bytes(read_array(&mut reader)?),
+                    pos: read_vec(&mut reader)?,
+                    speed: read_vec(&mut reader)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        ephemerides.push(Ephemeris {
+            object,
+            center,
+            states,
+        });
+    }
+    Ok(ephemerides)
+}
+
+/// Reads the segments of an OEM in KVN form, ignoring the covariance data. The states are rotated to the
+/// simulation frame, so only the EME2000 (or ICRF, which is the same to the precision of the simulation) and
+/// ECLIPJ2000 reference frames are supported
+pub fn read_oem(reader: impl BufRead) -> Result<Vec<Ephemeris>> {
+    let mut ephemerides: Vec<Ephemeris> = Vec::new();
+    // Rotation from the frame of each segment to the simulation frame
+    let mut frames: Vec<DQuat> = Vec::new();
+    let mut in_meta = false;
+    let mut in_covariance = false;
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("COMMENT") {
+            continue;
+        }
+        match line {
+            "META_START" => {
+                in_meta = true;
+                ephemerides.push(Ephemeris {
+                    object: String::new(),
+                    center: String::new(),
+                    states: Vec::new(),
+                });
+                frames.push(ecliptic_to_equatorial().inverse());
+            }
+            "META_STOP" => in_meta = false,
+            "COVARIANCE_START" => in_covariance = true,
+            "COVARIANCE_STOP" => in_covariance = false,
+            _ if in_covariance => {}
+            _ if in_meta => {
+                let Some((key, value)) = line.split_once('=') else {
+                    bail!("Invalid metadata line: {}", line);
+                };
+                let ephemeris = ephemerides.last_mut().unwrap();
+                match key.trim() {
+                    "OBJECT_NAME" => ephemeris.object = value.trim().to_owned(),
+                    "CENTER_NAME" => ephemeris.center = value.trim().to_owned(),
+                    "TIME_SYSTEM" if !["TDB", "TT", "UTC"].contains(&value.trim()) => {
+                        bail!("Unsupported time system {}", value.trim())
+                    }
+                    "REF_FRAME" => {
+                        *frames.last_mut().unwrap() = match value.trim() {
+                            "EME2000" | "ICRF" => ecliptic_to_equatorial().inverse(),
+                            "ECLIPJ2000" => DQuat::IDENTITY,
+                            frame => bail!("Unsupported reference frame {}", frame),
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            // Header
+            _ if line.contains('=') => {}
+            _ => {
+                let ephemeris = ephemerides
+                    .last_mut()
+                    .ok_or(eyre!("Data line before metadata: {}", line))?;
+                let fields: Vec<_> = line.split_whitespace().collect();
+                if fields.len() != 7 && fields.len() != 10 {
+                    bail!("Invalid data line: {}", line);
+                }
+                let values = fields[1..7]
+                    .iter()
+                    .map(|f| f.parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()?;
+                let frame = *frames.last().unwrap();
+                ephemeris.states.push(EphemerisState {
+                    simtick: parse_epoch(fields[0])?,
+                    pos: frame * DVec3::from_slice(&values[..3]),
+                    speed: frame * DVec3::from_slice(&values[3..]) * SECONDS_PER_DAY,
+                });
+            }
+        }
+    }
+    for ephemeris in &mut ephemerides {
+        ephemeris.states.sort_by_key(|s| s.simtick);
+    }
+    Ok(ephemerides)
+}
+
+/// Rotation from the simulation frame, the ecliptic of J2000, to EME2000
+fn ecliptic_to_equatorial() -> DQuat {
+    DQuat::from_rotation_x(J2000_OBLIQUITY.to_radians())
+}
+
+pub(crate) fn format_epoch(simtick: u64) -> String {
+    format_date(J2000_UNIX_DAYS + simtick as f64 * GAMETIME_PER_SIMTICK)
+}
+
+pub(crate) fn parse_epoch(s: &str) -> Result<u64> {
+    let days = parse_date(s)? - J2000_UNIX_DAYS;
+    if days < 0. {
+        bail!("Epoch {} is before {}", s, EPOCH_J2000_DATE);
+    }
+    Ok((days / GAMETIME_PER_SIMTICK).round() as u64)
+}
+
+/// Formats a number of days since the unix epoch as an ISO 8601 date, to the millisecond
+fn format_date(days: f64) -> String {
+    let millis = (days * SECONDS_PER_DAY * 1e3).round() as i64;
+    let (day, millis) = (millis.div_euclid(86_400_000), millis.rem_euclid(86_400_000));
+    let (y, m, d) = civil_from_days(day);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}",
+        y,
+        m,
+        d,
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Parses an ISO 8601 date ("YYYY-MM-DDThh:mm:ss" with optional fractional seconds) into days since the unix epoch
+fn parse_date(s: &str) -> Result<f64> {
+    let invalid = || eyre!("Invalid epoch {}", s);
+    let (date, time) = s.split_once('T').ok_or_else(invalid)?;
+    let date: Vec<i64> = date
+        .split('-')
+        .map(|x| x.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+    let time: Vec<f64> = time
+        .trim_end_matches('Z')
+        .split(':')
+        .map(|x| x.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+    let (&[y, m, d], &[h, min, sec]) = (&date[..], &time[..]) else {
+        return Err(invalid());
+    };
+    Ok(days_from_civil(y, m, d) as f64 + (h * 3600. + min * 60. + sec) / SECONDS_PER_DAY)
+}
+
+// See http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + if m <= 2 { 1 } else { 0 }, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use bevy::ecs::system::SystemState;
+
+    use crate::prelude::*;
+
+    use super::*;
+
+    fn earth_ephemeris(range: SampleRange) -> Ephemeris {
+        let mut app = App::new();
+        app.add_plugins(ClientPlugin::testing().in_mode(ClientMode::Singleplayer));
+        app.update();
+        #[allow(clippy::type_complexity)]
+        let mut system_state: SystemState<(
+            Query<(&EllipticalOrbit, &BodyInfo)>,
+            Res<BodiesMapping>,
+        )> = SystemState::new(app.world_mut());
+        let (mut bodies, mapping) = system_state.get_mut(app.world_mut());
+        bodies_ephemerides(
+            &[id_from("terre")],
+            &mut bodies.as_query_lens(),
+            &mapping.0,
+            id_from("soleil"),
+            range,
+        )
+        .unwrap()
+        .remove(0)
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(format_epoch(0), EPOCH_J2000_DATE);
+        assert_eq!(parse_epoch(EPOCH_J2000_DATE).unwrap(), 0);
+        assert_eq!(format_date(0.), "1970-01-01T00:00:00.000");
+        assert_eq!(parse_date("2024-02-29T06:00:00Z").unwrap(), 19782.25);
+        for simtick in [1, 365_250, 12_345_678] {
+            assert_eq!(parse_epoch(&format_epoch(simtick)).unwrap(), simtick);
+        }
+        assert!(parse_epoch("1999-12-31T00:00:00").is_err());
+        assert!(parse_epoch("2000-01-01").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let ephemerides = vec![earth_ephemeris(SampleRange {
+            start: 0,
+            end: 1000,
+            step: 100,
+        })];
+        assert_eq!(ephemerides[0].states.len(), 11);
+        assert_eq!(ephemerides[0].states[1].simtick, 1000);
+
+        let mut binary = Vec::new();
+        write_ephemerides(&ephemerides, EphemerisFormat::Binary, &mut binary).unwrap();
+        assert_eq!(read_binary(&binary[..]).unwrap(), ephemerides);
+
+        let mut oem = Vec::new();
+        write_ephemerides(&ephemerides, EphemerisFormat::Oem, &mut oem).unwrap();
+        let read = read_oem(BufReader::new(&oem[..])).unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].object, "terre");
+        assert_eq!(read[0].center, "soleil");
+        for (a, b) in read[0].states.iter().zip(&ephemerides[0].states) {
+            assert_eq!(a.simtick, b.simtick);
+            assert!((a.pos - b.pos).length() < 1e-9 * b.pos.length());
+            assert!((a.speed - b.speed).length() < 1e-9 * b.speed.length());
+        }
+
+        let mut csv = Vec::new();
+        write_ephemerides(&ephemerides, EphemerisFormat::Csv, &mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap().lines().count(), 12);
+    }
+
+    #[test]
+    fn test_oem_frames() {
+        let oem = |frame: &str, state: &str| {
+            format!(
+                "CCSDS_OEM_VERS = 2.0\nMETA_START\nOBJECT_NAME = probe\nCENTER_NAME = SUN\n\
+                 REF_FRAME = {frame}\nTIME_SYSTEM = TDB\nMETA_STOP\n2000-01-01T12:00:00 {state}\n"
+            )
+        };
+        let read = |oem: String| read_oem(BufReader::new(oem.as_bytes()));
+        let ecliptic = read(oem("ECLIPJ2000", "1 2 3 0 0 0")).unwrap();
+        assert_eq!(ecliptic[0].states[0].pos, DVec3::new(1., 2., 3.));
+        // The north pole of the equator is tilted away from the one of the ecliptic
+        let equatorial = read(oem("EME2000", "0 0 1 0 0 0")).unwrap();
+        let obliquity = J2000_OBLIQUITY.to_radians();
+        let expected = DVec3::new(0., obliquity.sin(), obliquity.cos());
+        assert!((equatorial[0].states[0].pos - expected).length() < 1e-12);
+        assert!(read(oem("GCRF", "0 0 1 0 0 0")).is_err());
+
+        // The ecliptic earth orbit is inclined by the obliquity in the written file
+        let ephemerides = vec![earth_ephemeris(SampleRange {
+            start: 0,
+            end: 0,
+            step: 1,
+        })];
+        let mut oem = Vec::new();
+        write_ephemerides(&ephemerides, EphemerisFormat::Oem, &mut oem).unwrap();
+        let oem = String::from_utf8(oem).unwrap();
+        assert!(oem.contains("REF_FRAME = EME2000"));
+        let raw = read(oem.replace("EME2000", "ECLIPJ2000")).unwrap();
+        let (written, state) = (raw[0].states[0], ephemerides[0].states[0]);
+        let normal = state.pos.cross(state.speed).normalize();
+        let written_normal = written.pos.cross(written.speed).normalize();
+        assert!(normal.z > 0.9999);
+        assert!((written_normal.angle_between(normal) - obliquity).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_state_at() {
+        let range = |step| SampleRange {
+            start: 0,
+            end: 1000,
+            step,
+        };
+        let sparse = earth_ephemeris(range(20));
+        let dense = earth_ephemeris(range(1));
+        for s in &dense.states {
+            let (pos, speed) = sparse.state_at(s.simtick).unwrap();
+            assert!((pos - s.pos).length() < 1e-6 * s.pos.length());
+            assert!((speed - s.speed).length() < 1e-4 * s.speed.length());
+        }
+        assert_eq!(
+            sparse.state_at(u64::MAX).unwrap().0,
+            sparse.states.last().unwrap().pos
+        );
+    }
+}
@@ -1,9 +1,11 @@
 use std::{
     fs::create_dir_all,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
-use bevy::{prelude::*, state::app::StatesPlugin};
+use bevy::{app::ScheduleRunnerPlugin, prelude::*, state::app::StatesPlugin};
+use serde::{Deserialize, Serialize};
 use tempfile::{tempdir, TempDir};
 
 use crate::{
@@ -11,13 +13,17 @@ use crate::{
     objects::{
         bodies::BodiesPlugin,
         prelude::BodiesMapping,
-        ships::{trajectory::TRAJECTORIES_PATH, ShipsMapping, ShipsPlugin},
+        ships::{
+            trajectory::{TrajectoryStore, TRAJECTORIES_PATH},
+            ShipsMapping, ShipsPlugin,
+        },
         ObjectsUpdate,
     },
     physics::{
         influence::InfluenceUpdate, orbit::OrbitsUpdate, prelude::ToggleTime, PhysicsPlugin,
         PhysicsUpdate,
     },
+    replay,
     ui::gui::GUIUpdate,
 };
 
@@ -26,16 +32,23 @@ pub mod prelude {
 }
 
 pub const GAME_FILES_PATH: &str = "gamefiles";
+/// Time between two updates when the game runs without a window, which would otherwise pace them
+const WINDOWLESS_FRAME_DURATION: Duration = Duration::from_micros(16_667);
 
 /// This plugin's role is to handle everything that is about the main game, and that is common to both the server and the client
 #[derive(Default)]
 pub struct GamePlugin {
     pub testing: bool,
+    /// Runs the game without opening a window, for instance to play in the terminal only
+    pub windowless: bool,
 }
 
 impl GamePlugin {
     pub fn testing() -> Self {
-        Self { testing: true }
+        Self {
+            testing: true,
+            ..Default::default()
+        }
     }
 }
 
@@ -49,17 +62,24 @@ impl Plugin for GamePlugin {
         } else {
             GAME_FILES_PATH.into()
         };
+        let files = GameFiles::new(path).unwrap();
         if self.testing {
             app.add_plugins((MinimalPlugins, StatesPlugin))
+        } else if self.windowless {
+            app.add_plugins((
+                MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(WINDOWLESS_FRAME_DURATION)),
+                StatesPlugin,
+            ))
         } else {
             app.add_plugins(DefaultPlugins)
         }
-        .add_plugins((PhysicsPlugin, BodiesPlugin, ShipsPlugin))
+        .add_plugins((PhysicsPlugin, BodiesPlugin, ShipsPlugin, replay::plugin))
         .add_computed_state::<InGame>()
         .add_computed_state::<Authoritative>()
         .add_sub_state::<GameStage>()
         .add_computed_state::<Loaded>()
-        .insert_resource(GameFiles::new(path).unwrap())
+        .insert_resource(files)
+        .add_systems(PreStartup, open_trajectory_store)
         .configure_sets(
             OnEnter(Loaded),
             (ObjectsUpdate, OrbitsUpdate, InfluenceUpdate, GUIUpdate).chain(),
@@ -118,7 +138,7 @@ impl ComputedStates for InGame {
     }
 }
 
-#[derive(SubStates, Debug, Hash, PartialEq, Eq, Clone, Default)]
+#[derive(SubStates, Debug, Hash, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
 #[source(InGame = InGame)]
 pub enum GameStage {
     #[default]
@@ -183,6 +203,15 @@ fn clear_loaded(mut commands: Commands, query: Query<Entity, With<ClearOnUnload>
     commands.remove_resource::<ShipsMapping>();
 }
 
+/// Opens the trajectory store once the logs are set up, starting without any trajectory if it can't be read
+fn open_trajectory_store(mut commands: Commands, files: Res<GameFiles>) {
+    let store = TrajectoryStore::open(&files).unwrap_or_else(|err| {
+        error!("Could not open the trajectories, starting without them: {err}");
+        TrajectoryStore::empty(&files)
+    });
+    commands.insert_resource(store);
+}
+
 fn enable_time(mut toggle: ResMut<ToggleTime>) {
     toggle.0 = true;
 }
@@ -192,9 +221,13 @@ fn disable_time(mut toggle: ResMut<ToggleTime>) {
 
 #[cfg(test)]
 mod tests {
-    use bevy::{app::App, state::state::State};
+    use std::fs::create_dir;
+
+    use bevy::{app::App, ecs::system::RunSystemOnce, state::state::State};
 
-    use crate::prelude::*;
+    use crate::{objects::ships::trajectory::store::TRAJECTORIES_WAL, prelude::*};
+
+    use super::*;
 
     fn new_app() -> App {
         let mut app = App::new();
@@ -213,4 +246,17 @@ mod tests {
             GameStage::Preparation
         );
     }
+
+    #[test]
+    fn test_unreadable_trajectories() {
+        let root = tempdir().unwrap();
+        let files = GameFiles::new(root.path()).unwrap();
+        // Reading the write-ahead log fails when it is a directory
+        create_dir(root.path().join(TRAJECTORIES_WAL)).unwrap();
+        assert!(TrajectoryStore::open(&files).is_err());
+        let mut world = World::new();
+        world.insert_resource(files);
+        world.run_system_once(open_trajectory_store);
+        assert_eq!(world.resource::<TrajectoryStore>().iter().count(), 0);
+    }
 }
@@ -1,6 +1,15 @@
+pub mod binding;
 pub mod key;
 pub mod keyboard;
+pub mod mouse;
+pub mod validation;
+pub mod window_keys;
 
 pub mod prelude {
-    pub use super::{key::Key, keyboard::Keymap};
+    pub use super::{
+        binding::{Binding, KeyPress},
+        key::Key,
+        keyboard::Keymap,
+        mouse::MouseGesture,
+    };
 }
@@ -0,0 +1,167 @@
+use bevy::prelude::*;
+use bevy_ratatui::event::KeyEvent;
+use crossterm::event::{KeyEvent as CKeyEvent, KeyEventKind};
+use serde::{Deserialize, Serialize};
+
+use super::{key::Key, keyboard::Keymap};
+
+/// Maximum number of keys pressed one after the other in a chord
+pub const MAX_CHORD_LENGTH: usize = 2;
+
+/// Keys pressed one after the other to trigger an action
+pub type Chord = Vec<Key>;
+
+/// The chords triggering an action, any of which can be used.
+/// In the keymap file, a binding is either a key or a list whose items are keys or chords:
+/// `zoom_in = ["+", "="]` or `autoscale = ["x", ["g", "g"]]`
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(from = "RawBinding", into = "RawBinding")]
+pub struct Binding(pub Vec<Chord>);
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawChord {
+    Key(Key),
+    Chord(Chord),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawBinding {
+    Key(Key),
+    Alternatives(Vec<RawChord>),
+}
+
+impl From<RawBinding> for Binding {
+    fn from(value: RawBinding) -> Self {
+        Self(match value {
+            RawBinding::Key(key) => vec![vec![key]],
+            RawBinding::Alternatives(chords) => chords
+                .into_iter()
+                .map(|chord| match chord {
+                    RawChord::Key(key) => vec![key],
+                    RawChord::Chord(keys) => keys,
+                })
+                .collect(),
+        })
+    }
+}
+
+impl From<Binding> for RawBinding {
+    fn from(value: Binding) -> Self {
+        let mut chords: Vec<_> = value
+            .0
+            .into_iter()
+            .map(|chord| {
+                if chord.len() == 1 {
+                    RawChord::Key(chord[0])
+                } else {
+                    RawChord::Chord(chord)
+                }
+            })
+            .collect();
+        match chords.pop() {
+            Some(RawChord::Key(key)) if chords.is_empty() => RawBinding::Key(key),
+            last => {
+                chords.extend(last);
+                RawBinding::Alternatives(chords)
+            }
+        }
+    }
+}
+
+impl From<Key> for Binding {
+    fn from(value: Key) -> Self {
+        Self(vec![vec![value]])
+    }
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("unbound");
+        }
+        let chords: Vec<_> = self
+            .0
+            .iter()
+            .map(|chord| {
+                chord
+                    .iter()
+                    .map(|key| key.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" → ")
+            })
+            .collect();
+        f.write_str(&chords.join(" or "))
+    }
+}
+
+impl Binding {
+    pub fn from_str_unchecked(s: &str) -> Self {
+        Key::from_str_unchecked(s).into()
+    }
+
+    /// Whether the key press ends one of the chords, the keys before it having been pressed just before
+    pub fn matches(&self, press: &KeyPress) -> bool {
+        self.0.iter().any(|chord| press.ends_chord(chord))
+    }
+}
+
+/// A key pressed in the terminal or in the window, along with the keys pressed just before it
+#[derive(Event, Debug, Clone)]
+pub struct KeyPress {
+    pub event: CKeyEvent,
+    /// The last keys pressed, the most recent one last, that the chords ending with this key start with
+    pub previous: Vec<CKeyEvent>,
+}
+
+impl KeyPress {
+    pub fn ends_chord(&self, chord: &[Key]) -> bool {
+        let Some((last, start)) = chord.split_last() else {
+            return false;
+        };
+        last.matches(&self.event)
+            && start.len() <= self.previous.len()
+            && start
+                .iter()
+                .zip(&self.previous[self.previous.len() - start.len()..])
+                .all(|(key, event)| key.matches(event))
+    }
+}
+
+/// Keys pressed last on the current screen, which may start a chord
+#[derive(Resource, Default)]
+pub struct KeyHistory(pub Vec<CKeyEvent>);
+
+/// Turns the key events into key presses remembering the keys before them, so that the screens can match them
+/// against chords
+pub fn record_key_presses(
+    mut key_events: EventReader<KeyEvent>,
+    keymap: Res<Keymap>,
+    mut history: ResMut<KeyHistory>,
+    mut presses: EventWriter<KeyPress>,
+) {
+    for KeyEvent(event) in key_events.read() {
+        if event.kind == KeyEventKind::Release {
+            continue;
+        }
+        let press = KeyPress {
+            event: *event,
+            previous: history.0.clone(),
+        };
+        // A completed chord does not start the next one
+        if keymap
+            .bindings()
+            .iter()
+            .flat_map(|(_, binding)| &binding.0)
+            .any(|chord| chord.len() > 1 && press.ends_chord(chord))
+        {
+            history.0.clear();
+        } else {
+            history.0.push(*event);
+            let excess = history.0.len().saturating_sub(MAX_CHORD_LENGTH - 1);
+            history.0.drain(..excess);
+        }
+        presses.send(press);
+    }
+}
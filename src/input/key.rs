@@ -1,3 +1,5 @@
+use std::str::FromStr;
+
 use bevy::utils::HashMap;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use serde::{
@@ -5,7 +7,7 @@ use serde::{
     Deserialize, Serialize,
 };
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Key {
     pub code: KeyCode,
     pub modifiers: KeyModifiers,
@@ -128,10 +130,27 @@ impl Serialize for Key {
     }
 }
 
+impl FromStr for Key {
+    type Err = serde::de::value::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let d: StrDeserializer<serde::de::value::Error> = s.into_deserializer();
+        Key::deserialize(d)
+    }
+}
+
+impl From<&KeyEvent> for Key {
+    fn from(value: &KeyEvent) -> Self {
+        Self {
+            code: value.code,
+            modifiers: value.modifiers,
+        }
+    }
+}
+
 impl Key {
     pub fn from_str_unchecked(s: &str) -> Self {
-        let d: StrDeserializer<serde::de::value::Error> = s.into_deserializer();
-        Key::deserialize(d).unwrap()
+        s.parse().unwrap()
     }
 
     pub fn matches(&self, event: &KeyEvent) -> bool {
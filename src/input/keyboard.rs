@@ -1,13 +1,16 @@
 use std::{
     fs::File,
-    io::{Read, Result, Write},
+    io::{Error, ErrorKind, Read, Result, Write},
     path::Path,
 };
 
-use bevy::ecs::system::Resource;
+use bevy::{ecs::system::Resource, utils::HashMap};
 use serde::{Deserialize, Serialize};
 
-use super::key::Key;
+use super::{
+    binding::Binding,
+    validation::{binding_issues, binding_lines, unknown_keys, KeymapIssue, KeymapIssues},
+};
 
 #[derive(Resource, Default, Clone, Serialize, Deserialize, Debug)]
 pub struct Keymap {
@@ -15,6 +18,15 @@ pub struct Keymap {
     pub start_menu: StartMenuKeymap,
     pub fleet_screen: FleetScreenKeymap,
     pub editor: EditorKeymap,
+    #[serde(default)]
+    pub settings: SettingsKeymap,
+    /// Keys working on every screen
+    #[serde(default)]
+    pub global: GlobalKeymap,
+    #[serde(default)]
+    pub palette: PaletteKeymap,
+    #[serde(default)]
+    pub console: ConsoleKeymap,
 }
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone)]
@@ -25,33 +37,94 @@ pub struct ExplorerKeymap {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct StartMenuKeymap {
-    pub select_next: Key,
-    pub select_previous: Key,
-    pub quit: Key,
-    pub validate: Key,
+    pub select_next: Binding,
+    pub select_previous: Binding,
+    pub quit: Binding,
+    pub validate: Binding,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FleetScreenKeymap {
-    pub select_next: Key,
-    pub select_previous: Key,
-    pub back: Key,
-    pub edit_trajectory: Key,
-    pub new_ship: Key,
-    pub cycle_options: Key,
-    pub cycle_options_back: Key,
-    pub validate_new_ship: Key,
-    pub delete_char: Key,
-    pub enter_explorer: Key,
+    pub select_next: Binding,
+    pub select_previous: Binding,
+    pub back: Binding,
+    pub edit_trajectory: Binding,
+    pub new_ship: Binding,
+    pub cycle_options: Binding,
+    pub cycle_options_back: Binding,
+    pub validate_new_ship: Binding,
+    pub delete_char: Binding,
+    pub enter_explorer: Binding,
+    pub cycle_sail_attitude: Binding,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EditorKeymap {
-    pub select_next: Key,
-    pub select_previous: Key,
-    pub back: Key,
-    pub new_node: Key,
-    pub open_scheduler: Key,
+    pub select_next: Binding,
+    pub select_previous: Binding,
+    pub back: Binding,
+    pub new_node: Binding,
+    pub delete_node: Binding,
+    pub undo: Binding,
+    pub redo: Binding,
+    pub edit_node: Binding,
+    pub cycle_options: Binding,
+    pub cycle_options_back: Binding,
+    pub validate_node: Binding,
+    pub delete_char: Binding,
+    pub cycle_target: Binding,
+    pub longer_horizon: Binding,
+    pub shorter_horizon: Binding,
+    pub cycle_horizon_unit: Binding,
+    pub open_scheduler: Binding,
+    pub zoom_in: Binding,
+    pub zoom_out: Binding,
+    pub cycle_focus: Binding,
+    pub thrust_prograde: Binding,
+    pub thrust_retrograde: Binding,
+    pub thrust_radial: Binding,
+    pub thrust_antiradial: Binding,
+    pub thrust_normal: Binding,
+    pub thrust_antinormal: Binding,
+    pub cycle_thrust_step: Binding,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SettingsKeymap {
+    pub select_next: Binding,
+    pub select_previous: Binding,
+    pub back: Binding,
+    pub rebind: Binding,
+    pub add_key: Binding,
+    pub add_chord: Binding,
+    pub reset: Binding,
+    pub save: Binding,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GlobalKeymap {
+    pub open_palette: Binding,
+    pub toggle_console: Binding,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PaletteKeymap {
+    pub select_next: Binding,
+    pub select_previous: Binding,
+    pub close: Binding,
+    pub validate: Binding,
+    pub delete_char: Binding,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConsoleKeymap {
+    pub close: Binding,
+    pub validate: Binding,
+    pub delete_char: Binding,
+    pub complete: Binding,
+    pub history_previous: Binding,
+    pub history_next: Binding,
 }
 
 impl Keymap {
@@ -59,7 +132,76 @@ impl Keymap {
         let mut file = File::open(path)?;
         let mut buf = String::new();
         file.read_to_string(&mut buf)?;
-        toml::from_str(&buf).map_err(std::io::Error::other)
+        Self::from_toml_str(&buf)
+    }
+
+    /// Parses a keymap, rejecting it with every unknown key and conflicting binding found, along with their lines
+    pub fn from_toml_str(source: &str) -> Result<Self> {
+        let table: toml::Table = toml::from_str(source).map_err(Error::other)?;
+        let lines = binding_lines(source);
+        let mut issues = unknown_keys(&table, &lines);
+        if issues.is_empty() {
+            let keymap: Keymap = toml::from_str(source).map_err(Error::other)?;
+            issues = keymap.issues(&lines);
+            if issues.is_empty() {
+                return Ok(keymap);
+            }
+        }
+        Err(Error::new(ErrorKind::InvalidData, KeymapIssues(issues)))
+    }
+
+    /// Every action of the keymap along with its binding, the actions being named by their path in the keymap file
+    /// such as `explorer.tree.zoom_in`
+    pub fn bindings(&self) -> Vec<(String, Binding)> {
+        fn collect(prefix: &str, table: &toml::Table, bindings: &mut Vec<(String, Binding)>) {
+            for (name, value) in table {
+                let path = if prefix.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{prefix}.{name}")
+                };
+                match value {
+                    toml::Value::Table(table) => collect(&path, table, bindings),
+                    value => bindings.extend(value.clone().try_into().ok().map(|b| (path, b))),
+                }
+            }
+        }
+        let mut bindings = Vec::new();
+        if let Ok(toml::Value::Table(table)) = toml::Value::try_from(self) {
+            collect("", &table, &mut bindings);
+        }
+        bindings
+    }
+
+    pub fn binding(&self, action: &str) -> Option<Binding> {
+        self.bindings()
+            .into_iter()
+            .find_map(|(path, binding)| (path == action).then_some(binding))
+    }
+
+    /// Replaces the binding of an action, named by its path in the keymap file
+    pub fn set_binding(&mut self, action: &str, binding: Binding) -> Result<()> {
+        let unknown = || Error::new(ErrorKind::NotFound, format!("Unknown action {action}"));
+        let mut value = toml::Value::try_from(&*self).map_err(Error::other)?;
+        let mut segments: Vec<_> = action.split('.').collect();
+        let name = segments.pop().ok_or_else(unknown)?;
+        let mut table = value.as_table_mut().ok_or_else(unknown)?;
+        for segment in segments {
+            table = table
+                .get_mut(segment)
+                .and_then(toml::Value::as_table_mut)
+                .ok_or_else(unknown)?;
+        }
+        let entry = table.get_mut(name).ok_or_else(unknown)?;
+        *entry = toml::Value::try_from(binding).map_err(Error::other)?;
+        *self = value.try_into().map_err(Error::other)?;
+        Ok(())
+    }
+
+    /// The chords too long and the actions of a same screen triggered by the same keys, with the lines of the
+    /// actions in the keymap file when given
+    pub fn issues(&self, lines: &HashMap<String, usize>) -> Vec<KeymapIssue> {
+        binding_issues(&self.bindings(), lines)
     }
 
     pub fn write_to_file(&self, path: impl AsRef<Path>, overwrite: bool) -> Result<()> {
@@ -78,58 +220,58 @@ impl Keymap {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TreeViewKeymap {
-    pub select_next: Key,
-    pub select_previous: Key,
-    pub zoom_in: Key,
-    pub zoom_out: Key,
-    pub toggle_expand: Key,
-    pub map_offset_up: Key,
-    pub map_offset_down: Key,
-    pub map_offset_left: Key,
-    pub map_offset_right: Key,
-    pub map_offset_reset: Key,
-    pub enter_search: Key,
-    pub focus: Key,
-    pub autoscale: Key,
-    pub back: Key,
-    pub speed_up: Key,
-    pub slow_down: Key,
-    pub toggle_time: Key,
-    pub toggle_info: Key,
+    pub select_next: Binding,
+    pub select_previous: Binding,
+    pub zoom_in: Binding,
+    pub zoom_out: Binding,
+    pub toggle_expand: Binding,
+    pub map_offset_up: Binding,
+    pub map_offset_down: Binding,
+    pub map_offset_left: Binding,
+    pub map_offset_right: Binding,
+    pub map_offset_reset: Binding,
+    pub enter_search: Binding,
+    pub focus: Binding,
+    pub autoscale: Binding,
+    pub back: Binding,
+    pub speed_up: Binding,
+    pub slow_down: Binding,
+    pub toggle_time: Binding,
+    pub toggle_info: Binding,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SearchViewKeymap {
-    pub move_cursor_right: Key,
-    pub move_cursor_left: Key,
-    pub select_next: Key,
-    pub select_previous: Key,
-    pub leave_search: Key,
-    pub validate_search: Key,
-    pub delete_char: Key,
+    pub move_cursor_right: Binding,
+    pub move_cursor_left: Binding,
+    pub select_next: Binding,
+    pub select_previous: Binding,
+    pub leave_search: Binding,
+    pub validate_search: Binding,
+    pub delete_char: Binding,
 }
 
 impl Default for TreeViewKeymap {
     fn default() -> Self {
         Self {
-            select_next: Key::from_str_unchecked("down"),
-            select_previous: Key::from_str_unchecked("up"),
-            zoom_in: Key::from_str_unchecked("+"),
-            zoom_out: Key::from_str_unchecked("-"),
-            toggle_info: Key::from_str_unchecked("i"),
-            toggle_expand: Key::from_str_unchecked("space"),
-            map_offset_up: Key::from_str_unchecked("w"),
-            map_offset_down: Key::from_str_unchecked("s"),
-            map_offset_left: Key::from_str_unchecked("a"),
-            map_offset_right: Key::from_str_unchecked("d"),
-            map_offset_reset: Key::from_str_unchecked("0"),
-            enter_search: Key::from_str_unchecked("/"),
-            focus: Key::from_str_unchecked("f"),
-            autoscale: Key::from_str_unchecked("x"),
-            back: Key::from_str_unchecked("esc"),
-            speed_up: Key::from_str_unchecked(">"),
-            slow_down: Key::from_str_unchecked("<"),
-            toggle_time: Key::from_str_unchecked("t"),
+            select_next: Binding::from_str_unchecked("down"),
+            select_previous: Binding::from_str_unchecked("up"),
+            zoom_in: Binding::from_str_unchecked("+"),
+            zoom_out: Binding::from_str_unchecked("-"),
+            toggle_info: Binding::from_str_unchecked("i"),
+            toggle_expand: Binding::from_str_unchecked("space"),
+            map_offset_up: Binding::from_str_unchecked("w"),
+            map_offset_down: Binding::from_str_unchecked("s"),
+            map_offset_left: Binding::from_str_unchecked("a"),
+            map_offset_right: Binding::from_str_unchecked("d"),
+            map_offset_reset: Binding::from_str_unchecked("0"),
+            enter_search: Binding::from_str_unchecked("/"),
+            focus: Binding::from_str_unchecked("f"),
+            autoscale: Binding::from_str_unchecked("x"),
+            back: Binding::from_str_unchecked("esc"),
+            speed_up: Binding::from_str_unchecked(">"),
+            slow_down: Binding::from_str_unchecked("<"),
+            toggle_time: Binding::from_str_unchecked("t"),
         }
     }
 }
@@ -137,13 +279,13 @@ impl Default for TreeViewKeymap {
 impl Default for SearchViewKeymap {
     fn default() -> Self {
         Self {
-            move_cursor_right: Key::from_str_unchecked("right"),
-            move_cursor_left: Key::from_str_unchecked("left"),
-            select_next: Key::from_str_unchecked("down"),
-            select_previous: Key::from_str_unchecked("up"),
-            leave_search: Key::from_str_unchecked("esc"),
-            validate_search: Key::from_str_unchecked("enter"),
-            delete_char: Key::from_str_unchecked("backspace"),
+            move_cursor_right: Binding::from_str_unchecked("right"),
+            move_cursor_left: Binding::from_str_unchecked("left"),
+            select_next: Binding::from_str_unchecked("down"),
+            select_previous: Binding::from_str_unchecked("up"),
+            leave_search: Binding::from_str_unchecked("esc"),
+            validate_search: Binding::from_str_unchecked("enter"),
+            delete_char: Binding::from_str_unchecked("backspace"),
         }
     }
 }
@@ -151,10 +293,10 @@ impl Default for SearchViewKeymap {
 impl Default for StartMenuKeymap {
     fn default() -> Self {
         Self {
-            select_next: Key::from_str_unchecked("down"),
-            select_previous: Key::from_str_unchecked("up"),
-            quit: Key::from_str_unchecked("esc"),
-            validate: Key::from_str_unchecked("space"),
+            select_next: Binding::from_str_unchecked("down"),
+            select_previous: Binding::from_str_unchecked("up"),
+            quit: Binding::from_str_unchecked("esc"),
+            validate: Binding::from_str_unchecked("space"),
         }
     }
 }
@@ -162,16 +304,17 @@ impl Default for StartMenuKeymap {
 impl Default for FleetScreenKeymap {
     fn default() -> Self {
         Self {
-            select_next: Key::from_str_unchecked("down"),
-            select_previous: Key::from_str_unchecked("up"),
-            back: Key::from_str_unchecked("esc"),
-            edit_trajectory: Key::from_str_unchecked("space"),
-            new_ship: Key::from_str_unchecked("n"),
-            cycle_options: Key::from_str_unchecked("tab"),
-            cycle_options_back: Key::from_str_unchecked("S backtab"),
-            validate_new_ship: Key::from_str_unchecked("enter"),
-            delete_char: Key::from_str_unchecked("backspace"),
-            enter_explorer: Key::from_str_unchecked("e"),
+            select_next: Binding::from_str_unchecked("down"),
+            select_previous: Binding::from_str_unchecked("up"),
+            back: Binding::from_str_unchecked("esc"),
+            edit_trajectory: Binding::from_str_unchecked("space"),
+            new_ship: Binding::from_str_unchecked("n"),
+            cycle_options: Binding::from_str_unchecked("tab"),
+            cycle_options_back: Binding::from_str_unchecked("S backtab"),
+            validate_new_ship: Binding::from_str_unchecked("enter"),
+            delete_char: Binding::from_str_unchecked("backspace"),
+            enter_explorer: Binding::from_str_unchecked("e"),
+            cycle_sail_attitude: Binding::from_str_unchecked("a"),
         }
     }
 }
@@ -179,21 +322,151 @@ impl Default for FleetScreenKeymap {
 impl Default for EditorKeymap {
     fn default() -> Self {
         Self {
-            select_next: Key::from_str_unchecked("down"),
-            select_previous: Key::from_str_unchecked("up"),
-            back: Key::from_str_unchecked("esc"),
-            new_node: Key::from_str_unchecked("n"),
-            open_scheduler: Key::from_str_unchecked("s"),
+            select_next: Binding::from_str_unchecked("down"),
+            select_previous: Binding::from_str_unchecked("up"),
+            back: Binding::from_str_unchecked("esc"),
+            new_node: Binding::from_str_unchecked("n"),
+            delete_node: Binding::from_str_unchecked("del"),
+            undo: Binding::from_str_unchecked("C z"),
+            redo: Binding::from_str_unchecked("C y"),
+            edit_node: Binding::from_str_unchecked("e"),
+            cycle_options: Binding::from_str_unchecked("tab"),
+            cycle_options_back: Binding::from_str_unchecked("S backtab"),
+            validate_node: Binding::from_str_unchecked("enter"),
+            delete_char: Binding::from_str_unchecked("backspace"),
+            cycle_target: Binding::from_str_unchecked("t"),
+            longer_horizon: Binding::from_str_unchecked("]"),
+            shorter_horizon: Binding::from_str_unchecked("["),
+            cycle_horizon_unit: Binding::from_str_unchecked("h"),
+            open_scheduler: Binding::from_str_unchecked("s"),
+            zoom_in: Binding::from_str_unchecked("+"),
+            zoom_out: Binding::from_str_unchecked("-"),
+            cycle_focus: Binding::from_str_unchecked("f"),
+            thrust_prograde: Binding::from_str_unchecked("i"),
+            thrust_retrograde: Binding::from_str_unchecked("k"),
+            thrust_radial: Binding::from_str_unchecked("l"),
+            thrust_antiradial: Binding::from_str_unchecked("j"),
+            thrust_normal: Binding::from_str_unchecked("u"),
+            thrust_antinormal: Binding::from_str_unchecked("o"),
+            cycle_thrust_step: Binding::from_str_unchecked("g"),
+        }
+    }
+}
+
+impl Default for SettingsKeymap {
+    fn default() -> Self {
+        Self {
+            select_next: Binding::from_str_unchecked("down"),
+            select_previous: Binding::from_str_unchecked("up"),
+            back: Binding::from_str_unchecked("esc"),
+            rebind: Binding::from_str_unchecked("enter"),
+            add_key: Binding::from_str_unchecked("a"),
+            add_chord: Binding::from_str_unchecked("c"),
+            reset: Binding::from_str_unchecked("r"),
+            save: Binding::from_str_unchecked("w"),
+        }
+    }
+}
+
+impl Default for GlobalKeymap {
+    fn default() -> Self {
+        Self {
+            open_palette: Binding::from_str_unchecked("C p"),
+            toggle_console: Binding::from_str_unchecked("`"),
+        }
+    }
+}
+
+impl Default for PaletteKeymap {
+    fn default() -> Self {
+        Self {
+            select_next: Binding::from_str_unchecked("down"),
+            select_previous: Binding::from_str_unchecked("up"),
+            close: Binding::from_str_unchecked("esc"),
+            validate: Binding::from_str_unchecked("enter"),
+            delete_char: Binding::from_str_unchecked("backspace"),
+        }
+    }
+}
+
+impl Default for ConsoleKeymap {
+    fn default() -> Self {
+        Self {
+            close: Binding::from_str_unchecked("esc"),
+            validate: Binding::from_str_unchecked("enter"),
+            delete_char: Binding::from_str_unchecked("backspace"),
+            complete: Binding::from_str_unchecked("tab"),
+            history_previous: Binding::from_str_unchecked("up"),
+            history_next: Binding::from_str_unchecked("down"),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use bevy::utils::HashMap;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
     use super::Keymap;
+    use crate::input::{
+        binding::{Binding, KeyPress},
+        key::Key,
+    };
 
     #[test]
     fn test_default_keymap() {
-        Keymap::default();
+        let keymap = Keymap::default();
+        assert!(keymap.issues(&HashMap::new()).is_empty());
+        let source = toml::to_string_pretty(&keymap).unwrap();
+        let parsed = Keymap::from_toml_str(&source).unwrap();
+        assert_eq!(parsed.bindings(), keymap.bindings());
+    }
+
+    #[test]
+    fn test_keymap_issues() {
+        let mut source = toml::to_string_pretty(&Keymap::default()).unwrap();
+        source = source
+            .replace("focus = \"f\"", "focus = \"x\"")
+            .replace("toggle_time = \"t\"", "toggle_time = \"ctrl\"");
+        let line = |prefix: &str| source.lines().position(|l| l.starts_with(prefix)).unwrap() + 1;
+        let (focus, toggle_time) = (line("focus ="), line("toggle_time ="));
+        let error = Keymap::from_toml_str(&source).unwrap_err().to_string();
+        assert!(error.contains(&format!("line {toggle_time}")), "{error}");
+        assert!(error.contains("\"ctrl\""), "{error}");
+        source = source.replace("toggle_time = \"ctrl\"", "toggle_time = \"t\"");
+        let error = Keymap::from_toml_str(&source).unwrap_err().to_string();
+        assert!(error.contains(&format!("line {focus}")), "{error}");
+        assert!(error.contains("explorer.tree.autoscale"), "{error}");
+    }
+
+    #[test]
+    fn test_multiple_keys_and_chords() {
+        let mut source = toml::to_string_pretty(&Keymap::default()).unwrap();
+        source = source.replace(
+            "zoom_in = \"+\"",
+            "zoom_in = [\"+\", \"=\", [\"z\", \"z\"]]",
+        );
+        let keymap = Keymap::from_toml_str(&source).unwrap();
+        let zoom_in = &keymap.explorer.tree.zoom_in;
+        assert_eq!(zoom_in.0.len(), 3);
+        let press = |c, previous: &[char]| KeyPress {
+            event: KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE),
+            previous: previous
+                .iter()
+                .map(|&c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))
+                .collect(),
+        };
+        assert!(zoom_in.matches(&press('=', &[])));
+        assert!(zoom_in.matches(&press('z', &['z'])));
+        assert!(!zoom_in.matches(&press('z', &[])));
+        // The chord conflicts with the actions triggered by its first key
+        let mut keymap = Keymap::default();
+        keymap
+            .set_binding(
+                "explorer.tree.zoom_in",
+                Binding(vec![vec![Key::from_str_unchecked("f"); 2]]),
+            )
+            .unwrap();
+        assert_eq!(keymap.issues(&HashMap::new()).len(), 1);
     }
 }
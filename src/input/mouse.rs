@@ -0,0 +1,151 @@
+use std::io::stdout;
+
+use bevy::prelude::*;
+use bevy_ratatui::event::MouseEvent;
+use crossterm::{
+    event::{DisableMouseCapture, EnableMouseCapture, MouseButton, MouseEventKind},
+    execute,
+};
+
+use crate::utils::Direction2;
+
+/// What the screens make of the mouse events of the terminal, at the given cell
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub enum MouseGesture {
+    Click {
+        column: u16,
+        row: u16,
+    },
+    Scroll {
+        column: u16,
+        row: u16,
+        direction: Direction2,
+    },
+    /// The left button moved by the given number of cells while pressed, from the given cell
+    Drag {
+        column: u16,
+        row: u16,
+        delta: (i32, i32),
+    },
+}
+
+/// Makes the terminal report the mouse events for as long as this resource exists
+#[derive(Resource)]
+pub struct MouseCapture;
+
+impl MouseCapture {
+    pub fn enable() -> std::io::Result<Self> {
+        execute!(stdout(), EnableMouseCapture)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for MouseCapture {
+    fn drop(&mut self) {
+        let _ = execute!(stdout(), DisableMouseCapture);
+    }
+}
+
+pub fn capture_mouse(mut commands: Commands) {
+    match MouseCapture::enable() {
+        Ok(capture) => commands.insert_resource(capture),
+        Err(e) => warn!("Could not capture the mouse in the terminal: {e}"),
+    }
+}
+
+/// Turns the mouse events of the terminal into gestures, remembering where the left button was last seen
+/// to follow drags
+pub fn read_mouse_gestures(
+    mut mouse_events: EventReader<MouseEvent>,
+    mut last_drag_cell: Local<Option<(u16, u16)>>,
+    mut gestures: EventWriter<MouseGesture>,
+) {
+    for MouseEvent(event) in mouse_events.read() {
+        let (column, row) = (event.column, event.row);
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                *last_drag_cell = Some((column, row));
+                gestures.send(MouseGesture::Click { column, row });
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                if let Some((c, r)) = last_drag_cell.replace((column, row)) {
+                    gestures.send(MouseGesture::Drag {
+                        column: c,
+                        row: r,
+                        delta: (column as i32 - c as i32, row as i32 - r as i32),
+                    });
+                }
+            }
+            MouseEventKind::Up(MouseButton::Left) => *last_drag_cell = None,
+            MouseEventKind::ScrollUp => {
+                gestures.send(MouseGesture::Scroll {
+                    column,
+                    row,
+                    direction: Direction2::Up,
+                });
+            }
+            MouseEventKind::ScrollDown => {
+                gestures.send(MouseGesture::Scroll {
+                    column,
+                    row,
+                    direction: Direction2::Down,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{app::App, prelude::*};
+    use bevy_ratatui::event::MouseEvent;
+    use crossterm::event::{KeyModifiers, MouseButton, MouseEvent as CMouseEvent, MouseEventKind};
+
+    use super::{read_mouse_gestures, MouseGesture};
+
+    #[test]
+    fn test_drag_gesture() {
+        let mut app = App::new();
+        app.add_event::<MouseEvent>()
+            .add_event::<MouseGesture>()
+            .add_systems(Update, read_mouse_gestures);
+        let event = |kind, column, row| {
+            MouseEvent(CMouseEvent {
+                kind,
+                column,
+                row,
+                modifiers: KeyModifiers::NONE,
+            })
+        };
+        app.world_mut().send_event_batch([
+            event(MouseEventKind::Down(MouseButton::Left), 10, 10),
+            event(MouseEventKind::Drag(MouseButton::Left), 12, 9),
+            event(MouseEventKind::Drag(MouseButton::Left), 15, 9),
+            event(MouseEventKind::Up(MouseButton::Left), 15, 9),
+            event(MouseEventKind::Drag(MouseButton::Left), 20, 20),
+        ]);
+        app.update();
+        let events = app.world().resource::<Events<MouseGesture>>();
+        let gestures: Vec<_> = events.get_reader().read(events).copied().collect();
+        assert_eq!(
+            gestures,
+            vec![
+                MouseGesture::Click {
+                    column: 10,
+                    row: 10
+                },
+                MouseGesture::Drag {
+                    column: 10,
+                    row: 10,
+                    delta: (2, -1)
+                },
+                MouseGesture::Drag {
+                    column: 12,
+                    row: 9,
+                    delta: (3, 0)
+                },
+            ]
+        );
+    }
+}
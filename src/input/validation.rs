@@ -0,0 +1,192 @@
+use bevy::utils::HashMap;
+
+use super::{
+    binding::{Binding, MAX_CHORD_LENGTH},
+    key::Key,
+};
+
+/// A problem found in a keymap, along with the line of the keymap file where it is when known
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeymapIssue {
+    UnknownKey {
+        action: String,
+        key: String,
+        line: Option<usize>,
+    },
+    ChordTooLong {
+        action: String,
+        line: Option<usize>,
+    },
+    /// Two actions of the same screen triggered by the same keys, one chord starting or ending with the other
+    Conflict {
+        first: String,
+        second: String,
+        keys: String,
+        lines: (Option<usize>, Option<usize>),
+    },
+}
+
+fn line_prefix(line: Option<usize>) -> String {
+    line.map_or(String::new(), |l| format!("line {l}: "))
+}
+
+impl std::fmt::Display for KeymapIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeymapIssue::UnknownKey { action, key, line } => {
+                write!(
+                    f,
+                    "{}unknown key \"{key}\" for {action}",
+                    line_prefix(*line)
+                )
+            }
+            KeymapIssue::ChordTooLong { action, line } => write!(
+                f,
+                "{}chord of {action} longer than {MAX_CHORD_LENGTH} keys",
+                line_prefix(*line)
+            ),
+            KeymapIssue::Conflict {
+                first,
+                second,
+                keys,
+                lines,
+            } => {
+                write!(f, "{}{second} conflicts with {first}", line_prefix(lines.1))?;
+                if let Some(line) = lines.0 {
+                    write!(f, " (line {line})")?;
+                }
+                write!(f, ", both being triggered by {keys}")
+            }
+        }
+    }
+}
+
+/// Every issue found in a keymap, which is rejected because of them
+#[derive(Debug)]
+pub struct KeymapIssues(pub Vec<KeymapIssue>);
+
+impl std::fmt::Display for KeymapIssues {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Invalid keymap:")?;
+        for issue in &self.0 {
+            writeln!(f, "  {issue}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for KeymapIssues {}
+
+/// Lines of the keymap file where the actions are bound, the actions being named by their path such as
+/// `explorer.tree.zoom_in`
+pub fn binding_lines(source: &str) -> HashMap<String, usize> {
+    let mut section = String::new();
+    let mut lines = HashMap::new();
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = header.trim().to_owned();
+        } else if let Some((name, _)) = line.split_once('=') {
+            let name = name.trim();
+            let path = if section.is_empty() {
+                name.to_owned()
+            } else {
+                format!("{section}.{name}")
+            };
+            lines.insert(path, i + 1);
+        }
+    }
+    lines
+}
+
+/// The keys of the keymap file which can't be parsed, the file being read as a plain table so that they are all
+/// found rather than the first one
+pub fn unknown_keys(table: &toml::Table, lines: &HashMap<String, usize>) -> Vec<KeymapIssue> {
+    fn keys(value: &toml::Value) -> Vec<&str> {
+        match value {
+            toml::Value::String(s) => vec![s.as_str()],
+            toml::Value::Array(items) => items.iter().flat_map(keys).collect(),
+            _ => Vec::new(),
+        }
+    }
+    fn walk(
+        prefix: &str,
+        table: &toml::Table,
+        lines: &HashMap<String, usize>,
+        issues: &mut Vec<KeymapIssue>,
+    ) {
+        for (name, value) in table {
+            let action = if prefix.is_empty() {
+                name.clone()
+            } else {
+                format!("{prefix}.{name}")
+            };
+            if let toml::Value::Table(table) = value {
+                walk(&action, table, lines, issues);
+                continue;
+            }
+            for key in keys(value) {
+                if key.parse::<Key>().is_err() {
+                    issues.push(KeymapIssue::UnknownKey {
+                        action: action.clone(),
+                        key: key.to_owned(),
+                        line: lines.get(&action).copied(),
+                    });
+                }
+            }
+        }
+    }
+    let mut issues = Vec::new();
+    walk("", table, lines, &mut issues);
+    issues
+}
+
+/// Whether pressing one of the chords can trigger the other as well
+fn overlap(a: &[Key], b: &[Key]) -> bool {
+    a.starts_with(b) || b.starts_with(a) || a.ends_with(b) || b.ends_with(a)
+}
+
+/// Section of the keymap whose keys work on every screen, and so must not conflict with any other key
+const GLOBAL_SECTION: &str = "global";
+
+/// The chords too long, and the pairs of actions of a same screen that the same keys can trigger
+pub fn binding_issues(
+    bindings: &[(String, Binding)],
+    lines: &HashMap<String, usize>,
+) -> Vec<KeymapIssue> {
+    let line = |action: &String| lines.get(action).copied();
+    let section = |action: &str| action.rsplit_once('.').map_or("", |(s, _)| s).to_owned();
+    let mut issues: Vec<_> = bindings
+        .iter()
+        .filter(|(_, binding)| binding.0.iter().any(|c| c.len() > MAX_CHORD_LENGTH))
+        .map(|(action, _)| KeymapIssue::ChordTooLong {
+            action: action.clone(),
+            line: line(action),
+        })
+        .collect();
+    for (i, (first, a)) in bindings.iter().enumerate() {
+        for (second, b) in &bindings[i + 1..] {
+            let sections = (section(first), section(second));
+            if sections.0 != sections.1
+                && sections.0 != GLOBAL_SECTION
+                && sections.1 != GLOBAL_SECTION
+            {
+                continue;
+            }
+            let shared = a.0.iter().find_map(|ca| {
+                b.0.iter()
+                    .find(|cb| !ca.is_empty() && !cb.is_empty() && overlap(ca, cb))
+                    .map(|cb| if ca.len() <= cb.len() { ca } else { cb })
+            });
+            if let Some(chord) = shared {
+                issues.push(KeymapIssue::Conflict {
+                    first: first.clone(),
+                    second: second.clone(),
+                    keys: Binding(vec![chord.clone()]).to_string(),
+                    lines: (line(first), line(second)),
+                });
+            }
+        }
+    }
+    issues
+}
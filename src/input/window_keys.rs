@@ -0,0 +1,161 @@
+use bevy::{
+    input::{
+        keyboard::{Key as LogicalKey, KeyboardInput},
+        ButtonState,
+    },
+    prelude::*,
+};
+use bevy_ratatui::event::KeyEvent;
+use crossterm::event::{KeyCode as CKeyCode, KeyEvent as CKeyEvent, KeyModifiers};
+
+/// Forwards the keys pressed in the Bevy window as terminal key events, so that the screens read them
+/// through the same [Keymap](super::keyboard::Keymap) bindings
+pub fn forward_window_keys(
+    mut inputs: EventReader<KeyboardInput>,
+    pressed: Res<ButtonInput<KeyCode>>,
+    mut key_events: EventWriter<KeyEvent>,
+) {
+    for input in inputs.read() {
+        if input.state != ButtonState::Pressed {
+            continue;
+        }
+        if let Some(event) = window_key_event(input, modifiers(&pressed)) {
+            key_events.send(KeyEvent(event));
+        }
+    }
+}
+
+fn modifiers(pressed: &ButtonInput<KeyCode>) -> KeyModifiers {
+    let mut modifiers = KeyModifiers::NONE;
+    if pressed.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        modifiers |= KeyModifiers::CONTROL;
+    }
+    if pressed.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]) {
+        modifiers |= KeyModifiers::ALT;
+    }
+    if pressed.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        modifiers |= KeyModifiers::SHIFT;
+    }
+    modifiers
+}
+
+/// The key event a terminal would send for this key press, if it has an equivalent.
+/// As in terminals, shift is only reported for uppercase characters and backtab
+pub fn window_key_event(input: &KeyboardInput, modifiers: KeyModifiers) -> Option<CKeyEvent> {
+    let shift = modifiers.contains(KeyModifiers::SHIFT);
+    let code = match &input.logical_key {
+        LogicalKey::Character(s) => {
+            let c = s.chars().next().filter(|c| !c.is_control());
+            // With control held, some platforms give the control character instead of the letter
+            let c = c.or_else(|| letter(input.key_code))?;
+            return Some(CKeyEvent::new(
+                CKeyCode::Char(c),
+                if c.is_uppercase() {
+                    modifiers
+                } else {
+                    modifiers.difference(KeyModifiers::SHIFT)
+                },
+            ));
+        }
+        LogicalKey::Space => CKeyCode::Char(' '),
+        LogicalKey::Tab if shift => CKeyCode::BackTab,
+        LogicalKey::Tab => CKeyCode::Tab,
+        LogicalKey::Enter => CKeyCode::Enter,
+        LogicalKey::Escape => CKeyCode::Esc,
+        LogicalKey::Backspace => CKeyCode::Backspace,
+        LogicalKey::Delete => CKeyCode::Delete,
+        LogicalKey::Insert => CKeyCode::Insert,
+        LogicalKey::ArrowLeft => CKeyCode::Left,
+        LogicalKey::ArrowRight => CKeyCode::Right,
+        LogicalKey::ArrowUp => CKeyCode::Up,
+        LogicalKey::ArrowDown => CKeyCode::Down,
+        LogicalKey::Home => CKeyCode::Home,
+        LogicalKey::End => CKeyCode::End,
+        LogicalKey::PageUp => CKeyCode::PageUp,
+        LogicalKey::PageDown => CKeyCode::PageDown,
+        LogicalKey::F1 => CKeyCode::F(1),
+        LogicalKey::F2 => CKeyCode::F(2),
+        LogicalKey::F3 => CKeyCode::F(3),
+        LogicalKey::F4 => CKeyCode::F(4),
+        LogicalKey::F5 => CKeyCode::F(5),
+        LogicalKey::F6 => CKeyCode::F(6),
+        LogicalKey::F7 => CKeyCode::F(7),
+        LogicalKey::F8 => CKeyCode::F(8),
+        LogicalKey::F9 => CKeyCode::F(9),
+        LogicalKey::F10 => CKeyCode::F(10),
+        LogicalKey::F11 => CKeyCode::F(11),
+        LogicalKey::F12 => CKeyCode::F(12),
+        _ => return None,
+    };
+    let modifiers = if code == CKeyCode::BackTab {
+        modifiers
+    } else {
+        modifiers.difference(KeyModifiers::SHIFT)
+    };
+    Some(CKeyEvent::new(code, modifiers))
+}
+
+/// The lowercase letter of a physical key of the alphabet
+fn letter(key_code: KeyCode) -> Option<char> {
+    let name = format!("{key_code:?}");
+    let letter = name.strip_prefix("Key")?;
+    let mut chars = letter.chars();
+    chars
+        .next()
+        .filter(|_| chars.next().is_none())
+        .map(|c| c.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        input::{
+            keyboard::{Key as LogicalKey, KeyboardInput},
+            ButtonState,
+        },
+        prelude::*,
+    };
+    use crossterm::event::{KeyCode as CKeyCode, KeyModifiers};
+
+    use super::window_key_event;
+    use crate::input::prelude::Key;
+
+    fn press(key_code: KeyCode, logical_key: LogicalKey) -> KeyboardInput {
+        KeyboardInput {
+            key_code,
+            logical_key,
+            state: ButtonState::Pressed,
+            window: Entity::PLACEHOLDER,
+        }
+    }
+
+    #[test]
+    fn test_window_keys_match_keymap() {
+        let undo = window_key_event(
+            &press(KeyCode::KeyZ, LogicalKey::Character("\u{1a}".into())),
+            KeyModifiers::CONTROL,
+        )
+        .unwrap();
+        assert!(Key::from_str_unchecked("C z").matches(&undo));
+        let speed_up = window_key_event(
+            &press(KeyCode::Period, LogicalKey::Character(">".into())),
+            KeyModifiers::SHIFT,
+        )
+        .unwrap();
+        assert!(Key::from_str_unchecked(">").matches(&speed_up));
+        let back =
+            window_key_event(&press(KeyCode::Tab, LogicalKey::Tab), KeyModifiers::SHIFT).unwrap();
+        assert!(Key::from_str_unchecked("S backtab").matches(&back));
+        let space = window_key_event(
+            &press(KeyCode::Space, LogicalKey::Space),
+            KeyModifiers::NONE,
+        )
+        .unwrap();
+        assert_eq!(space.code, CKeyCode::Char(' '));
+        assert!(window_key_event(
+            &press(KeyCode::ShiftLeft, LogicalKey::Shift),
+            KeyModifiers::SHIFT
+        )
+        .is_none());
+    }
+}
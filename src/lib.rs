@@ -1,10 +1,13 @@
+pub mod batch;
 pub mod client;
+pub mod ephemeris;
 pub mod game;
 pub mod input;
 pub mod network;
 pub mod objects;
 pub mod physics;
 pub mod prelude;
+pub mod replay;
 pub mod server;
 pub mod ui;
 pub mod utils;
@@ -67,9 +67,11 @@ pub fn build_system(mut commands: Commands, config: Res<BodiesConfig>) {
     let mut id_mapping = HashMap::new();
     for (data, orbiting_obj) in bodies_orbiting_obj {
         let id = data.id;
+        let harmonics = ZonalHarmonics::of_body(&data);
         let mut entity = commands.spawn((
             Position::default(),
             EllipticalOrbit::from(&data),
+            BodyRotation::from(&data),
             Mass(data.mass),
             BodyInfo(data),
             Velocity::default(),
@@ -79,6 +81,9 @@ pub fn build_system(mut commands: Commands, config: Res<BodiesConfig>) {
         if id == primary_body {
             entity.insert(PrimaryBody);
         }
+        if let Some(harmonics) = harmonics {
+            entity.insert(harmonics);
+        }
         id_mapping.insert(id, entity.id());
     }
     commands.insert_resource(BodiesMapping(id_mapping));
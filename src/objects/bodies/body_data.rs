@@ -2,6 +2,8 @@ use std::fmt::Display;
 
 use serde::{Deserialize, Serialize};
 
+use crate::physics::harmonics::ZonalHarmonics;
+
 use super::BodyID;
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default, PartialOrd)]
@@ -52,7 +54,11 @@ pub struct BodyData {
     pub revolution_period: f64,
     // Time required to rotate around itself (in earth hours)
     pub rotation_period: f64,
+    // Angle between the rotation axis and the normal of the orbital plane (in degrees)
+    pub axial_tilt: f64,
 
     pub radius: f64,
     pub mass: f64,
+    /// Zonal harmonics of the gravity field, if the body is not treated as a point mass
+    pub harmonics: Option<ZonalHarmonics>,
 }
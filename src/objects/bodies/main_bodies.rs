@@ -5,6 +5,7 @@ use serde::{de::Visitor, Deserialize, Deserializer};
 use crate::{
     objects::{id::{id_from, MAX_ID_LENGTH}, 
             orbiting_obj::OrbitingObjects},
+    physics::harmonics::ZonalHarmonics,
     utils::de::deserialize_options,
 };
 
@@ -15,7 +16,7 @@ use super::{
 
 const ID_PREFIX: &str = "https://api.le-systeme-solaire.net/rest/bodies/";
 const MAIN_OBJECT_FILE_PATH: &str = "main_objects.json";
-const SUN_ID: &str = "soleil";
+pub const SUN_ID: &str = "soleil";
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct MainBodyID(pub String);
@@ -115,11 +116,15 @@ pub struct MainBodyData {
     // Time required to rotate around itself (in earth hours)
     #[serde(alias = "sideralRotation")]
     pub rotation_period: f64,
+    #[serde(default)]
+    pub axial_tilt: f64,
 
     #[serde(alias = "meanRadius")]
     radius: f64,
     #[serde(deserialize_with = "deserialize_options")]
     mass: Mass,
+    #[serde(default)]
+    harmonics: Option<ZonalHarmonics>,
 }
 
 impl From<MainBodyData> for BodyData {
@@ -139,8 +144,10 @@ impl From<MainBodyData> for BodyData {
             apoapsis: value.apoapsis as f64,
             revolution_period: value.revolution_period,
             rotation_period: value.rotation_period,
+            axial_tilt: value.axial_tilt,
             radius: value.radius,
             mass: value.mass.into(),
+            harmonics: value.harmonics,
         }
     }
 }
@@ -268,8 +275,10 @@ mod tests {
                 apoapsis: 405500.,
                 revolution_period: 27.32170,
                 rotation_period: 655.72800,
+                axial_tilt: 6.68,
                 radius: 1737.,
-                mass: 7.346e22
+                mass: 7.346e22,
+                harmonics: None
             }
         );
     }
@@ -1,13 +1,27 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashMap};
 use crate::objects::{ships, bodies};
 use crate::objects::bodies::main_bodies::MainBodyData;
 
-#[derive(PartialEq, Debug, Clone)]
-pub(crate) enum OrbitalObjID {
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum OrbitalObjID {
     Body(bodies::BodyID),
     Ship(ships::ShipID),
 }
 
+impl OrbitalObjID {
+    /// Entity of the object, looked up in the mapping of its kind
+    pub fn entity(
+        &self,
+        bodies_mapping: &HashMap<bodies::BodyID, Entity>,
+        ships_mapping: &HashMap<ships::ShipID, Entity>,
+    ) -> Option<Entity> {
+        match self {
+            OrbitalObjID::Body(id) => bodies_mapping.get(id).copied(),
+            OrbitalObjID::Ship(id) => ships_mapping.get(id).copied(),
+        }
+    }
+}
+
 #[derive(Component, PartialEq, Debug, Clone)]
 pub struct OrbitingObjects(pub(crate) Vec<OrbitalObjID>);
 
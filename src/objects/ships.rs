@@ -3,6 +3,7 @@
 
 use arrayvec::ArrayString;
 use bevy::{math::DVec3, prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
 use std::f64::consts::PI;
 
 use crate::game::{ClearOnUnload, Loaded};
@@ -19,6 +20,8 @@ use super::prelude::{BodiesMapping, BodyInfo, PrimaryBody};
 use super::ObjectsUpdate;
 use scheduler::{ShipSchedule};
 
+pub mod autopilot;
+pub mod ghost;
 pub mod trajectory;
 pub mod scheduler;
 
@@ -42,7 +45,7 @@ pub struct ShipsPlugin;
 
 impl Plugin for ShipsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(trajectory::plugin)
+        app.add_plugins((trajectory::plugin, scheduler::plugin, ghost::plugin, autopilot::plugin))
             .add_event::<ShipEvent>()
             .add_systems(Update, 
                 (
@@ -65,7 +68,7 @@ pub(crate) struct HostBody(pub BodyID);
 
 pub type ShipID = ArrayString<MAX_ID_LENGTH>;
 
-#[derive(Component, Clone, Default, PartialEq, Debug)]
+#[derive(Component, Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
 pub struct ShipInfo {
     pub id: ShipID,
     pub spawn_pos: DVec3,
@@ -75,7 +78,7 @@ pub struct ShipInfo {
 #[derive(Resource, Default)]
 pub struct ShipsMapping(pub HashMap<ShipID, Entity>);
 
-#[derive(Event, Debug)]
+#[derive(Event, Clone, Debug, Serialize, Deserialize)]
 pub enum ShipEvent {
     Create(ShipInfo),
     Remove(ShipID),
@@ -132,6 +135,7 @@ fn handle_ship_create(
                         influence,
                         pos,
                         Velocity(info.spawn_speed),
+                        ShipSchedule::default(),
                         TransformBundle::from_transform(Transform::from_xyz(0., 0., 1.)),
                         ClearOnUnload,
                     ))
@@ -332,8 +336,10 @@ mod tests {
             apoapsis: 0.,
             revolution_period: 0.,
             rotation_period: 0.,
+            axial_tilt: 7.25,
             radius: 695508.,
-            mass: 1.989e30
+            mass: 1.989e30,
+            harmonics: None
         };
         let earth_data = BodyData {
             id: id_from("terre"),
@@ -350,8 +356,10 @@ mod tests {
             apoapsis: 152100000.,
             revolution_period: 365.256,
             rotation_period: 23.9345,
+            axial_tilt: 23.4393,
             radius: 6371.00840,
-            mass: 5.97237e24
+            mass: 5.97237e24,
+            harmonics: None
         };
         let primary_body = app.world_mut().spawn( (
             Position::default(),
@@ -0,0 +1,576 @@
+//! Autopilots are Rhai scripts attached to ships and run on every tick. A script reads the state of its ship and of
+//! the bodies, and steers the ship by adding or removing maneuver nodes. Each run is given a budget of operations so
+//! that a faulty script stops instead of stalling the simulation.
+//!
+//! The variables given to the scripts are:
+//! - `tick` and `time`, the current tick and the elapsed time in days
+//! - `ship`, with the `id`, `pos`, `speed`, `host`, `elements` and `nodes` properties and the
+//!   `add_node(tick, prograde, radial, normal[, origin])` and `remove_node(tick)` methods
+//! - `bodies`, a map from the body IDs to their `pos`, `speed`, `mass` and `radius`
+//! - `memory`, a map kept from one tick to the next
+use std::{fs, io, path::Path};
+
+use bevy::{math::DVec3, prelude::*, utils::HashMap};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, ParseError, Scope, AST};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    objects::{prelude::*, ObjectsUpdate},
+    physics::{
+        influence::InfluenceUpdate, orbit::OsculatingElements, prelude::*, time::TickEvent,
+        PhysicsUpdate, G,
+    },
+};
+
+use super::trajectory::{ManeuverNode, TrajectoryEvent, TrajectoryStore, TrajectoryUpdate};
+
+/// Number of operations an autopilot may run on each tick before being stopped
+pub const AUTOPILOT_STEP_BUDGET: u64 = 100_000;
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<AutopilotEngine>()
+        .init_resource::<AutopilotDiagnostics>()
+        .add_event::<AutopilotEvent>()
+        .add_systems(
+            Update,
+            handle_autopilot_events
+                .in_set(ObjectsUpdate)
+                .run_if(resource_exists::<ShipsMapping>),
+        )
+        .add_systems(
+            FixedUpdate,
+            run_autopilots
+                .run_if(on_event::<TickEvent>())
+                .run_if(resource_exists::<BodiesMapping>)
+                .after(InfluenceUpdate)
+                .before(TrajectoryUpdate)
+                .in_set(PhysicsUpdate),
+        );
+}
+
+/// Script steering a ship, along with the variables it keeps from one tick to the next
+#[derive(Component)]
+pub struct Autopilot {
+    script: AST,
+    memory: Map,
+}
+
+/// Starts or stops the autopilot of a ship. Scripts are given by their source, which is compiled when they are
+/// attached, so that the events can be journaled and replayed
+#[derive(Event, Clone, Debug, Serialize, Deserialize)]
+pub enum AutopilotEvent {
+    Attach { ship: ShipID, source: String },
+    Detach(ShipID),
+}
+
+/// Why the autopilots of the ships were stopped
+#[derive(Resource, Default)]
+pub struct AutopilotDiagnostics(pub HashMap<ShipID, String>);
+
+#[derive(Debug)]
+pub enum AutopilotError {
+    Io(io::Error),
+    Parse(ParseError),
+    Run(Box<EvalAltResult>),
+    UnknownBody(String),
+    /// A node was added without origin while the ship has no main influencer
+    NoOrigin,
+}
+
+impl From<io::Error> for AutopilotError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<ParseError> for AutopilotError {
+    fn from(value: ParseError) -> Self {
+        Self::Parse(value)
+    }
+}
+
+impl From<Box<EvalAltResult>> for AutopilotError {
+    fn from(value: Box<EvalAltResult>) -> Self {
+        Self::Run(value)
+    }
+}
+
+impl std::error::Error for AutopilotError {}
+
+impl std::fmt::Display for AutopilotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AutopilotError::Io(e) => write!(f, "Could not read the script: {e}"),
+            AutopilotError::Parse(e) => write!(f, "Syntax error in the script: {e}"),
+            AutopilotError::Run(e) => write!(f, "Autopilot stopped: {e}"),
+            AutopilotError::UnknownBody(id) => {
+                write!(f, "Autopilot stopped: unknown body \"{id}\"")
+            }
+            AutopilotError::NoOrigin => write!(
+                f,
+                "Autopilot stopped: a node needs an origin as the ship has no main influencer"
+            ),
+        }
+    }
+}
+
+/// Scripting engine exposing the ships and bodies to the autopilots
+#[derive(Resource)]
+pub struct AutopilotEngine(Engine);
+
+impl Default for AutopilotEngine {
+    fn default() -> Self {
+        let mut engine = Engine::new();
+        engine.set_max_operations(AUTOPILOT_STEP_BUDGET);
+        engine.on_print(|text| info!("{text}"));
+        engine
+            .register_type_with_name::<ShipHandle>("Ship")
+            .register_get("id", |s: &mut ShipHandle| s.id.to_string())
+            .register_get("pos", |s: &mut ShipHandle| vector(s.pos))
+            .register_get("speed", |s: &mut ShipHandle| vector(s.speed))
+            .register_get("host", |s: &mut ShipHandle| {
+                s.host.map_or(Dynamic::UNIT, |h| h.to_string().into())
+            })
+            .register_get("elements", |s: &mut ShipHandle| {
+                s.elements
+                    .as_ref()
+                    .map_or(Dynamic::UNIT, |e| Dynamic::from_map(elements(e)))
+            })
+            .register_get("nodes", |s: &mut ShipHandle| {
+                s.nodes
+                    .iter()
+                    .map(|&tick| Dynamic::from_int(tick as i64))
+                    .collect::<Array>()
+            })
+            .register_fn(
+                "add_node",
+                |s: &mut ShipHandle,
+                 tick: i64,
+                 prograde: Dynamic,
+                 radial: Dynamic,
+                 normal: Dynamic| {
+                    s.add_node(tick, [prograde, radial, normal], None)
+                },
+            )
+            .register_fn(
+                "add_node",
+                |s: &mut ShipHandle,
+                 tick: i64,
+                 prograde: Dynamic,
+                 radial: Dynamic,
+                 normal: Dynamic,
+                 origin: &str| {
+                    s.add_node(tick, [prograde, radial, normal], Some(origin.to_owned()))
+                },
+            )
+            .register_fn("remove_node", |s: &mut ShipHandle, tick: i64| {
+                s.maneuvers.push(Maneuver::Remove(tick_from(tick)?));
+                Ok::<_, Box<EvalAltResult>>(())
+            });
+        Self(engine)
+    }
+}
+
+impl AutopilotEngine {
+    pub fn compile(&self, source: &str) -> Result<AST, AutopilotError> {
+        Ok(self.0.compile(source)?)
+    }
+
+    /// Reads the source of a script, checking that it compiles
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<String, AutopilotError> {
+        let source = fs::read_to_string(path)?;
+        self.compile(&source)?;
+        Ok(source)
+    }
+}
+
+/// A maneuver asked by an autopilot, applied once its script ran without error
+#[derive(Clone, Debug)]
+enum Maneuver {
+    Add {
+        tick: u64,
+        thrust: DVec3,
+        origin: Option<String>,
+    },
+    Remove(u64),
+}
+
+/// The ship as seen by its autopilot
+#[derive(Clone, Debug)]
+struct ShipHandle {
+    id: ShipID,
+    pos: DVec3,
+    speed: DVec3,
+    host: Option<BodyID>,
+    /// Elements of the orbit around the host
+    elements: Option<OsculatingElements>,
+    /// Ticks of the nodes of the trajectory
+    nodes: Vec<u64>,
+    maneuvers: Vec<Maneuver>,
+}
+
+impl ShipHandle {
+    /// Adds a node whose thrust is given along the prograde, radial and normal directions
+    fn add_node(
+        &mut self,
+        tick: i64,
+        thrust: [Dynamic; 3],
+        origin: Option<String>,
+    ) -> Result<(), Box<EvalAltResult>> {
+        let [prograde, radial, normal] = thrust.map(number);
+        self.maneuvers.push(Maneuver::Add {
+            tick: tick_from(tick)?,
+            thrust: DVec3::new(prograde?, radial?, -normal?),
+            origin,
+        });
+        Ok(())
+    }
+}
+
+fn tick_from(tick: i64) -> Result<u64, Box<EvalAltResult>> {
+    u64::try_from(tick).map_err(|_| format!("Invalid tick {tick}").into())
+}
+
+/// Integers are accepted as well as floats
+fn number(value: Dynamic) -> Result<f64, Box<EvalAltResult>> {
+    value
+        .as_float()
+        .or_else(|_| value.as_int().map(|i| i as f64))
+        .map_err(|t| format!("Expected a number, found {t}").into())
+}
+
+fn vector(v: DVec3) -> Array {
+    v.to_array().into_iter().map(Dynamic::from_float).collect()
+}
+
+fn elements(e: &OsculatingElements) -> Map {
+    let optional = |v: Option<f64>| v.map_or(Dynamic::UNIT, Dynamic::from_float);
+    Map::from_iter([
+        (
+            "semimajor_axis".into(),
+            Dynamic::from_float(e.semimajor_axis),
+        ),
+        ("eccentricity".into(), Dynamic::from_float(e.eccentricity)),
+        ("inclination".into(), Dynamic::from_float(e.inclination)),
+        ("long_asc_node".into(), Dynamic::from_float(e.long_asc_node)),
+        ("arg_periapsis".into(), Dynamic::from_float(e.arg_periapsis)),
+        ("true_anomaly".into(), Dynamic::from_float(e.true_anomaly)),
+        ("periapsis".into(), Dynamic::from_float(e.periapsis)),
+        ("apoapsis".into(), optional(e.apoapsis)),
+        ("period".into(), optional(e.period)),
+    ])
+}
+
+fn handle_autopilot_events(
+    mut commands: Commands,
+    mut reader: EventReader<AutopilotEvent>,
+    mapping: Res<ShipsMapping>,
+    engine: Res<AutopilotEngine>,
+    mut diagnostics: ResMut<AutopilotDiagnostics>,
+) {
+    for event in reader.read() {
+        match event {
+            AutopilotEvent::Attach { ship, source } => {
+                let Some(&entity) = mapping.0.get(ship) else {
+                    warn!("Autopilot not attached: unknown ship {ship}");
+                    continue;
+                };
+                match engine.compile(source) {
+                    Ok(script) => {
+                        diagnostics.0.remove(ship);
+                        commands.entity(entity).insert(Autopilot {
+                            script,
+                            memory: Map::new(),
+                        });
+                    }
+                    Err(e) => {
+                        warn!("Autopilot of {ship} not attached: {e}");
+                        diagnostics.0.insert(*ship, e.to_string());
+                    }
+                }
+            }
+            AutopilotEvent::Detach(ship) => {
+                if let Some(&entity) = mapping.0.get(ship) {
+                    commands.entity(entity).remove::<Autopilot>();
+                }
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_autopilots(
+    mut commands: Commands,
+    mut autopilots: Query<(
+        Entity,
+        &ShipInfo,
+        &mut Autopilot,
+        &Position,
+        &Velocity,
+        Option<&Influenced>,
+    )>,
+    bodies: Query<(&BodyInfo, &Position, &Velocity)>,
+    mapping: Res<BodiesMapping>,
+    store: Option<Res<TrajectoryStore>>,
+    engine: Res<AutopilotEngine>,
+    time: Res<GameTime>,
+    mut diagnostics: ResMut<AutopilotDiagnostics>,
+    mut trajectory_events: EventWriter<TrajectoryEvent>,
+) {
+    if autopilots.is_empty() {
+        return;
+    }
+    let body_states: Map = bodies
+        .iter()
+        .map(|(BodyInfo(data), Position(pos), Velocity(speed))| {
+            let state = Map::from_iter([
+                ("pos".into(), Dynamic::from_array(vector(*pos))),
+                ("speed".into(), Dynamic::from_array(vector(*speed))),
+                ("mass".into(), Dynamic::from_float(data.mass)),
+                ("radius".into(), Dynamic::from_float(data.radius)),
+            ]);
+            (data.id.as_str().into(), Dynamic::from_map(state))
+        })
+        .collect();
+    for (entity, info, mut autopilot, Position(pos), Velocity(speed), influenced) in &mut autopilots
+    {
+        let host = influenced
+            .and_then(|i| i.main_influencer)
+            .and_then(|e| bodies.get(e).ok());
+        let ship = ShipHandle {
+            id: info.id,
+            pos: *pos,
+            speed: *speed,
+            host: host.map(|(BodyInfo(data), ..)| data.id),
+            elements: host.map(
+                |(BodyInfo(data), Position(host_pos), Velocity(host_speed))| {
+                    OsculatingElements::from_state(
+                        *pos - *host_pos,
+                        *speed - *host_speed,
+                        G * data.mass,
+                    )
+                },
+            ),
+            nodes: store
+                .as_ref()
+                .and_then(|s| s.get(&info.id))
+                .map_or(Vec::new(), |t| t.nodes.keys().copied().collect()),
+            maneuvers: Vec::new(),
+        };
+        let mut scope = Scope::new();
+        scope
+            .push_constant("tick", time.tick() as i64)
+            .push_constant("time", time.time())
+            .push_constant("bodies", body_states.clone())
+            .push("ship", ship.clone())
+            .push("memory", std::mem::take(&mut autopilot.memory));
+        let outcome = engine
+            .0
+            .run_ast_with_scope(&mut scope, &autopilot.script)
+            .map_err(AutopilotError::from)
+            .and_then(|()| {
+                let maneuvers = scope
+                    .get_value::<ShipHandle>("ship")
+                    .map_or(Vec::new(), |s| s.maneuvers);
+                maneuvers
+                    .into_iter()
+                    .map(|maneuver| to_event(maneuver, &ship, &mapping))
+                    .collect::<Result<Vec<_>, _>>()
+            });
+        autopilot.memory = scope.get_value("memory").unwrap_or_default();
+        match outcome {
+            Ok(events) => {
+                trajectory_events.send_batch(events);
+            }
+            Err(e) => {
+                warn!("Autopilot of {} stopped: {e}", info.id);
+                diagnostics.0.insert(info.id, e.to_string());
+                commands.entity(entity).remove::<Autopilot>();
+            }
+        }
+    }
+}
+
+fn to_event(
+    maneuver: Maneuver,
+    ship: &ShipHandle,
+    mapping: &BodiesMapping,
+) -> Result<TrajectoryEvent, AutopilotError> {
+    Ok(match maneuver {
+        Maneuver::Add {
+            tick,
+            thrust,
+            origin,
+        } => {
+            let origin = match origin {
+                Some(id) => BodyID::from(&id)
+                    .ok()
+                    .filter(|b| mapping.0.contains_key(b))
+                    .ok_or(AutopilotError::UnknownBody(id))?,
+                None => ship.host.ok_or(AutopilotError::NoOrigin)?,
+            };
+            TrajectoryEvent::AddNode {
+                ship: ship.id,
+                node: ManeuverNode {
+                    name: "Autopilot".into(),
+                    thrust,
+                    origin,
+                },
+                tick,
+            }
+        }
+        Maneuver::Remove(tick) => TrajectoryEvent::RemoveNode {
+            ship: ship.id,
+            tick,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{math::DVec3, prelude::*};
+    use rhai::{EvalAltResult, Map, Scope};
+
+    use crate::{
+        objects::ships::trajectory::TrajectoryStore, prelude::*, replay::step,
+        utils::algebra::circular_orbit_around_body,
+    };
+
+    use super::{
+        Autopilot, AutopilotDiagnostics, AutopilotEngine, AutopilotEvent, Maneuver, ShipHandle,
+    };
+
+    fn ship() -> ShipHandle {
+        ShipHandle {
+            id: id_from("s"),
+            pos: DVec3::new(7000., 0., 0.),
+            speed: DVec3::ZERO,
+            host: Some(id_from("terre")),
+            elements: None,
+            nodes: vec![12],
+            maneuvers: Vec::new(),
+        }
+    }
+
+    fn run(engine: &AutopilotEngine, source: &str, scope: &mut Scope) -> Result<(), String> {
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+        engine
+            .0
+            .run_ast_with_scope(scope, &ast)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_script_maneuvers() {
+        let engine = AutopilotEngine::default();
+        let mut scope = Scope::new();
+        scope
+            .push_constant("tick", 10_i64)
+            .push("ship", ship())
+            .push("memory", Map::new());
+        let source = "
+            if memory.burns == () { memory.burns = 0; }
+            if !ship.nodes.contains(tick + 5) && ship.pos[0] > 6500.0 {
+                ship.add_node(tick + 5, 1, 0.5, 2);
+                memory.burns += 1;
+            }
+            ship.remove_node(12);
+        ";
+        run(&engine, source, &mut scope).unwrap();
+        let ship = scope.get_value::<ShipHandle>("ship").unwrap();
+        assert!(matches!(
+            ship.maneuvers[..],
+            [
+                Maneuver::Add { tick: 15, thrust, origin: None },
+                Maneuver::Remove(12)
+            ] if thrust == DVec3::new(1., 0.5, -2.)
+        ));
+        let memory = scope.get_value::<Map>("memory").unwrap();
+        assert_eq!(memory["burns"].as_int(), Ok(1));
+        assert!(run(&engine, "ship.add_node(-1, 0, 0, 0)", &mut scope).is_err());
+    }
+
+    #[test]
+    fn test_step_budget() {
+        let engine = AutopilotEngine::default();
+        let endless = engine.compile("let n = 0; loop { n += 1; }").unwrap();
+        let err = engine.0.run_ast(&endless).unwrap_err();
+        assert!(matches!(*err, EvalAltResult::ErrorTooManyOperations(_)));
+        // Scripts within the budget run to their end
+        let bounded = engine
+            .compile("let n = 0; while n < 1000 { n += 1; } n")
+            .unwrap();
+        assert_eq!(engine.0.eval_ast::<i64>(&bounded).unwrap(), 1000);
+    }
+
+    #[test]
+    fn test_attach_autopilot() {
+        let mut app = App::new();
+        app.add_plugins(
+            ClientPlugin::testing()
+                .deterministic()
+                .in_mode(ClientMode::Singleplayer),
+        );
+        step(&mut app);
+        let world = app.world_mut();
+        let earth = world.resource::<BodiesMapping>().0[&id_from("terre")];
+        let (&mass, &pos, &speed) = world
+            .query::<(&Mass, &Position, &Velocity)>()
+            .get(world, earth)
+            .unwrap();
+        let (spawn_pos, spawn_speed) = circular_orbit_around_body(1e5, mass.0, pos.0, speed.0);
+        let id = id_from("s");
+        world.send_event(ShipEvent::Create(ShipInfo {
+            id,
+            spawn_pos,
+            spawn_speed,
+        }));
+        step(&mut app);
+        app.world_mut()
+            .resource_mut::<NextState<GameStage>>()
+            .set(GameStage::Action);
+        app.world_mut().send_event(AutopilotEvent::Attach {
+            ship: id,
+            source: "if ship.nodes.is_empty() { ship.add_node(tick + 10, 1, 0, 0); }".into(),
+        });
+        step(&mut app);
+        let ship = app.world().resource::<ShipsMapping>().0[&id];
+        assert!(app.world().get::<Autopilot>(ship).is_some());
+        let node = |app: &App| {
+            app.world()
+                .resource::<TrajectoryStore>()
+                .get(&id)
+                .and_then(|t| t.nodes.values().next().cloned())
+        };
+        let mut steps = 0;
+        while node(&app).is_none() {
+            step(&mut app);
+            steps += 1;
+            assert!(steps < 100);
+        }
+        assert_eq!(node(&app).unwrap().name, "Autopilot");
+
+        // A failing script is detached, and the reason is kept for the fleet screen
+        app.world_mut().send_event(AutopilotEvent::Attach {
+            ship: id,
+            source: "ship.add_node(tick + 10, 1, 0, 0, \"nowhere\");".into(),
+        });
+        let mut steps = 0;
+        while app.world().get::<Autopilot>(ship).is_some() {
+            step(&mut app);
+            steps += 1;
+            assert!(steps < 100);
+        }
+        let diagnostics = app.world().resource::<AutopilotDiagnostics>();
+        assert!(diagnostics.0[&id].contains("unknown body \"nowhere\""));
+
+        // Scripts attached to unknown ships are ignored
+        app.world_mut().send_event(AutopilotEvent::Attach {
+            ship: id_from("unknown"),
+            source: "ship.remove_node(1);".into(),
+        });
+        step(&mut app);
+        let world = app.world_mut();
+        assert_eq!(world.query::<&Autopilot>().iter(world).count(), 0);
+    }
+}
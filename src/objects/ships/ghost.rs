@@ -0,0 +1,178 @@
+//! Ghost ships replay externally computed ephemerides: they are displayed like ships but follow the imported
+//! states instead of being simulated, and can't be controlled. The center of an ephemeris is matched with the
+//! ID or the english name of a body, ignoring the case, and ghosts whose center is unknown are not spawned.
+use std::{fs::File, io::BufReader, path::PathBuf};
+
+use bevy::{math::DVec3, prelude::*};
+
+use crate::{
+    ephemeris::{read_oem, Ephemeris},
+    game::{ClearOnUnload, Loaded},
+    objects::{
+        prelude::{BodiesMapping, BodyInfo},
+        ObjectsUpdate,
+    },
+    physics::{
+        orbit::{update_global, OrbitsUpdate},
+        prelude::*,
+    },
+    utils::ecs::exit_on_error_if_app,
+};
+
+pub fn plugin(app: &mut App) {
+    app.add_event::<GhostEvent>()
+        .add_systems(
+            OnEnter(Loaded),
+            import_ghosts
+                .pipe(exit_on_error_if_app)
+                .in_set(ObjectsUpdate),
+        )
+        .add_systems(
+            Update,
+            (handle_ghost_events, update_ghosts)
+                .chain()
+                .in_set(ObjectsUpdate)
+                .run_if(resource_exists::<BodiesMapping>),
+        )
+        .add_systems(
+            FixedUpdate,
+            update_ghosts.after(update_global).in_set(OrbitsUpdate),
+        );
+}
+
+/// OEM files whose segments are spawned as ghost ships when a game is loaded
+#[derive(Resource, Default, Clone)]
+pub struct GhostImports(pub Vec<PathBuf>);
+
+#[derive(Component, Debug)]
+pub struct GhostShip(pub Ephemeris);
+
+/// Body the states of a ghost are relative to
+#[derive(Component, Debug)]
+pub struct GhostCenter(pub Entity);
+
+#[derive(Event, Debug)]
+pub enum GhostEvent {
+    Spawn(Ephemeris),
+    Remove(String),
+}
+
+fn import_ghosts(
+    imports: Option<Res<GhostImports>>,
+    mut writer: EventWriter<GhostEvent>,
+) -> color_eyre::Result<()> {
+    for path in imports.iter().flat_map(|i| &i.0) {
+        writer.send_batch(
+            read_oem(BufReader::new(File::open(path)?))?
+                .into_iter()
+                .map(GhostEvent::Spawn),
+        );
+    }
+    Ok(())
+}
+
+fn handle_ghost_events(
+    mut commands: Commands,
+    mut reader: EventReader<GhostEvent>,
+    ghosts: Query<(Entity, &GhostShip)>,
+    bodies: Query<(Entity, &BodyInfo)>,
+) {
+    for event in reader.read() {
+        match event {
+            GhostEvent::Spawn(ephemeris) => {
+                let Some((center, _)) = bodies.iter().find(|(_, BodyInfo(data))| {
+                    data.id.eq_ignore_ascii_case(&ephemeris.center)
+                        || data.name.eq_ignore_ascii_case(&ephemeris.center)
+                }) else {
+                    warn!(
+                        "Ghost {} not spawned: unknown center {}",
+                        ephemeris.object, ephemeris.center
+                    );
+                    continue;
+                };
+                commands.spawn((
+                    GhostShip(ephemeris.clone()),
+                    GhostCenter(center),
+                    Position::default(),
+                    Velocity::default(),
+                    TransformBundle::from_transform(Transform::from_xyz(0., 0., 1.)),
+                    ClearOnUnload,
+                ));
+            }
+            GhostEvent::Remove(object) => {
+                for (e, _) in ghosts.iter().filter(|(_, g)| &g.0.object == object) {
+                    commands.entity(e).despawn();
+                }
+            }
+        }
+    }
+}
+
+/// Moves the ghosts to their state at the current simtick, relative to their center
+fn update_ghosts(
+    mut ghosts: Query<(&GhostShip, &GhostCenter, &mut Position, &mut Velocity)>,
+    bodies: Query<(&Position, &Velocity), Without<GhostShip>>,
+    time: Res<GameTime>,
+) {
+    for (GhostShip(ephemeris), GhostCenter(center), mut pos, mut speed) in ghosts.iter_mut() {
+        let Some((p, v)) = ephemeris.state_at(time.simtick) else {
+            continue;
+        };
+        let (center_pos, center_speed) = bodies
+            .get(*center)
+            .map_or((DVec3::ZERO, DVec3::ZERO), |(p, v)| (p.0, v.0));
+        pos.0 = center_pos + p;
+        speed.0 = center_speed + v;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ephemeris::EphemerisState, prelude::*};
+
+    use super::*;
+
+    #[test]
+    fn test_ghosts() {
+        let mut app = App::new();
+        app.add_plugins(ClientPlugin::testing().in_mode(ClientMode::Singleplayer));
+        app.update();
+        let offset = DVec3::new(1e5, 0., 0.);
+        let state = |simtick| EphemerisState {
+            simtick,
+            pos: offset,
+            speed: DVec3::ZERO,
+        };
+        // Centers are matched with the ID or the english name of the bodies
+        for (object, center) in [
+            ("ghost", "TERRE"),
+            ("english", "EARTH"),
+            ("lost", "EARTH-MOON BARYCENTER"),
+        ] {
+            app.world_mut().send_event(GhostEvent::Spawn(Ephemeris {
+                object: object.to_owned(),
+                center: center.to_owned(),
+                states: vec![state(0), state(1000)],
+            }));
+        }
+        app.update();
+
+        let world = app.world_mut();
+        let earth = world.resource::<BodiesMapping>().0[&id_from("terre")];
+        let earth_pos = world.get::<Position>(earth).unwrap().0;
+        let ghosts: Vec<_> = world
+            .query::<(&GhostShip, &Position)>()
+            .iter(world)
+            .map(|(g, p)| (g.0.object.clone(), p.0))
+            .collect();
+        assert_eq!(ghosts.len(), 2);
+        assert!(ghosts.iter().all(|(_, pos)| *pos == earth_pos + offset));
+
+        for object in ["ghost", "english"] {
+            world.send_event(GhostEvent::Remove(object.to_owned()));
+        }
+        app.update();
+        let world = app.world_mut();
+        assert!(world.query::<&GhostShip>().iter(world).next().is_none());
+    }
+}
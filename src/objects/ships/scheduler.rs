@@ -3,24 +3,25 @@ use crate::objects::ships::{ShipID};
 use crate::physics::time::GameTime;
 use crate::prelude::{ShipInfo, ShipsMapping};
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 pub fn plugin(app: &mut App) {
     app.add_systems(FixedUpdate, handle_schedules);
-    app.add_systems(Update, handle_add_action_to_schedule);
+    app.add_systems(Update, handle_add_action_to_schedule.run_if(resource_exists::<ShipsMapping>));
     app.add_event::<AddAction>();
 }
 
-#[derive(Clone)]
-pub(crate) enum ShipActionKind {
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ShipActionKind {
     AddNode{ node: ManeuverNode },
     // OtherAction,
 }
 
-#[derive(Component)]
+#[derive(Component, Default)]
 pub(crate) struct ShipSchedule (pub(crate) Vec<(u64, ShipActionKind)>);
 
-#[derive(Event)]
-pub(crate) struct AddAction{pub ship_id: ShipID, pub tick: u64, pub action: ShipActionKind}
+#[derive(Event, Clone, Debug, Serialize, Deserialize)]
+pub struct AddAction{pub ship_id: ShipID, pub tick: u64, pub action: ShipActionKind}
 
 fn handle_schedules (
     mut query: Query<(&mut ShipSchedule, &ShipInfo)>,
@@ -1,6 +1,6 @@
 use std::{
-    collections::{btree_map, BTreeMap},
-    fs::{read_dir, remove_file, File},
+    collections::{btree_map, BTreeMap, BTreeSet},
+    fs::File,
     io::{Read, Write},
     iter::Peekable,
     path::{Path, PathBuf},
@@ -10,24 +10,38 @@ use std::{
 // use arrayvec::ArrayString;
 use vectorize;
 
-use bevy::{math::DVec3, prelude::*};
+use bevy::{math::DVec3, prelude::*, utils::HashMap};
 use serde::{Deserialize, Serialize};
 use std::sync::Mutex;
 
 use crate::{
-    game::{Authoritative, GameFiles},
+    game::Authoritative,
     objects::prelude::{BodiesMapping, BodyID},
     physics::{prelude::*, time::TickEvent},
     prelude::{exit_on_error_if_app, GameStage},
-    utils::algebra::orbital_to_global_matrix,
+    replay::Deterministic,
+    utils::{algebra::orbital_to_global_matrix, ecs::for_each_mut},
 };
 
 use super::{ShipID, ShipInfo, ShipsMapping};
 
+pub use self::store::TrajectoryStore;
+
+pub mod store;
+
 pub const TRAJECTORIES_PATH: &str = "trajectories";
 
+/// Version of the trajectory files written by [write_trajectory]
+pub const TRAJECTORY_VERSION: u32 = 2;
+
+/// Migrations of the trajectory files, the one at index `i` upgrading files from version `i + 1`
+const MIGRATIONS: [Migration; 1] = [migrate_v1];
+
+type Migration = fn(&mut toml::Table) -> Result<(), TrajectoryError>;
+
 pub fn plugin(app: &mut App) {
-    app.add_event::<TrajectoryEvent>()
+    app.init_resource::<TrajectoryDiagnostics>()
+        .add_event::<TrajectoryEvent>()
         .add_event::<VelocityUpdate>()
         // This system set is currently configured in the [physics] module
         .add_systems(
@@ -47,7 +61,8 @@ pub fn plugin(app: &mut App) {
             OnEnter(GameStage::Preparation),
             remove_old_nodes.run_if(in_state(Authoritative)),
         )
-        .add_systems(Update, handle_trajectory_event.pipe(exit_on_error_if_app));
+        .add_systems(Update, handle_trajectory_event)
+        .add_systems(Last, persist_trajectories.pipe(exit_on_error_if_app));
 }
 
 #[derive(SystemSet, Debug, Clone, Copy, Hash, PartialEq, Eq)]
@@ -67,6 +82,133 @@ pub struct Trajectory {
     pub nodes: BTreeMap<u64, ManeuverNode>,
 }
 
+/// A maneuver node as stored in trajectory files
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeEntry {
+    pub tick: u64,
+    pub name: String,
+    pub thrust: DVec3,
+    pub origin: BodyID,
+}
+
+/// Versioned content of a trajectory file, whose nodes may not be valid yet
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TrajectoryFile {
+    pub version: u32,
+    #[serde(default)]
+    pub nodes: Vec<NodeEntry>,
+}
+
+impl From<&Trajectory> for TrajectoryFile {
+    fn from(trajectory: &Trajectory) -> Self {
+        Self {
+            version: TRAJECTORY_VERSION,
+            nodes: trajectory
+                .nodes
+                .iter()
+                .map(|(&tick, node)| NodeEntry {
+                    tick,
+                    name: node.name.clone(),
+                    thrust: node.thrust,
+                    origin: node.origin,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<TrajectoryFile> for Trajectory {
+    /// Only the first of the nodes sharing a tick is kept
+    fn from(file: TrajectoryFile) -> Self {
+        let mut nodes = BTreeMap::new();
+        for NodeEntry {
+            tick,
+            name,
+            thrust,
+            origin,
+        } in file.nodes
+        {
+            nodes.entry(tick).or_insert(ManeuverNode {
+                name,
+                thrust,
+                origin,
+            });
+        }
+        Self { nodes }
+    }
+}
+
+impl TrajectoryFile {
+    /// Checks that the nodes are relative to known bodies, are not before the given tick and don't share a tick
+    pub fn validate(
+        &self,
+        bodies: &HashMap<BodyID, Entity>,
+        tick: u64,
+    ) -> Vec<TrajectoryDiagnostic> {
+        let mut diagnostics = self.duplicate_nodes();
+        for node in &self.nodes {
+            if node.tick < tick {
+                diagnostics.push(TrajectoryDiagnostic::PastNode {
+                    tick: node.tick,
+                    current: tick,
+                });
+            }
+            if !bodies.contains_key(&node.origin) {
+                diagnostics.push(TrajectoryDiagnostic::UnknownOrigin {
+                    tick: node.tick,
+                    origin: node.origin,
+                });
+            }
+        }
+        diagnostics
+    }
+
+    /// Nodes sharing their tick with a previous node, which are dropped when converted to a [Trajectory]
+    pub fn duplicate_nodes(&self) -> Vec<TrajectoryDiagnostic> {
+        let mut ticks = BTreeSet::new();
+        self.nodes
+            .iter()
+            .filter(|node| !ticks.insert(node.tick))
+            .map(|node| TrajectoryDiagnostic::DuplicateNode(node.tick))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrajectoryDiagnostic {
+    UnknownOrigin { tick: u64, origin: BodyID },
+    PastNode { tick: u64, current: u64 },
+    DuplicateNode(u64),
+}
+
+impl std::fmt::Display for TrajectoryDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrajectoryDiagnostic::UnknownOrigin { tick, origin } => {
+                write!(
+                    f,
+                    "node at tick {} is relative to unknown body {}",
+                    tick, origin
+                )
+            }
+            TrajectoryDiagnostic::PastNode { tick, current } => {
+                write!(
+                    f,
+                    "node at tick {} is before the current tick {}",
+                    tick, current
+                )
+            }
+            TrajectoryDiagnostic::DuplicateNode(tick) => {
+                write!(f, "several nodes at tick {}", tick)
+            }
+        }
+    }
+}
+
+/// Errors raised when the trajectories were dispatched, displayed in the fleet screen
+#[derive(Resource, Default, Debug)]
+pub struct TrajectoryDiagnostics(pub HashMap<ShipID, String>);
+
 /// A trajectory taken by an object, storing a peekable queue of all remaining maneuver nodes
 #[derive(Component, Debug)]
 pub struct CurrentTrajectory {
@@ -81,7 +223,7 @@ impl CurrentTrajectory {
     }
 }
 
-#[derive(Event, Debug, Clone, PartialEq)]
+#[derive(Event, Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum TrajectoryEvent {
     Create {
         ship: ShipID,
@@ -99,6 +241,17 @@ pub enum TrajectoryEvent {
     },
 }
 
+impl TrajectoryEvent {
+    pub fn ship(&self) -> ShipID {
+        use TrajectoryEvent::*;
+        match self {
+            Create { ship, .. } | Delete(ship) | AddNode { ship, .. } | RemoveNode { ship, .. } => {
+                *ship
+            }
+        }
+    }
+}
+
 #[derive(Event, Debug)]
 pub struct VelocityUpdate {
     pub ship_id: ShipID,
@@ -110,6 +263,10 @@ pub enum TrajectoryError {
     Io(std::io::Error),
     De(toml::de::Error),
     Ser(toml::ser::Error),
+    Log(serde_json::Error),
+    Version(u32),
+    Migration(String),
+    Invalid(Vec<TrajectoryDiagnostic>),
 }
 
 impl From<std::io::Error> for TrajectoryError {
@@ -130,26 +287,110 @@ impl From<toml::ser::Error> for TrajectoryError {
     }
 }
 
+impl From<serde_json::Error> for TrajectoryError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Log(value)
+    }
+}
+
 impl std::fmt::Display for TrajectoryError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             TrajectoryError::Io(err) => write!(f, "Error when reading trajectory: {}", err),
             TrajectoryError::De(err) => write!(f, "Error when deserializing trajectory: {}", err),
             TrajectoryError::Ser(err) => write!(f, "Error when serializing trajectory: {}", err),
+            TrajectoryError::Log(err) => write!(f, "Error when logging trajectory: {}", err),
+            TrajectoryError::Version(v) => write!(f, "Unsupported trajectory version {}", v),
+            TrajectoryError::Migration(err) => {
+                write!(f, "Error when migrating trajectory: {}", err)
+            }
+            TrajectoryError::Invalid(diagnostics) => {
+                write!(f, "Invalid trajectory: ")?;
+                for (i, d) in diagnostics.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", d)?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
 impl std::error::Error for TrajectoryError {}
 
-fn read_trajectory(path: impl AsRef<Path>) -> Result<Trajectory, TrajectoryError> {
+/// Parses a trajectory file of any supported version, migrating it to the current one
+pub fn parse_trajectory(s: &str) -> Result<TrajectoryFile, TrajectoryError> {
+    let mut table: toml::Table = toml::from_str(s)?;
+    // The first files had no version field
+    let version = match table.get("version") {
+        None => 1,
+        Some(toml::Value::Integer(v)) => u32::try_from(*v).unwrap_or(0),
+        Some(_) => 0,
+    };
+    if version == 0 || version > TRAJECTORY_VERSION {
+        return Err(TrajectoryError::Version(version));
+    }
+    for migrate in &MIGRATIONS[version as usize - 1..] {
+        migrate(&mut table)?;
+    }
+    Ok(toml::Value::Table(table).try_into()?)
+}
+
+/// Version 1 stored the nodes as `[tick, node]` pairs
+fn migrate_v1(table: &mut toml::Table) -> Result<(), TrajectoryError> {
+    let invalid = || TrajectoryError::Migration("expected [tick, node] pairs".to_owned());
+    let nodes = match table.remove("nodes") {
+        Some(toml::Value::Array(nodes)) => nodes,
+        None => Vec::new(),
+        Some(_) => return Err(invalid()),
+    };
+    let nodes = nodes
+        .into_iter()
+        .map(|pair| match pair {
+            toml::Value::Array(pair) => match <[_; 2]>::try_from(pair) {
+                Ok([tick, toml::Value::Table(mut node)]) => {
+                    node.insert("tick".to_owned(), tick);
+                    Ok(toml::Value::Table(node))
+                }
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        })
+        .collect::<Result<_, _>>()?;
+    table.insert("nodes".to_owned(), toml::Value::Array(nodes));
+    table.insert("version".to_owned(), toml::Value::Integer(2));
+    Ok(())
+}
+
+pub fn read_trajectory_file(path: impl AsRef<Path>) -> Result<TrajectoryFile, TrajectoryError> {
     let mut file = File::open(&path)?;
     let mut buf = String::new();
     file.read_to_string(&mut buf)?;
-    Ok(toml::from_str::<Trajectory>(&buf)?)
+    parse_trajectory(&buf)
+}
+
+pub fn read_trajectory(path: impl AsRef<Path>) -> Result<Trajectory, TrajectoryError> {
+    Ok(read_trajectory_file(path)?.into())
 }
 
-fn build_path(dir: impl AsRef<Path>, id: ShipID) -> PathBuf {
+/// Reads a trajectory and fails if any of its nodes is invalid at the given tick
+pub fn load_trajectory(
+    path: impl AsRef<Path>,
+    bodies: &HashMap<BodyID, Entity>,
+    tick: u64,
+) -> Result<Trajectory, TrajectoryError> {
+    let file = read_trajectory_file(path)?;
+    let diagnostics = file.validate(bodies, tick);
+    if diagnostics.is_empty() {
+        Ok(file.into())
+    } else {
+        Err(TrajectoryError::Invalid(diagnostics))
+    }
+}
+
+pub(crate) fn build_path(dir: impl AsRef<Path>, id: ShipID) -> PathBuf {
     dir.as_ref().join(id.to_string())
 }
 
@@ -161,7 +402,7 @@ pub fn read_ship_trajectory(
 }
 
 pub fn write_trajectory(path: impl AsRef<Path>, t: &Trajectory) -> Result<(), TrajectoryError> {
-    let s = toml::to_string_pretty(t)?;
+    let s = toml::to_string_pretty(&TrajectoryFile::from(t))?;
     Ok(File::create(path)?.write_all(s.as_bytes())?)
 }
 
@@ -171,11 +412,12 @@ fn follow_trajectory(
     coords: Query<(&Position, &Velocity)>,
     mut trajectories: Query<(Entity, &mut CurrentTrajectory, &ShipInfo)>,
     time: Res<GameTime>,
+    deterministic: Res<Deterministic>,
     // mut ship_event_writer: EventWriter<ShipEvent>,
 ) {
     let velocity_updates = Arc::new(Mutex::new(Vec::new()));
     // let ship_events = Arc::new(Mutex::new(Vec::new()));
-    trajectories.par_iter_mut().for_each(|(e, mut t, info)| {
+    for_each_mut(&mut trajectories, &deterministic, |(e, mut t, info)| {
         if let Some((tick, n)) = t.queue.peek() {
             if *tick <= time.tick() {
                 // let id = ArrayString::from(&info.id).unwrap();
@@ -224,71 +466,71 @@ pub fn handle_thrusts(
 
 pub fn dispatch_trajectories(
     mut commands: Commands,
-    dir: Res<GameFiles>,
+    store: Res<TrajectoryStore>,
     mapping: Res<ShipsMapping>,
+    bodies: Res<BodiesMapping>,
+    time: Res<GameTime>,
+    mut diagnostics: ResMut<TrajectoryDiagnostics>,
 ) {
-    if let Ok(dir) = read_dir(&dir.trajectories) {
-        for entry in dir.flatten() {
-            let path = entry.path();
-            if let Ok(traj) = read_trajectory(&path) {
-                if let Some(e) = path
-                    .file_name()
-                    .and_then(|s| s.to_str())
-                    .and_then(|s| ShipID::from(s).ok())
-                    .and_then(|id| mapping.0.get(&id))
-                {
-                    commands.entity(*e).insert(CurrentTrajectory::new(traj));
-                }
-            }
+    diagnostics.0.clear();
+    diagnostics.0.extend(
+        store
+            .load_errors()
+            .filter(|(id, _)| mapping.0.contains_key(*id))
+            .map(|(id, err)| (*id, err.clone())),
+    );
+    for (id, traj) in store.iter() {
+        let Some(e) = mapping.0.get(id) else {
+            continue;
+        };
+        if diagnostics.0.contains_key(id) {
+            continue;
+        }
+        let invalid = TrajectoryFile::from(traj).validate(&bodies.0, time.tick());
+        if invalid.is_empty() {
+            commands
+                .entity(*e)
+                .insert(CurrentTrajectory::new(traj.clone()));
+        } else {
+            diagnostics
+                .0
+                .insert(*id, TrajectoryError::Invalid(invalid).to_string());
         }
     }
 }
 
-pub fn remove_old_nodes(dir: Res<GameFiles>, time: Res<GameTime>) {
-    if let Ok(dir) = read_dir(&dir.trajectories) {
-        for entry in dir.flatten() {
-            let path = entry.path();
-            if let Ok(mut traj) = read_trajectory(&path) {
-                traj.nodes.retain(|t, _| *t >= time.tick());
-                write_trajectory(path, &traj).expect("Could not write trajectory");
-            }
-        }
+pub fn remove_old_nodes(mut store: ResMut<TrajectoryStore>, time: Res<GameTime>) {
+    let old: Vec<_> = store
+        .iter()
+        .flat_map(|(ship, traj)| {
+            traj.nodes
+                .range(..time.tick())
+                .map(|(tick, _)| TrajectoryEvent::RemoveNode {
+                    ship: *ship,
+                    tick: *tick,
+                })
+        })
+        .collect();
+    for event in &old {
+        store.apply(event);
     }
 }
 
 pub fn handle_trajectory_event(
     mut reader: EventReader<TrajectoryEvent>,
-    dir: Res<GameFiles>,
-) -> color_eyre::Result<()> {
-    use TrajectoryEvent::*;
+    mut store: ResMut<TrajectoryStore>,
+) {
     for event in reader.read() {
-        let path = build_path(
-            &dir.trajectories,
-            *match event {
-                Create { ship, .. } => ship,
-                Delete(s) => s,
-                AddNode { ship, .. } => ship,
-                RemoveNode { ship, .. } => ship,
-            },
-        );
-        match event {
-            Create { trajectory, .. } => {
-                write_trajectory(path, trajectory)?;
-            }
-            Delete(_) => remove_file(path).unwrap_or_default(),
-            AddNode { node, tick, .. } => {
-                let mut t = read_trajectory(&path).unwrap_or_default();
-                t.nodes.insert(*tick, node.clone());
-                write_trajectory(path, &t)?;
-            }
-            RemoveNode { tick, .. } => {
-                let mut t = read_trajectory(&path)?;
-                t.nodes.remove(tick);
-                write_trajectory(path, &t)?;
-            }
-        }
+        store.apply(event);
+    }
+}
+
+fn persist_trajectories(mut store: ResMut<TrajectoryStore>) -> color_eyre::Result<()> {
+    store.persist();
+    match store.take_error() {
+        Some(err) => Err(err.into()),
+        None => Ok(()),
     }
-    Ok(())
 }
 
 #[cfg(test)]
@@ -304,7 +546,7 @@ mod tests {
         state::state::NextState,
     };
 
-    use crate::{objects::ships::{ShipEvent, DisableShipOrbitCheck}, physics::time::SIMTICKS_PER_TICK, prelude::*};
+    use crate::{game::GameFiles, objects::ships::{ShipEvent, DisableShipOrbitCheck}, physics::time::SIMTICKS_PER_TICK, prelude::*};
 
     use super::*;
 
@@ -337,6 +579,7 @@ mod tests {
             trajectory: trajectory.clone(),
         });
         app.update();
+        app.world().resource::<TrajectoryStore>().flush();
         let path = app.world_mut().resource::<GameFiles>().trajectories.clone();
         let mut files = read_dir(path).unwrap();
         let file_path = files.next().unwrap()?.path();
@@ -350,6 +593,86 @@ mod tests {
         Ok(())
     }
 
+    const FIXTURE_V1: &str = include_str!("../../../tests/fixtures/trajectories/v1.toml");
+    const FIXTURE_V2: &str = include_str!("../../../tests/fixtures/trajectories/v2.toml");
+    const FIXTURE_INVALID: &str = include_str!("../../../tests/fixtures/trajectories/invalid.toml");
+
+    #[test]
+    fn test_trajectory_versions() -> color_eyre::Result<()> {
+        let v1 = parse_trajectory(FIXTURE_V1)?;
+        let v2 = parse_trajectory(FIXTURE_V2)?;
+        assert_eq!(v1, v2);
+        assert_eq!(v1.version, TRAJECTORY_VERSION);
+        assert_eq!(v1.nodes.len(), 2);
+        assert!(matches!(
+            parse_trajectory("version = 3"),
+            Err(TrajectoryError::Version(3))
+        ));
+        assert!(matches!(
+            parse_trajectory("nodes = [1, 2]"),
+            Err(TrajectoryError::Migration(_))
+        ));
+
+        // Files are written in the current version, and migrated when rewritten
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("s");
+        let trajectory = Trajectory::from(v1);
+        write_trajectory(&path, &trajectory)?;
+        assert_eq!(read_trajectory_file(&path)?, v2);
+        assert_eq!(read_trajectory(&path)?, trajectory);
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_trajectory() -> color_eyre::Result<()> {
+        let app = new_app_with(App::new());
+        let bodies = &app.world().resource::<BodiesMapping>().0;
+        assert!(parse_trajectory(FIXTURE_V2)?.validate(bodies, 0).is_empty());
+        let file = parse_trajectory(FIXTURE_INVALID)?;
+        assert_eq!(
+            file.validate(bodies, 0),
+            vec![
+                TrajectoryDiagnostic::DuplicateNode(120),
+                TrajectoryDiagnostic::UnknownOrigin {
+                    tick: 480,
+                    origin: id_from("nowhere")
+                },
+            ]
+        );
+        assert_eq!(file.validate(bodies, 200).len(), 4);
+        // Only the first of the duplicate nodes is kept
+        assert_eq!(Trajectory::from(file).nodes[&120].name, "departure");
+        Ok(())
+    }
+
+    #[test]
+    fn test_dispatch_invalid_trajectory() -> color_eyre::Result<()> {
+        let mut app = new_app_with(App::new());
+        let id = id_from("s");
+        app.world_mut().send_event(ShipEvent::Create(ShipInfo {
+            id,
+            spawn_pos: DVec3::new(1e6, 0., 0.),
+            spawn_speed: DVec3::new(0., 1e6, 0.),
+        }));
+        app.world_mut().send_event(TrajectoryEvent::Create {
+            ship: id,
+            trajectory: parse_trajectory(FIXTURE_INVALID)?.into(),
+        });
+        app.update();
+        app.world_mut()
+            .resource_mut::<NextState<GameStage>>()
+            .set(GameStage::Action);
+        app.update();
+        let world = app.world_mut();
+        assert!(world
+            .query::<&CurrentTrajectory>()
+            .iter(world)
+            .next()
+            .is_none());
+        assert!(world.resource::<TrajectoryDiagnostics>().0[&id].contains("nowhere"));
+        Ok(())
+    }
+
     #[test]
     fn test_dispatch_trajectory() {
         let mut app = App::new();
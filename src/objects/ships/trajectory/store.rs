@@ -0,0 +1,401 @@
+//! In-memory source of truth for the trajectories, persisted in the background.
+//!
+//! The [TrajectoryStore] applies the [TrajectoryEvent]s immediately and queues them. Once per frame the queued
+//! events are handed to a writer thread, which appends them to a write-ahead log, rewrites the files of the
+//! ships they touched (writing a temporary file then renaming it over the old one) and finally truncates the
+//! log. When the store is opened, the events left in the log by an interrupted write are replayed over the
+//! trajectory files, which is safe since replaying events over a state they were already applied to is a no-op.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{read_dir, remove_file, rename, File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use bevy::prelude::*;
+
+use crate::{game::GameFiles, objects::ships::ShipID};
+
+use super::{
+    build_path, read_trajectory_file, write_trajectory, Trajectory, TrajectoryError,
+    TrajectoryEvent,
+};
+
+/// Name of the write-ahead log, in the root of the game files
+pub const TRAJECTORIES_WAL: &str = "trajectories.wal";
+
+#[derive(Resource)]
+pub struct TrajectoryStore {
+    trajectories: BTreeMap<ShipID, Trajectory>,
+    /// Why the files of some ships couldn't be loaded, until their trajectory is created again
+    load_errors: BTreeMap<ShipID, String>,
+    /// Events applied since the last call to [TrajectoryStore::persist]
+    pending: Vec<TrajectoryEvent>,
+    writer: Writer,
+}
+
+impl TrajectoryStore {
+    /// Loads the trajectory files and replays the write-ahead log over them
+    pub fn open(files: &GameFiles) -> Result<Self, TrajectoryError> {
+        let mut trajectories = BTreeMap::new();
+        let mut load_errors = BTreeMap::new();
+        for entry in read_dir(&files.trajectories)?.flatten() {
+            let path = entry.path();
+            let Some(id) = path
+                .file_name()
+                .and_then(|s| s.to_str())
+                .and_then(|s| ShipID::from(s).ok())
+            else {
+                continue;
+            };
+            match read_trajectory_file(&path) {
+                Ok(file) => {
+                    let duplicates = file.duplicate_nodes();
+                    if !duplicates.is_empty() {
+                        load_errors.insert(id, TrajectoryError::Invalid(duplicates).to_string());
+                    }
+                    trajectories.insert(id, file.into());
+                }
+                Err(err) => {
+                    load_errors.insert(id, err.to_string());
+                }
+            }
+        }
+        let wal = files.root.join(TRAJECTORIES_WAL);
+        let mut store = Self {
+            trajectories,
+            load_errors,
+            ..Self::empty(files)
+        };
+        match File::open(&wal) {
+            Ok(file) => {
+                // The last line may have been cut by a crash, in which case its event was never applied
+                for line in BufReader::new(file).lines() {
+                    match serde_json::from_str::<TrajectoryEvent>(&line?) {
+                        Ok(event) => store.apply(&event),
+                        Err(_) => break,
+                    }
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        store.persist();
+        Ok(store)
+    }
+
+    /// A store without any trajectory, persisted to the game files
+    pub fn empty(files: &GameFiles) -> Self {
+        Self {
+            trajectories: BTreeMap::new(),
+            load_errors: BTreeMap::new(),
+            pending: Vec::new(),
+            writer: Writer::spawn(
+                files.trajectories.clone(),
+                files.root.clone(),
+                files.root.join(TRAJECTORIES_WAL),
+            ),
+        }
+    }
+
+    pub fn get(&self, ship: &ShipID) -> Option<&Trajectory> {
+        self.trajectories.get(ship)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&ShipID, &Trajectory)> {
+        self.trajectories.iter()
+    }
+
+    pub fn load_errors(&self) -> impl Iterator<Item = (&ShipID, &String)> {
+        self.load_errors.iter()
+    }
+
+    /// Applies the event in memory and queues it for persistence.
+    ///
+    /// Nodes can't be added to or removed from a ship whose file couldn't be loaded, since persisting them would
+    /// overwrite the file: its trajectory has to be created again first.
+    pub fn apply(&mut self, event: &TrajectoryEvent) {
+        use TrajectoryEvent::*;
+        if let (AddNode { ship, .. } | RemoveNode { ship, .. }, Some(err)) =
+            (event, self.load_errors.get(&event.ship()))
+        {
+            warn!("Trajectory of {ship} not modified, its file could not be loaded: {err}");
+            return;
+        }
+        match event {
+            Create { ship, trajectory } => {
+                self.load_errors.remove(ship);
+                self.trajectories.insert(*ship, trajectory.clone());
+            }
+            Delete(ship) => {
+                self.load_errors.remove(ship);
+                self.trajectories.remove(ship);
+            }
+            AddNode { ship, node, tick } => {
+                self.trajectories
+                    .entry(*ship)
+                    .or_default()
+                    .nodes
+                    .insert(*tick, node.clone());
+            }
+            RemoveNode { ship, tick } => {
+                if let Some(t) = self.trajectories.get_mut(ship) {
+                    t.nodes.remove(tick);
+                }
+            }
+        }
+        self.pending.push(event.clone());
+    }
+
+    /// Hands the queued events to the writer thread, along with the trajectories of the ships they touched
+    pub fn persist(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let ships: BTreeSet<_> = self.pending.iter().map(TrajectoryEvent::ship).collect();
+        let snapshots = ships
+            .into_iter()
+            .map(|ship| (ship, self.trajectories.get(&ship).cloned()))
+            .collect();
+        self.writer.send(WriterMessage::Write {
+            events: std::mem::take(&mut self.pending),
+            snapshots,
+        });
+    }
+
+    /// Blocks until everything persisted so far is written
+    pub fn flush(&self) {
+        let (sender, receiver) = channel();
+        self.writer.send(WriterMessage::Flush(sender));
+        receiver.recv().unwrap_or_default();
+    }
+
+    /// Last error raised by the writer thread
+    pub fn take_error(&self) -> Option<TrajectoryError> {
+        self.writer.error.lock().unwrap().take()
+    }
+}
+
+enum WriterMessage {
+    Write {
+        events: Vec<TrajectoryEvent>,
+        snapshots: Vec<(ShipID, Option<Trajectory>)>,
+    },
+    Flush(Sender<()>),
+}
+
+struct Writer {
+    sender: Option<Sender<WriterMessage>>,
+    thread: Option<JoinHandle<()>>,
+    error: Arc<Mutex<Option<TrajectoryError>>>,
+}
+
+impl Writer {
+    fn spawn(dir: PathBuf, tmp_dir: PathBuf, wal: PathBuf) -> Self {
+        let (sender, receiver) = channel();
+        let error = Arc::new(Mutex::new(None));
+        let thread_error = error.clone();
+        let thread = thread::spawn(move || {
+            for message in receiver {
+                match message {
+                    WriterMessage::Write { events, snapshots } => {
+                        if let Err(err) = write(&dir, &tmp_dir, &wal, &events, snapshots) {
+                            *thread_error.lock().unwrap() = Some(err);
+                        }
+                    }
+                    WriterMessage::Flush(sender) => sender.send(()).unwrap_or_default(),
+                }
+            }
+        });
+        Self {
+            sender: Some(sender),
+            thread: Some(thread),
+            error,
+        }
+    }
+
+    fn send(&self, message: WriterMessage) {
+        if let Some(sender) = &self.sender {
+            sender.send(message).unwrap_or_default();
+        }
+    }
+}
+
+impl Drop for Writer {
+    /// Waits for the queued writes to complete
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap_or_default();
+        }
+    }
+}
+
+fn write(
+    dir: &Path,
+    tmp_dir: &Path,
+    wal: &Path,
+    events: &[TrajectoryEvent],
+    snapshots: Vec<(ShipID, Option<Trajectory>)>,
+) -> Result<(), TrajectoryError> {
+    let mut log = OpenOptions::new().create(true).append(true).open(wal)?;
+    for event in events {
+        serde_json::to_writer(&mut log, event)?;
+        log.write_all(b"\n")?;
+    }
+    log.sync_data()?;
+    for (ship, trajectory) in snapshots {
+        let path = build_path(dir, ship);
+        match trajectory {
+            Some(trajectory) => {
+                let tmp = tmp_dir.join(format!("{}.tmp", ship));
+                write_trajectory(&tmp, &trajectory)?;
+                File::open(&tmp)?.sync_all()?;
+                rename(tmp, path)?;
+            }
+            None => match remove_file(path) {
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            },
+        }
+    }
+    log.set_len(0)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, fs::read_to_string};
+
+    use bevy::math::DVec3;
+
+    use crate::{
+        objects::ships::trajectory::{read_ship_trajectory, ManeuverNode},
+        prelude::*,
+    };
+
+    use super::*;
+
+    fn node(name: &str) -> ManeuverNode {
+        ManeuverNode {
+            name: name.to_owned(),
+            thrust: DVec3::X,
+            origin: id_from("terre"),
+        }
+    }
+
+    #[test]
+    fn test_store_persistence() -> color_eyre::Result<()> {
+        let root = tempfile::tempdir()?;
+        let files = GameFiles::new(root.path())?;
+        let (a, b) = (id_from("a"), id_from("b"));
+        let mut store = TrajectoryStore::open(&files)?;
+        store.apply(&TrajectoryEvent::AddNode {
+            ship: a,
+            node: node("1"),
+            tick: 1,
+        });
+        store.apply(&TrajectoryEvent::AddNode {
+            ship: b,
+            node: node("2"),
+            tick: 2,
+        });
+        // Nothing is written before the events are persisted
+        assert!(read_ship_trajectory(&files.trajectories, a).is_err());
+        store.persist();
+        store.apply(&TrajectoryEvent::Delete(b));
+        store.persist();
+        store.flush();
+        assert_eq!(
+            read_ship_trajectory(&files.trajectories, a)?.nodes[&1],
+            node("1")
+        );
+        assert!(read_ship_trajectory(&files.trajectories, b).is_err());
+        assert!(read_to_string(root.path().join(TRAJECTORIES_WAL))?.is_empty());
+        assert!(store.take_error().is_none());
+        drop(store);
+
+        let store = TrajectoryStore::open(&files)?;
+        assert_eq!(store.iter().map(|(id, _)| *id).collect::<Vec<_>>(), [a]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_store_recovery() -> color_eyre::Result<()> {
+        let root = tempfile::tempdir()?;
+        let files = GameFiles::new(root.path())?;
+        let ship = id_from("a");
+        write_trajectory(
+            build_path(&files.trajectories, ship),
+            &Trajectory {
+                nodes: BTreeMap::from([(1, node("1")), (2, node("2"))]),
+            },
+        )?;
+        std::fs::write(build_path(&files.trajectories, id_from("c")), "nodes = 1")?;
+        // A crash after logging two events, the second one being cut
+        let mut wal = File::create(root.path().join(TRAJECTORIES_WAL))?;
+        serde_json::to_writer(&mut wal, &TrajectoryEvent::RemoveNode { ship, tick: 1 })?;
+        wal.write_all(b"\n{\"Delete\":")?;
+
+        let store = TrajectoryStore::open(&files)?;
+        store.flush();
+        let nodes: Vec<_> = store.get(&ship).unwrap().nodes.keys().copied().collect();
+        assert_eq!(nodes, [2]);
+        assert_eq!(
+            read_ship_trajectory(&files.trajectories, ship)?.nodes.len(),
+            1
+        );
+        assert!(read_to_string(root.path().join(TRAJECTORIES_WAL))?.is_empty());
+        assert_eq!(store.load_errors().count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_file_not_overwritten() -> color_eyre::Result<()> {
+        let root = tempfile::tempdir()?;
+        let files = GameFiles::new(root.path())?;
+        let ship = id_from("c");
+        let path = build_path(&files.trajectories, ship);
+        std::fs::write(&path, "nodes = 1")?;
+
+        let mut store = TrajectoryStore::open(&files)?;
+        store.apply(&TrajectoryEvent::AddNode {
+            ship,
+            node: node("1"),
+            tick: 1,
+        });
+        store.apply(&TrajectoryEvent::RemoveNode { ship, tick: 1 });
+        store.persist();
+        store.flush();
+        assert_eq!(read_to_string(&path)?, "nodes = 1");
+        assert!(store.get(&ship).is_none());
+        assert_eq!(
+            store.load_errors().map(|(id, _)| *id).collect::<Vec<_>>(),
+            [ship]
+        );
+
+        // Creating the trajectory again replaces the file
+        store.apply(&TrajectoryEvent::Create {
+            ship,
+            trajectory: Trajectory::default(),
+        });
+        store.apply(&TrajectoryEvent::AddNode {
+            ship,
+            node: node("1"),
+            tick: 1,
+        });
+        store.persist();
+        store.flush();
+        assert_eq!(
+            read_ship_trajectory(&files.trajectories, ship)?.nodes[&1],
+            node("1")
+        );
+        assert_eq!(store.load_errors().count(), 0);
+        Ok(())
+    }
+}
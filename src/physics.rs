@@ -1,4 +1,5 @@
 use bevy::{math::DVec3, prelude::*};
+use serde::{Deserialize, Serialize};
 use influence::InfluenceUpdate;
 use leapfrog::LeapfrogUpdate;
 use orbit::OrbitsUpdate;
@@ -6,11 +7,17 @@ use time::{TimeUpdate, ToggleTime};
 
 use crate::objects::ships::trajectory::TrajectoryUpdate;
 
+pub mod forces;
+pub mod harmonics;
 pub mod influence;
 pub mod leapfrog;
+pub mod nbody;
+pub mod octree;
 pub mod orbit;
 pub mod predictions;
+pub mod rotation;
 pub mod time;
+pub mod warp;
 
 pub const SECONDS_PER_DAY: f64 = 24. * 3600.;
 
@@ -19,10 +26,13 @@ pub const G: f64 = 6.6743e-11 * SECONDS_PER_DAY * SECONDS_PER_DAY * 1e-9;
 
 pub(crate) mod prelude {
     pub use super::{
+        forces::ForceModels,
+        harmonics::ZonalHarmonics,
         influence::Influenced,
         leapfrog::Acceleration,
+        nbody::{NBody, NBodyConfig},
         orbit::{EllipticalOrbit, SystemSize},
-        predictions::Prediction,
+        rotation::{BodyRotation, LaunchSite, SurfaceCoordinates},
         time::{GameTime, ToggleTime},
         Mass, Position, Velocity,
     };
@@ -34,7 +44,7 @@ pub struct Position(pub DVec3);
 #[derive(Component, Debug, Default, Clone, Copy)]
 pub struct Velocity(pub DVec3);
 
-#[derive(Component, Clone, Copy, Debug)]
+#[derive(Component, Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Mass(pub f64);
 
 pub struct PhysicsPlugin;
@@ -43,9 +53,13 @@ impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins((
             orbit::plugin,
+            rotation::plugin,
+            forces::plugin,
+            nbody::plugin,
             influence::plugin,
             leapfrog::plugin,
             time::plugin,
+            warp::plugin,
         ))
         .configure_sets(
             FixedUpdate,
@@ -0,0 +1,380 @@
+//! Non-gravitational forces acting on ships.
+//!
+//! Each ship can carry a [ForceModels] registry, whose models add their acceleration to the gravitational
+//! pull of the bodies, both in the simulation and in the predictions. New models only need to implement
+//! the [ForceModel] trait.
+use std::fmt::Display;
+
+use bevy::{math::DVec3, prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::objects::{prelude::*, ObjectsUpdate};
+
+use super::SECONDS_PER_DAY;
+
+pub fn plugin(app: &mut App) {
+    app.add_event::<ForceModelEvent>().add_systems(
+        Update,
+        handle_force_model_events
+            .after(ObjectsUpdate)
+            .run_if(resource_exists::<ShipsMapping>),
+    );
+}
+
+/// Astronomical unit in kilometers
+pub const ASTRONOMICAL_UNIT: f64 = 1.495978707e8;
+
+/// Solar radiation pressure on a perfectly absorbing surface at one astronomical unit (in N/m²)
+pub const SOLAR_PRESSURE: f64 = 4.56e-6;
+
+/// State of a ship used to compute the forces acting on it
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ForceContext {
+    /// Position of the ship relative to the primary body
+    pub pos: DVec3,
+    /// Velocity of the ship relative to the primary body
+    pub speed: DVec3,
+}
+
+impl ForceContext {
+    pub fn new(pos: DVec3, speed: DVec3, primary_pos: DVec3, primary_speed: DVec3) -> Self {
+        Self {
+            pos: pos - primary_pos,
+            speed: speed - primary_speed,
+        }
+    }
+}
+
+pub trait ForceModel: Send + Sync + 'static {
+    /// Acceleration (in km/d²) created by this model in the given context
+    fn acceleration(&self, ctx: &ForceContext) -> DVec3;
+
+    /// Short description displayed in the UI
+    fn describe(&self) -> String;
+
+    /// Copy of this model, so that predictions can be computed on another thread
+    fn clone_model(&self) -> Box<dyn ForceModel>;
+
+    /// Current attitude for models that can be oriented
+    fn attitude(&self) -> Option<SailAttitude> {
+        None
+    }
+
+    /// Changes the attitude of the model, returning false if it can't be oriented
+    fn set_attitude(&mut self, _attitude: SailAttitude) -> bool {
+        false
+    }
+}
+
+/// The force models attached to a ship
+#[derive(Component, Default)]
+pub struct ForceModels(pub Vec<Box<dyn ForceModel>>);
+
+impl Clone for ForceModels {
+    fn clone(&self) -> Self {
+        Self(self.0.iter().map(|m| m.clone_model()).collect())
+    }
+}
+
+impl ForceModels {
+    pub fn with(mut self, model: impl ForceModel) -> Self {
+        self.0.push(Box::new(model));
+        self
+    }
+
+    pub fn acceleration(&self, ctx: &ForceContext) -> DVec3 {
+        self.0.iter().map(|m| m.acceleration(ctx)).sum()
+    }
+}
+
+/// Radiation pressure from the primary body at the given distance, converted to km·m²/(kg·d²)
+/// so that multiplying it by an area-to-mass ratio (in m²/kg) gives an acceleration in km/d²
+fn radiation_pressure(dist: f64) -> f64 {
+    SOLAR_PRESSURE * (ASTRONOMICAL_UNIT / dist).powi(2) * 1e-3 * SECONDS_PER_DAY * SECONDS_PER_DAY
+}
+
+/// Radiation pressure on a ship seen as a sphere ("cannonball" model), always pushing away from the primary body
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SolarRadiationPressure {
+    /// Cross-section exposed to the light (in m²)
+    pub area: f64,
+    /// Mass of the ship (in kg)
+    pub mass: f64,
+    /// Fraction of the light that is reflected, between 0 and 1
+    pub reflectivity: f64,
+}
+
+impl ForceModel for SolarRadiationPressure {
+    fn acceleration(&self, ctx: &ForceContext) -> DVec3 {
+        let dist = ctx.pos.length();
+        if dist == 0. {
+            return DVec3::ZERO;
+        }
+        radiation_pressure(dist) * (1. + self.reflectivity) * self.area / self.mass * ctx.pos / dist
+    }
+
+    fn describe(&self) -> String {
+        format!("Radiation pressure ({} m², {} kg)", self.area, self.mass)
+    }
+
+    fn clone_model(&self) -> Box<dyn ForceModel> {
+        Box::new(*self)
+    }
+}
+
+/// Orientation of a solar sail
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SailAttitude {
+    /// The sail is parallel to the light and produces no thrust
+    Feathered,
+    /// The sail faces the primary body, pushing the ship straight away from it
+    FaceSun,
+    /// The sail normal is tilted from the sun line towards the velocity by the given angle (in degrees),
+    /// positive angles raise the orbit and negative ones lower it
+    Cone(f64),
+}
+
+/// Cone angle giving the largest thrust along the velocity, arctan(1/√2)
+pub const OPTIMAL_CONE_ANGLE: f64 = 35.26;
+
+impl SailAttitude {
+    /// Next attitude in the order they are cycled through in the UI
+    pub fn next(self) -> Self {
+        match self {
+            Self::FaceSun => Self::Cone(OPTIMAL_CONE_ANGLE),
+            Self::Cone(a) if a > 0. => Self::Cone(-OPTIMAL_CONE_ANGLE),
+            Self::Cone(_) => Self::Feathered,
+            Self::Feathered => Self::FaceSun,
+        }
+    }
+}
+
+impl Display for SailAttitude {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Feathered => write!(f, "feathered"),
+            Self::FaceSun => write!(f, "facing the sun"),
+            Self::Cone(a) => write!(f, "cone angle {:.1}°", a),
+        }
+    }
+}
+
+/// A flat solar sail whose thrust depends on its orientation with respect to the light
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SolarSail {
+    /// Area of the sail (in m²)
+    pub area: f64,
+    /// Mass of the ship including the sail (in kg)
+    pub mass: f64,
+    /// Fraction of the light that is specularly reflected, between 0 and 1
+    pub reflectivity: f64,
+    pub attitude: SailAttitude,
+}
+
+impl SolarSail {
+    /// Unit normal of the sail, pointing away from the primary body
+    fn normal(&self, ctx: &ForceContext) -> Option<DVec3> {
+        let radial = ctx.pos.normalize_or_zero();
+        match self.attitude {
+            SailAttitude::Feathered => None,
+            SailAttitude::FaceSun => Some(radial),
+            SailAttitude::Cone(angle) => {
+                let transverse = ctx.speed.reject_from(radial).normalize_or_zero();
+                let angle = angle.to_radians();
+                Some(angle.cos() * radial + angle.sin() * transverse)
+            }
+        }
+    }
+}
+
+impl ForceModel for SolarSail {
+    fn acceleration(&self, ctx: &ForceContext) -> DVec3 {
+        let dist = ctx.pos.length();
+        let Some(normal) = self.normal(ctx).filter(|_| dist > 0.) else {
+            return DVec3::ZERO;
+        };
+        let radial = ctx.pos / dist;
+        let cos = radial.dot(normal).max(0.);
+        radiation_pressure(dist)
+            * self.area
+            * cos
+            * ((1. - self.reflectivity) * radial + 2. * self.reflectivity * cos * normal)
+            / self.mass
+    }
+
+    fn describe(&self) -> String {
+        format!("Solar sail ({} m², {})", self.area, self.attitude)
+    }
+
+    fn clone_model(&self) -> Box<dyn ForceModel> {
+        Box::new(*self)
+    }
+
+    fn attitude(&self) -> Option<SailAttitude> {
+        Some(self.attitude)
+    }
+
+    fn set_attitude(&mut self, attitude: SailAttitude) -> bool {
+        self.attitude = attitude;
+        true
+    }
+}
+
+/// The force models that can be attached to a ship through a [ForceModelEvent], so that it can be journaled
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ForceModelKind {
+    RadiationPressure(SolarRadiationPressure),
+    SolarSail(SolarSail),
+}
+
+impl ForceModelKind {
+    pub fn model(&self) -> Box<dyn ForceModel> {
+        match self {
+            Self::RadiationPressure(m) => m.clone_model(),
+            Self::SolarSail(m) => m.clone_model(),
+        }
+    }
+}
+
+#[derive(Event, Clone, Debug, Serialize, Deserialize)]
+pub enum ForceModelEvent {
+    Attach {
+        ship: ShipID,
+        model: ForceModelKind,
+    },
+    SetSailAttitude {
+        ship: ShipID,
+        attitude: SailAttitude,
+    },
+    CycleSailAttitude(ShipID),
+}
+
+fn handle_force_model_events(
+    mut commands: Commands,
+    mut events: EventReader<ForceModelEvent>,
+    mut ships: Query<&mut ForceModels>,
+    mapping: Res<ShipsMapping>,
+) {
+    let mut new_models: HashMap<Entity, ForceModels> = HashMap::new();
+    for event in events.read() {
+        match event {
+            ForceModelEvent::Attach { ship, model } => {
+                let Some(&e) = mapping.0.get(ship) else {
+                    continue;
+                };
+                if let Ok(mut models) = ships.get_mut(e) {
+                    models.0.push(model.model());
+                } else {
+                    new_models.entry(e).or_default().0.push(model.model());
+                }
+            }
+            ForceModelEvent::SetSailAttitude { ship, attitude } => {
+                if let Some(mut models) = mapping.0.get(ship).and_then(|e| ships.get_mut(*e).ok()) {
+                    models.0.iter_mut().for_each(|m| {
+                        m.set_attitude(*attitude);
+                    });
+                }
+            }
+            ForceModelEvent::CycleSailAttitude(ship) => {
+                if let Some(mut models) = mapping.0.get(ship).and_then(|e| ships.get_mut(*e).ok()) {
+                    models.0.iter_mut().for_each(|m| {
+                        if let Some(attitude) = m.attitude() {
+                            m.set_attitude(attitude.next());
+                        }
+                    });
+                }
+            }
+        }
+    }
+    for (e, models) in new_models {
+        commands.entity(e).insert(models);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{app::App, math::DVec3};
+
+    use super::*;
+    use crate::prelude::*;
+
+    fn sail(attitude: SailAttitude) -> SolarSail {
+        SolarSail {
+            area: 1e4,
+            mass: 100.,
+            reflectivity: 0.9,
+            attitude,
+        }
+    }
+
+    #[test]
+    fn test_radiation_pressure() {
+        let model = SolarRadiationPressure {
+            area: 10.,
+            mass: 1000.,
+            reflectivity: 0.,
+        };
+        let ctx = |dist: f64| ForceContext {
+            pos: DVec3::new(0., dist, 0.),
+            speed: DVec3::ZERO,
+        };
+        let acc = model.acceleration(&ctx(ASTRONOMICAL_UNIT));
+        // 4.56e-8 m/s² pushing away from the sun
+        assert!((acc.y / SECONDS_PER_DAY.powi(2) * 1e3 - 4.56e-8).abs() < 1e-12);
+        assert!(acc.x == 0. && acc.z == 0.);
+        let far = model.acceleration(&ctx(2. * ASTRONOMICAL_UNIT));
+        assert!((4. * far.y - acc.y).abs() < 1e-12 * acc.y);
+    }
+
+    #[test]
+    fn test_sail_attitude() {
+        let ctx = ForceContext {
+            pos: DVec3::new(ASTRONOMICAL_UNIT, 0., 0.),
+            speed: DVec3::new(0., 2.5e6, 0.),
+        };
+        assert_eq!(
+            sail(SailAttitude::Feathered).acceleration(&ctx),
+            DVec3::ZERO
+        );
+        let radial = sail(SailAttitude::FaceSun).acceleration(&ctx);
+        assert!(radial.x > 0. && radial.y == 0.);
+        let raising = sail(SailAttitude::Cone(OPTIMAL_CONE_ANGLE)).acceleration(&ctx);
+        let lowering = sail(SailAttitude::Cone(-OPTIMAL_CONE_ANGLE)).acceleration(&ctx);
+        assert!(raising.y > 0. && lowering.y < 0.);
+        assert!(raising.length() < radial.length());
+        let mut attitude = SailAttitude::FaceSun;
+        for _ in 0..4 {
+            attitude = attitude.next();
+        }
+        assert_eq!(attitude, SailAttitude::FaceSun);
+    }
+
+    #[test]
+    fn test_force_model_events() {
+        let mut app = App::new();
+        app.add_plugins(ClientPlugin::testing().in_mode(ClientMode::Singleplayer));
+        app.update();
+        let id = id_from("s");
+        app.world_mut().send_event(ShipEvent::Create(ShipInfo {
+            id,
+            spawn_pos: DVec3::new(ASTRONOMICAL_UNIT, 0., 0.),
+            ..default()
+        }));
+        app.world_mut().send_event(ForceModelEvent::Attach {
+            ship: id,
+            model: ForceModelKind::SolarSail(sail(SailAttitude::FaceSun)),
+        });
+        app.update();
+        app.world_mut()
+            .send_event(ForceModelEvent::CycleSailAttitude(id));
+        app.update();
+        let world = app.world_mut();
+        let ship = world.resource::<ShipsMapping>().0[&id];
+        let models = world.query::<&ForceModels>().get(world, ship).unwrap();
+        assert_eq!(models.0.len(), 1);
+        assert_eq!(
+            models.0[0].attitude(),
+            Some(SailAttitude::Cone(OPTIMAL_CONE_ANGLE))
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! Zonal harmonics of the gravity field of oblate bodies.
+//!
+//! Bodies are point masses by default, but for bodies where the coefficients are known
+//! the first zonal terms (J2, J3, J4) are added to the acceleration of the objects they influence.
+//! The coefficients are read from the `harmonics` of the bodies in main_objects.json, the table of this module
+//! being used for the bodies without them.
+//! The symmetry axis of the gravity field is the rotation axis given by [BodyRotation].
+use bevy::{math::DVec3, prelude::*};
+use serde::Deserialize;
+
+use crate::objects::prelude::*;
+
+use super::{rotation::BodyRotation, G};
+
+/// Zonal harmonic coefficients (dimensionless) and the reference radius (in kilometers) they are expressed for
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZonalHarmonics {
+    pub j2: f64,
+    #[serde(default)]
+    pub j3: f64,
+    #[serde(default)]
+    pub j4: f64,
+    pub reference_radius: f64,
+}
+
+/// Known coefficients, used when the data of a body doesn't give them,
+/// see https://ssd.jpl.nasa.gov/planets/phys_par.html
+const KNOWN_HARMONICS: [(&str, ZonalHarmonics); 9] = [
+    (
+        "mercure",
+        ZonalHarmonics {
+            j2: 5.03e-5,
+            j3: 0.,
+            j4: 0.,
+            reference_radius: 2440.5,
+        },
+    ),
+    (
+        "venus",
+        ZonalHarmonics {
+            j2: 4.458e-6,
+            j3: 0.,
+            j4: 0.,
+            reference_radius: 6051.8,
+        },
+    ),
+    (
+        "terre",
+        ZonalHarmonics {
+            j2: 1.08263e-3,
+            j3: -2.532e-6,
+            j4: -1.620e-6,
+            reference_radius: 6378.137,
+        },
+    ),
+    (
+        "lune",
+        ZonalHarmonics {
+            j2: 2.034e-4,
+            j3: 8.476e-6,
+            j4: -9.592e-6,
+            reference_radius: 1738.1,
+        },
+    ),
+    (
+        "mars",
+        ZonalHarmonics {
+            j2: 1.96045e-3,
+            j3: 3.15e-5,
+            j4: -1.54e-5,
+            reference_radius: 3396.2,
+        },
+    ),
+    (
+        "jupiter",
+        ZonalHarmonics {
+            j2: 1.4736e-2,
+            j3: 0.,
+            j4: -5.87e-4,
+            reference_radius: 71492.,
+        },
+    ),
+    (
+        "saturne",
+        ZonalHarmonics {
+            j2: 1.6298e-2,
+            j3: 0.,
+            j4: -9.15e-4,
+            reference_radius: 60268.,
+        },
+    ),
+    (
+        "uranus",
+        ZonalHarmonics {
+            j2: 3.34343e-3,
+            j3: 0.,
+            j4: -2.885e-5,
+            reference_radius: 25559.,
+        },
+    ),
+    (
+        "neptune",
+        ZonalHarmonics {
+            j2: 3.411e-3,
+            j3: 0.,
+            j4: -3.35e-5,
+            reference_radius: 24764.,
+        },
+    ),
+];
+
+impl ZonalHarmonics {
+    /// Returns the coefficients of the body if they are known, from its data or else from the known ones
+    pub fn of_body(data: &BodyData) -> Option<Self> {
+        data.harmonics.or_else(|| {
+            KNOWN_HARMONICS
+                .iter()
+                .find(|(known, _)| *known == data.id.as_str())
+                .map(|(_, h)| *h)
+        })
+    }
+
+    /// Computes the perturbation (with respect to the point mass acceleration) created by these harmonics
+    /// on an object at the given position relative to the body center, `axis` being the unit vector of the rotation axis
+    pub fn acceleration(&self, relative_pos: DVec3, mass: f64, axis: DVec3) -> DVec3 {
+        let mu = G * mass;
+        let r2 = relative_pos.length_squared();
+        let r = r2.sqrt();
+        if r == 0. {
+            return DVec3::ZERO;
+        }
+        let z = relative_pos.dot(axis);
+        let u = z * z / r2;
+        let radius = self.reference_radius;
+        let mut acc = DVec3::ZERO;
+        if self.j2 != 0. {
+            let k = -1.5 * self.j2 * mu * radius.powi(2) / r.powi(5);
+            acc += k * ((1. - 5. * u) * relative_pos + 2. * z * axis);
+        }
+        if self.j3 != 0. {
+            let k = -2.5 * self.j3 * mu * radius.powi(3) / r.powi(7);
+            acc += k * ((3. * z - 7. * z * u) * relative_pos + (3. * z * z - 0.6 * r2) * axis);
+        }
+        if self.j4 != 0. {
+            let k = 15. / 8. * self.j4 * mu * radius.powi(4) / r.powi(7);
+            acc +=
+                k * ((1. - 14. * u + 21. * u * u) * relative_pos + (4. - 28. / 3. * u) * z * axis);
+        }
+        acc
+    }
+}
+
+/// Sum of the perturbations of the oblate influencers, given their positions, masses, harmonics and rotations
+pub fn get_harmonics_acceleration<'a>(
+    object_pos: DVec3,
+    influencers: impl Iterator<Item = (DVec3, f64, &'a ZonalHarmonics, &'a BodyRotation)>,
+) -> DVec3 {
+    influencers
+        .map(|(body_pos, mass, harmonics, rotation)| {
+            harmonics.acceleration(object_pos - body_pos, mass, rotation.axis())
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use bevy::math::DVec3;
+
+    use crate::{
+        objects::bodies::main_bodies::read_main_bodies,
+        physics::{
+            leapfrog::{get_acceleration, get_dv, get_dx},
+            G,
+        },
+    };
+
+    use super::*;
+
+    const EARTH_MASS: f64 = 5.97237e24;
+
+    fn ascending_node(pos: DVec3, speed: DVec3) -> f64 {
+        let h = pos.cross(speed);
+        let node = DVec3::Z.cross(h);
+        node.y.atan2(node.x)
+    }
+
+    #[test]
+    fn test_nodal_precession() {
+        let harmonics = ZonalHarmonics {
+            j3: 0.,
+            j4: 0.,
+            ..ZonalHarmonics::of_body(&BodyData {
+                id: id_from("terre"),
+                ..default()
+            })
+            .unwrap()
+        };
+        let (a, i) = (7000., 45_f64.to_radians());
+        let mu = G * EARTH_MASS;
+        let mut pos = DVec3::new(a, 0., 0.);
+        let mut speed = (mu / a).sqrt() * DVec3::new(0., i.cos(), i.sin());
+        let initial_node = ascending_node(pos, speed);
+
+        let dt = 1e-5;
+        let duration = 1.;
+        let acceleration = |pos: DVec3| {
+            get_acceleration(pos, [(DVec3::ZERO, EARTH_MASS)].into_iter())
+                + harmonics.acceleration(pos, EARTH_MASS, DVec3::Z)
+        };
+        let mut acc = acceleration(pos);
+        for _ in 0..(duration / dt) as usize {
+            pos += get_dx(speed, acc, dt);
+            let previous_acc = acc;
+            acc = acceleration(pos);
+            speed += get_dv(previous_acc, acc, dt);
+        }
+        let drift = ascending_node(pos, speed) - initial_node;
+
+        let n = (mu / a.powi(3)).sqrt();
+        let expected = -1.5 * n * harmonics.j2 * (harmonics.reference_radius / a).powi(2) * i.cos();
+        // Short period terms make the osculating node oscillate around the mean drift
+        assert!((drift - expected * duration).abs() < 0.05 * expected.abs());
+        // Sanity check : the earth's J2 makes such an orbit drift by about 5 degrees per day
+        assert!((drift.to_degrees() + 5.).abs() < 1.);
+        assert!(drift.abs() < PI);
+    }
+
+    #[test]
+    fn test_axis_symmetry() {
+        let harmonics = ZonalHarmonics::of_body(&BodyData {
+            id: id_from("jupiter"),
+            ..default()
+        })
+        .unwrap();
+        // Along the axis and in the equatorial plane the perturbation is radial
+        let polar = harmonics.acceleration(DVec3::new(0., 0., 1e5), 1.898e27, DVec3::Z);
+        let equatorial = harmonics.acceleration(DVec3::new(1e5, 0., 0.), 1.898e27, DVec3::Z);
+        assert!(polar.truncate().length() < 1e-12 * polar.length());
+        assert!(equatorial.y.abs() + equatorial.z.abs() < 1e-12 * equatorial.length());
+        // An oblate body pulls harder in its equatorial plane
+        assert!(equatorial.x < 0.);
+    }
+
+    #[test]
+    fn test_harmonics_from_data() {
+        let bodies = read_main_bodies().unwrap();
+        let data = |id: &str| &bodies.iter().find(|(d, _)| d.id == id_from(id)).unwrap().0;
+        let earth = data("terre").harmonics.unwrap();
+        assert_eq!(earth.j2, 1.08263e-3);
+        assert_eq!(earth.reference_radius, 6378.137);
+        assert_eq!(ZonalHarmonics::of_body(data("terre")), Some(earth));
+        // Bodies without coefficients are point masses
+        assert!(data("soleil").harmonics.is_none());
+        assert!(ZonalHarmonics::of_body(data("soleil")).is_none());
+        // The known coefficients are used when the data doesn't give them
+        let custom = BodyData {
+            id: id_from("terre"),
+            ..default()
+        };
+        assert_eq!(ZonalHarmonics::of_body(&custom), Some(earth));
+    }
+}
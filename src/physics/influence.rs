@@ -1,4 +1,4 @@
-use bevy::{math::DVec3, prelude::*};
+use bevy::{math::DVec3, prelude::*, utils::HashMap};
 
 use crate::game::Loaded;
 use crate::objects::prelude::*;
@@ -9,14 +9,22 @@ use crate::objects::{
 };
 use crate::physics::{orbit::EllipticalOrbit, Mass};
 
+use super::octree::Octree;
 use super::time::TickEvent;
 use super::Position;
 
 pub fn plugin(app: &mut App) {
-    app.add_systems(OnEnter(Loaded), setup_hill_spheres.in_set(InfluenceUpdate))
+    app.init_resource::<BodiesOctree>()
+        .add_systems(
+            OnEnter(Loaded),
+            (setup_hill_spheres, update_bodies_octree)
+                .chain()
+                .in_set(InfluenceUpdate),
+        )
         .add_systems(
             FixedUpdate,
-            update_influence
+            (update_bodies_octree, update_influence)
+                .chain()
                 .in_set(InfluenceUpdate)
                 .run_if(on_event::<TickEvent>()),
         );
@@ -29,7 +37,7 @@ pub struct InfluenceUpdate;
 pub struct HillRadius(pub f64);
 
 /// Component storing the bodies that influence the object's trajectory
-#[derive(Component, Default, Debug)]
+#[derive(Component, Default, Debug, Clone)]
 pub struct Influenced {
     pub main_influencer: Option<Entity>,
     pub influencers: Vec<Entity>,
@@ -80,6 +88,68 @@ impl Influenced {
     }
 }
 
+/// Octree over the spheres of influence of the bodies, rebuilt at each tick
+#[derive(Resource, Default)]
+pub struct BodiesOctree {
+    tree: Octree,
+    entities: Vec<Entity>,
+    /// Index of the host of each body, none for the primary
+    hosts: Vec<Option<usize>>,
+}
+
+impl BodiesOctree {
+    /// Builds the octree from the entities, positions, masses, Hill radii and hosts of the bodies, which are given
+    /// in the order of a depth-first walk of the system from the primary
+    pub fn new(
+        bodies: impl IntoIterator<Item = (Entity, DVec3, f64, f64, Option<Entity>)>,
+    ) -> Self {
+        let bodies: Vec<_> = bodies.into_iter().collect();
+        let indices: HashMap<_, _> = bodies.iter().enumerate().map(|(i, b)| (b.0, i)).collect();
+        Self {
+            tree: Octree::with_radii(
+                bodies
+                    .iter()
+                    .map(|&(_, pos, mass, radius, _)| (pos, mass, radius)),
+            ),
+            entities: bodies.iter().map(|b| b.0).collect(),
+            hosts: bodies
+                .iter()
+                .map(|b| b.4.and_then(|host| indices.get(&host).copied()))
+                .collect(),
+        }
+    }
+
+    /// Computes the influence on an object at the given position, as [Influenced::new] does: the bodies whose
+    /// sphere of influence contains the object along with the spheres of all their hosts, in the order of the walk
+    /// down the system, the main influencer having the smallest sphere
+    pub fn influence(&self, Position(object_pos): &Position) -> Influenced {
+        let radii = self.tree.radii();
+        let containing = self.tree.containing(*object_pos);
+        let mut influences: Vec<_> = containing
+            .iter()
+            .copied()
+            .filter(|&i| {
+                let mut host = self.hosts[i];
+                while let Some(h) = host {
+                    if !containing.contains(&h) {
+                        return false;
+                    }
+                    host = self.hosts[h];
+                }
+                true
+            })
+            .collect();
+        influences.sort_unstable();
+        Influenced {
+            main_influencer: influences
+                .iter()
+                .min_by(|a, b| radii[**a].total_cmp(&radii[**b]))
+                .map(|i| self.entities[*i]),
+            influencers: influences.into_iter().map(|i| self.entities[i]).collect(),
+        }
+    }
+}
+
 fn setup_hill_spheres(
     mut commands: Commands,
     query: Query<(&BodyInfo, &EllipticalOrbit, &OrbitingObjects, &Mass)>,
@@ -113,17 +183,36 @@ fn setup_hill_spheres(
         .insert(HillRadius(f64::INFINITY));
 }
 
+fn update_bodies_octree(
+    mut octree: ResMut<BodiesOctree>,
+    bodies: Query<(&Position, &Mass, &HillRadius, &OrbitingObjects)>,
+    primary: Query<Entity, With<PrimaryBody>>,
+    mapping: Res<BodiesMapping>,
+) {
+    // Depth-first walk of the system, so that the influencers are ordered as in [Influenced::new]
+    let mut walk = Vec::new();
+    let mut stack: Vec<_> = primary.iter().map(|e| (e, None)).collect();
+    while let Some((entity, host)) = stack.pop() {
+        let Ok((pos, mass, radius, OrbitingObjects(orbiting))) = bodies.get(entity) else {
+            continue;
+        };
+        walk.push((entity, pos.0, mass.0, radius.0, host));
+        stack.extend(orbiting.iter().rev().filter_map(|obj| match obj {
+            OrbitalObjID::Body(id) => mapping.0.get(id).map(|child| (*child, Some(entity))),
+            OrbitalObjID::Ship(_) => None,
+        }));
+    }
+    *octree = BodiesOctree::new(walk);
+}
+
 fn update_influence(
     mut influenced: Query<(&Position, &mut Influenced)>,
-    bodies: Query<(&Position, &HillRadius, &OrbitingObjects)>,
-    mapping: Res<BodiesMapping>,
-    main_body: Query<&BodyInfo, With<PrimaryBody>>,
+    octree: Res<BodiesOctree>,
 ) {
-    let main_body = main_body.single().0.id;
     influenced
         .par_iter_mut()
         .for_each(|(object_pos, mut influence)| {
-            *influence = Influenced::new(object_pos, &bodies, mapping.as_ref(), main_body);
+            *influence = octree.influence(object_pos);
         });
 }
 
@@ -131,7 +220,63 @@ fn update_influence(
 mod tests {
     use bevy::app::App;
 
+    use bevy::{ecs::system::SystemState, math::DVec3, prelude::*};
+
+    use super::{BodiesOctree, HillRadius};
+    use crate::objects::orbiting_obj::OrbitingObjects;
     use crate::{prelude::*, utils::algebra::circular_orbit_around_body};
+
+    #[test]
+    fn test_octree_influence() {
+        let mut app = App::new();
+        app.add_plugins(
+            ClientPlugin::testing()
+                .with_bodies(BodiesConfig::SmallestBodyType(BodyType::Moon))
+                .in_mode(ClientMode::Explorer),
+        );
+        app.update();
+        let world = app.world_mut();
+        #[allow(clippy::type_complexity)]
+        let mut system_state: SystemState<(
+            Query<(&Position, &HillRadius, &OrbitingObjects)>,
+            Res<BodiesMapping>,
+            Res<BodiesOctree>,
+        )> = SystemState::new(world);
+        let (bodies, mapping, octree) = system_state.get(world);
+        // Positions around every body, at distances ranging from their surface to far away
+        for (&Position(body_pos), &HillRadius(radius), _) in bodies.iter() {
+            let radius = radius.min(1e10);
+            for k in [1e-3, 0.5, 0.99, 1.01, 3.] {
+                let pos = Position(body_pos + DVec3::new(0.6, -0.48, 0.64) * radius * k);
+                let expected = Influenced::new(&pos, &bodies, &mapping, id_from("soleil"));
+                let influence = octree.influence(&pos);
+                assert_eq!(influence.main_influencer, expected.main_influencer);
+                assert_eq!(influence.influencers, expected.influencers);
+            }
+        }
+    }
+
+    #[test]
+    fn test_octree_hierarchy() {
+        let (sun, planet, moon) = (
+            Entity::from_raw(0),
+            Entity::from_raw(1),
+            Entity::from_raw(2),
+        );
+        let octree = BodiesOctree::new([
+            (sun, DVec3::ZERO, 1e30, f64::INFINITY, None),
+            (planet, DVec3::new(1e8, 0., 0.), 1e24, 1e6, Some(sun)),
+            (moon, DVec3::new(1e8 + 9e5, 0., 0.), 1e22, 5e5, Some(planet)),
+        ]);
+        let influence = octree.influence(&Position(DVec3::new(1e8 + 8e5, 0., 0.)));
+        assert_eq!(influence.influencers, vec![sun, planet, moon]);
+        assert_eq!(influence.main_influencer, Some(moon));
+        // The part of the sphere of the moon outside the one of its planet is not influenced by the moon
+        let influence = octree.influence(&Position(DVec3::new(1e8 + 1.2e6, 0., 0.)));
+        assert_eq!(influence.influencers, vec![sun]);
+        assert_eq!(influence.main_influencer, Some(sun));
+    }
+
     #[test]
     fn test_influence() {
         let mut app = App::new();
@@ -1,11 +1,15 @@
 use bevy::{math::DVec3, prelude::*};
 
 use super::{
+    forces::{ForceContext, ForceModels},
+    harmonics::{get_harmonics_acceleration, ZonalHarmonics},
     prelude::*,
     time::{SimStepSize, GAMETIME_PER_SIMTICK},
     G,
 };
-use crate::game::InGame;
+use crate::{
+    game::InGame, objects::prelude::PrimaryBody, replay::Deterministic, utils::ecs::for_each_mut,
+};
 
 // See https://en.wikipedia.org/wiki/Leapfrog_integration#Algorithm
 pub fn plugin(app: &mut App) {
@@ -41,34 +45,69 @@ impl Acceleration {
     }
 }
 
+/// The sum only runs over the few influencers of each object, found through the
+/// [`BodiesOctree`](super::influence::BodiesOctree) at each tick: querying the octree
+/// here would read body positions up to a tick old, while this runs at each simtick
+#[allow(clippy::type_complexity)]
 fn update_acceleration(
-    mut gravity_bound: Query<(&Position, &mut Acceleration, &Influenced)>,
+    mut gravity_bound: Query<(
+        &Position,
+        &Velocity,
+        &mut Acceleration,
+        &Influenced,
+        Option<&ForceModels>,
+    )>,
     bodies: Query<(&Position, &Mass)>,
+    oblate_bodies: Query<(&Position, &Mass, &ZonalHarmonics, &BodyRotation)>,
+    primary: Query<(&Position, &Velocity), With<PrimaryBody>>,
+    deterministic: Res<Deterministic>,
 ) {
-    gravity_bound
-        .par_iter_mut()
-        .for_each(|(object_pos, mut acceleration, influenced)| {
+    let (primary_pos, primary_speed) = primary
+        .get_single()
+        .map_or((DVec3::ZERO, DVec3::ZERO), |(p, v)| (p.0, v.0));
+    for_each_mut(
+        &mut gravity_bound,
+        &deterministic,
+        |(object_pos, speed, mut acceleration, influenced, forces)| {
             acceleration.previous = acceleration.current;
             acceleration.current = get_acceleration(
                 object_pos.0,
                 bodies
                     .iter_many(&influenced.influencers)
                     .map(|(p, m)| (p.0, m.0)),
-            );
-        });
+            ) + get_harmonics_acceleration(
+                object_pos.0,
+                oblate_bodies
+                    .iter_many(&influenced.influencers)
+                    .map(|(p, m, h, r)| (p.0, m.0, h, r)),
+            ) + forces.map_or(DVec3::ZERO, |f| {
+                f.acceleration(&ForceContext::new(
+                    object_pos.0,
+                    speed.0,
+                    primary_pos,
+                    primary_speed,
+                ))
+            });
+        },
+    );
 }
 
 fn update_position(
     mut query: Query<(&mut Position, &Velocity, &Acceleration)>,
     step: Res<SimStepSize>,
+    deterministic: Res<Deterministic>,
 ) {
-    query.par_iter_mut().for_each(|(mut pos, speed, acc)| {
+    for_each_mut(&mut query, &deterministic, |(mut pos, speed, acc)| {
         pos.0 += get_dx(speed.0, acc.current, GAMETIME_PER_SIMTICK * step.0 as f64)
     });
 }
 
-fn update_velocity(mut query: Query<(&mut Velocity, &Acceleration)>, step: Res<SimStepSize>) {
-    query.par_iter_mut().for_each(|(mut speed, acc)| {
+fn update_velocity(
+    mut query: Query<(&mut Velocity, &Acceleration)>,
+    step: Res<SimStepSize>,
+    deterministic: Res<Deterministic>,
+) {
+    for_each_mut(&mut query, &deterministic, |(mut speed, acc)| {
         speed.0 += get_dv(
             acc.previous,
             acc.current,
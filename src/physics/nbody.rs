@@ -0,0 +1,208 @@
+//! Optional N-body integration of the bodies.
+//!
+//! By default the bodies follow their Keplerian orbits ("rails"). The bodies selected by [NBodyConfig]
+//! are instead integrated with the leapfrog, starting from their Keplerian state when the system is loaded,
+//! and attracted by every other body. Bodies on rails orbiting an integrated body follow its integrated motion.
+use bevy::prelude::*;
+
+use crate::{game::Loaded, objects::prelude::*, replay::Deterministic, utils::ecs::for_each_mut};
+
+use super::{
+    leapfrog::{get_dv, get_dx, Acceleration},
+    octree::Octree,
+    orbit::{update_global, update_local, OrbitsUpdate},
+    time::{SimStepSize, GAMETIME_PER_SIMTICK},
+    Mass, Position, Velocity,
+};
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<NBodyConfig>()
+        .add_systems(
+            OnEnter(Loaded),
+            setup_n_body.after(update_global).in_set(OrbitsUpdate),
+        )
+        .add_systems(
+            FixedUpdate,
+            (
+                drift_bodies.after(update_local).before(update_global),
+                kick_bodies.after(update_global),
+            )
+                .in_set(OrbitsUpdate),
+        );
+}
+
+#[derive(Resource, Clone)]
+pub struct NBodyConfig {
+    /// Bodies that are integrated instead of following their orbits, all bodies are on rails if `None`
+    pub bodies: Option<BodiesConfig>,
+    /// Opening angle of the Barnes–Hut approximation, 0 computes the exact sum of the attractions
+    pub opening_angle: f64,
+}
+
+impl Default for NBodyConfig {
+    fn default() -> Self {
+        Self {
+            bodies: None,
+            opening_angle: if cfg!(feature = "asteroids") { 0.5 } else { 0. },
+        }
+    }
+}
+
+/// Marker for the bodies that are integrated, along with their acceleration
+#[derive(Component, Debug, Default)]
+pub struct NBody(pub Acceleration);
+
+fn setup_n_body(
+    mut commands: Commands,
+    config: Res<NBodyConfig>,
+    bodies: Query<(Entity, &BodyInfo, &Position, &Mass)>,
+) {
+    let Some(selection) = &config.bodies else {
+        return;
+    };
+    let mut filter = selection.clone().into_filter();
+    let tree = Octree::new(bodies.iter().map(|(_, _, p, m)| (p.0, m.0)));
+    for (e, BodyInfo(data), pos, _) in bodies.iter() {
+        if filter(data) {
+            commands.entity(e).insert(NBody(Acceleration::new(
+                tree.acceleration(pos.0, config.opening_angle),
+            )));
+        }
+    }
+}
+
+/// First half of the leapfrog step of the integrated bodies, moving them to the new simtick so that the bodies on
+/// rails orbiting them are placed relative to their new positions
+pub fn drift_bodies(
+    mut integrated: Query<(&mut Position, &Velocity, &NBody)>,
+    step: Res<SimStepSize>,
+    deterministic: Res<Deterministic>,
+) {
+    let dt = GAMETIME_PER_SIMTICK * step.0 as f64;
+    for_each_mut(
+        &mut integrated,
+        &deterministic,
+        |(mut pos, speed, n_body)| pos.0 += get_dx(speed.0, n_body.0.current, dt),
+    );
+}
+
+/// Second half of the leapfrog step of the integrated bodies, once every body is at the new simtick
+pub fn kick_bodies(
+    mut integrated: Query<(&Position, &mut Velocity, &mut NBody, &Mass)>,
+    bodies: Query<(&Position, &Mass), Without<NBody>>,
+    config: Res<NBodyConfig>,
+    step: Res<SimStepSize>,
+    deterministic: Res<Deterministic>,
+) {
+    if integrated.is_empty() {
+        return;
+    }
+    let dt = GAMETIME_PER_SIMTICK * step.0 as f64;
+    let tree = Octree::new(
+        bodies
+            .iter()
+            .chain(integrated.iter().map(|(p, _, _, m)| (p, m)))
+            .map(|(p, m)| (p.0, m.0)),
+    );
+    for_each_mut(
+        &mut integrated,
+        &deterministic,
+        |(pos, mut speed, mut n_body, _)| {
+            let acc = &mut n_body.0;
+            acc.previous = acc.current;
+            acc.current = tree.acceleration(pos.0, config.opening_angle);
+            speed.0 += get_dv(acc.previous, acc.current, dt);
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::app::App;
+
+    use super::*;
+    use crate::prelude::*;
+
+    #[test]
+    fn test_n_body() {
+        let mut app = App::new();
+        app.add_plugins(
+            ClientPlugin::testing()
+                .with_n_body(NBodyConfig {
+                    bodies: Some(BodiesConfig::IDs(vec![id_from("terre")])),
+                    opening_angle: 0.,
+                })
+                .in_mode(ClientMode::Explorer),
+        );
+        app.update();
+        let world = app.world_mut();
+        let earth = world.resource::<BodiesMapping>().0[&id_from("terre")];
+        assert!(world.get::<NBody>(earth).is_some());
+        let initial = world.get::<Position>(earth).unwrap().0;
+        for _ in 0..100 {
+            app.world_mut().run_schedule(FixedUpdate);
+        }
+        let world = app.world_mut();
+        let pos = world.get::<Position>(earth).unwrap().0;
+        let orbit = world.get::<EllipticalOrbit>(earth).unwrap();
+        // The earth moved, and stays close to its Keplerian orbit
+        assert!((pos - initial).length() > 1e5);
+        assert!((pos - orbit.local_pos).length() < 1e3);
+        let mars = world.resource::<BodiesMapping>().0[&id_from("mars")];
+        assert!(world.get::<NBody>(mars).is_none());
+    }
+
+    #[test]
+    fn test_rails_children() {
+        let mut app = App::new();
+        app.add_plugins(
+            ClientPlugin::testing()
+                .with_bodies(BodiesConfig::SmallestBodyType(BodyType::Moon))
+                .with_n_body(NBodyConfig {
+                    bodies: Some(BodiesConfig::IDs(vec![id_from("terre")])),
+                    opening_angle: 0.5,
+                })
+                .in_mode(ClientMode::Explorer),
+        );
+        app.update();
+        for _ in 0..10 {
+            app.world_mut().run_schedule(FixedUpdate);
+        }
+        let world = app.world_mut();
+        let mapping = &world.resource::<BodiesMapping>().0;
+        let (earth, moon) = (mapping[&id_from("terre")], mapping[&id_from("lune")]);
+        let earth_pos = world.get::<Position>(earth).unwrap().0;
+        let moon_pos = world.get::<Position>(moon).unwrap().0;
+        let orbit = world.get::<EllipticalOrbit>(moon).unwrap();
+        assert!((moon_pos - earth_pos - orbit.local_pos).length() < 1e-6);
+    }
+
+    #[test]
+    fn test_acceleration_at_current_positions() {
+        let mut app = App::new();
+        app.add_plugins(
+            ClientPlugin::testing()
+                .with_n_body(NBodyConfig {
+                    bodies: Some(BodiesConfig::IDs(vec![id_from("terre")])),
+                    opening_angle: 0.,
+                })
+                .in_mode(ClientMode::Explorer),
+        );
+        app.update();
+        for _ in 0..10 {
+            app.world_mut().run_schedule(FixedUpdate);
+        }
+        let world = app.world_mut();
+        let earth = world.resource::<BodiesMapping>().0[&id_from("terre")];
+        let tree = Octree::new(
+            world
+                .query::<(&Position, &Mass)>()
+                .iter(world)
+                .map(|(p, m)| (p.0, m.0)),
+        );
+        let pos = world.get::<Position>(earth).unwrap().0;
+        let acc = world.get::<NBody>(earth).unwrap().0.current;
+        // The bodies on rails are at the same simtick as the integrated ones
+        assert!((acc - tree.acceleration(pos, 0.)).length() < 1e-12 * acc.length());
+    }
+}
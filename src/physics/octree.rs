@@ -0,0 +1,309 @@
+//! Octree over point masses, used to approximate the gravitational pull of many bodies
+//! with the Barnes–Hut algorithm (see https://en.wikipedia.org/wiki/Barnes%E2%80%93Hut_simulation),
+//! and to find the spheres (such as spheres of influence) containing a given position
+use bevy::math::DVec3;
+
+use super::G;
+
+/// Maximum number of points stored in a leaf before it is split
+const LEAF_CAPACITY: usize = 8;
+
+/// Leaves at this depth are never split, so that coincident points don't recurse forever
+const MAX_DEPTH: usize = 32;
+
+#[derive(Clone, Debug)]
+pub struct OctreeNode {
+    pub center: DVec3,
+    pub half_size: f64,
+    pub mass: f64,
+    pub center_of_mass: DVec3,
+    /// Largest radius of the points in this node
+    pub max_radius: f64,
+    /// Index of the first of the eight children, if the node has been split
+    children: Option<usize>,
+    /// Indices of the points of a leaf
+    points: Vec<usize>,
+}
+
+impl OctreeNode {
+    fn new(center: DVec3, half_size: f64) -> Self {
+        Self {
+            center,
+            half_size,
+            mass: 0.,
+            center_of_mass: DVec3::ZERO,
+            max_radius: 0.,
+            children: None,
+            points: Vec::new(),
+        }
+    }
+
+    fn octant(&self, pos: DVec3) -> usize {
+        (pos.x >= self.center.x) as usize
+            | ((pos.y >= self.center.y) as usize) << 1
+            | ((pos.z >= self.center.z) as usize) << 2
+    }
+
+    fn contains(&self, pos: DVec3) -> bool {
+        (pos - self.center).abs().max_element() <= self.half_size
+    }
+
+    /// Distance between the position and the closest point of the node
+    fn distance(&self, pos: DVec3) -> f64 {
+        ((pos - self.center).abs() - self.half_size)
+            .max(DVec3::ZERO)
+            .length()
+    }
+}
+
+/// An octree built from a set of points (position, mass), which are referred to by their index
+#[derive(Clone, Debug, Default)]
+pub struct Octree {
+    points: Vec<(DVec3, f64)>,
+    radii: Vec<f64>,
+    nodes: Vec<OctreeNode>,
+}
+
+impl Octree {
+    pub fn new(points: impl IntoIterator<Item = (DVec3, f64)>) -> Self {
+        Self::with_radii(points.into_iter().map(|(p, m)| (p, m, 0.)))
+    }
+
+    /// Builds an octree of spheres given by their center, mass and radius
+    pub fn with_radii(spheres: impl IntoIterator<Item = (DVec3, f64, f64)>) -> Self {
+        let (points, radii): (Vec<_>, Vec<_>) =
+            spheres.into_iter().map(|(p, m, r)| ((p, m), r)).unzip();
+        let (min, max) = points.iter().fold(
+            (DVec3::splat(f64::INFINITY), DVec3::splat(f64::NEG_INFINITY)),
+            |(min, max), (p, _)| (min.min(*p), max.max(*p)),
+        );
+        let mut tree = Self {
+            nodes: Vec::new(),
+            points: Vec::new(),
+            radii,
+        };
+        if points.is_empty() {
+            return tree;
+        }
+        let half_size = ((max - min).max_element() / 2.).max(f64::MIN_POSITIVE);
+        tree.nodes
+            .push(OctreeNode::new((min + max) / 2., half_size));
+        tree.points = points;
+        for i in 0..tree.points.len() {
+            tree.insert(0, i, 0);
+        }
+        tree.compute_mass(0);
+        tree
+    }
+
+    pub fn points(&self) -> &[(DVec3, f64)] {
+        &self.points
+    }
+
+    pub fn radii(&self) -> &[f64] {
+        &self.radii
+    }
+
+    pub fn nodes(&self) -> &[OctreeNode] {
+        &self.nodes
+    }
+
+    fn insert(&mut self, node: usize, point: usize, depth: usize) {
+        if let Some(first) = self.nodes[node].children {
+            let child = first + self.nodes[node].octant(self.points[point].0);
+            self.insert(child, point, depth + 1);
+            return;
+        }
+        self.nodes[node].points.push(point);
+        if self.nodes[node].points.len() > LEAF_CAPACITY && depth < MAX_DEPTH {
+            let OctreeNode {
+                center, half_size, ..
+            } = self.nodes[node];
+            let first = self.nodes.len();
+            for octant in 0..8 {
+                let offset = DVec3::new(
+                    if octant & 1 == 0 { -1. } else { 1. },
+                    if octant & 2 == 0 { -1. } else { 1. },
+                    if octant & 4 == 0 { -1. } else { 1. },
+                ) * half_size
+                    / 2.;
+                self.nodes
+                    .push(OctreeNode::new(center + offset, half_size / 2.));
+            }
+            self.nodes[node].children = Some(first);
+            for p in std::mem::take(&mut self.nodes[node].points) {
+                self.insert(node, p, depth);
+            }
+        }
+    }
+
+    /// Computes the mass, center of mass and largest radius of the node and its children
+    fn compute_mass(&mut self, node: usize) -> (f64, DVec3, f64) {
+        let (mass, weighted, max_radius) = match self.nodes[node].children {
+            Some(first) => (first..first + 8)
+                .map(|child| {
+                    let (m, com, r) = self.compute_mass(child);
+                    (m, com * m, r)
+                })
+                .fold((0., DVec3::ZERO, 0.), |(m, w, r), (cm, cw, cr)| {
+                    (m + cm, w + cw, f64::max(r, cr))
+                }),
+            None => self.nodes[node]
+                .points
+                .iter()
+                .map(|&p| (self.points[p], self.radii[p]))
+                .fold((0., DVec3::ZERO, 0.), |(m, w, r), ((pos, pm), pr)| {
+                    (m + pm, w + pos * pm, f64::max(r, pr))
+                }),
+        };
+        let center_of_mass = if mass > 0. {
+            weighted / mass
+        } else {
+            self.nodes[node].center
+        };
+        self.nodes[node].mass = mass;
+        self.nodes[node].center_of_mass = center_of_mass;
+        self.nodes[node].max_radius = max_radius;
+        (mass, center_of_mass, max_radius)
+    }
+
+    /// Indices of the spheres that strictly contain the given position
+    pub fn containing(&self, pos: DVec3) -> Vec<usize> {
+        let mut found = Vec::new();
+        if self.nodes.is_empty() {
+            return found;
+        }
+        let mut stack = vec![0];
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            if node.distance(pos) >= node.max_radius {
+                continue;
+            }
+            match node.children {
+                None => found.extend(
+                    node.points
+                        .iter()
+                        .filter(|&&p| (self.points[p].0 - pos).length() < self.radii[p]),
+                ),
+                Some(first) => stack.extend(first..first + 8),
+            }
+        }
+        found
+    }
+
+    /// Gravitational acceleration at the given position.
+    ///
+    /// Nodes that are seen under an angle smaller than `opening_angle` are replaced by their center of mass,
+    /// so an opening angle of 0 gives the exact sum. Points located exactly at `pos` are ignored.
+    pub fn acceleration(&self, pos: DVec3, opening_angle: f64) -> DVec3 {
+        let mut acc = DVec3::ZERO;
+        if self.nodes.is_empty() {
+            return acc;
+        }
+        let mut stack = vec![0];
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            if node.mass == 0. {
+                continue;
+            }
+            match node.children {
+                None => {
+                    for &p in &node.points {
+                        acc += point_acceleration(pos, self.points[p]);
+                    }
+                }
+                Some(first) => {
+                    let dist = (node.center_of_mass - pos).length();
+                    if !node.contains(pos) && 2. * node.half_size < opening_angle * dist {
+                        acc += point_acceleration(pos, (node.center_of_mass, node.mass));
+                    } else {
+                        stack.extend(first..first + 8);
+                    }
+                }
+            }
+        }
+        acc
+    }
+}
+
+fn point_acceleration(pos: DVec3, (body_pos, mass): (DVec3, f64)) -> DVec3 {
+    let r = pos - body_pos;
+    let dist = r.length();
+    if dist == 0. {
+        DVec3::ZERO
+    } else {
+        -r * G * mass / dist.powi(3)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec3;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::physics::leapfrog::get_acceleration;
+
+    use super::Octree;
+
+    #[test]
+    fn test_containing() {
+        let points = cloud(500);
+        let tree = Octree::with_radii(
+            points
+                .iter()
+                .enumerate()
+                .map(|(i, &(p, m))| (p, m, 1e6 * (i % 50) as f64)),
+        );
+        let pos = points[7].0 + DVec3::new(1e6, 0., 0.);
+        let mut found = tree.containing(pos);
+        found.sort();
+        let expected: Vec<_> = (0..points.len())
+            .filter(|&i| (points[i].0 - pos).length() < 1e6 * (i % 50) as f64)
+            .collect();
+        assert!(expected.contains(&7));
+        assert_eq!(found, expected);
+        let tree = Octree::with_radii([(DVec3::ZERO, 1., f64::INFINITY)]);
+        assert_eq!(tree.containing(DVec3::splat(1e12)), vec![0]);
+    }
+
+    fn cloud(n: usize) -> Vec<(DVec3, f64)> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..n)
+            .map(|_| {
+                (
+                    DVec3::new(
+                        rng.gen_range(-1e9..1e9),
+                        rng.gen_range(-1e9..1e9),
+                        rng.gen_range(-1e8..1e8),
+                    ),
+                    rng.gen_range(1e15..1e20),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_octree_acceleration() {
+        let points = cloud(2000);
+        let tree = Octree::new(points.iter().cloned());
+        let mass: f64 = points.iter().map(|p| p.1).sum();
+        assert!((tree.nodes()[0].mass - mass).abs() < 1e-12 * mass);
+        let pos = DVec3::new(3e8, -2e8, 1e7);
+        let exact = get_acceleration(pos, points.iter().cloned());
+        assert!((tree.acceleration(pos, 0.) - exact).length() < 1e-9 * exact.length());
+        let approx = tree.acceleration(pos, 0.5);
+        assert!((approx - exact).length() < 1e-2 * exact.length());
+        // A point of the tree doesn't attract itself
+        let (p, _) = points[0];
+        let exact = get_acceleration(p, points[1..].iter().cloned());
+        assert!((tree.acceleration(p, 0.) - exact).length() < 1e-9 * exact.length());
+    }
+
+    #[test]
+    fn test_coincident_points() {
+        let tree = Octree::new([(DVec3::ONE, 1.); 100]);
+        assert_eq!(tree.nodes()[0].mass, 100.);
+        assert_eq!(tree.acceleration(DVec3::ONE, 0.5), DVec3::ZERO);
+        assert_eq!(Octree::new([]).acceleration(DVec3::ONE, 0.5), DVec3::ZERO);
+    }
+}
@@ -1,4 +1,4 @@
-use std::f64::consts::PI;
+use std::f64::consts::{PI, TAU};
 
 use bevy::{
     math::{DVec2, DVec3},
@@ -13,7 +13,7 @@ use crate::{
 };
 use crate::objects::orbiting_obj::{OrbitingObjects, OrbitalObjID};
 
-use super::time::GameTime;
+use super::{nbody::NBody, time::GameTime};
 
 pub fn plugin(app: &mut App) {
     app.add_systems(
@@ -126,14 +126,92 @@ impl From<&BodyData> for EllipticalOrbit {
     }
 }
 
+/// Osculating elements of an orbit, computed from the state of an object relative to its host (angles in degrees)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OsculatingElements {
+    /// Negative for hyperbolic trajectories
+    pub semimajor_axis: f64,
+    pub eccentricity: f64,
+    pub inclination: f64,
+    pub long_asc_node: f64,
+    pub arg_periapsis: f64,
+    pub true_anomaly: f64,
+    /// Distance from the center of the host at periapsis
+    pub periapsis: f64,
+    /// Distance from the center of the host at apoapsis, if the orbit is closed
+    pub apoapsis: Option<f64>,
+    /// Revolution period in days, if the orbit is closed
+    pub period: Option<f64>,
+}
+
+impl OsculatingElements {
+    /// Computes the elements from the position and speed relative to a host of gravitational parameter `mu`
+    pub fn from_state(pos: DVec3, speed: DVec3, mu: f64) -> Self {
+        let r = pos.length();
+        let h = pos.cross(speed);
+        let e_vec = speed.cross(h) / mu - pos / r;
+        let e = e_vec.length();
+        let energy = speed.length_squared() / 2. - mu / r;
+        let semimajor_axis = -mu / (2. * energy);
+        let inclination = (h.z / h.length()).clamp(-1., 1.).acos();
+        // Angle between two vectors, measured towards `positive` when it is negative
+        let angle = |from: DVec3, to: DVec3, positive: bool| {
+            let angle = (from.dot(to) / (from.length() * to.length()))
+                .clamp(-1., 1.)
+                .acos();
+            if positive {
+                angle
+            } else {
+                TAU - angle
+            }
+        };
+        // Equatorial orbits have no ascending node and circular ones no periapsis, the x axis and the
+        // ascending node are used as the references instead
+        let node = DVec3::new(-h.y, h.x, 0.);
+        let node = if node.length() < 1e-12 * h.length() {
+            DVec3::X
+        } else {
+            node
+        };
+        let long_asc_node = angle(DVec3::X, node, node.y >= 0.);
+        let periapsis_dir = if e < 1e-9 { node } else { e_vec };
+        let arg_periapsis = if e < 1e-9 {
+            0.
+        } else {
+            angle(node, e_vec, h.dot(node.cross(e_vec)) >= 0.)
+        };
+        let true_anomaly = angle(periapsis_dir, pos, h.dot(periapsis_dir.cross(pos)) >= 0.);
+        let periapsis = h.length_squared() / (mu * (1. + e));
+        let closed = e < 1.;
+        Self {
+            semimajor_axis,
+            eccentricity: e,
+            inclination: inclination.to_degrees(),
+            long_asc_node: long_asc_node.to_degrees(),
+            arg_periapsis: arg_periapsis.to_degrees(),
+            true_anomaly: true_anomaly.to_degrees(),
+            periapsis,
+            apoapsis: closed.then(|| semimajor_axis * (1. + e)),
+            period: closed.then(|| TAU * (semimajor_axis.powi(3) / mu).sqrt()),
+        }
+    }
+}
+
 pub fn update_local(mut orbits: Query<&mut EllipticalOrbit>, time: Res<GameTime>) {
     orbits
         .par_iter_mut()
         .for_each(|mut o| o.update_pos(time.time()));
 }
 
+/// Computes the global coordinates of the bodies on rails from the ones of their host body
 pub fn update_global(
-    mut query: Query<(&mut Position, &mut Velocity, &EllipticalOrbit, &OrbitingObjects)>,
+    mut query: Query<(
+        &mut Position,
+        &mut Velocity,
+        &EllipticalOrbit,
+        &OrbitingObjects,
+        Has<NBody>,
+    )>,
     primary: Query<&BodyInfo, With<PrimaryBody>>,
     bodies_mapping: Res<BodiesMapping>,
     ships_mapping: Res<ShipsMapping>,
@@ -147,12 +225,14 @@ pub fn update_global(
             OrbitalObjID::Ship(ship_id) => ships_mapping.0.get(ship_id),
         };
         if let Some(entity) = option_entity {
-            if let Ok((mut world_pos, mut world_velocity, orbit, OrbitingObjects(orbiting))) = query.get_mut(*entity) {
-                let pos = *parent_pos + orbit.local_pos;
-                let velocity = *parent_velocity + orbit.local_speed;
-                world_pos.0 = pos;
-                world_velocity.0 = velocity;
-                queue.extend(orbiting.iter().map(|o_id| (o_id.clone(), (pos, velocity))));
+            if let Ok((mut world_pos, mut world_velocity, orbit, OrbitingObjects(orbiting), n_body)) = query.get_mut(*entity) {
+                // Integrated bodies are moved by the N-body simulation
+                if !n_body {
+                    world_pos.0 = *parent_pos + orbit.local_pos;
+                    world_velocity.0 = *parent_velocity + orbit.local_speed;
+                }
+                let (pos, velocity) = (world_pos.0, world_velocity.0);
+                queue.extend(orbiting.iter().map(|o_id| (*o_id, (pos, velocity))));
             }
         }
         i += 1;
@@ -175,7 +255,8 @@ pub fn insert_system_size(mut commands: Commands, body_positions: Query<&Positio
 mod tests {
     use bevy::app::App;
 
-    use crate::prelude::*;
+    use super::OsculatingElements;
+    use crate::{physics::G, prelude::*};
 
     #[test]
     fn test_update_local() {
@@ -214,4 +295,39 @@ mod tests {
         assert!(min <= moon_length);
         assert!(moon_length <= max)
     }
+
+    #[test]
+    fn test_osculating_elements() {
+        let mut app = App::new();
+        app.add_plugins(ClientPlugin::testing().in_mode(ClientMode::Explorer));
+        app.update();
+        let world = app.world_mut();
+        let mut query = world.query::<(&EllipticalOrbit, &BodyInfo)>();
+        let (orbit, BodyInfo(data)) = query
+            .iter(world)
+            .find(|(_, BodyInfo(data))| data.id == id_from("terre"))
+            .map(|(orbit, info)| (orbit.clone(), info.clone()))
+            .unwrap();
+        let (&Mass(sun_mass), _) = world
+            .query::<(&Mass, &BodyInfo)>()
+            .iter(world)
+            .find(|(_, BodyInfo(data))| data.id == id_from("soleil"))
+            .unwrap();
+        let elements =
+            OsculatingElements::from_state(orbit.local_pos, orbit.local_speed, G * sun_mass);
+        let closeness = |a: f64, b: f64, tolerance: f64| (a - b).abs() <= tolerance;
+        assert!(closeness(
+            elements.semimajor_axis,
+            data.semimajor_axis,
+            1e-2 * data.semimajor_axis
+        ));
+        assert!(closeness(elements.eccentricity, data.eccentricity, 1e-3));
+        assert!(elements.periapsis < elements.apoapsis.unwrap());
+        assert!(closeness(elements.period.unwrap(), data.revolution_period, 1.));
+
+        let escape =
+            OsculatingElements::from_state(orbit.local_pos, 2. * orbit.local_speed, G * sun_mass);
+        assert!(escape.eccentricity > 1.);
+        assert!(escape.apoapsis.is_none() && escape.period.is_none());
+    }
 }
@@ -1,6 +1,18 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
 
-use bevy::{ecs::system::QueryLens, math::DVec3, prelude::*, utils::HashMap};
+use bevy::{
+    ecs::system::QueryLens,
+    math::DVec3,
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task},
+    utils::HashMap,
+};
 
 use crate::{
     objects::{prelude::*, ships::trajectory::ManeuverNode},
@@ -9,24 +21,27 @@ use crate::{
 };
 
 use super::{
+    forces::{ForceContext, ForceModels},
+    harmonics::get_harmonics_acceleration,
     influence::HillRadius,
     leapfrog::{get_acceleration, get_dv, get_dx},
+    orbit::OsculatingElements,
     time::{GAMETIME_PER_SIMTICK, SIMTICKS_PER_TICK},
+    G,
 };
 use crate::objects::orbiting_obj::{OrbitalObjID, OrbitingObjects};
 
-/// Number of client updates between two predictions
-pub const PREDICTIONS_STEP: usize = 20;
+/// Number of simticks propagated between two checks for cancellation, whose predictions are handed over at once
+/// by a [PredictionTask]
+const PREDICTIONS_CHUNK: usize = 500;
 
-/// A component representing identifying a prediction of a ship at a selected time
-#[derive(Component, Clone, Copy)]
-pub struct Prediction {
-    pub ship: Entity,
-    pub index: usize,
-    pub simtick: u64,
-}
+/// Horizon of the predictions when it isn't given in orbits (in days)
+pub const DEFAULT_HORIZON_DAYS: f64 = 10.;
+
+/// Longest horizon the predictions can have, whatever its unit (in days)
+pub const MAX_HORIZON_DAYS: f64 = 1000.;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Default)]
 pub struct PredictionStart {
     pub pos: DVec3,
     pub speed: DVec3,
@@ -34,166 +49,582 @@ pub struct PredictionStart {
     pub simtick: u64,
 }
 
+/// Coordinates of a ship predicted at some simtick
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Prediction {
+    pub simtick: u64,
+    pub pos: DVec3,
+    pub speed: DVec3,
+}
+
+/// How far in the future the predictions go
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PredictionHorizon {
+    /// Game time covered by the predictions (in days)
+    Days(f64),
+    /// Number of revolutions around the main influencer, as given by the osculating orbit of the ship at the start
+    /// of the predictions
+    Orbits(f64),
+}
+
+impl Default for PredictionHorizon {
+    fn default() -> Self {
+        Self::Days(DEFAULT_HORIZON_DAYS)
+    }
+}
+
+impl std::fmt::Display for PredictionHorizon {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Days(days) => write!(f, "{days} days"),
+            Self::Orbits(orbits) => write!(f, "{orbits} orbits"),
+        }
+    }
+}
+
+impl PredictionHorizon {
+    /// Lengthens (or shortens if negative) the horizon by the given number of steps of its unit
+    pub fn add_steps(&mut self, steps: f64) {
+        match self {
+            Self::Days(days) => *days = (*days + steps).clamp(0., MAX_HORIZON_DAYS),
+            Self::Orbits(orbits) => *orbits = (*orbits + 0.25 * steps).max(0.),
+        }
+    }
+
+    /// Switches between days and orbits, going back to the default amount of the unit
+    pub fn cycle_unit(&mut self) {
+        *self = match self {
+            Self::Days(_) => Self::Orbits(1.),
+            Self::Orbits(_) => Self::default(),
+        }
+    }
+
+    /// Number of simticks covered by the predictions of a ship. Open orbits have no period, so each orbit of
+    /// their horizon lasts [DEFAULT_HORIZON_DAYS] instead
+    pub fn simticks(
+        &self,
+        start: &PredictionStart,
+        influence: &Influenced,
+        bodies: &BodiesSnapshot,
+    ) -> usize {
+        let days = match *self {
+            Self::Days(days) => days,
+            Self::Orbits(orbits) => {
+                let period = influence.main_influencer.and_then(|main| {
+                    let (body_pos, body_speed) =
+                        bodies.coordinates(std::iter::once(main), start.simtick)[0];
+                    OsculatingElements::from_state(
+                        start.pos - body_pos,
+                        start.speed - body_speed,
+                        G * bodies.bodies[&main].mass,
+                    )
+                    .period
+                });
+                orbits * period.unwrap_or(DEFAULT_HORIZON_DAYS)
+            }
+        };
+        (days.clamp(0., MAX_HORIZON_DAYS) / GAMETIME_PER_SIMTICK).round() as usize
+    }
+}
+
+/// How the propagated paths are sampled into predictions. The ships are propagated at every simtick, but a
+/// prediction is only kept once their velocity relative to their main influencer changed enough, so that the
+/// predictions are denser where the paths bend or the ships accelerate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sampling {
+    /// Change of the relative velocity between two predictions, as a fraction of its norm (for a constant speed,
+    /// this is about the angle it turns by, in radians)
+    pub max_change: f64,
+    /// Maximum number of simticks between two predictions
+    pub max_spacing: u64,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self {
+            max_change: 0.05,
+            max_spacing: 10 * SIMTICKS_PER_TICK,
+        }
+    }
+}
+
+impl Sampling {
+    /// Keeps a prediction at every simtick
+    pub const EVERY_SIMTICK: Self = Self {
+        max_change: 0.,
+        max_spacing: 1,
+    };
+}
+
+/// The data of a body needed to compute predictions
+#[derive(Clone, Debug)]
+struct PredictedBody {
+    orbit: EllipticalOrbit,
+    host: Option<Entity>,
+    mass: f64,
+    hill_radius: f64,
+    children: Vec<Entity>,
+    harmonics: Option<(ZonalHarmonics, BodyRotation)>,
+}
+
+/// Copy of the bodies used by the predictions, which unlike queries can be moved to another thread
+#[derive(Clone, Debug, Default)]
+pub struct BodiesSnapshot {
+    bodies: HashMap<Entity, PredictedBody>,
+    mapping: HashMap<BodyID, Entity>,
+    /// The root of the system, relative to which the forces are computed
+    primary: Option<Entity>,
+}
+
+impl BodiesSnapshot {
+    pub fn new(
+        bodies: &Query<(Entity, &EllipticalOrbit, &BodyInfo, &HillRadius)>,
+        orbiting: &Query<&OrbitingObjects>,
+        oblate_bodies: &Query<(&ZonalHarmonics, &BodyRotation)>,
+        mapping: &HashMap<BodyID, Entity>,
+    ) -> Self {
+        let bodies = bodies
+            .iter()
+            .map(|(e, orbit, BodyInfo(data), hill_radius)| {
+                let body = PredictedBody {
+                    orbit: orbit.clone(),
+                    host: data.host_body.map(|id| mapping[&id]),
+                    mass: data.mass,
+                    hill_radius: hill_radius.0,
+                    children: children_entities(e, orbiting, mapping),
+                    harmonics: oblate_bodies.get(e).ok().map(|(h, r)| (*h, *r)),
+                };
+                (e, body)
+            })
+            .collect::<HashMap<_, _>>();
+        let primary = bodies
+            .iter()
+            .find(|(_, body)| body.host.is_none())
+            .map(|(e, _)| *e);
+        Self {
+            bodies,
+            mapping: mapping.clone(),
+            primary,
+        }
+    }
+
+    /// Positions and velocities of the selected bodies at the given simtick
+    fn coordinates(
+        &self,
+        selected_bodies: impl Iterator<Item = Entity>,
+        simtick: u64,
+    ) -> Vec<(DVec3, DVec3)> {
+        fn compute_pos_rec(
+            snapshot: &BodiesSnapshot,
+            e: Entity,
+            map: &mut HashMap<Entity, (DVec3, DVec3)>,
+            simtick: u64,
+        ) -> (DVec3, DVec3) {
+            if let Some(coords) = map.get(&e) {
+                return *coords;
+            }
+            let body = &snapshot.bodies[&e];
+            let mut o = body.orbit.clone();
+            o.update_pos(simtick as f64 * GAMETIME_PER_SIMTICK);
+            let (pos, speed) = body.host.map_or((DVec3::ZERO, DVec3::ZERO), |parent| {
+                let (parent_pos, parent_speed) = compute_pos_rec(snapshot, parent, map, simtick);
+                (parent_pos + o.local_pos, parent_speed + o.local_speed)
+            });
+            map.insert(e, (pos, speed));
+            (pos, speed)
+        }
+
+        let mut map = HashMap::new();
+        selected_bodies
+            .map(|e| compute_pos_rec(self, e, &mut map, simtick))
+            .collect()
+    }
+}
+
 impl PredictionStart {
-    /// Compute the future positions of this point with respect to a given referential and considering some influencer's gravitationnal pull on it
-    #[allow(clippy::too_many_arguments)]
+    /// Compute the future positions of this point at every simtick with respect to a given referential and considering some influencer's gravitationnal pull on it
     pub fn compute_predictions(
         &self,
         number: usize,
         influence: &Influenced,
         reference: Option<Entity>,
-        bodies: &mut QueryLens<(&EllipticalOrbit, &BodyInfo, &HillRadius)>,
-        orbiting: &Query<&OrbitingObjects>,
-        mapping: &HashMap<BodyID, Entity>,
+        bodies: &BodiesSnapshot,
+        forces: Option<&ForceModels>,
         nodes: &BTreeMap<u64, ManeuverNode>,
-    ) -> Vec<(DVec3, DVec3)> {
-        let dt = GAMETIME_PER_SIMTICK;
-        let mut bodies = bodies.query();
-        let mut pos = self.pos;
-        let mut speed = self.speed;
-        let mut predictions = Vec::new();
-        let simulated = simulated_from_influence(
+    ) -> Vec<Prediction> {
+        let member = FleetMember {
+            start: self.clone(),
+            influence: influence.clone(),
+            forces: forces.cloned(),
+            nodes: nodes.clone(),
+        };
+        compute_fleet_predictions(
+            &[member],
+            number,
+            reference,
+            bodies,
+            Sampling::EVERY_SIMTICK,
+        )
+        .swap_remove(0)
+    }
+}
+
+/// A ship taking part in a fleet prediction, with its planned maneuvers
+#[derive(Clone, Default)]
+pub struct FleetMember {
+    pub start: PredictionStart,
+    pub influence: Influenced,
+    pub forces: Option<ForceModels>,
+    pub nodes: BTreeMap<u64, ManeuverNode>,
+}
+
+/// A ship being propagated, along with the bodies simulated around it
+struct PredictedShip<'a> {
+    pos: DVec3,
+    speed: DVec3,
+    acc: DVec3,
+    /// Velocity relative to the main influencer at the last prediction kept
+    sampled_speed: DVec3,
+    /// Coordinates and Hill radius of the simulated bodies. Ordered maps are used so that the accelerations are
+    /// always summed in the same order
+    map: BTreeMap<Entity, (DVec3, DVec3, f64)>,
+    influencers: BTreeMap<Entity, f64>,
+    main: Option<Entity>,
+    forces: Option<&'a ForceModels>,
+    nodes: &'a BTreeMap<u64, ManeuverNode>,
+}
+
+impl<'a> PredictedShip<'a> {
+    fn new(member: &'a FleetMember, bodies: &BodiesSnapshot) -> Self {
+        let FleetMember {
+            start,
             influence,
-            orbiting,
-            mapping,
-        );
+            forces,
+            nodes,
+        } = member;
+        let mut simulated = influence.influencers.clone();
+        if let Some(main) = influence.main_influencer {
+            simulated.extend(bodies.bodies[&main].children.iter().copied());
+        }
         let mut map = simulated
             .iter()
-            .map(|e| (*e, (DVec3::ZERO, DVec3::ZERO, bodies.get(*e).unwrap().2 .0)))
-            .collect::<HashMap<_, _>>();
-        let mut influencers = influence
-            .influencers
-            .iter()
-            .map(|e| {
-                let comp = bodies.get(*e).unwrap();
-                (*e, comp.1 .0.mass)
-            })
-            .collect::<HashMap<_, _>>();
-        let mut main = influence.main_influencer;
-        let initial_bodies_coords = get_bodies_coordinates(
-            map.keys().cloned(),
-            &mut bodies.transmute_lens::<(&EllipticalOrbit, &BodyInfo)>(),
-            mapping,
-            self.simtick,
-        );
+            .map(|e| (*e, (DVec3::ZERO, DVec3::ZERO, bodies.bodies[e].hill_radius)))
+            .collect::<BTreeMap<_, _>>();
+        let initial_bodies_coords = bodies.coordinates(map.keys().cloned(), start.simtick);
         map.values_mut()
             .enumerate()
             .for_each(|(i, v)| (v.0, v.1) = initial_bodies_coords[i]);
-        let initial_ref_coords = reference.and_then(|r| map.get(&r).cloned()).unwrap_or((
-            DVec3::ZERO,
-            DVec3::ZERO,
-            f64::INFINITY,
-        ));
-        let mut acc = self.acc;
-        let mut previous_acc;
-        for i in 1..number + 1 {
-            let simtick = self.simtick + i as u64;
-            let bodies_coords = get_bodies_coordinates(
-                map.keys().cloned(),
-                &mut bodies.transmute_lens::<(&EllipticalOrbit, &BodyInfo)>(),
-                mapping,
-                simtick,
-            );
-            map.values_mut()
-                .enumerate()
-                .for_each(|(i, v)| (v.0, v.1) = bodies_coords[i]);
-
-            if simtick % SIMTICKS_PER_TICK == 0 {
-                if let Some(node) = nodes.get(&(simtick / SIMTICKS_PER_TICK)) {
-                    // For now, the origin body must be simulated
-                    if let Some(node_origin) = mapping.get(&node.origin) {
-                        if let Some(&(origin_pos, origin_speed, _)) = map.get(node_origin) {
-                            speed += orbital_to_global_matrix(origin_pos, origin_speed, pos, speed)
-                                * node.thrust;
-                        }
+        let mut ship = Self {
+            pos: start.pos,
+            speed: start.speed,
+            acc: start.acc,
+            sampled_speed: DVec3::ZERO,
+            map,
+            influencers: influence
+                .influencers
+                .iter()
+                .map(|e| (*e, bodies.bodies[e].mass))
+                .collect(),
+            main: influence.main_influencer,
+            forces: forces.as_ref(),
+            nodes,
+        };
+        ship.sampled_speed = ship.relative_speed();
+        ship
+    }
+
+    /// Advances the ship to the given simtick, which must follow the previous one
+    fn step(&mut self, simtick: u64, bodies: &BodiesSnapshot) {
+        let dt = GAMETIME_PER_SIMTICK;
+        let bodies_coords = bodies.coordinates(self.map.keys().cloned(), simtick);
+        self.map
+            .values_mut()
+            .enumerate()
+            .for_each(|(i, v)| (v.0, v.1) = bodies_coords[i]);
+
+        if simtick.is_multiple_of(SIMTICKS_PER_TICK) {
+            if let Some(node) = self.nodes.get(&(simtick / SIMTICKS_PER_TICK)) {
+                // For now, the origin body must be simulated
+                if let Some(node_origin) = bodies.mapping.get(&node.origin) {
+                    if let Some(&(origin_pos, origin_speed, _)) = self.map.get(node_origin) {
+                        self.speed += orbital_to_global_matrix(
+                            origin_pos,
+                            origin_speed,
+                            self.pos,
+                            self.speed,
+                        ) * node.thrust;
                     }
                 }
+            }
 
-                let (new_main, new_radius) = map
-                    .iter()
-                    .filter_map(|(e, (body_pos, _, r))| {
-                        let dist = (*body_pos - pos).length();
-                        if dist > *r {
-                            None
-                        } else {
-                            Some((*e, *r))
-                        }
-                    })
-                    .min_by(|(_, a), (_, b)| a.total_cmp(b))
-                    .unwrap();
-                if let Some(main_entity) = main {
-                    if new_main != main_entity {
-                        let radius = map.get(&main_entity).unwrap().2;
-                        let new_children = children_entities(
-                            new_main,
-                            orbiting,
-                            mapping,
-                        );
-                        map.retain(|k, _| {
-                            influencers.contains_key(k) || [new_main, main_entity].contains(k)
-                        });
-                        map.extend(
-                            new_children.into_iter().map(|e| {
-                                (e, (DVec3::ZERO, DVec3::ZERO, bodies.get(e).unwrap().2 .0))
-                            }),
-                        );
-                        if radius > new_radius {
-                            influencers.insert(new_main, bodies.get(new_main).unwrap().1 .0.mass);
-                        } else {
-                            influencers.remove(&main_entity);
-                        }
-                        main = Some(new_main);
+            let (new_main, new_radius) = self
+                .map
+                .iter()
+                .filter_map(|(e, (body_pos, _, r))| {
+                    let dist = (*body_pos - self.pos).length();
+                    if dist > *r {
+                        None
+                    } else {
+                        Some((*e, *r))
+                    }
+                })
+                .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                .unwrap();
+            if let Some(main_entity) = self.main {
+                if new_main != main_entity {
+                    let radius = self.map.get(&main_entity).unwrap().2;
+                    let influencers = &self.influencers;
+                    self.map.retain(|k, _| {
+                        influencers.contains_key(k) || [new_main, main_entity].contains(k)
+                    });
+                    self.map.extend(
+                        bodies.bodies[&new_main].children.iter().map(|e| {
+                            (*e, (DVec3::ZERO, DVec3::ZERO, bodies.bodies[e].hill_radius))
+                        }),
+                    );
+                    if radius > new_radius {
+                        self.influencers
+                            .insert(new_main, bodies.bodies[&new_main].mass);
+                    } else {
+                        self.influencers.remove(&main_entity);
                     }
+                    self.main = Some(new_main);
                 }
             }
+        }
 
-            pos += get_dx(speed, acc, dt);
-            let ref_coords = reference.and_then(|r| map.get(&r).cloned()).unwrap_or((
-                DVec3::ZERO,
-                DVec3::ZERO,
-                f64::INFINITY,
-            ));
-            previous_acc = acc;
-            acc = get_acceleration(pos, influencers.iter().map(|(e, m)| (map[e].0, *m)));
-            speed += get_dv(previous_acc, acc, dt);
-            predictions.push((
-                pos - ref_coords.0 + initial_ref_coords.0,
-                speed - ref_coords.1,
+        self.pos += get_dx(self.speed, self.acc, dt);
+        let previous_acc = self.acc;
+        let map = &self.map;
+        self.acc = get_acceleration(
+            self.pos,
+            self.influencers.iter().map(|(e, m)| (map[e].0, *m)),
+        ) + get_harmonics_acceleration(
+            self.pos,
+            self.influencers.iter().filter_map(|(e, m)| {
+                bodies.bodies[e]
+                    .harmonics
+                    .as_ref()
+                    .map(|(h, r)| (map[e].0, *m, h, r))
+            }),
+        );
+        if let Some(forces) = self.forces {
+            // Relative to the simulated primary, as in the simulation where it can move
+            let (primary_pos, primary_speed) = self.body_coords(bodies.primary);
+            self.acc += forces.acceleration(&ForceContext::new(
+                self.pos,
+                self.speed,
+                primary_pos,
+                primary_speed,
             ));
         }
-        predictions
+        self.speed += get_dv(previous_acc, self.acc, dt);
+    }
+
+    fn relative_speed(&self) -> DVec3 {
+        self.speed - self.body_coords(self.main).1
+    }
+
+    /// Whether a prediction must be kept at the given simtick, because a node is applied or the velocity changed
+    /// too much since the last prediction
+    fn needs_sample(&self, simtick: u64, sampling: &Sampling) -> bool {
+        (simtick.is_multiple_of(SIMTICKS_PER_TICK)
+            && self.nodes.contains_key(&(simtick / SIMTICKS_PER_TICK)))
+            || (self.relative_speed() - self.sampled_speed).length()
+                > sampling.max_change * self.sampled_speed.length()
+    }
+
+    /// Coordinates of a simulated body, or the origin if it isn't simulated
+    fn body_coords(&self, body: Option<Entity>) -> (DVec3, DVec3) {
+        body.and_then(|b| self.map.get(&b))
+            .map_or((DVec3::ZERO, DVec3::ZERO), |&(pos, speed, _)| (pos, speed))
     }
 }
 
-fn simulated_from_influence(
-    influence: &Influenced,
-    bodies: &Query<&OrbitingObjects>,
-    bodies_mapping: &HashMap<BodyID, Entity>,
-) -> Vec<Entity> {
-    let mut v = influence.influencers.clone();
-    if let Some(main) = influence.main_influencer {
-        v.extend(children_entities(main, bodies, bodies_mapping));
+/// Predictions of several ships propagated in lockstep from the simtick of the first one, each of them being
+/// given relative to the reference as seen from the first ship. The predictions of all the ships are kept at the
+/// same simticks, the ships starting after the first one being held until their start and only predicted from
+/// there
+pub fn compute_fleet_predictions(
+    members: &[FleetMember],
+    number: usize,
+    reference: Option<Entity>,
+    bodies: &BodiesSnapshot,
+    sampling: Sampling,
+) -> Vec<Vec<Prediction>> {
+    let mut predictions: Vec<_> = members.iter().map(|_| Vec::new()).collect();
+    stream_fleet_predictions(members, number, reference, bodies, sampling, |chunk| {
+        for (path, new) in predictions.iter_mut().zip(chunk) {
+            path.extend(new);
+        }
+        true
+    });
+    predictions
+}
+
+/// Computes the predictions of a fleet over `number` simticks, handing them over to `send` in chunks holding the
+/// new predictions of each ship. The computation stops as soon as `send` returns false
+pub fn stream_fleet_predictions(
+    members: &[FleetMember],
+    number: usize,
+    reference: Option<Entity>,
+    bodies: &BodiesSnapshot,
+    sampling: Sampling,
+    mut send: impl FnMut(Vec<Vec<Prediction>>) -> bool,
+) {
+    let Some(first) = members.first() else {
+        return;
+    };
+    let new_chunk = || -> Vec<Vec<Prediction>> { members.iter().map(|_| Vec::new()).collect() };
+    let mut ships: Vec<_> = members
+        .iter()
+        .map(|m| PredictedShip::new(m, bodies))
+        .collect();
+    let (initial_ref_pos, _) = ships[0].body_coords(reference);
+    let mut chunk = new_chunk();
+    let mut last_sample = first.start.simtick;
+    for i in 1..number as u64 + 1 {
+        let simtick = first.start.simtick + i;
+        let started = |m: &FleetMember| m.start.simtick < simtick;
+        for (ship, _) in ships.iter_mut().zip(members).filter(|(_, m)| started(m)) {
+            ship.step(simtick, bodies);
+        }
+        // The last prediction is always kept, so that the whole horizon is covered
+        if i == number as u64
+            || simtick - last_sample >= sampling.max_spacing
+            || ships
+                .iter()
+                .zip(members)
+                .any(|(s, m)| started(m) && s.needs_sample(simtick, &sampling))
+        {
+            last_sample = simtick;
+            let (ref_pos, ref_speed) = ships[0].body_coords(reference);
+            for ((ship, predictions), _) in ships
+                .iter_mut()
+                .zip(&mut chunk)
+                .zip(members)
+                .filter(|(_, m)| started(m))
+            {
+                ship.sampled_speed = ship.relative_speed();
+                predictions.push(Prediction {
+                    simtick,
+                    pos: ship.pos - ref_pos + initial_ref_pos,
+                    speed: ship.speed - ref_speed,
+                });
+            }
+        }
+        if i.is_multiple_of(PREDICTIONS_CHUNK as u64)
+            && !send(std::mem::replace(&mut chunk, new_chunk()))
+        {
+            return;
+        }
+    }
+    if !chunk[0].is_empty() {
+        send(chunk);
+    }
+}
+
+/// Predictions of two ships predicted together at the same simticks, the second one possibly starting later
+pub fn aligned<'a>(
+    a: &'a [Prediction],
+    b: &'a [Prediction],
+) -> impl Iterator<Item = (&'a Prediction, &'a Prediction)> {
+    let start = b.first().map_or(u64::MAX, |p| p.simtick);
+    a.iter().skip_while(move |p| p.simtick < start).zip(b)
+}
+
+/// Simtick at which two ships predicted together are the closest, along with their distance
+pub fn closest_approach(a: &[Prediction], b: &[Prediction]) -> Option<(u64, f64)> {
+    aligned(a, b)
+        .map(|(a, b)| (a.simtick, a.pos.distance(b.pos)))
+        .min_by(|(_, x), (_, y)| x.total_cmp(y))
+}
+
+/// State shared between a [PredictionTask] and the thread computing its predictions
+#[derive(Default)]
+struct PredictionProgress {
+    /// Predictions of each ship not yet received
+    received: Mutex<Vec<Vec<Prediction>>>,
+    finished: AtomicBool,
+    cancelled: AtomicBool,
+}
+
+/// Predictions computed in the background by the [AsyncComputeTaskPool], which can be received while they are
+/// being computed. Dropping the task cancels the computation
+pub struct PredictionTask {
+    progress: Arc<PredictionProgress>,
+    _task: Task<()>,
+}
+
+impl PredictionTask {
+    /// Starts predicting the ships of the fleet, see [stream_fleet_predictions]
+    pub fn spawn(
+        members: Vec<FleetMember>,
+        number: usize,
+        reference: Option<Entity>,
+        bodies: BodiesSnapshot,
+        sampling: Sampling,
+    ) -> Self {
+        let progress = Arc::new(PredictionProgress {
+            received: Mutex::new(vec![Vec::new(); members.len()]),
+            ..Default::default()
+        });
+        let shared = progress.clone();
+        let task = AsyncComputeTaskPool::get().spawn(async move {
+            stream_fleet_predictions(&members, number, reference, &bodies, sampling, |chunk| {
+                let mut received = shared.received.lock().unwrap();
+                for (predictions, new) in received.iter_mut().zip(chunk) {
+                    predictions.extend(new);
+                }
+                !shared.cancelled.load(Ordering::Relaxed)
+            });
+            shared.finished.store(true, Ordering::Release);
+        });
+        Self {
+            progress,
+            _task: task,
+        }
+    }
+
+    /// Appends the predictions of each ship computed since the last call, returning whether all of them were
+    /// computed
+    pub fn receive(&self, predictions: &mut [Vec<Prediction>]) -> bool {
+        let finished = self.progress.finished.load(Ordering::Acquire);
+        let mut received = self.progress.received.lock().unwrap();
+        for (predictions, new) in predictions.iter_mut().zip(received.iter_mut()) {
+            predictions.append(new);
+        }
+        finished
+    }
+}
+
+impl Drop for PredictionTask {
+    fn drop(&mut self) {
+        self.progress.cancelled.store(true, Ordering::Relaxed);
     }
-    v
 }
 
 fn children_entities(
     parent: Entity,
     objects: &Query<&OrbitingObjects>,
     bodies_mapping: &HashMap<BodyID, Entity>,
-    ) -> Vec<Entity> {
-        let orbiting = match objects.get(parent) {
-            Ok(o) => o,
-            Err(_) => return vec![],
-        };
+) -> Vec<Entity> {
+    let orbiting = match objects.get(parent) {
+        Ok(o) => o,
+        Err(_) => return vec![],
+    };
 
-        orbiting.0.iter().filter_map(|orbital_obj| {
+    orbiting
+        .0
+        .iter()
+        .filter_map(|orbital_obj| {
             let id = match orbital_obj {
                 OrbitalObjID::Body(body_id) => body_id,
                 OrbitalObjID::Ship(ship_id) => ship_id,
             };
             bodies_mapping.get(id).cloned()
-        }).collect()
+        })
+        .collect()
 }
 
 pub fn get_bodies_coordinates(
@@ -237,7 +668,12 @@ mod tests {
     use bevy::{ecs::system::SystemState, prelude::*};
 
     use crate::{
-        physics::leapfrog::get_acceleration, prelude::*, utils::algebra::circular_orbit_around_body,
+        physics::{
+            forces::{SailAttitude, SolarSail},
+            leapfrog::get_acceleration,
+        },
+        prelude::*,
+        utils::algebra::circular_orbit_around_body,
     };
 
     use super::*;
@@ -264,27 +700,40 @@ mod tests {
         #[allow(clippy::type_complexity)]
         let mut system_state: SystemState<(
             Res<BodiesMapping>,
-            Query<(&EllipticalOrbit, &BodyInfo, &HillRadius)>,
+            Query<(Entity, &EllipticalOrbit, &BodyInfo, &HillRadius)>,
             Query<(&Position, &Mass)>,
             Query<&OrbitingObjects>,
+            Query<(&ZonalHarmonics, &BodyRotation)>,
         )> = SystemState::new(world);
-        let (mapping, mut bodies, query, orbiting) = system_state.get(world);
-        let predictions = PredictionStart {
+        let (mapping, bodies, query, orbiting, oblate_bodies) = system_state.get(world);
+        let bodies = BodiesSnapshot::new(&bodies, &orbiting, &oblate_bodies, &mapping.0);
+        let start = PredictionStart {
             pos,
             speed,
             simtick: 0,
             acc: get_acceleration(pos, query.iter_many(&influencers).map(|(p, m)| (p.0, m.0))),
-        }
-        .compute_predictions(
+        };
+        let predictions =
+            start.compute_predictions(3, &influence, Some(earth), &bodies, None, &BTreeMap::new());
+        // A sail facing the sun pushes the predictions away from it
+        let sail = ForceModels::default().with(SolarSail {
+            area: 1e6,
+            mass: 1e3,
+            reflectivity: 1.,
+            attitude: SailAttitude::FaceSun,
+        });
+        let pushed = start.compute_predictions(
             3,
             &influence,
             Some(earth),
-            &mut bodies.as_query_lens(),
-            &orbiting,
-            &mapping.0,
+            &bodies,
+            Some(&sail),
             &BTreeMap::new(),
         );
-        for (i, (p, _)) in predictions.into_iter().enumerate() {
+        let drift = pushed[2].pos - predictions[2].pos;
+        assert!(drift.length() > 0.);
+        assert!(drift.normalize().dot(pos.normalize()) > 0.99);
+        for (i, Prediction { pos: p, .. }) in predictions.into_iter().enumerate() {
             // dbg!(p);
             // dbg!(pos + (i + 1) as f64 * (speed - earth_speed.0) * GAMETIME_PER_SIMTICK);
             assert!(
@@ -293,5 +742,124 @@ mod tests {
                     <= 5e4
             );
         }
+
+        // The same predictions are received from the background task, whatever the chunks they come in
+        let number = 2 * PREDICTIONS_CHUNK + 1;
+        let expected = start.compute_predictions(
+            number,
+            &influence,
+            Some(earth),
+            &bodies,
+            None,
+            &BTreeMap::new(),
+        );
+        let member = FleetMember {
+            start,
+            influence,
+            ..Default::default()
+        };
+        let task = PredictionTask::spawn(
+            vec![member.clone()],
+            number,
+            Some(earth),
+            bodies.clone(),
+            Sampling::EVERY_SIMTICK,
+        );
+        let mut received = [Vec::new()];
+        while !task.receive(&mut received) {
+            std::thread::yield_now();
+        }
+        assert_eq!(received[0], expected);
+
+        // Ships propagated in lockstep follow the same paths as when they are predicted alone
+        let burn = FleetMember {
+            nodes: BTreeMap::from([(
+                2,
+                ManeuverNode {
+                    name: "Burn".into(),
+                    thrust: DVec3::new(1e3, 0., 0.),
+                    origin: id_from("terre"),
+                },
+            )]),
+            ..member.clone()
+        };
+        let fleet = compute_fleet_predictions(
+            &[member.clone(), burn.clone()],
+            100,
+            Some(earth),
+            &bodies,
+            Sampling::EVERY_SIMTICK,
+        );
+        assert_eq!(fleet[0][..], expected[..100]);
+        let alone = burn.start.compute_predictions(
+            100,
+            &burn.influence,
+            Some(earth),
+            &bodies,
+            None,
+            &burn.nodes,
+        );
+        assert_eq!(fleet[1], alone);
+        // The ships split up at the burn
+        let (simtick, distance) = closest_approach(&fleet[0], &fleet[1]).unwrap();
+        assert!(simtick < 20 && distance == 0.);
+        assert!(fleet[0][99].pos.distance(fleet[1][99].pos) > 0.);
+
+        // A ship starting later is held until its start, and then follows the same path as when it is alone
+        let late = FleetMember {
+            start: PredictionStart {
+                simtick: 10,
+                ..member.start
+            },
+            ..member.clone()
+        };
+        let fleet = compute_fleet_predictions(
+            &[member.clone(), late.clone()],
+            100,
+            None,
+            &bodies,
+            Sampling::EVERY_SIMTICK,
+        );
+        let alone =
+            late.start
+                .compute_predictions(90, &late.influence, None, &bodies, None, &late.nodes);
+        assert_eq!(fleet[1], alone);
+        assert_eq!(fleet[1][0].simtick, 11);
+        assert!(aligned(&fleet[0], &fleet[1]).all(|(a, b)| a.simtick == b.simtick));
+        assert!(closest_approach(&fleet[0], &fleet[1]).unwrap().0 > 10);
+
+        // Adaptive sampling keeps fewer predictions, at the node and at the end of the horizon
+        let dense = burn.start.compute_predictions(
+            number,
+            &burn.influence,
+            Some(earth),
+            &bodies,
+            None,
+            &burn.nodes,
+        );
+        let sampled = compute_fleet_predictions(
+            &[burn.clone()],
+            number,
+            Some(earth),
+            &bodies,
+            Sampling::default(),
+        )
+        .swap_remove(0);
+        assert!(sampled.len() < dense.len() / 2);
+        assert!(sampled.iter().any(|p| p.simtick == 20));
+        assert_eq!(sampled.last().unwrap().simtick, number as u64);
+        for p in &sampled {
+            assert_eq!(*p, dense[p.simtick as usize - 1]);
+        }
+
+        // The horizon of a circular orbit matches its period
+        let period = 2. * std::f64::consts::PI * (1e15 / (G * mass.0)).sqrt();
+        let simticks =
+            PredictionHorizon::Orbits(2.).simticks(&member.start, &member.influence, &bodies);
+        assert!((simticks as f64 * GAMETIME_PER_SIMTICK - 2. * period).abs() < 1e-2 * period);
+        assert_eq!(
+            PredictionHorizon::Days(1.).simticks(&member.start, &member.influence, &bodies),
+            1000
+        );
     }
 }
@@ -0,0 +1,288 @@
+//! Spin state of the bodies, and conversions between surface coordinates
+//! (latitude, longitude, altitude) and coordinates in the inertial frame.
+use std::f64::consts::TAU;
+
+use bevy::{
+    math::{DQuat, DVec3},
+    prelude::*,
+};
+
+use crate::{game::Loaded, objects::prelude::*};
+
+use super::{
+    orbit::OrbitsUpdate,
+    time::{GameTime, GAMETIME_PER_SIMTICK},
+    SECONDS_PER_DAY,
+};
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(OnEnter(Loaded), update_rotation.in_set(OrbitsUpdate))
+        .add_systems(FixedUpdate, update_rotation.in_set(OrbitsUpdate));
+}
+
+/// Rotation of a body around its own axis.
+///
+/// The rotation axis is obtained by tilting the normal of the orbital plane of the body around the line of nodes,
+/// and the prime meridian points towards the ascending node at time 0.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+pub struct BodyRotation {
+    /// Sidereal rotation period (in days), negative for a retrograde rotation and zero if the body doesn't spin
+    pub period: f64,
+    /// Angle between the rotation axis and the normal of the orbital plane (in radians)
+    pub axial_tilt: f64,
+    /// Orientation of the orbital plane, its X axis pointing towards the ascending node and its Z axis along the
+    /// normal of the plane
+    pub orbital_plane: DQuat,
+    /// Current angle of the prime meridian around the rotation axis (in radians)
+    pub angle: f64,
+}
+
+impl From<&BodyData> for BodyRotation {
+    fn from(data: &BodyData) -> Self {
+        Self {
+            period: data.rotation_period * 3600. / SECONDS_PER_DAY,
+            axial_tilt: data.axial_tilt.to_radians(),
+            orbital_plane: DQuat::from_rotation_z(data.long_asc_node.to_radians())
+                * DQuat::from_rotation_x(data.inclination.to_radians()),
+            angle: 0.,
+        }
+    }
+}
+
+/// Position on the surface of a body, angles are in degrees and the altitude is in kilometers
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SurfaceCoordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f64,
+}
+
+impl BodyRotation {
+    /// Angle of the prime meridian at the given game time (in days)
+    pub fn angle_at(&self, time: f64) -> f64 {
+        if self.period == 0. {
+            return 0.;
+        }
+        (TAU * time / self.period).rem_euclid(TAU)
+    }
+
+    pub fn update(&mut self, time: f64) {
+        self.angle = self.angle_at(time);
+    }
+
+    /// Rotation from the body-fixed frame to the inertial frame for a given angle of the prime meridian
+    pub fn orientation(&self, angle: f64) -> DQuat {
+        self.orbital_plane * DQuat::from_rotation_x(self.axial_tilt) * DQuat::from_rotation_z(angle)
+    }
+
+    /// Unit vector along the rotation axis (north pole direction)
+    pub fn axis(&self) -> DVec3 {
+        self.orbital_plane * DQuat::from_rotation_x(self.axial_tilt) * DVec3::Z
+    }
+
+    /// Angular velocity vector (in radians per day)
+    pub fn angular_velocity(&self) -> DVec3 {
+        if self.period == 0. {
+            DVec3::ZERO
+        } else {
+            self.axis() * TAU / self.period
+        }
+    }
+
+    /// Position relative to the body center of a point on its surface, using the current angle
+    pub fn surface_to_local(&self, coords: SurfaceCoordinates, radius: f64) -> DVec3 {
+        self.surface_to_local_at(coords, radius, self.angle)
+    }
+
+    pub fn surface_to_local_at(
+        &self,
+        coords: SurfaceCoordinates,
+        radius: f64,
+        angle: f64,
+    ) -> DVec3 {
+        let (lat, lon) = (coords.latitude.to_radians(), coords.longitude.to_radians());
+        let fixed = DVec3::new(lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin())
+            * (radius + coords.altitude);
+        self.orientation(angle) * fixed
+    }
+
+    /// Surface coordinates of a point given by its position relative to the body center, using the current angle
+    pub fn local_to_surface(&self, local_pos: DVec3, radius: f64) -> SurfaceCoordinates {
+        self.local_to_surface_at(local_pos, radius, self.angle)
+    }
+
+    pub fn local_to_surface_at(
+        &self,
+        local_pos: DVec3,
+        radius: f64,
+        angle: f64,
+    ) -> SurfaceCoordinates {
+        let fixed = self.orientation(angle).inverse() * local_pos;
+        let dist = fixed.length();
+        SurfaceCoordinates {
+            latitude: (fixed.z / dist).clamp(-1., 1.).asin().to_degrees(),
+            longitude: fixed.y.atan2(fixed.x).to_degrees(),
+            altitude: dist - radius,
+        }
+    }
+
+    /// Velocity relative to the body center of a point fixed with respect to its surface
+    pub fn surface_velocity(&self, local_pos: DVec3) -> DVec3 {
+        self.angular_velocity().cross(local_pos)
+    }
+}
+
+/// A point on the surface of a body from which ships can be launched
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LaunchSite {
+    pub body: BodyID,
+    pub coords: SurfaceCoordinates,
+}
+
+impl LaunchSite {
+    /// Global position and velocity of a ship standing at this launch site,
+    /// given the global coordinates, radius and rotation of the body
+    pub fn spawn_coordinates(
+        &self,
+        rotation: &BodyRotation,
+        radius: f64,
+        body_pos: DVec3,
+        body_speed: DVec3,
+    ) -> (DVec3, DVec3) {
+        let local_pos = rotation.surface_to_local(self.coords, radius);
+        (
+            body_pos + local_pos,
+            body_speed + rotation.surface_velocity(local_pos),
+        )
+    }
+}
+
+/// Computes the ground track of an object from its positions relative to the body center at the given simticks
+pub fn ground_track(
+    rotation: &BodyRotation,
+    radius: f64,
+    positions: impl IntoIterator<Item = (u64, DVec3)>,
+) -> Vec<SurfaceCoordinates> {
+    positions
+        .into_iter()
+        .map(|(simtick, local_pos)| {
+            let angle = rotation.angle_at(simtick as f64 * GAMETIME_PER_SIMTICK);
+            rotation.local_to_surface_at(local_pos, radius, angle)
+        })
+        .collect()
+}
+
+fn update_rotation(mut rotations: Query<&mut BodyRotation>, time: Res<GameTime>) {
+    rotations
+        .par_iter_mut()
+        .for_each(|mut r| r.update(time.time()));
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{app::App, math::DVec3};
+
+    use super::*;
+    use crate::prelude::*;
+
+    fn earth_rotation() -> BodyRotation {
+        BodyRotation {
+            period: 23.9345 / 24.,
+            axial_tilt: 23.4393_f64.to_radians(),
+            orbital_plane: DQuat::IDENTITY,
+            angle: 1.,
+        }
+    }
+
+    #[test]
+    fn test_tilt_from_orbital_plane() {
+        let data = BodyData {
+            inclination: 30.,
+            long_asc_node: 90.,
+            axial_tilt: 0.,
+            ..default()
+        };
+        let rotation = BodyRotation::from(&data);
+        // Without tilt, the axis is the normal of the orbit
+        let (i, node) = (30_f64.to_radians(), DVec3::Y);
+        let normal = DVec3::new(i.sin(), 0., i.cos());
+        assert!((rotation.axis() - normal).length() < 1e-12);
+        assert!(rotation.axis().dot(node).abs() < 1e-12);
+        let tilted = BodyRotation::from(&BodyData {
+            axial_tilt: 10.,
+            ..data
+        });
+        assert!((tilted.axis().angle_between(normal) - 10_f64.to_radians()).abs() < 1e-12);
+        // The tilt is towards the orbital plane, around the line of nodes
+        assert!(tilted.axis().dot(node).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_surface_conversion() {
+        let rotation = earth_rotation();
+        let coords = SurfaceCoordinates {
+            latitude: 43.3,
+            longitude: 5.4,
+            altitude: 10.,
+        };
+        let local = rotation.surface_to_local(coords, 6371.);
+        assert!((local.length() - 6381.).abs() < 1e-6);
+        let back = rotation.local_to_surface(local, 6371.);
+        assert!((back.latitude - coords.latitude).abs() < 1e-9);
+        assert!((back.longitude - coords.longitude).abs() < 1e-9);
+        assert!((back.altitude - coords.altitude).abs() < 1e-6);
+        // The north pole lies on the rotation axis whatever the angle
+        let pole = rotation.surface_to_local(
+            SurfaceCoordinates {
+                latitude: 90.,
+                ..default()
+            },
+            1.,
+        );
+        assert!((pole - rotation.axis()).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_launch_site() {
+        let rotation = earth_rotation();
+        let site = LaunchSite {
+            body: id_from("terre"),
+            coords: SurfaceCoordinates::default(),
+        };
+        let (pos, speed) = site.spawn_coordinates(&rotation, 6371., DVec3::ZERO, DVec3::ZERO);
+        // Equatorial rotation speed of the earth is about 465 m/s
+        assert!((speed.length() / SECONDS_PER_DAY - 0.465).abs() < 1e-3);
+        assert!(pos.dot(speed).abs() < 1e-6);
+        // After a full rotation, the site is back at the same place
+        let track = ground_track(
+            &BodyRotation {
+                angle: 0.,
+                ..rotation
+            },
+            6371.,
+            [
+                (0, pos),
+                ((rotation.period / GAMETIME_PER_SIMTICK) as u64, pos),
+            ],
+        );
+        assert!((track[0].longitude - track[1].longitude).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_update_rotation() {
+        let mut app = App::new();
+        app.add_plugins(ClientPlugin::testing().in_mode(ClientMode::Explorer));
+        app.update();
+        app.world_mut().resource_mut::<GameTime>().simtick = 250;
+        app.world_mut().run_schedule(FixedUpdate);
+        let world = app.world_mut();
+        let (rotation, _) = world
+            .query::<(&BodyRotation, &BodyInfo)>()
+            .iter(world)
+            .find(|(_, BodyInfo(data))| data.id == id_from("terre"))
+            .unwrap();
+        let time = world.resource::<GameTime>().time();
+        assert!((rotation.angle - rotation.angle_at(time)).abs() < 1e-12);
+        assert!(rotation.angle > 0.);
+    }
+}
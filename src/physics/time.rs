@@ -1,7 +1,10 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::utils::Direction2;
 
+use super::warp::WarpTarget;
+
 /// Number of server updates (ticks) per real time second
 // pub const TPS: f32 = 1.; comment
 /// Number of simulation updates (simticks) per real time second
@@ -21,7 +24,7 @@ pub fn plugin(app: &mut App) {
         .add_event::<TickEvent>()
         .add_systems(
             FixedUpdate,
-            (update_simtick, update_tick).in_set(TimeUpdate),
+            (update_simtick, update_tick).chain().in_set(TimeUpdate),
         )
         .add_systems(Update, handle_time_events);
 }
@@ -61,7 +64,7 @@ impl Default for SimStepSize {
 #[derive(Event, Default)]
 pub struct TickEvent;
 
-#[derive(Event, Clone, Copy)]
+#[derive(Event, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum TimeEvent {
     /// Change the number of simticks that are simulated per update.
     ///
@@ -72,6 +75,11 @@ pub enum TimeEvent {
     /// This does not change simulation outcome, but leads to heavier CPU load.
     ChangeUpdateRate(Direction2),
     ToggleTime,
+    /// Warp to the target, choosing the step size until it is reached.
+    ///
+    /// **THIS CAN CHANGE SIMULATION OUTCOME**
+    WarpTo(WarpTarget),
+    StopWarp,
 }
 
 fn update_tick(mut writer: EventWriter<TickEvent>, game_time: Res<GameTime>) {
@@ -105,6 +113,8 @@ fn handle_time_events(
                 Direction2::Down => step_size.0 /= 2,
             },
             ToggleTime => toggle_time.0 = !toggle_time.0,
+            // Handled by the warp
+            WarpTo(_) | StopWarp => (),
         }
     }
 }
@@ -0,0 +1,645 @@
+//! Warping to a target tick or event.
+//!
+//! While warping, the step size is set to the largest one keeping the trajectories of the ships
+//! accurate, then lowered when approaching the target so that it is reached at 1x.
+//! The steps are kept on ticks whenever possible, so that the influence, the autopilots and the
+//! maneuver nodes are still updated, and the nodes of every ship are landed on exactly.
+
+use std::f64::consts::{PI, TAU};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game::GameStage,
+    objects::{
+        prelude::*,
+        ships::{scheduler::ShipSchedule, trajectory::TrajectoryStore},
+    },
+};
+
+use super::{
+    influence::{HillRadius, Influenced},
+    orbit::OsculatingElements,
+    time::{
+        update_simtick, GameTime, SimStepSize, TimeEvent, TimeUpdate, ToggleTime,
+        GAMETIME_PER_SIMTICK, SIMTICKS_PER_TICK, STPS,
+    },
+    Position, Velocity, G,
+};
+
+/// The step is at most the remaining simticks to the target divided by this, so that it is approached smoothly
+pub const WARP_APPROACH: u64 = 8;
+
+/// Largest step while warping, in simticks
+pub const MAX_WARP_STEP: u64 = SIMTICKS_PER_TICK << 10;
+
+/// Number of steps per radian of the orbits of the ships, using their dynamical time at periapsis
+const STEPS_PER_RADIAN: f64 = 50.;
+
+/// The warp drops back to 1x when the target is less than a real second away
+const SLOWDOWN_SIMTICKS: u64 = STPS as u64;
+
+pub fn plugin(app: &mut App) {
+    app.add_event::<WarpEnd>()
+        .add_systems(Update, handle_warp_events)
+        .add_systems(
+            FixedUpdate,
+            update_warp.in_set(TimeUpdate).before(update_simtick),
+        );
+}
+
+/// What a warp goes to
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WarpTarget {
+    Tick(u64),
+    /// Elapsed game time in days
+    Date(f64),
+    /// The next maneuver node of the ship
+    NextNode(ShipID),
+    /// The next change of main influencer of the ship
+    SoiChange(ShipID),
+    Periapsis(ShipID),
+    Apoapsis(ShipID),
+}
+
+impl std::fmt::Display for WarpTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WarpTarget::Tick(tick) => write!(f, "tick {tick}"),
+            WarpTarget::Date(date) => write!(f, "day {date}"),
+            WarpTarget::NextNode(ship) => write!(f, "the next node of {ship}"),
+            WarpTarget::SoiChange(ship) => write!(f, "the next SOI change of {ship}"),
+            WarpTarget::Periapsis(ship) => write!(f, "the periapsis of {ship}"),
+            WarpTarget::Apoapsis(ship) => write!(f, "the apoapsis of {ship}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WarpError {
+    UnknownShip(ShipID),
+    /// The target is already past
+    Past,
+    NoNode(ShipID),
+    NoHost(ShipID),
+    /// The apoapsis of an open orbit, or the periapsis once it is passed
+    NoApsis(ShipID),
+}
+
+impl std::fmt::Display for WarpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WarpError::UnknownShip(id) => write!(f, "Unknown ship \"{id}\""),
+            WarpError::Past => write!(f, "The target is already past"),
+            WarpError::NoNode(id) => write!(f, "{id} has no upcoming maneuver node"),
+            WarpError::NoHost(id) => write!(f, "{id} has no main influencer"),
+            WarpError::NoApsis(id) => write!(f, "The orbit of {id} doesn't reach it"),
+        }
+    }
+}
+
+impl std::error::Error for WarpError {}
+
+/// Sent when a warp ends, with the reason
+#[derive(Event, Debug, Clone, PartialEq)]
+pub enum WarpEnd {
+    Reached(WarpTarget),
+    Stopped,
+    Failed(WarpError),
+    /// An action of the schedule of the ship is executed
+    ScheduledAction(ShipID),
+    /// The ship is about to crash into the body
+    Collision {
+        ship: ShipID,
+        body: BodyID,
+    },
+}
+
+impl std::fmt::Display for WarpEnd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WarpEnd::Reached(target) => write!(f, "Warped to {target}"),
+            WarpEnd::Stopped => write!(f, "Warp stopped"),
+            WarpEnd::Failed(e) => write!(f, "Can't warp: {e}"),
+            WarpEnd::ScheduledAction(ship) => {
+                write!(f, "Warp stopped for a scheduled action of {ship}")
+            }
+            WarpEnd::Collision { ship, body } => {
+                write!(f, "Warp stopped, {ship} is about to crash into {body}")
+            }
+        }
+    }
+}
+
+/// The ongoing warp
+#[derive(Resource, Debug, Clone)]
+pub struct Warp {
+    pub target: WarpTarget,
+    /// Simtick of the target, when it is known in advance
+    end: Option<u64>,
+    /// Ship awaiting a change of main influencer, with its main influencer when the warp started
+    soi: Option<(ShipID, Entity, Entity)>,
+    /// Next scheduled action and its tick, remembered as the schedules drop the actions they execute
+    action: Option<(ShipID, u64)>,
+    /// Step size restored at the end of the warp
+    previous_step: u64,
+}
+
+/// Orbit of a ship around its main influencer
+struct ShipOrbit {
+    host: Entity,
+    elements: OsculatingElements,
+    mu: f64,
+    distance: f64,
+}
+
+impl ShipOrbit {
+    /// Days until the true anomaly (in radians) is reached, if it is ever reached
+    fn time_to_anomaly(&self, anomaly: f64) -> Option<f64> {
+        let e = self.elements.eccentricity;
+        let a = self.elements.semimajor_axis.abs();
+        let mean_motion = (self.mu / a.powi(3)).sqrt();
+        let current = mean_anomaly(e, self.elements.true_anomaly.to_radians());
+        let target = mean_anomaly(e, anomaly);
+        let delta = if e < 1. {
+            (target - current).rem_euclid(TAU)
+        } else {
+            target - current
+        };
+        (delta.is_finite() && delta >= 0.).then_some(delta / mean_motion)
+    }
+
+    /// Days until the distance to the host reaches the radius, going outwards or inwards
+    fn time_to_radius(&self, radius: f64, outwards: bool) -> Option<f64> {
+        let e = self.elements.eccentricity;
+        let semilatus_rectum = self.elements.periapsis * (1. + e);
+        let cos = (semilatus_rectum / radius - 1.) / e;
+        if !(-1. ..=1.).contains(&cos) {
+            return None;
+        }
+        let anomaly = cos.acos();
+        self.time_to_anomaly(if outwards { anomaly } else { -anomaly })
+    }
+
+    /// Largest step keeping the trajectory accurate, in simticks
+    fn safe_step(&self) -> u64 {
+        let r = self.distance.min(self.elements.periapsis);
+        let dynamical_time = (r.powi(3) / self.mu).sqrt();
+        (dynamical_time / STEPS_PER_RADIAN / GAMETIME_PER_SIMTICK) as u64
+    }
+}
+
+/// Mean anomaly at the true anomaly (in radians), for closed and open orbits
+fn mean_anomaly(e: f64, true_anomaly: f64) -> f64 {
+    let nu = if true_anomaly > PI {
+        true_anomaly - TAU
+    } else {
+        true_anomaly
+    };
+    let half_tan = (nu / 2.).tan();
+    if e < 1. {
+        let eccentric = 2. * (((1. - e) / (1. + e)).sqrt() * half_tan).atan();
+        eccentric - e * eccentric.sin()
+    } else {
+        let hyperbolic = 2. * (((e - 1.) / (e + 1.)).sqrt() * half_tan).atanh();
+        e * hyperbolic.sinh() - hyperbolic
+    }
+}
+
+#[derive(SystemParam)]
+struct Orbits<'w, 's> {
+    ships: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static ShipInfo,
+            &'static Position,
+            &'static Velocity,
+            Option<&'static Influenced>,
+        ),
+    >,
+    bodies: Query<
+        'w,
+        's,
+        (
+            &'static BodyInfo,
+            &'static Position,
+            &'static Velocity,
+            &'static HillRadius,
+        ),
+    >,
+}
+
+impl Orbits<'_, '_> {
+    fn get(&self, ship: Entity) -> Option<ShipOrbit> {
+        let (_, _, Position(pos), Velocity(speed), influenced) = self.ships.get(ship).ok()?;
+        let host = influenced?.main_influencer?;
+        let (BodyInfo(data), Position(host_pos), Velocity(host_speed), _) =
+            self.bodies.get(host).ok()?;
+        let mu = G * data.mass;
+        Some(ShipOrbit {
+            host,
+            elements: OsculatingElements::from_state(*pos - *host_pos, *speed - *host_speed, mu),
+            mu,
+            distance: pos.distance(*host_pos),
+        })
+    }
+}
+
+/// Simtick in `days` from now
+fn simtick_in(time: &GameTime, days: f64) -> u64 {
+    time.simtick + (days / GAMETIME_PER_SIMTICK).round() as u64
+}
+
+fn resolve_target(
+    target: WarpTarget,
+    time: &GameTime,
+    mapping: Option<&ShipsMapping>,
+    store: Option<&TrajectoryStore>,
+    orbits: &Orbits,
+) -> Result<Warp, WarpError> {
+    let ship_entity = |id: ShipID| {
+        mapping
+            .and_then(|m| m.0.get(&id).copied())
+            .ok_or(WarpError::UnknownShip(id))
+    };
+    let orbit =
+        |id: ShipID| ship_entity(id).and_then(|e| orbits.get(e).ok_or(WarpError::NoHost(id)));
+    let mut soi = None;
+    let end = match target {
+        WarpTarget::Tick(tick) => Some(tick * SIMTICKS_PER_TICK),
+        WarpTarget::Date(date) => Some((date / GAMETIME_PER_SIMTICK).round() as u64),
+        WarpTarget::NextNode(id) => {
+            ship_entity(id)?;
+            let tick = store
+                .and_then(|s| s.get(&id))
+                .and_then(|t| t.nodes.range(time.tick() + 1..).next())
+                .map(|(&tick, _)| tick)
+                .ok_or(WarpError::NoNode(id))?;
+            Some(tick * SIMTICKS_PER_TICK)
+        }
+        WarpTarget::SoiChange(id) => {
+            let entity = ship_entity(id)?;
+            soi = Some((id, entity, orbit(id)?.host));
+            None
+        }
+        WarpTarget::Periapsis(id) | WarpTarget::Apoapsis(id) => {
+            let anomaly = if matches!(target, WarpTarget::Periapsis(_)) {
+                0.
+            } else {
+                PI
+            };
+            let days = orbit(id)?
+                .time_to_anomaly(anomaly)
+                .ok_or(WarpError::NoApsis(id))?;
+            Some(simtick_in(time, days))
+        }
+    };
+    if end.is_some_and(|end| end <= time.simtick) {
+        return Err(WarpError::Past);
+    }
+    Ok(Warp {
+        target,
+        end,
+        soi,
+        action: None,
+        previous_step: 1,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_warp_events(
+    mut commands: Commands,
+    mut reader: EventReader<TimeEvent>,
+    mut writer: EventWriter<WarpEnd>,
+    warp: Option<Res<Warp>>,
+    time: Res<GameTime>,
+    mut step: ResMut<SimStepSize>,
+    mut toggle_time: ResMut<ToggleTime>,
+    stage: Option<Res<State<GameStage>>>,
+    mut next_stage: Option<ResMut<NextState<GameStage>>>,
+    mapping: Option<Res<ShipsMapping>>,
+    store: Option<Res<TrajectoryStore>>,
+    orbits: Orbits,
+) {
+    // The step to restore is the one before the first warp, as the resource is only updated by commands
+    let mut previous_step = warp.as_ref().map(|w| w.previous_step);
+    for event in reader.read() {
+        match event {
+            TimeEvent::WarpTo(target) => {
+                match resolve_target(
+                    *target,
+                    &time,
+                    mapping.as_deref(),
+                    store.as_deref(),
+                    &orbits,
+                ) {
+                    Ok(mut warp) => {
+                        warp.previous_step = *previous_step.get_or_insert(step.0);
+                        commands.insert_resource(warp);
+                        // In a game, time runs during the action stage
+                        match (&stage, next_stage.as_mut()) {
+                            (Some(_), Some(next_stage)) => next_stage.set(GameStage::Action),
+                            _ => toggle_time.0 = true,
+                        }
+                    }
+                    Err(e) => {
+                        writer.send(WarpEnd::Failed(e));
+                    }
+                }
+            }
+            TimeEvent::StopWarp => {
+                if let Some(previous) = previous_step.take() {
+                    step.0 = previous;
+                    commands.remove_resource::<Warp>();
+                    writer.send(WarpEnd::Stopped);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Largest step going at most `limit` simticks further, landing on the next tick first
+fn warp_step(simtick: u64, limit: u64) -> u64 {
+    let limit = limit.max(1);
+    let misalignment = simtick % SIMTICKS_PER_TICK;
+    if misalignment != 0 {
+        (SIMTICKS_PER_TICK - misalignment).min(limit)
+    } else if limit < SIMTICKS_PER_TICK {
+        limit
+    } else {
+        SIMTICKS_PER_TICK << (limit / SIMTICKS_PER_TICK).ilog2()
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_warp(
+    mut commands: Commands,
+    warp: Option<ResMut<Warp>>,
+    time: Res<GameTime>,
+    mut step: ResMut<SimStepSize>,
+    mut writer: EventWriter<WarpEnd>,
+    orbits: Orbits,
+    schedules: Query<(&ShipInfo, &ShipSchedule)>,
+    store: Option<Res<TrajectoryStore>>,
+) {
+    let Some(mut warp) = warp else {
+        return;
+    };
+    let now = time.simtick;
+    let mut end = None;
+    // Until the target
+    let mut target = warp.end;
+    if let Some((id, ship, host)) = warp.soi {
+        match orbits.ships.get(ship) {
+            Err(_) => end = Some(WarpEnd::Failed(WarpError::UnknownShip(id))),
+            Ok((.., Some(influenced))) if influenced.main_influencer != Some(host) => {
+                end = Some(WarpEnd::Reached(warp.target))
+            }
+            // Estimated from the time to leave the sphere of influence of the host
+            Ok(_) => {
+                target = orbits.get(ship).and_then(|orbit| {
+                    let (.., HillRadius(radius)) = orbits.bodies.get(host).ok()?;
+                    let days = orbit.time_to_radius(*radius, true)?;
+                    Some(simtick_in(&time, days))
+                })
+            }
+        }
+    }
+    if warp.end.is_some_and(|t| t <= now) {
+        end = Some(WarpEnd::Reached(warp.target));
+    }
+    let mut limit = target.map_or(MAX_WARP_STEP, |t| slowdown(t - now.min(t)));
+
+    // Scheduled actions and nodes of every ship. An action may be executed before the warp is updated at its
+    // tick, so the one the warp is heading to is kept until it is reached
+    let action = schedules
+        .iter()
+        .flat_map(|(info, schedule)| schedule.0.iter().map(move |(tick, _)| (info.id, *tick)))
+        .chain(warp.action)
+        .filter(|(_, tick)| tick * SIMTICKS_PER_TICK >= now)
+        .min_by_key(|(_, tick)| *tick);
+    warp.action = action;
+    if let Some((ship, tick)) = action {
+        let remaining = tick * SIMTICKS_PER_TICK - now;
+        if remaining == 0 {
+            end = end.or(Some(WarpEnd::ScheduledAction(ship)));
+        }
+        limit = limit.min(slowdown(remaining));
+    }
+    if let Some(store) = &store {
+        for (_, info, ..) in &orbits.ships {
+            if let Some((&tick, _)) = store
+                .get(&info.id)
+                .and_then(|t| t.nodes.range(time.tick() + 1..).next())
+            {
+                limit = limit.min(tick * SIMTICKS_PER_TICK - now);
+            }
+        }
+    }
+
+    // Accuracy and collisions
+    for (entity, info, ..) in &orbits.ships {
+        let Some(orbit) = orbits.get(entity) else {
+            continue;
+        };
+        // Landed or crashed ships are left out
+        let Ok((BodyInfo(host), ..)) = orbits.bodies.get(orbit.host) else {
+            continue;
+        };
+        if orbit.distance <= host.radius {
+            continue;
+        }
+        limit = limit.min(orbit.safe_step());
+        if let Some(days) = orbit.time_to_radius(host.radius, false) {
+            let remaining = simtick_in(&time, days) - now;
+            if remaining <= SLOWDOWN_SIMTICKS {
+                end = end.or(Some(WarpEnd::Collision {
+                    ship: info.id,
+                    body: host.id,
+                }));
+            }
+            limit = limit.min(slowdown(remaining));
+        }
+    }
+
+    match end {
+        Some(reason) => {
+            step.0 = warp.previous_step;
+            commands.remove_resource::<Warp>();
+            writer.send(reason);
+        }
+        None => step.0 = warp_step(now, limit.min(MAX_WARP_STEP)),
+    }
+}
+
+/// Largest step when the target is `remaining` simticks away
+fn slowdown(remaining: u64) -> u64 {
+    if remaining <= SLOWDOWN_SIMTICKS {
+        1
+    } else {
+        remaining / WARP_APPROACH
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{app::App, math::DVec3};
+
+    use super::*;
+    use crate::{
+        objects::ships::{
+            scheduler::{AddAction, ShipActionKind},
+            trajectory::{ManeuverNode, TrajectoryEvent},
+        },
+        prelude::*,
+        replay::step,
+        utils::algebra::circular_orbit_around_body,
+    };
+
+    #[test]
+    fn test_warp_step() {
+        assert_eq!(warp_step(0, 0), 1);
+        assert_eq!(warp_step(0, 5), 5);
+        assert_eq!(warp_step(3, 100), 7);
+        assert_eq!(warp_step(3, 4), 4);
+        assert_eq!(warp_step(10, 45), 40);
+        assert_eq!(warp_step(10, 1e9 as u64), SIMTICKS_PER_TICK << 26);
+    }
+
+    #[test]
+    fn test_time_to_apsis() {
+        let mu = 4e5;
+        let pos = DVec3::new(1e4, 0., 0.);
+        let speed = DVec3::new(0., 1.2 * (mu / pos.length()).sqrt(), 0.);
+        let elements = OsculatingElements::from_state(pos, speed, mu);
+        let period = elements.period.unwrap();
+        let orbit = ShipOrbit {
+            host: Entity::PLACEHOLDER,
+            elements,
+            mu,
+            distance: pos.length(),
+        };
+        assert!((orbit.time_to_anomaly(PI).unwrap() - period / 2.).abs() < 1e-6 * period);
+        let outwards = orbit.time_to_radius(1.5e4, true).unwrap();
+        let inwards = orbit.time_to_radius(1.5e4, false).unwrap();
+        assert!(outwards < period / 2. && inwards > period / 2.);
+        assert!((outwards + inwards - period).abs() < 1e-6 * period);
+        assert!(orbit.time_to_radius(1e5, true).is_none());
+    }
+
+    /// App running a game with a ship orbiting the earth
+    fn new_app() -> (App, ShipID) {
+        let mut app = App::new();
+        app.add_plugins(
+            ClientPlugin::testing()
+                .deterministic()
+                .in_mode(ClientMode::Singleplayer),
+        );
+        step(&mut app);
+        let world = app.world_mut();
+        let earth = world.resource::<BodiesMapping>().0[&id_from("terre")];
+        let (&mass, &pos, &speed) = world
+            .query::<(&Mass, &Position, &Velocity)>()
+            .get(world, earth)
+            .unwrap();
+        let (spawn_pos, spawn_speed) = circular_orbit_around_body(2e5, mass.0, pos.0, speed.0);
+        let id = id_from("s");
+        world.send_event(ShipEvent::Create(ShipInfo {
+            id,
+            spawn_pos,
+            spawn_speed,
+        }));
+        step(&mut app);
+        (app, id)
+    }
+
+    /// Steps the app until the warp ends, returning the simticks it went through and the ends of the warp
+    fn run_warp(app: &mut App, target: WarpTarget) -> (Vec<u64>, Vec<WarpEnd>) {
+        app.world_mut().send_event(TimeEvent::WarpTo(target));
+        let mut simticks = Vec::new();
+        for _ in 0..1000 {
+            step(app);
+            simticks.push(app.world().resource::<GameTime>().simtick);
+            if !app.world().contains_resource::<Warp>() {
+                break;
+            }
+        }
+        assert!(!app.world().contains_resource::<Warp>());
+        let ends = app
+            .world_mut()
+            .resource_mut::<Events<WarpEnd>>()
+            .drain()
+            .collect();
+        (simticks, ends)
+    }
+
+    fn node() -> ManeuverNode {
+        ManeuverNode {
+            name: "node".to_owned(),
+            thrust: DVec3::new(1e3, 0., 0.),
+            origin: id_from("terre"),
+        }
+    }
+
+    #[test]
+    fn test_warp_to_tick() {
+        let (mut app, _) = new_app();
+        app.world_mut()
+            .send_event(TimeEvent::WarpTo(WarpTarget::Tick(100)));
+        let mut largest_step = 0;
+        for _ in 0..1000 {
+            step(&mut app);
+            largest_step = largest_step.max(app.world().resource::<SimStepSize>().0);
+            if !app.world().contains_resource::<Warp>() {
+                break;
+            }
+        }
+        assert!(!app.world().contains_resource::<Warp>());
+        assert!(largest_step > SIMTICKS_PER_TICK);
+        // Time goes on at 1x once the target is reached
+        assert_eq!(app.world().resource::<GameTime>().simtick, 1001);
+        assert_eq!(app.world().resource::<SimStepSize>().0, 1);
+        let ends: Vec<_> = app
+            .world_mut()
+            .resource_mut::<Events<WarpEnd>>()
+            .drain()
+            .collect();
+        assert_eq!(ends, vec![WarpEnd::Reached(WarpTarget::Tick(100))]);
+    }
+
+    #[test]
+    fn test_warp_to_next_node() {
+        let (mut app, id) = new_app();
+        app.world_mut().send_event(TrajectoryEvent::AddNode {
+            ship: id,
+            node: node(),
+            tick: 70,
+        });
+        step(&mut app);
+        let (simticks, ends) = run_warp(&mut app, WarpTarget::NextNode(id));
+        // The node is landed on exactly, and time goes on at 1x from there
+        assert!(simticks.contains(&700));
+        assert_eq!(simticks.last(), Some(&701));
+        assert_eq!(ends, vec![WarpEnd::Reached(WarpTarget::NextNode(id))]);
+    }
+
+    #[test]
+    fn test_warp_stops_on_scheduled_action() {
+        let (mut app, id) = new_app();
+        app.world_mut().send_event(AddAction {
+            ship_id: id,
+            tick: 40,
+            action: ShipActionKind::AddNode { node: node() },
+        });
+        step(&mut app);
+        let (simticks, ends) = run_warp(&mut app, WarpTarget::Tick(100));
+        assert!(simticks.contains(&400));
+        assert_eq!(simticks.last(), Some(&401));
+        assert_eq!(ends, vec![WarpEnd::ScheduledAction(id)]);
+        assert_eq!(app.world().resource::<SimStepSize>().0, 1);
+    }
+}
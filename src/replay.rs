@@ -0,0 +1,564 @@
+//! Deterministic simulation, journaling of the player inputs and replay of a recorded session.
+//!
+//! In deterministic mode the virtual clock is paused, so that the fixed schedules only run when the app is
+//! explicitly [stepped](step), and the physics systems visit the entities in a fixed order.
+//! While an [InputJournal] resource exists, the inputs handled at each frame are recorded in it along with the
+//! frame and simtick at which they were sent. A [Replay] feeds them back to a fresh app at the same frames,
+//! which reproduces a session recorded in deterministic mode bit for bit.
+//! In [Lockstep] mode the app steps itself at each frame, so that the client can record a session to a file
+//! and replay it with its own runner.
+use std::{
+    fs::File,
+    io::{Read, Write},
+    iter::Peekable,
+    path::{Path, PathBuf},
+    vec,
+};
+
+use bevy::{
+    app::{AppExit, FixedMain},
+    ecs::event::ManualEventReader,
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game::GameStage,
+    objects::ships::{
+        autopilot::AutopilotEvent, scheduler::AddAction, trajectory::TrajectoryEvent, ShipEvent,
+    },
+    physics::{
+        forces::ForceModelEvent,
+        time::{GameTime, TimeEvent},
+    },
+};
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<Deterministic>()
+        .init_resource::<Lockstep>()
+        .init_resource::<JournalClock>()
+        .init_resource::<JournalCursor<ShipEvent>>()
+        .init_resource::<JournalCursor<TrajectoryEvent>>()
+        .init_resource::<JournalCursor<TimeEvent>>()
+        .init_resource::<JournalCursor<AddAction>>()
+        .init_resource::<JournalCursor<AutopilotEvent>>()
+        .init_resource::<JournalCursor<ForceModelEvent>>()
+        .add_systems(
+            Startup,
+            pause_virtual_time.run_if(resource_equals(Deterministic(true))),
+        )
+        .add_systems(First, feed_replay.run_if(resource_exists::<Replay>))
+        // Events sent by the simulation itself are not inputs
+        .add_systems(
+            FixedLast,
+            (
+                skip_events::<ShipEvent>,
+                skip_events::<TrajectoryEvent>,
+                skip_events::<TimeEvent>,
+                skip_events::<AddAction>,
+                skip_events::<AutopilotEvent>,
+                skip_events::<ForceModelEvent>,
+            ),
+        )
+        .add_systems(
+            Last,
+            (
+                record_events::<ShipEvent>,
+                record_events::<TrajectoryEvent>,
+                record_events::<TimeEvent>,
+                record_events::<AddAction>,
+                record_events::<AutopilotEvent>,
+                record_events::<ForceModelEvent>,
+                record_stage,
+                advance_clock,
+                save_journal.run_if(on_event::<AppExit>()),
+                run_fixed_main.run_if(resource_equals(Lockstep(true))),
+            )
+                .chain(),
+        );
+}
+
+/// Whether the simulation only advances when stepped, iterating over entities in a fixed order
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq)]
+pub struct Deterministic(pub bool);
+
+/// Whether the fixed schedules run exactly once at the end of each frame, as when the app is [stepped](step)
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq)]
+pub struct Lockstep(pub bool);
+
+/// File the [InputJournal] is saved to when the app exits
+#[derive(Resource, Debug, Clone)]
+pub struct JournalPath(pub PathBuf);
+
+/// Number of frames elapsed since the app started
+#[derive(Resource, Default, Debug, Clone, Copy)]
+pub struct JournalClock(pub u64);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum JournalInput {
+    Ship(ShipEvent),
+    Trajectory(TrajectoryEvent),
+    Time(TimeEvent),
+    Action(AddAction),
+    Autopilot(AutopilotEvent),
+    Force(ForceModelEvent),
+    Stage(GameStage),
+}
+
+impl From<ShipEvent> for JournalInput {
+    fn from(value: ShipEvent) -> Self {
+        Self::Ship(value)
+    }
+}
+
+impl From<TrajectoryEvent> for JournalInput {
+    fn from(value: TrajectoryEvent) -> Self {
+        Self::Trajectory(value)
+    }
+}
+
+impl From<TimeEvent> for JournalInput {
+    fn from(value: TimeEvent) -> Self {
+        Self::Time(value)
+    }
+}
+
+impl From<AddAction> for JournalInput {
+    fn from(value: AddAction) -> Self {
+        Self::Action(value)
+    }
+}
+
+impl From<AutopilotEvent> for JournalInput {
+    fn from(value: AutopilotEvent) -> Self {
+        Self::Autopilot(value)
+    }
+}
+
+impl From<ForceModelEvent> for JournalInput {
+    fn from(value: ForceModelEvent) -> Self {
+        Self::Force(value)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JournalEntry {
+    pub frame: u64,
+    pub simtick: u64,
+    pub input: JournalInput,
+}
+
+/// The inputs of a session, in the order they were sent
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct InputJournal {
+    pub entries: Vec<JournalEntry>,
+    /// Number of frames recorded
+    pub frames: u64,
+}
+
+impl InputJournal {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, JournalError> {
+        let mut buf = String::new();
+        File::open(path)?.read_to_string(&mut buf)?;
+        Ok(serde_json::from_str(&buf)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), JournalError> {
+        let s = serde_json::to_string(self)?;
+        Ok(File::create(path)?.write_all(s.as_bytes())?)
+    }
+}
+
+#[derive(Debug)]
+pub enum JournalError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// The replayed simulation is not at the simtick at which an input was recorded
+    Desync {
+        frame: u64,
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl From<std::io::Error> for JournalError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for JournalError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
+
+impl std::fmt::Display for JournalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JournalError::Io(err) => write!(f, "Error when reading journal: {}", err),
+            JournalError::Json(err) => write!(f, "Error when (de)serializing journal: {}", err),
+            JournalError::Desync {
+                frame,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Replay desynchronized at frame {}: expected simtick {}, found {}",
+                frame, expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for JournalError {}
+
+/// Runs one frame of the app, followed by exactly one run of the fixed schedules
+pub fn step(app: &mut App) {
+    app.update();
+    app.world_mut().run_schedule(FixedMain);
+}
+
+/// Feeds the inputs of a journal to an app, either by stepping it or, as a resource, at each frame of a
+/// [Lockstep] app
+#[derive(Resource)]
+pub struct Replay {
+    entries: Peekable<vec::IntoIter<JournalEntry>>,
+    frames: u64,
+}
+
+impl Replay {
+    pub fn new(journal: InputJournal) -> Self {
+        Self {
+            entries: journal.entries.into_iter().peekable(),
+            frames: journal.frames,
+        }
+    }
+
+    /// Sends the inputs recorded at the current frame of the app, then [steps](step) it
+    pub fn step(&mut self, app: &mut App) -> Result<(), JournalError> {
+        self.feed(app.world_mut())?;
+        step(app);
+        Ok(())
+    }
+
+    /// Sends the inputs recorded at the current frame of the world
+    fn feed(&mut self, world: &mut World) -> Result<(), JournalError> {
+        let frame = world.resource::<JournalClock>().0;
+        let simtick = world.resource::<GameTime>().simtick;
+        while let Some(entry) = self.entries.next_if(|e| e.frame <= frame) {
+            if entry.simtick != simtick {
+                return Err(JournalError::Desync {
+                    frame,
+                    expected: entry.simtick,
+                    found: simtick,
+                });
+            }
+            match entry.input {
+                JournalInput::Ship(e) => {
+                    world.send_event(e);
+                }
+                JournalInput::Trajectory(e) => {
+                    world.send_event(e);
+                }
+                JournalInput::Time(e) => {
+                    world.send_event(e);
+                }
+                JournalInput::Action(e) => {
+                    world.send_event(e);
+                }
+                JournalInput::Autopilot(e) => {
+                    world.send_event(e);
+                }
+                JournalInput::Force(e) => {
+                    world.send_event(e);
+                }
+                JournalInput::Stage(s) => world.resource_mut::<NextState<GameStage>>().set(s),
+            }
+        }
+        Ok(())
+    }
+
+    /// Steps the app until all the recorded frames have been replayed
+    pub fn run(mut self, app: &mut App) -> Result<(), JournalError> {
+        while app.world().resource::<JournalClock>().0 < self.frames {
+            self.step(app)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+struct JournalCursor<E: Event>(ManualEventReader<E>);
+
+impl<E: Event> Default for JournalCursor<E> {
+    fn default() -> Self {
+        Self(ManualEventReader::default())
+    }
+}
+
+fn pause_virtual_time(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+}
+
+fn feed_replay(world: &mut World) {
+    let Some(mut replay) = world.remove_resource::<Replay>() else {
+        return;
+    };
+    match replay.feed(world) {
+        Ok(()) if replay.entries.peek().is_some() => world.insert_resource(replay),
+        Ok(()) => info!("Replay finished"),
+        Err(e) => error!("{e}"),
+    }
+}
+
+fn run_fixed_main(world: &mut World) {
+    world.run_schedule(FixedMain);
+}
+
+fn save_journal(journal: Option<Res<InputJournal>>, path: Option<Res<JournalPath>>) {
+    let (Some(journal), Some(path)) = (journal, path) else {
+        return;
+    };
+    if let Err(e) = journal.save(&path.0) {
+        error!("Could not save the journal to {}: {e}", path.0.display());
+    }
+}
+
+fn skip_events<E: Event>(mut cursor: ResMut<JournalCursor<E>>, events: Res<Events<E>>) {
+    cursor.0.clear(&events);
+}
+
+fn record_events<E: Event + Clone + Into<JournalInput>>(
+    mut cursor: ResMut<JournalCursor<E>>,
+    events: Res<Events<E>>,
+    journal: Option<ResMut<InputJournal>>,
+    clock: Res<JournalClock>,
+    time: Res<GameTime>,
+) {
+    let Some(mut journal) = journal else {
+        cursor.0.clear(&events);
+        return;
+    };
+    journal
+        .entries
+        .extend(cursor.0.read(&events).map(|e| JournalEntry {
+            frame: clock.0,
+            simtick: time.simtick,
+            input: e.clone().into(),
+        }));
+}
+
+fn record_stage(
+    stage: Option<Res<State<GameStage>>>,
+    journal: Option<ResMut<InputJournal>>,
+    clock: Res<JournalClock>,
+    time: Res<GameTime>,
+) {
+    let (Some(stage), Some(mut journal)) = (stage, journal) else {
+        return;
+    };
+    // The stage is added with its default value when the game is loaded
+    if stage.is_changed() && !stage.is_added() {
+        journal.entries.push(JournalEntry {
+            frame: clock.0,
+            simtick: time.simtick,
+            input: JournalInput::Stage(stage.get().clone()),
+        });
+    }
+}
+
+fn advance_clock(mut clock: ResMut<JournalClock>, journal: Option<ResMut<InputJournal>>) {
+    clock.0 += 1;
+    if let Some(mut journal) = journal {
+        journal.frames = clock.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{app::App, math::DVec3};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{
+        objects::ships::{scheduler::ShipActionKind, trajectory::ManeuverNode},
+        physics::forces::{ForceModelKind, SailAttitude, SolarSail, OPTIMAL_CONE_ANGLE},
+        prelude::*,
+        utils::algebra::circular_orbit_around_body,
+    };
+
+    fn new_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(
+            ClientPlugin::testing()
+                .deterministic()
+                .in_mode(ClientMode::Singleplayer),
+        );
+        app
+    }
+
+    fn ships_state(app: &mut App) -> Vec<(ShipID, DVec3, DVec3)> {
+        let world = app.world_mut();
+        let mut ships: Vec<_> = world
+            .query::<(&ShipInfo, &Position, &Velocity)>()
+            .iter(world)
+            .map(|(info, p, v)| (info.id, p.0, v.0))
+            .collect();
+        ships.sort_by_key(|s| s.0);
+        ships
+    }
+
+    fn node(thrust: DVec3) -> ManeuverNode {
+        ManeuverNode {
+            name: "node".to_owned(),
+            thrust,
+            origin: id_from("terre"),
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut app = new_app();
+        app.insert_resource(InputJournal::default());
+        step(&mut app);
+        let world = app.world_mut();
+        let earth = world.resource::<BodiesMapping>().0[&id_from("terre")];
+        let (&mass, &pos, &speed) = world
+            .query::<(&Mass, &Position, &Velocity)>()
+            .get(world, earth)
+            .unwrap();
+        let (a, b) = (id_from("a"), id_from("b"));
+        for (id, radius) in [(a, 1e5), (b, 2e5)] {
+            let (spawn_pos, spawn_speed) =
+                circular_orbit_around_body(radius, mass.0, pos.0, speed.0);
+            app.world_mut().send_event(ShipEvent::Create(ShipInfo {
+                id,
+                spawn_pos,
+                spawn_speed,
+            }));
+        }
+        step(&mut app);
+        app.world_mut().send_event(TrajectoryEvent::AddNode {
+            ship: a,
+            node: node(DVec3::new(1e3, 0., 0.)),
+            tick: 2,
+        });
+        app.world_mut().send_event(AddAction {
+            ship_id: b,
+            tick: 3,
+            action: ShipActionKind::AddNode {
+                node: node(DVec3::new(0., 1e3, 0.)),
+            },
+        });
+        app.world_mut().send_event(ForceModelEvent::Attach {
+            ship: b,
+            model: ForceModelKind::SolarSail(SolarSail {
+                area: 1e4,
+                mass: 100.,
+                reflectivity: 0.9,
+                attitude: SailAttitude::FaceSun,
+            }),
+        });
+        step(&mut app);
+        app.world_mut()
+            .resource_mut::<NextState<GameStage>>()
+            .set(GameStage::Action);
+        step(&mut app);
+        step(&mut app);
+        // Ticks are only sent when the simtick is a multiple of SIMTICKS_PER_TICK, so keep it even
+        app.world_mut()
+            .send_event(TimeEvent::ChangeStepSize(Direction2::Up));
+        for i in 0..50 {
+            if i == 20 {
+                app.world_mut()
+                    .send_event(ForceModelEvent::SetSailAttitude {
+                        ship: b,
+                        attitude: SailAttitude::Cone(OPTIMAL_CONE_ANGLE),
+                    });
+            }
+            step(&mut app);
+        }
+        assert!(app.world().resource::<GameTime>().simtick > 30);
+        let journal = app.world_mut().remove_resource::<InputJournal>().unwrap();
+        // The node added by the schedule of the ship is not an input
+        assert_eq!(journal.entries.len(), 8);
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("journal.json");
+        journal.save(&path).unwrap();
+
+        let mut replayed = new_app();
+        Replay::new(InputJournal::load(&path).unwrap())
+            .run(&mut replayed)
+            .unwrap();
+        assert_eq!(
+            app.world().resource::<GameTime>().simtick,
+            replayed.world().resource::<GameTime>().simtick
+        );
+        let original = ships_state(&mut app);
+        assert_eq!(original.len(), 2);
+        assert_eq!(original, ships_state(&mut replayed));
+        let world = replayed.world_mut();
+        let ship = world.resource::<ShipsMapping>().0[&b];
+        let models = world.query::<&ForceModels>().get(world, ship).unwrap();
+        assert_eq!(
+            models.0[0].attitude(),
+            Some(SailAttitude::Cone(OPTIMAL_CONE_ANGLE))
+        );
+
+        // Without the maneuver node, the first ship ends up elsewhere
+        let mut altered = journal.clone();
+        altered
+            .entries
+            .retain(|e| !matches!(e.input, JournalInput::Trajectory(_)));
+        let mut replayed = new_app();
+        Replay::new(altered).run(&mut replayed).unwrap();
+        let altered = ships_state(&mut replayed);
+        assert_ne!(original[0], altered[0]);
+        assert_eq!(original[1], altered[1]);
+    }
+
+    #[test]
+    fn test_lockstep_replay() {
+        let mut app = new_app();
+        app.insert_resource(Lockstep(true))
+            .insert_resource(InputJournal::default());
+        app.update();
+        let world = app.world_mut();
+        let earth = world.resource::<BodiesMapping>().0[&id_from("terre")];
+        let (&mass, &pos, &speed) = world
+            .query::<(&Mass, &Position, &Velocity)>()
+            .get(world, earth)
+            .unwrap();
+        let (spawn_pos, spawn_speed) = circular_orbit_around_body(1e5, mass.0, pos.0, speed.0);
+        world.send_event(ShipEvent::Create(ShipInfo {
+            id: id_from("a"),
+            spawn_pos,
+            spawn_speed,
+        }));
+        app.update();
+        app.world_mut()
+            .resource_mut::<NextState<GameStage>>()
+            .set(GameStage::Action);
+        for _ in 0..20 {
+            app.update();
+        }
+        assert!(app.world().resource::<GameTime>().simtick > 10);
+        let journal = app.world_mut().remove_resource::<InputJournal>().unwrap();
+
+        // A lockstep session is replayed by stepping an app, and by the app itself
+        let mut stepped = new_app();
+        Replay::new(journal.clone()).run(&mut stepped).unwrap();
+        let mut fed = new_app();
+        fed.insert_resource(Lockstep(true))
+            .insert_resource(Replay::new(journal.clone()));
+        for _ in 0..journal.frames {
+            fed.update();
+        }
+        assert!(!fed.world().contains_resource::<Replay>());
+        let original = ships_state(&mut app);
+        assert_eq!(original.len(), 1);
+        assert_eq!(original, ships_state(&mut stepped));
+        assert_eq!(original, ships_state(&mut fed));
+    }
+}
@@ -1,9 +1,21 @@
+use std::path::PathBuf;
+
 use bevy::prelude::*;
-use bevy_ratatui::{event::KeyEvent, RatatuiPlugins};
+use bevy_ratatui::{
+    event::{KeyEvent, MouseEvent},
+    RatatuiPlugins,
+};
 
-use crate::input::prelude::Keymap;
+use crate::input::{
+    binding::{record_key_presses, KeyHistory},
+    mouse::{capture_mouse, read_mouse_gestures},
+    prelude::*,
+};
+use self::{console::ConsoleScript, screen::settings::KeymapPath};
 
+pub mod console;
 pub mod gui;
+pub mod palette;
 pub mod screen;
 pub mod widget;
 
@@ -20,6 +32,10 @@ pub mod prelude {
 pub struct TuiPlugin {
     pub headless: bool,
     pub keymap: Keymap,
+    /// File the keymap is saved to from the settings screen, `keymap.toml` by default
+    pub keymap_path: Option<PathBuf>,
+    /// Console commands run once the game is loaded
+    pub console_script: Option<PathBuf>,
 }
 
 impl TuiPlugin {
@@ -34,14 +50,28 @@ impl TuiPlugin {
 impl Plugin for TuiPlugin {
     fn build(&self, app: &mut App) {
         if self.headless {
-            app.add_event::<KeyEvent>();
+            app.add_event::<KeyEvent>().add_event::<MouseEvent>();
         } else {
-            app.add_plugins(RatatuiPlugins::default());
+            app.add_plugins(RatatuiPlugins::default())
+                .add_systems(Startup, capture_mouse);
         }
-        app.add_plugins(screen::plugin)
+        app.add_plugins((screen::plugin, palette::plugin, console::plugin))
             .insert_resource(self.keymap.clone())
+            .add_event::<MouseGesture>()
+            .add_event::<KeyPress>()
+            .init_resource::<KeyHistory>()
+            .add_systems(
+                Update,
+                (record_key_presses, read_mouse_gestures).before(InputReading),
+            )
             .configure_sets(PostUpdate, (UiUpdate, RenderSet).chain())
             .configure_sets(Update, (InputReading, EventHandling).chain());
+        if let Some(path) = &self.keymap_path {
+            app.insert_resource(KeymapPath(path.clone()));
+        }
+        if let Some(path) = &self.console_script {
+            app.insert_resource(ConsoleScript(path.clone()));
+        }
     }
 }
 
@@ -0,0 +1,597 @@
+//! In-game console running text commands on the loaded game, which are parsed in [command] and turned into the
+//! events of the ships, their trajectories and schedules, and of time
+use std::{collections::VecDeque, fs, path::PathBuf};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use ratatui::{
+    layout::{Constraint, Layout},
+    text::Line,
+    widgets::{Block, Clear, Paragraph, StatefulWidget, Widget},
+};
+
+use crate::{
+    input::binding::record_key_presses,
+    objects::ships::{
+        autopilot::{AutopilotEngine, AutopilotEvent},
+        scheduler::{AddAction, ShipActionKind},
+        trajectory::{ManeuverNode, TrajectoryEvent},
+    },
+    physics::{
+        time::TimeEvent,
+        warp::{WarpEnd, WarpTarget},
+    },
+    prelude::*,
+    ui::{palette, screen::editor::form_focused},
+    utils::algebra::circular_orbit_around_body,
+};
+
+use self::command::{
+    complete, parse_command, CommandError, ConsoleCommand, NodeCommand, ShipWarp, Spawn,
+};
+
+pub mod command;
+
+/// Height of the console pane, borders included
+pub const CONSOLE_HEIGHT: u16 = 12;
+
+/// Number of lines of output kept by the console
+const MAX_OUTPUT_LINES: usize = 200;
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<Console>()
+        .add_event::<ConsoleEvent>()
+        .add_event::<RunCommand>()
+        .add_systems(
+            Update,
+            (
+                read_input
+                    .after(record_key_presses)
+                    .before(palette::read_input)
+                    .run_if(not(form_focused)),
+                (handle_events, run_commands, print_warp_ends)
+                    .chain()
+                    .in_set(InputReading),
+                (load_script, run_script)
+                    .chain()
+                    .before(InputReading)
+                    .run_if(in_state(Loaded))
+                    .run_if(resource_exists::<ShipsMapping>),
+            ),
+        );
+}
+
+/// Script whose commands are run once the game is loaded, one per update so that each one sees the outcome of the
+/// previous ones
+#[derive(Resource, Clone, Debug)]
+pub struct ConsoleScript(pub PathBuf);
+
+#[derive(Event, Debug)]
+pub enum ConsoleEvent {
+    Toggle,
+    Close,
+    WriteChar(char),
+    DeleteChar,
+    Complete,
+    /// Goes to the previous or next command of the history
+    History(Direction2),
+    Submit,
+}
+
+/// A command line to run, typed in the console or read from a script
+#[derive(Event, Clone, Debug)]
+pub struct RunCommand(pub String);
+
+#[derive(Resource, Default)]
+pub struct Console {
+    open: bool,
+    input: String,
+    /// Commands submitted, the oldest first
+    history: Vec<String>,
+    /// Position in the history while browsing it, `None` while writing a new command
+    history_index: Option<usize>,
+    /// Lines printed by the commands, the oldest first
+    output: VecDeque<String>,
+    /// Lines of the startup script not run yet
+    script: VecDeque<String>,
+}
+
+impl Console {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn print(&mut self, line: impl Into<String>) {
+        self.output.push_back(line.into());
+        if self.output.len() > MAX_OUTPUT_LINES {
+            self.output.pop_front();
+        }
+    }
+
+    fn browse_history(&mut self, direction: Direction2) {
+        self.history_index = match (direction, self.history_index) {
+            (Direction2::Up, None) => self.history.len().checked_sub(1),
+            (Direction2::Up, Some(i)) => Some(i.saturating_sub(1)),
+            (Direction2::Down, Some(i)) if i + 1 < self.history.len() => Some(i + 1),
+            (Direction2::Down, _) => None,
+        };
+        self.input = self
+            .history_index
+            .map_or(String::new(), |i| self.history[i].clone());
+    }
+}
+
+/// Takes the key presses from the screens while the console is open, and opens it when its key is pressed
+fn read_input(
+    console: Res<Console>,
+    palette: Res<palette::Palette>,
+    mut presses: ResMut<Events<KeyPress>>,
+    keymap: Res<Keymap>,
+    mut internal_event: EventWriter<ConsoleEvent>,
+) {
+    use ConsoleEvent::*;
+    use Direction2::*;
+    if palette.is_open() {
+        return;
+    }
+    let toggle_key = &keymap.global.toggle_console;
+    if !console.open {
+        if presses
+            .iter_current_update_events()
+            .any(|press| toggle_key.matches(press))
+        {
+            presses.clear();
+            internal_event.send(Toggle);
+        }
+        return;
+    }
+    let keymap = &keymap.console;
+    for press in presses.drain() {
+        internal_event.send(match &press {
+            e if toggle_key.matches(e) || keymap.close.matches(e) => Close,
+            e if keymap.validate.matches(e) => Submit,
+            e if keymap.delete_char.matches(e) => DeleteChar,
+            e if keymap.complete.matches(e) => Complete,
+            e if keymap.history_previous.matches(e) => History(Up),
+            e if keymap.history_next.matches(e) => History(Down),
+            KeyPress {
+                event:
+                    crossterm::event::KeyEvent {
+                        code: crossterm::event::KeyCode::Char(c),
+                        ..
+                    },
+                ..
+            } => WriteChar(*c),
+            _ => continue,
+        });
+    }
+}
+
+fn handle_events(
+    mut events: EventReader<ConsoleEvent>,
+    mut console: ResMut<Console>,
+    bodies: Option<Res<BodiesMapping>>,
+    ships: Option<Res<ShipsMapping>>,
+    mut run: EventWriter<RunCommand>,
+) {
+    for event in events.read() {
+        match event {
+            ConsoleEvent::Toggle => console.open = !console.open,
+            ConsoleEvent::Close => console.open = false,
+            ConsoleEvent::WriteChar(c) => {
+                console.input.push(*c);
+                console.history_index = None;
+            }
+            ConsoleEvent::DeleteChar => {
+                console.input.pop();
+            }
+            ConsoleEvent::Complete => {
+                let candidates = command::KEYWORDS
+                    .iter()
+                    .map(|k| k.to_string())
+                    .chain(
+                        bodies
+                            .iter()
+                            .flat_map(|b| b.0.keys().map(ToString::to_string)),
+                    )
+                    .chain(
+                        ships
+                            .iter()
+                            .flat_map(|s| s.0.keys().map(ToString::to_string)),
+                    );
+                let (input, matches) = complete(&console.input, candidates);
+                console.input = input;
+                if matches.len() > 1 {
+                    let line = matches.join("  ");
+                    console.print(line);
+                }
+            }
+            ConsoleEvent::History(direction) => console.browse_history(*direction),
+            ConsoleEvent::Submit => {
+                let line = std::mem::take(&mut console.input);
+                console.history_index = None;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if console.history.last() != Some(&line) {
+                    console.history.push(line.clone());
+                }
+                run.send(RunCommand(line));
+            }
+        }
+    }
+}
+
+/// What the commands act on, and the events they send
+#[derive(SystemParam)]
+struct CommandTargets<'w, 's> {
+    bodies_mapping: Option<Res<'w, BodiesMapping>>,
+    ships_mapping: Option<Res<'w, ShipsMapping>>,
+    bodies: Query<
+        'w,
+        's,
+        (
+            &'static Mass,
+            &'static Position,
+            &'static Velocity,
+            &'static BodyInfo,
+        ),
+    >,
+    influenced: Query<'w, 's, &'static Influenced>,
+    primary: Query<'w, 's, &'static BodyInfo, With<PrimaryBody>>,
+    ship_events: EventWriter<'w, ShipEvent>,
+    trajectory_events: EventWriter<'w, TrajectoryEvent>,
+    actions: EventWriter<'w, AddAction>,
+    time_events: EventWriter<'w, TimeEvent>,
+    autopilot_engine: Option<Res<'w, AutopilotEngine>>,
+    autopilot_events: EventWriter<'w, AutopilotEvent>,
+    screen: Option<Res<'w, State<AppScreen>>>,
+}
+
+impl CommandTargets<'_, '_> {
+    fn body(&self, id: &str) -> Result<Entity, CommandError> {
+        let mapping = self
+            .bodies_mapping
+            .as_ref()
+            .ok_or(CommandError::NotLoaded)?;
+        BodyID::from(id)
+            .ok()
+            .and_then(|id| mapping.0.get(&id).copied())
+            .ok_or_else(|| CommandError::UnknownBody(id.to_owned()))
+    }
+
+    /// The ship of the given ID, or the only ship when none is given
+    fn ship(&self, id: Option<&str>) -> Result<(ShipID, Entity), CommandError> {
+        let mapping = self.ships_mapping.as_ref().ok_or(CommandError::NotLoaded)?;
+        match id {
+            Some(id) => ShipID::from(id)
+                .ok()
+                .and_then(|id| mapping.0.get(&id).map(|e| (id, *e)))
+                .ok_or_else(|| CommandError::UnknownShip(id.to_owned())),
+            None if mapping.0.len() == 1 => {
+                Ok(mapping.0.iter().map(|(i, e)| (*i, *e)).next().unwrap())
+            }
+            None => Err(CommandError::MissingShip),
+        }
+    }
+
+    fn node(&self, command: &NodeCommand) -> Result<(ShipID, ManeuverNode), CommandError> {
+        let (ship, entity) = self.ship(command.ship.as_deref())?;
+        let origin = match &command.origin {
+            Some(body) => {
+                let (.., BodyInfo(data)) = self.bodies.get(self.body(body)?).unwrap();
+                data.id
+            }
+            None => self
+                .influenced
+                .get(entity)
+                .ok()
+                .and_then(|i| i.main_influencer)
+                .and_then(|e| self.bodies.get(e).ok())
+                .map(|(.., BodyInfo(data))| data.id)
+                .or_else(|| self.primary.get_single().ok().map(|b| b.0.id))
+                .ok_or(CommandError::NotLoaded)?,
+        };
+        Ok((
+            ship,
+            ManeuverNode {
+                name: "Node".into(),
+                thrust: command.thrust,
+                origin,
+            },
+        ))
+    }
+
+    /// Sends the events of the command, and describes what was done
+    fn run(&mut self, command: ConsoleCommand) -> Result<String, CommandError> {
+        Ok(match command {
+            ConsoleCommand::CreateShip { id, spawn } => {
+                let ship_id = ShipID::from(&id).map_err(|_| CommandError::InvalidId(id.clone()))?;
+                if self.ship(Some(id.as_str())).is_ok() {
+                    return Err(CommandError::ShipExists(id));
+                }
+                let (spawn_pos, spawn_speed) = match spawn {
+                    Spawn::Above { body, altitude } => {
+                        let (Mass(m), Position(p), Velocity(v), BodyInfo(data)) =
+                            self.bodies.get(self.body(&body)?).unwrap();
+                        circular_orbit_around_body(data.radius + altitude, *m, *p, *v)
+                    }
+                    Spawn::At { pos, speed } => (pos, speed),
+                };
+                self.ship_events.send(ShipEvent::Create(ShipInfo {
+                    id: ship_id,
+                    spawn_pos,
+                    spawn_speed,
+                }));
+                format!("Created ship {id}")
+            }
+            ConsoleCommand::RemoveShip(id) => {
+                let (ship, _) = self.ship(Some(id.as_str()))?;
+                if let Some(AppScreen::Editor(edited) | AppScreen::Scheduler(edited)) =
+                    self.screen.as_deref().map(State::get)
+                {
+                    if *edited == ship {
+                        return Err(CommandError::ShipInUse(id));
+                    }
+                }
+                self.ship_events.send(ShipEvent::Remove(ship));
+                format!("Removed ship {id}")
+            }
+            ConsoleCommand::AddNode(command) => {
+                let (ship, node) = self.node(&command)?;
+                self.trajectory_events.send(TrajectoryEvent::AddNode {
+                    ship,
+                    node,
+                    tick: command.tick,
+                });
+                format!("Added a node to {ship} at tick {}", command.tick)
+            }
+            ConsoleCommand::ScheduleNode(command) => {
+                let (ship, node) = self.node(&command)?;
+                self.actions.send(AddAction {
+                    ship_id: ship,
+                    tick: command.tick,
+                    action: ShipActionKind::AddNode { node },
+                });
+                format!("Scheduled a node for {ship} at tick {}", command.tick)
+            }
+            ConsoleCommand::RemoveNode { ship, tick } => {
+                let (ship, _) = self.ship(ship.as_deref())?;
+                self.trajectory_events
+                    .send(TrajectoryEvent::RemoveNode { ship, tick });
+                format!("Removed the node of {ship} at tick {tick}")
+            }
+            ConsoleCommand::Time(event) => {
+                self.time_events.send(event);
+                match event {
+                    TimeEvent::WarpTo(target) => format!("Warping to {target}"),
+                    _ => "Done".to_owned(),
+                }
+            }
+            ConsoleCommand::WarpToShip { ship, event } => {
+                let (ship, _) = self.ship(ship.as_deref())?;
+                let target = match event {
+                    ShipWarp::NextNode => WarpTarget::NextNode(ship),
+                    ShipWarp::SoiChange => WarpTarget::SoiChange(ship),
+                    ShipWarp::Periapsis => WarpTarget::Periapsis(ship),
+                    ShipWarp::Apoapsis => WarpTarget::Apoapsis(ship),
+                };
+                self.run(ConsoleCommand::Time(TimeEvent::WarpTo(target)))?
+            }
+            ConsoleCommand::Autopilot { ship, script } => {
+                let (ship, _) = self.ship(Some(ship.as_str()))?;
+                match script {
+                    Some(path) => {
+                        let engine = self
+                            .autopilot_engine
+                            .as_ref()
+                            .ok_or(CommandError::NotLoaded)?;
+                        let source = engine
+                            .load(&path)
+                            .map_err(|e| CommandError::Autopilot(e.to_string()))?;
+                        self.autopilot_events
+                            .send(AutopilotEvent::Attach { ship, source });
+                        format!("Autopilot of {ship} started from {}", path.display())
+                    }
+                    None => {
+                        self.autopilot_events.send(AutopilotEvent::Detach(ship));
+                        format!("Autopilot of {ship} stopped")
+                    }
+                }
+            }
+            ConsoleCommand::Help => command::USAGE.join("\n"),
+        })
+    }
+}
+
+fn run_commands(
+    mut commands: EventReader<RunCommand>,
+    mut console: ResMut<Console>,
+    mut targets: CommandTargets,
+) {
+    for RunCommand(line) in commands.read() {
+        console.print(format!("> {line}"));
+        let outcome = parse_command(line).and_then(|command| targets.run(command));
+        match outcome {
+            Ok(text) => text.lines().for_each(|l| console.print(l)),
+            Err(e) => console.print(format!("Error: {e}")),
+        }
+    }
+}
+
+/// Shows why the warps ended, as they are mostly started from the console
+fn print_warp_ends(mut reader: EventReader<WarpEnd>, mut console: ResMut<Console>) {
+    for end in reader.read() {
+        console.print(end.to_string());
+    }
+}
+
+fn load_script(
+    mut commands: Commands,
+    script: Option<Res<ConsoleScript>>,
+    mut console: ResMut<Console>,
+) {
+    let Some(script) = script else {
+        return;
+    };
+    match fs::read_to_string(&script.0) {
+        Ok(source) => console.script.extend(
+            source
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(str::to_owned),
+        ),
+        Err(e) => console.print(format!("Could not read {}: {e}", script.0.display())),
+    }
+    commands.remove_resource::<ConsoleScript>();
+}
+
+fn run_script(mut console: ResMut<Console>, mut run: EventWriter<RunCommand>) {
+    if let Some(line) = console.script.pop_front() {
+        run.send(RunCommand(line));
+    }
+}
+
+pub struct ConsoleWidget;
+
+impl StatefulWidget for ConsoleWidget {
+    type State = Console;
+
+    fn render(
+        self,
+        area: ratatui::prelude::Rect,
+        buf: &mut ratatui::prelude::Buffer,
+        state: &mut Self::State,
+    ) {
+        Clear.render(area, buf);
+        let block = Block::bordered().title_top("Console");
+        let [output_area, input_area] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(block.inner(area));
+        block.render(area, buf);
+        let skipped = state
+            .output
+            .len()
+            .saturating_sub(output_area.height as usize);
+        let lines: Vec<_> = state
+            .output
+            .iter()
+            .skip(skipped)
+            .map(|l| Line::from(l.as_str()))
+            .collect();
+        Paragraph::new(lines).render(output_area, buf);
+        Paragraph::new(format!("> {}", state.input)).render(input_area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::app::App;
+    use bevy_ratatui::event::KeyEvent;
+
+    use crate::prelude::*;
+
+    use super::{Console, RunCommand};
+
+    fn new_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            ClientPlugin::testing().in_mode(ClientMode::Explorer),
+            TuiPlugin::testing(),
+        ));
+        app.update();
+        app.update();
+        app.update();
+        app
+    }
+
+    fn press(app: &mut App, key: &str) {
+        app.world_mut()
+            .send_event(KeyEvent(Key::from_str_unchecked(key).into()));
+        app.update();
+    }
+
+    #[test]
+    fn test_type_command() {
+        let mut app = new_app();
+        press(&mut app, "`");
+        assert!(app.world().resource::<Console>().is_open());
+        for key in ["h", "e", "l", "tab"] {
+            press(&mut app, key);
+        }
+        assert_eq!(app.world().resource::<Console>().input, "help ");
+        press(&mut app, "enter");
+        let console = app.world().resource::<Console>();
+        assert_eq!(console.history, vec!["help ".to_owned()]);
+        assert!(console.output.iter().any(|l| l.starts_with("create ship")));
+
+        press(&mut app, "up");
+        assert_eq!(app.world().resource::<Console>().input, "help ");
+        press(&mut app, "esc");
+        assert!(!app.world().resource::<Console>().is_open());
+    }
+
+    #[test]
+    fn test_create_ship() {
+        let mut app = new_app();
+        app.world_mut()
+            .send_event(RunCommand("create ship s at 400 km above terre".into()));
+        app.update();
+        app.update();
+        assert!(app
+            .world()
+            .resource::<ShipsMapping>()
+            .0
+            .contains_key(&id_from("s")));
+
+        app.world_mut()
+            .send_event(RunCommand("add node at tick 10 with prograde 1".into()));
+        app.update();
+        let console = app.world().resource::<Console>();
+        assert_eq!(
+            console.output.back().map(String::as_str),
+            Some("Added a node to s at tick 10")
+        );
+    }
+
+    #[test]
+    fn test_remove_edited_ship() {
+        let mut app = App::new();
+        app.add_plugins((
+            ClientPlugin::testing().in_mode(ClientMode::Singleplayer),
+            TuiPlugin::testing(),
+        ));
+        app.update();
+        app.update();
+        app.world_mut()
+            .send_event(RunCommand("create ship s at 400 km above terre".into()));
+        app.update();
+        app.update();
+        let id = id_from("s");
+        app.world_mut()
+            .resource_mut::<NextState<AppScreen>>()
+            .set(AppScreen::Editor(id));
+        app.update();
+
+        // The ship can't be removed from under the editor
+        app.world_mut()
+            .send_event(RunCommand("remove ship s".into()));
+        app.update();
+        app.update();
+        let console = app.world().resource::<Console>();
+        assert_eq!(
+            console.output.back().map(String::as_str),
+            Some("Error: Ship \"s\" is being edited, leave its screen first")
+        );
+        assert!(app.world().resource::<ShipsMapping>().0.contains_key(&id));
+
+        app.world_mut()
+            .resource_mut::<NextState<AppScreen>>()
+            .set(AppScreen::Fleet);
+        app.update();
+        app.world_mut()
+            .send_event(RunCommand("remove ship s".into()));
+        app.update();
+        app.update();
+        assert!(!app.world().resource::<ShipsMapping>().0.contains_key(&id));
+    }
+}
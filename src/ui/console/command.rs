@@ -0,0 +1,511 @@
+use std::{iter::Peekable, path::PathBuf, str::SplitWhitespace};
+
+use bevy::math::DVec3;
+
+use crate::{
+    physics::{time::TimeEvent, warp::WarpTarget},
+    utils::Direction2,
+};
+
+/// Syntax of the commands, shown by `help`
+pub const USAGE: [&str; 14] = [
+    "create ship <id> at <altitude> km above <body>",
+    "create ship <id> at <x> <y> <z> moving <vx> <vy> <vz>",
+    "remove ship <id>",
+    "add node [to <ship>] at tick <tick> [with] [prograde|retrograde|radial|antiradial|normal|antinormal <Δv>]... [around <body>]",
+    "remove node [from <ship>] at tick <tick>",
+    "schedule node [for <ship>] at tick <tick> [with] ...",
+    "time toggle|faster|slower",
+    "warp to tick <tick>|day <day>",
+    "warp to node|soi|periapsis|apoapsis [of <ship>]",
+    "warp stop",
+    "autopilot <ship> <script file>",
+    "autopilot <ship> off",
+    "help",
+    "The ship can be left out when there is a single one",
+];
+
+/// Words of the grammar, completed along with the IDs
+pub const KEYWORDS: [&str; 36] = [
+    "create",
+    "remove",
+    "add",
+    "schedule",
+    "time",
+    "warp",
+    "autopilot",
+    "help",
+    "ship",
+    "node",
+    "at",
+    "km",
+    "above",
+    "moving",
+    "to",
+    "from",
+    "for",
+    "tick",
+    "with",
+    "around",
+    "prograde",
+    "retrograde",
+    "radial",
+    "antiradial",
+    "normal",
+    "antinormal",
+    "toggle",
+    "faster",
+    "slower",
+    "off",
+    "day",
+    "of",
+    "soi",
+    "periapsis",
+    "apoapsis",
+    "stop",
+];
+
+/// Where a ship created from the console starts
+#[derive(Debug, Clone, PartialEq)]
+pub enum Spawn {
+    /// On a circular orbit at the given altitude above the surface of the body
+    Above {
+        body: String,
+        altitude: f64,
+    },
+    At {
+        pos: DVec3,
+        speed: DVec3,
+    },
+}
+
+/// A maneuver node given in the console
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeCommand {
+    pub ship: Option<String>,
+    pub tick: u64,
+    /// Δv along the prograde, radial and opposite of normal directions, as in the editor
+    pub thrust: DVec3,
+    /// Body the thrust is relative to, the main influencer of the ship by default
+    pub origin: Option<String>,
+}
+
+/// Events of a ship that can be warped to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShipWarp {
+    NextNode,
+    SoiChange,
+    Periapsis,
+    Apoapsis,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConsoleCommand {
+    CreateShip {
+        id: String,
+        spawn: Spawn,
+    },
+    RemoveShip(String),
+    AddNode(NodeCommand),
+    /// Adds the node to the schedule of the ship, which adds it to the trajectory once the tick is reached
+    ScheduleNode(NodeCommand),
+    RemoveNode {
+        ship: Option<String>,
+        tick: u64,
+    },
+    Time(TimeEvent),
+    WarpToShip {
+        ship: Option<String>,
+        event: ShipWarp,
+    },
+    /// Attaches the script to the ship as its autopilot, or stops its autopilot when no script is given
+    Autopilot {
+        ship: String,
+        script: Option<PathBuf>,
+    },
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandError {
+    Empty,
+    Unknown(String),
+    Expected {
+        expected: &'static str,
+        found: Option<String>,
+    },
+    InvalidNumber(String),
+    NotLoaded,
+    InvalidId(String),
+    UnknownBody(String),
+    UnknownShip(String),
+    ShipExists(String),
+    /// The ship is open in the editor or the scheduler, which need it until they are left
+    ShipInUse(String),
+    /// No ship was given while there is not a single one
+    MissingShip,
+    Autopilot(String),
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::Empty => write!(f, "Empty command"),
+            CommandError::Unknown(word) => {
+                write!(f, "Unknown command \"{word}\", type help for the syntax")
+            }
+            CommandError::Expected {
+                expected,
+                found: Some(found),
+            } => write!(f, "Expected {expected}, found \"{found}\""),
+            CommandError::Expected {
+                expected,
+                found: None,
+            } => write!(f, "Expected {expected}"),
+            CommandError::InvalidNumber(word) => write!(f, "Invalid number \"{word}\""),
+            CommandError::NotLoaded => write!(f, "No game is loaded"),
+            CommandError::InvalidId(id) => write!(f, "Invalid ID \"{id}\""),
+            CommandError::UnknownBody(id) => write!(f, "Unknown body \"{id}\""),
+            CommandError::UnknownShip(id) => write!(f, "Unknown ship \"{id}\""),
+            CommandError::ShipExists(id) => write!(f, "Ship \"{id}\" already exists"),
+            CommandError::ShipInUse(id) => {
+                write!(f, "Ship \"{id}\" is being edited, leave its screen first")
+            }
+            CommandError::MissingShip => write!(f, "Expected a ship, as there is not a single one"),
+            CommandError::Autopilot(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+struct Tokens<'a>(Peekable<SplitWhitespace<'a>>);
+
+impl<'a> Tokens<'a> {
+    fn next(&mut self, expected: &'static str) -> Result<&'a str, CommandError> {
+        self.0.next().ok_or(CommandError::Expected {
+            expected,
+            found: None,
+        })
+    }
+
+    fn expect(&mut self, word: &'static str) -> Result<(), CommandError> {
+        match self.0.next() {
+            Some(w) if w == word => Ok(()),
+            found => Err(CommandError::Expected {
+                expected: word,
+                found: found.map(str::to_owned),
+            }),
+        }
+    }
+
+    /// Consumes the word if it comes next
+    fn accept(&mut self, word: &str) -> bool {
+        self.0.next_if_eq(&word).is_some()
+    }
+
+    fn number<T: std::str::FromStr>(&mut self, expected: &'static str) -> Result<T, CommandError> {
+        let word = self.next(expected)?;
+        word.parse()
+            .map_err(|_| CommandError::InvalidNumber(word.to_owned()))
+    }
+
+    fn vector(&mut self) -> Result<DVec3, CommandError> {
+        Ok(DVec3::new(
+            self.number("x")?,
+            self.number("y")?,
+            self.number("z")?,
+        ))
+    }
+
+    fn end(&mut self) -> Result<(), CommandError> {
+        match self.0.next() {
+            None => Ok(()),
+            Some(word) => Err(CommandError::Expected {
+                expected: "the end of the command",
+                found: Some(word.to_owned()),
+            }),
+        }
+    }
+}
+
+/// The ship given after the preposition if any, then the tick of the node
+fn ship_and_tick(
+    tokens: &mut Tokens,
+    preposition: &str,
+) -> Result<(Option<String>, u64), CommandError> {
+    let ship = if tokens.accept(preposition) {
+        Some(tokens.next("a ship")?.to_owned())
+    } else {
+        None
+    };
+    tokens.expect("at")?;
+    tokens.expect("tick")?;
+    Ok((ship, tokens.number("a tick")?))
+}
+
+fn parse_node(tokens: &mut Tokens, preposition: &str) -> Result<NodeCommand, CommandError> {
+    let (ship, tick) = ship_and_tick(tokens, preposition)?;
+    tokens.accept("with");
+    let mut thrust = DVec3::ZERO;
+    let mut origin = None;
+    while let Some(word) = tokens.0.next() {
+        let direction = match word {
+            "prograde" => DVec3::X,
+            "retrograde" => DVec3::NEG_X,
+            "radial" => DVec3::Y,
+            "antiradial" => DVec3::NEG_Y,
+            "normal" => DVec3::NEG_Z,
+            "antinormal" => DVec3::Z,
+            "around" => {
+                origin = Some(tokens.next("a body")?.to_owned());
+                continue;
+            }
+            word => {
+                return Err(CommandError::Expected {
+                    expected: "a direction or around",
+                    found: Some(word.to_owned()),
+                })
+            }
+        };
+        thrust += tokens.number::<f64>("a Δv")? * direction;
+    }
+    Ok(NodeCommand {
+        ship,
+        tick,
+        thrust,
+        origin,
+    })
+}
+
+fn parse_warp(tokens: &mut Tokens) -> Result<ConsoleCommand, CommandError> {
+    let expected = "tick, day, node, soi, periapsis or apoapsis";
+    let event = match tokens.next(expected)? {
+        "tick" => {
+            let target = WarpTarget::Tick(tokens.number("a tick")?);
+            return Ok(ConsoleCommand::Time(TimeEvent::WarpTo(target)));
+        }
+        "day" => {
+            let target = WarpTarget::Date(tokens.number("a day")?);
+            return Ok(ConsoleCommand::Time(TimeEvent::WarpTo(target)));
+        }
+        "node" => ShipWarp::NextNode,
+        "soi" => ShipWarp::SoiChange,
+        "periapsis" => ShipWarp::Periapsis,
+        "apoapsis" => ShipWarp::Apoapsis,
+        word => {
+            return Err(CommandError::Expected {
+                expected,
+                found: Some(word.to_owned()),
+            })
+        }
+    };
+    let ship = if tokens.accept("of") {
+        Some(tokens.next("a ship")?.to_owned())
+    } else {
+        None
+    };
+    Ok(ConsoleCommand::WarpToShip { ship, event })
+}
+
+pub fn parse_command(line: &str) -> Result<ConsoleCommand, CommandError> {
+    let mut tokens = Tokens(line.split_whitespace().peekable());
+    let command = match tokens.0.next().ok_or(CommandError::Empty)? {
+        "create" => {
+            tokens.expect("ship")?;
+            let id = tokens.next("a ship ID")?.to_owned();
+            tokens.expect("at")?;
+            let first = tokens.number("an altitude or a position")?;
+            let spawn = if tokens.accept("km") {
+                tokens.expect("above")?;
+                Spawn::Above {
+                    body: tokens.next("a body")?.to_owned(),
+                    altitude: first,
+                }
+            } else {
+                let pos = DVec3::new(first, tokens.number("y")?, tokens.number("z")?);
+                tokens.expect("moving")?;
+                Spawn::At {
+                    pos,
+                    speed: tokens.vector()?,
+                }
+            };
+            ConsoleCommand::CreateShip { id, spawn }
+        }
+        "remove" => match tokens.next("ship or node")? {
+            "ship" => ConsoleCommand::RemoveShip(tokens.next("a ship ID")?.to_owned()),
+            "node" => {
+                let (ship, tick) = ship_and_tick(&mut tokens, "from")?;
+                ConsoleCommand::RemoveNode { ship, tick }
+            }
+            word => {
+                return Err(CommandError::Expected {
+                    expected: "ship or node",
+                    found: Some(word.to_owned()),
+                })
+            }
+        },
+        "add" => {
+            tokens.expect("node")?;
+            ConsoleCommand::AddNode(parse_node(&mut tokens, "to")?)
+        }
+        "schedule" => {
+            tokens.expect("node")?;
+            ConsoleCommand::ScheduleNode(parse_node(&mut tokens, "for")?)
+        }
+        "time" => ConsoleCommand::Time(match tokens.next("toggle, faster or slower")? {
+            "toggle" => TimeEvent::ToggleTime,
+            "faster" => TimeEvent::ChangeStepSize(Direction2::Up),
+            "slower" => TimeEvent::ChangeStepSize(Direction2::Down),
+            word => {
+                return Err(CommandError::Expected {
+                    expected: "toggle, faster or slower",
+                    found: Some(word.to_owned()),
+                })
+            }
+        }),
+        "warp" => match tokens.next("to or stop")? {
+            "to" => parse_warp(&mut tokens)?,
+            "stop" => ConsoleCommand::Time(TimeEvent::StopWarp),
+            word => {
+                return Err(CommandError::Expected {
+                    expected: "to or stop",
+                    found: Some(word.to_owned()),
+                })
+            }
+        },
+        "autopilot" => {
+            let ship = tokens.next("a ship ID")?.to_owned();
+            let script = match tokens.next("a script file or off")? {
+                "off" => None,
+                path => Some(PathBuf::from(path)),
+            };
+            ConsoleCommand::Autopilot { ship, script }
+        }
+        "help" => ConsoleCommand::Help,
+        word => return Err(CommandError::Unknown(word.to_owned())),
+    };
+    tokens.end()?;
+    Ok(command)
+}
+
+/// Completes the last word of the input with the candidates starting with it, as far as they agree.
+/// Returns the completed input and the candidates
+pub fn complete(
+    input: &str,
+    candidates: impl IntoIterator<Item = String>,
+) -> (String, Vec<String>) {
+    let start = input.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+    let word = &input[start..];
+    let mut matches: Vec<_> = candidates
+        .into_iter()
+        .filter(|c| c.starts_with(word))
+        .collect();
+    matches.sort();
+    matches.dedup();
+    let completed = match matches.as_slice() {
+        [] => word.to_owned(),
+        [single] => format!("{single} "),
+        [first, rest @ ..] => {
+            let mut prefix = first.clone();
+            for candidate in rest {
+                while !candidate.starts_with(&prefix) {
+                    prefix.pop();
+                }
+            }
+            prefix
+        }
+    };
+    (format!("{}{completed}", &input[..start]), matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec3;
+
+    use super::{
+        complete, parse_command, CommandError, ConsoleCommand, NodeCommand, ShipWarp, Spawn,
+    };
+    use crate::physics::{time::TimeEvent, warp::WarpTarget};
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            parse_command("create ship X at 400 km above terre"),
+            Ok(ConsoleCommand::CreateShip {
+                id: "X".into(),
+                spawn: Spawn::Above {
+                    body: "terre".into(),
+                    altitude: 400.
+                }
+            })
+        );
+        assert_eq!(
+            parse_command("add node at tick 1200 with prograde 0.5 normal 2"),
+            Ok(ConsoleCommand::AddNode(NodeCommand {
+                ship: None,
+                tick: 1200,
+                thrust: DVec3::new(0.5, 0., -2.),
+                origin: None
+            }))
+        );
+        assert_eq!(
+            parse_command("schedule node for s at tick 10 retrograde 1 around lune"),
+            Ok(ConsoleCommand::ScheduleNode(NodeCommand {
+                ship: Some("s".into()),
+                tick: 10,
+                thrust: DVec3::NEG_X,
+                origin: Some("lune".into())
+            }))
+        );
+        assert_eq!(
+            parse_command("autopilot s off"),
+            Ok(ConsoleCommand::Autopilot {
+                ship: "s".into(),
+                script: None
+            })
+        );
+        assert_eq!(
+            parse_command("warp to day 2.5"),
+            Ok(ConsoleCommand::Time(TimeEvent::WarpTo(WarpTarget::Date(
+                2.5
+            ))))
+        );
+        assert_eq!(
+            parse_command("warp to apoapsis of s"),
+            Ok(ConsoleCommand::WarpToShip {
+                ship: Some("s".into()),
+                event: ShipWarp::Apoapsis
+            })
+        );
+        assert_eq!(
+            parse_command("warp stop"),
+            Ok(ConsoleCommand::Time(TimeEvent::StopWarp))
+        );
+        assert_eq!(
+            parse_command("remove node at tick x"),
+            Err(CommandError::InvalidNumber("x".into()))
+        );
+        assert_eq!(
+            parse_command("time toggle now"),
+            Err(CommandError::Expected {
+                expected: "the end of the command",
+                found: Some("now".into())
+            })
+        );
+    }
+
+    #[test]
+    fn test_complete() {
+        let ids = || ["terre".to_owned(), "lune".to_owned(), "tethys".to_owned()];
+        assert_eq!(
+            complete("create ship s at 4 km above l", ids()).0,
+            "create ship s at 4 km above lune "
+        );
+        let (input, matches) = complete("remove ship t", ids());
+        assert_eq!(input, "remove ship t");
+        assert_eq!(matches, vec!["terre".to_owned(), "tethys".to_owned()]);
+        assert_eq!(complete("remove ship te", ids()).0, "remove ship te");
+        assert_eq!(complete("remove ship ter", ids()).0, "remove ship terre ");
+    }
+}
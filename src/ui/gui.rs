@@ -4,7 +4,7 @@ use bevy::{
     input::{
         common_conditions::input_pressed,
         mouse::{MouseButtonInput, MouseMotion, MouseScrollUnit, MouseWheel},
-        ButtonState,
+        ButtonState, InputSystem,
     },
     math::{DVec2, DVec3},
     prelude::*,
@@ -13,8 +13,10 @@ use bevy::{
 };
 
 use crate::{
+    input::window_keys::forward_window_keys,
     objects::{
         orbiting_obj::{OrbitalObjID, OrbitingObjects},
+        ships::ghost::GhostShip,
         // ships::HostBody,
     }, physics::{influence::HillRadius, orbit::SystemSize}, prelude::*, utils::{
         algebra::{center_to_periapsis_direction, ellipse_half_sizes},
@@ -97,8 +99,14 @@ impl Plugin for GuiPlugin {
             )
             .add_systems(
                 PreUpdate,
-                send_select_object_event
-                    .run_if(on_event::<MouseButtonInput>().and_then(resource_exists::<SpaceMap>)),
+                (
+                    send_select_object_event.run_if(
+                        on_event::<MouseButtonInput>().and_then(resource_exists::<SpaceMap>),
+                    ),
+                    forward_window_keys
+                        .after(InputSystem)
+                        .run_if(resource_exists::<Events<bevy_ratatui::event::KeyEvent>>),
+                ),
             );
     }
 }
@@ -298,7 +306,8 @@ fn update_camera_pos(
     let (mut cam_pos, mut proj) = cam.single_mut();
     let focus_pos = space_map
         .focus_body
-        .map_or(DVec3::default(), |f| positions.get(f).unwrap().0);
+        .and_then(|f| positions.get(f).ok())
+        .map_or(DVec3::default(), |p| p.0);
     cam_pos.translation = ((focus_pos
         + DVec3::new(space_map.offset_amount.x, space_map.offset_amount.y, 0.))
         * scale)
@@ -332,6 +341,7 @@ fn draw_gizmos(
     orbit_query: Query<&EllipticalOrbit>,
     // orbital_ships: Query<(&Transform, &Velocity, &EllipticalOrbit, &HostBody), With<ShipInfo>>,
     ships: Query<(&Transform, &Velocity, &Influenced), With<ShipInfo>>,
+    ghosts: Query<(&Transform, &Velocity), With<GhostShip>>,
     bodies_mapping: Res<BodiesMapping>,
     ships_mapping: Res<ShipsMapping>,
 ) {
@@ -449,6 +459,19 @@ fn draw_gizmos(
                     Color::Srgba(GOLD),
                 );
             }
+            // Display ghost ships
+            for (t, speed) in ghosts.iter() {
+                let speed = (speed.0.normalize_or(DVec3::X) * MAX_HEIGHT as f64
+                    / (30. * zoom_level))
+                    .xy()
+                    .as_vec2();
+                let t = t.translation.xy() - speed / 3.;
+                let perp = speed.perp() / 3.;
+                gizmos.linestrip_2d(
+                    [t + speed, t + perp, t - perp, t + speed],
+                    Color::Srgba(GOLD).with_alpha(0.4),
+                );
+            }
         }
     }
 }
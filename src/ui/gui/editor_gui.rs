@@ -1,12 +1,11 @@
 use crate::{
-    physics::{predictions::Prediction, time::SIMTICKS_PER_TICK},
     prelude::*,
     ui::{
         gui::SelectionRadius,
         screen::editor::{
             editor_backend::{
-                ChangeNodeTick, ChangePredictionsNumber, ConfirmThrust, NumberOfPredictions,
-                ReloadPredictions, TempPrediction, UpdateThrust,
+                ChangeNodeTick, ChangePredictionsHorizon, ConfirmThrust, PredictionsReady,
+                ReloadPredictions, UpdateThrust,
             },
             ClearOnEditorExit, EditorContext, EditorEvents,
         },
@@ -16,7 +15,9 @@ use crate::{
     utils::algebra::relative_axes,
 };
 use bevy::{
-    color::palettes::css::{BLUE, DARK_BLUE, DARK_GREEN, DARK_RED, GREEN, ORANGE, RED, WHITE},
+    color::palettes::css::{
+        BLUE, DARK_BLUE, DARK_GREEN, DARK_RED, GREEN, ORANGE, RED, TURQUOISE, WHITE,
+    },
     input::{
         common_conditions::{input_just_released, input_pressed},
         mouse::{MouseButtonInput, MouseMotion, MouseScrollUnit, MouseWheel},
@@ -26,7 +27,7 @@ use bevy::{
     window::PrimaryWindow,
 };
 
-use super::{AdaptiveTranslation, SelectObjectEvent, MAX_HEIGHT};
+use super::{send_select_object_event, AdaptiveTranslation, SelectObjectEvent, MAX_HEIGHT};
 
 const GIZMO_COLORS: [Color; 6] = [
     Color::Srgba(RED),
@@ -59,16 +60,23 @@ pub fn plugin(app: &mut App) {
                     .run_if(input_pressed(MouseButton::Left).and_then(on_event::<MouseMotion>())),
                 handle_click_gizmo.run_if(on_event::<SelectObjectEvent>()),
                 handle_release_gizmo.run_if(input_just_released(MouseButton::Left)),
+                despawn_arrows.run_if(on_event::<ReloadPredictions>()),
                 (despawn_arrows, spawn_arrows)
                     .chain()
-                    .run_if(on_event::<ReloadPredictions>()),
+                    .run_if(on_event::<PredictionsReady>()),
             )
                 .run_if(resource_exists::<EditorContext>),
         )
+        .add_systems(
+            PreUpdate,
+            select_prediction
+                .after(send_select_object_event)
+                .run_if(on_event::<MouseButtonInput>().and_then(resource_exists::<EditorContext>)),
+        )
         .add_systems(
             PreUpdate,
             (
-                send_change_predictions_number.run_if(input_pressed(KeyCode::ShiftLeft)),
+                send_change_predictions_horizon.run_if(input_pressed(KeyCode::ShiftLeft)),
                 send_change_node_tick.run_if(input_pressed(KeyCode::ControlLeft)),
             )
                 .run_if(resource_exists::<EditorContext>.and_then(on_event::<MouseWheel>())),
@@ -94,28 +102,80 @@ pub(super) struct ArrowGizmo {
     pub local_direction: Vec3,
 }
 
-fn draw_predictions(
-    mut gizmos: Gizmos,
-    predictions: Query<(&Transform, &Prediction, Option<&TempPrediction>)>,
+fn draw_predictions(mut gizmos: Gizmos, context: Res<EditorContext>, space_map: Res<SpaceMap>) {
+    let scale = MAX_HEIGHT as f64 / space_map.system_size;
+    let horizon = context.horizon().max(1) as f32;
+    // The predictions are unevenly spaced, so the path is drawn through them and faded along the horizon rather
+    // than by their index
+    for (predictions, color) in [
+        (context.predictions(), Color::WHITE),
+        (context.temp_predictions(), Color::Srgba(ORANGE)),
+        (context.target_predictions(), Color::Srgba(TURQUOISE)),
+    ] {
+        let fading = |simtick: u64| 1. - (simtick - context.simtick) as f32 / horizon;
+        gizmos.linestrip_gradient_2d(predictions.iter().map(|p| {
+            (
+                (p.pos * scale).as_vec3().xy(),
+                color.with_alpha(0.1 + 0.4 * fading(p.simtick)),
+            )
+        }));
+        for p in predictions {
+            gizmos.circle_2d(
+                (p.pos * scale).as_vec3().xy(),
+                fading(p.simtick) * MAX_HEIGHT / (500. * space_map.zoom_level as f32),
+                color.with_alpha(0.2),
+            );
+        }
+    }
+}
+
+/// Selects the node at the prediction under the cursor, or inserts one there, unless another object was
+/// clicked
+#[allow(clippy::too_many_arguments)]
+fn select_prediction(
+    mut clicks: EventReader<MouseButtonInput>,
+    mut selected_objects: EventReader<SelectObjectEvent>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    cam: Query<(&Camera, &GlobalTransform)>,
+    context: Res<EditorContext>,
     space_map: Res<SpaceMap>,
-    predictions_number: Res<NumberOfPredictions>,
+    mut editor_events: EventWriter<EditorEvents>,
 ) {
-    for (t, p, temp) in predictions.iter() {
-        if p.index % SIMTICKS_PER_TICK as usize != 0 {
+    if selected_objects.read().count() > 0 {
+        clicks.clear();
+        return;
+    }
+    let (cam, cam_transform) = cam.single();
+    let scale = MAX_HEIGHT as f64 / space_map.system_size;
+    let radius = MAX_HEIGHT / (100. * space_map.zoom_level as f32);
+    for event in clicks.read() {
+        if !matches!(
+            (event.state, event.button),
+            (ButtonState::Pressed, MouseButton::Left)
+        ) {
             continue;
         }
-        let color = if temp.is_some() {
-            Color::Srgba(ORANGE)
-        } else {
-            Color::WHITE
+        let Some(cursor) = window
+            .single()
+            .cursor_position()
+            .and_then(|pos| cam.viewport_to_world_2d(cam_transform, pos))
+        else {
+            continue;
+        };
+        let nearest = context
+            .predictions()
+            .iter()
+            .map(|p| {
+                (
+                    p.simtick,
+                    ((p.pos * scale).as_vec3().xy() - cursor).length(),
+                )
+            })
+            .filter(|(_, dist)| *dist < radius)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+        if let Some((simtick, _)) = nearest {
+            editor_events.send(EditorEvents::SelectNearestOrInsert(simtick));
         }
-        .with_alpha(0.2);
-        gizmos.circle_2d(
-            t.translation.xy(),
-            (1. - p.index as f32 / predictions_number.0 as f32) * MAX_HEIGHT
-                / (500. * space_map.zoom_level as f32),
-            color,
-        );
     }
 }
 
@@ -124,15 +184,15 @@ fn spawn_arrows(
     space_map: Res<SpaceMap>,
     mut commands: Commands,
     positions: Query<&Transform>,
-    speeds: Query<&Velocity>,
 ) {
-    if let Some(e) = context.selected_prediction_entity() {
+    if let Some((pos, speed)) = context.selected_prediction() {
         let scale = MAX_HEIGHT as f64 / space_map.system_size;
-        let speed = (speeds.get(e).unwrap().0 * scale).as_vec3();
-        let pos = positions.get(e).unwrap().translation;
+        let speed = (speed * scale).as_vec3();
+        let pos = (pos * scale).as_vec3();
         let focus = space_map
             .focus_body
-            .map_or(Vec3::ZERO, |e| positions.get(e).unwrap().translation);
+            .and_then(|e| positions.get(e).ok())
+            .map_or(Vec3::ZERO, |t| t.translation);
         let [forward, right, down] = relative_axes(pos - focus, speed);
         let directions = [forward, -forward, right, -right, down, -down];
         let local_directions = [
@@ -179,12 +239,12 @@ fn draw_maneuver_node(
     mut gizmos: Gizmos,
     space_map: Res<SpaceMap>,
     context: Res<EditorContext>,
-    positions: Query<&Transform>,
     current_gizmo: Res<CurrentGizmo>,
     arrows: Query<(Entity, &ArrowGizmo, &GlobalTransform)>,
 ) {
-    if let Some(e) = context.selected_prediction_entity() {
-        let pos = positions.get(e).unwrap().translation;
+    if let Some((pos, _)) = context.selected_prediction() {
+        let scale = MAX_HEIGHT as f64 / space_map.system_size;
+        let pos = (pos * scale).as_vec3();
         let size = MAX_HEIGHT / (30. * space_map.zoom_level as f32);
         gizmos.circle_2d(pos.xy(), size, WHITE);
         for (gizmo, arrow, arrow_pos) in arrows.iter() {
@@ -253,8 +313,8 @@ fn handle_drag_gizmo(
     }
 }
 
-fn send_change_predictions_number(
-    mut events: EventWriter<ChangePredictionsNumber>,
+fn send_change_predictions_horizon(
+    mut events: EventWriter<ChangePredictionsHorizon>,
     mut scroll: EventReader<MouseWheel>,
 ) {
     for event in scroll.read() {
@@ -262,7 +322,7 @@ fn send_change_predictions_number(
             MouseScrollUnit::Line => true,
             MouseScrollUnit::Pixel => false,
         };
-        events.send(ChangePredictionsNumber {
+        events.send(ChangePredictionsHorizon::Scroll {
             is_step,
             amount: event.y,
         });
@@ -0,0 +1,669 @@
+//! Command palette listing the actions of the current screen along with global ones, searched with the fuzzy
+//! matcher of the explorer search. The chosen action sends the same events as its key.
+use bevy::{math::DVec3, prelude::*, utils::HashMap};
+use fuzzy_matcher::FuzzyMatcher;
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::Stylize,
+    widgets::{Block, Clear, List, ListState, Paragraph, StatefulWidget, Widget},
+};
+
+use crate::{
+    input::{binding::record_key_presses, mouse::read_mouse_gestures},
+    physics::time::TimeEvent,
+    prelude::*,
+    ui::{
+        console::ConsoleEvent,
+        screen::{
+            editor::{editor_backend::ChangePredictionsHorizon, form_focused, EditorEvents},
+            explorer::{ExplorerContext, ExplorerEvent, SidePaneMode, ViewEvent},
+            fleet::{FleetContext, FleetScreenEvent},
+            settings::{save_keymap, KeymapPath, SettingsEvent},
+            start::StartMenuEvent,
+        },
+        widget::{
+            search::{SearchEvent, SearchMatcher},
+            space_map::SpaceMapEvent,
+            tree::TreeEvent,
+        },
+    },
+    utils::ui::centered_rect,
+};
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<Palette>()
+        .add_event::<PaletteEvent>()
+        .add_event::<PaletteAction>()
+        .add_systems(
+            Update,
+            (
+                read_input
+                    .after(record_key_presses)
+                    .after(read_mouse_gestures)
+                    .before(InputReading)
+                    .run_if(not(form_focused)),
+                (handle_events, run_actions).chain().in_set(InputReading),
+            ),
+        );
+}
+
+/// What an action of the palette does, the events of the screens being built when it is run
+#[derive(Event, Clone, Copy)]
+pub(crate) enum PaletteAction {
+    Explorer(fn() -> ExplorerEvent),
+    Fleet(fn() -> FleetScreenEvent),
+    Editor(fn() -> EditorEvents),
+    Horizon(ChangePredictionsHorizon),
+    StartMenu(fn() -> StartMenuEvent),
+    Settings(fn() -> SettingsEvent),
+    Time(TimeEvent),
+    Screen(AppScreen),
+    Console,
+    LeaveGame,
+    SaveKeymap,
+    Quit,
+}
+
+#[derive(Clone)]
+pub(crate) struct Command {
+    name: String,
+    binding: Binding,
+    action: PaletteAction,
+}
+
+#[derive(Event, Debug)]
+pub enum PaletteEvent {
+    Open,
+    Close,
+    Select(Direction2),
+    /// Selects the command drawn at the given column and row of the terminal
+    SelectAt(u16, u16),
+    WriteChar(char),
+    DeleteChar,
+    Validate,
+}
+
+#[derive(Resource, Default)]
+pub struct Palette {
+    open: bool,
+    input: String,
+    /// Commands available on the screen the palette was opened from
+    commands: Vec<Command>,
+    /// Indices of the commands matching the input, the best match first
+    matches: Vec<usize>,
+    list_state: ListState,
+    list_area: Rect,
+    /// Outcome of the last command, for the ones which do not leave the palette
+    message: Option<String>,
+}
+
+impl Palette {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn update_matches(&mut self, matcher: &impl FuzzyMatcher) {
+        let mut scores: Vec<_> = self
+            .commands
+            .iter()
+            .enumerate()
+            .filter_map(|(i, command)| {
+                matcher
+                    .fuzzy_match(&command.name, &self.input)
+                    .map(|score| (i, score))
+            })
+            .collect();
+        scores.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        self.matches = scores.into_iter().map(|(i, _)| i).collect();
+        self.list_state
+            .select((!self.matches.is_empty()).then_some(0));
+    }
+
+    fn selected_command(&self) -> Option<&Command> {
+        self.list_state
+            .selected()
+            .and_then(|i| self.matches.get(i))
+            .map(|&i| &self.commands[i])
+    }
+}
+
+impl ClampedList for Palette {
+    fn list_state(&mut self) -> &mut ListState {
+        &mut self.list_state
+    }
+
+    fn len(&self) -> usize {
+        self.matches.len()
+    }
+
+    fn list_area(&self) -> Rect {
+        self.list_area
+    }
+}
+
+/// Name of the action bound in the keymap at the given path, such as `Zoom in` for `explorer.tree.zoom_in`
+fn action_name(path: &str) -> String {
+    let name = path.rsplit('.').next().unwrap_or(path).replace('_', " ");
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map_or(String::new(), |c| c.to_uppercase().chain(chars).collect())
+}
+
+/// The actions of the screen, named by their path in the keymap
+fn screen_actions(
+    screen: AppScreen,
+    side_pane_mode: Option<SidePaneMode>,
+) -> Vec<(&'static str, PaletteAction)> {
+    use Direction2::*;
+    use PaletteAction::*;
+    match (screen, side_pane_mode) {
+        (AppScreen::StartMenu, _) => vec![
+            (
+                "start_menu.select_next",
+                StartMenu(|| StartMenuEvent::Select(Down)),
+            ),
+            (
+                "start_menu.select_previous",
+                StartMenu(|| StartMenuEvent::Select(Up)),
+            ),
+            (
+                "start_menu.validate",
+                StartMenu(|| StartMenuEvent::Validate),
+            ),
+            ("start_menu.quit", StartMenu(|| StartMenuEvent::Quit)),
+        ],
+        (AppScreen::Explorer, Some(SidePaneMode::Search)) => vec![
+            (
+                "explorer.search.validate_search",
+                Explorer(|| ExplorerEvent::Search(SearchEvent::ValidateSearch)),
+            ),
+            (
+                "explorer.search.leave_search",
+                Explorer(|| ExplorerEvent::View(ViewEvent::ChangeSidePaneMode(SidePaneMode::Tree))),
+            ),
+        ],
+        (AppScreen::Explorer, _) => {
+            use crate::prelude::Direction4::*;
+            use ExplorerEvent::*;
+            vec![
+                (
+                    "explorer.tree.select_next",
+                    Explorer(|| Tree(TreeEvent::Select(Down))),
+                ),
+                (
+                    "explorer.tree.select_previous",
+                    Explorer(|| Tree(TreeEvent::Select(Up))),
+                ),
+                (
+                    "explorer.tree.toggle_expand",
+                    Explorer(|| Tree(TreeEvent::ToggleTreeExpansion)),
+                ),
+                (
+                    "explorer.tree.zoom_in",
+                    Explorer(|| SpaceMap(SpaceMapEvent::Zoom(Up))),
+                ),
+                (
+                    "explorer.tree.zoom_out",
+                    Explorer(|| SpaceMap(SpaceMapEvent::Zoom(Down))),
+                ),
+                (
+                    "explorer.tree.map_offset_up",
+                    Explorer(|| SpaceMap(SpaceMapEvent::MapOffset(Front))),
+                ),
+                (
+                    "explorer.tree.map_offset_down",
+                    Explorer(|| SpaceMap(SpaceMapEvent::MapOffset(Back))),
+                ),
+                (
+                    "explorer.tree.map_offset_left",
+                    Explorer(|| SpaceMap(SpaceMapEvent::MapOffset(Left))),
+                ),
+                (
+                    "explorer.tree.map_offset_right",
+                    Explorer(|| SpaceMap(SpaceMapEvent::MapOffset(Right))),
+                ),
+                (
+                    "explorer.tree.map_offset_reset",
+                    Explorer(|| SpaceMap(SpaceMapEvent::MapOffsetReset)),
+                ),
+                (
+                    "explorer.tree.focus",
+                    Explorer(|| SpaceMap(SpaceMapEvent::FocusBody)),
+                ),
+                (
+                    "explorer.tree.autoscale",
+                    Explorer(|| SpaceMap(SpaceMapEvent::Autoscale)),
+                ),
+                (
+                    "explorer.tree.enter_search",
+                    Explorer(|| View(ViewEvent::ChangeSidePaneMode(SidePaneMode::Search))),
+                ),
+                (
+                    "explorer.tree.toggle_info",
+                    Explorer(|| View(ViewEvent::ToggleInfo)),
+                ),
+                ("explorer.tree.back", Explorer(|| View(ViewEvent::Back))),
+                (
+                    "explorer.tree.speed_up",
+                    Explorer(|| Time(TimeEvent::ChangeStepSize(Up))),
+                ),
+                (
+                    "explorer.tree.slow_down",
+                    Explorer(|| Time(TimeEvent::ChangeStepSize(Down))),
+                ),
+                (
+                    "explorer.tree.toggle_time",
+                    Explorer(|| Time(TimeEvent::ToggleTime)),
+                ),
+            ]
+        }
+        (AppScreen::Fleet, _) => {
+            use FleetScreenEvent::*;
+            vec![
+                ("fleet_screen.select_next", Fleet(|| Select(Down))),
+                ("fleet_screen.select_previous", Fleet(|| Select(Up))),
+                ("fleet_screen.edit_trajectory", Fleet(|| EditTrajectory)),
+                ("fleet_screen.new_ship", Fleet(|| NewShip)),
+                ("fleet_screen.enter_explorer", Fleet(|| EnterExplorer)),
+                (
+                    "fleet_screen.cycle_sail_attitude",
+                    Fleet(|| CycleSailAttitude),
+                ),
+                ("fleet_screen.back", Fleet(|| Back)),
+            ]
+        }
+        (AppScreen::Editor(_), _) => {
+            use EditorEvents::*;
+            vec![
+                ("editor.select_next", Editor(|| SelectAdjacent(Down))),
+                ("editor.select_previous", Editor(|| SelectAdjacent(Up))),
+                ("editor.new_node", Editor(|| NewNode)),
+                ("editor.edit_node", Editor(|| EditNode)),
+                ("editor.delete_node", Editor(|| DeleteSelected)),
+                ("editor.undo", Editor(|| Undo)),
+                ("editor.redo", Editor(|| Redo)),
+                ("editor.cycle_target", Editor(|| CycleTarget)),
+                (
+                    "editor.longer_horizon",
+                    Horizon(ChangePredictionsHorizon::Scroll {
+                        is_step: true,
+                        amount: 1.,
+                    }),
+                ),
+                (
+                    "editor.shorter_horizon",
+                    Horizon(ChangePredictionsHorizon::Scroll {
+                        is_step: true,
+                        amount: -1.,
+                    }),
+                ),
+                (
+                    "editor.cycle_horizon_unit",
+                    Horizon(ChangePredictionsHorizon::CycleUnit),
+                ),
+                ("editor.zoom_in", Editor(|| Zoom(Up))),
+                ("editor.zoom_out", Editor(|| Zoom(Down))),
+                ("editor.cycle_focus", Editor(|| CycleFocus)),
+                ("editor.thrust_prograde", Editor(|| NudgeThrust(DVec3::X))),
+                (
+                    "editor.thrust_retrograde",
+                    Editor(|| NudgeThrust(DVec3::NEG_X)),
+                ),
+                ("editor.thrust_radial", Editor(|| NudgeThrust(DVec3::Y))),
+                (
+                    "editor.thrust_antiradial",
+                    Editor(|| NudgeThrust(DVec3::NEG_Y)),
+                ),
+                ("editor.thrust_normal", Editor(|| NudgeThrust(DVec3::NEG_Z))),
+                ("editor.thrust_antinormal", Editor(|| NudgeThrust(DVec3::Z))),
+                ("editor.cycle_thrust_step", Editor(|| CycleThrustStep)),
+                ("editor.back", Screen(AppScreen::Fleet)),
+            ]
+        }
+        (AppScreen::Settings, _) => {
+            use crate::ui::screen::settings::Capture;
+            use SettingsEvent::*;
+            vec![
+                ("settings.select_next", Settings(|| Select(Down))),
+                ("settings.select_previous", Settings(|| Select(Up))),
+                (
+                    "settings.rebind",
+                    Settings(|| StartCapture(Capture::Replace)),
+                ),
+                (
+                    "settings.add_key",
+                    Settings(|| StartCapture(Capture::AddKey)),
+                ),
+                (
+                    "settings.add_chord",
+                    Settings(|| StartCapture(Capture::AddChord(Vec::new()))),
+                ),
+                ("settings.reset", Settings(|| Reset)),
+                ("settings.save", Settings(|| Save)),
+                ("settings.back", Settings(|| Back)),
+            ]
+        }
+        (AppScreen::Scheduler(_), _) => Vec::new(),
+    }
+}
+
+/// The actions available on every screen where they make sense
+fn global_actions(
+    screen: AppScreen,
+    mode: ClientMode,
+    in_fleet: bool,
+) -> Vec<(&'static str, PaletteAction)> {
+    use Direction2::*;
+    use PaletteAction::*;
+    let loaded = mode != ClientMode::None;
+    let mut actions = Vec::new();
+    if screen != AppScreen::Settings {
+        actions.push(("Open settings", Screen(AppScreen::Settings)));
+    }
+    if in_fleet && screen != AppScreen::Fleet {
+        actions.push(("Open fleet", Screen(AppScreen::Fleet)));
+    }
+    if loaded && screen != AppScreen::Explorer {
+        actions.push(("Enter explorer", Screen(AppScreen::Explorer)));
+    }
+    if loaded {
+        actions.extend([
+            ("Toggle time", Time(TimeEvent::ToggleTime)),
+            ("Speed up", Time(TimeEvent::ChangeStepSize(Up))),
+            ("Slow down", Time(TimeEvent::ChangeStepSize(Down))),
+            ("Stop warp", Time(TimeEvent::StopWarp)),
+            ("Leave game", LeaveGame),
+        ]);
+    } else if screen != AppScreen::StartMenu {
+        actions.push(("Open start menu", Screen(AppScreen::StartMenu)));
+    }
+    actions.extend([
+        ("Open console", Console),
+        ("Save keymap", SaveKeymap),
+        ("Quit", Quit),
+    ]);
+    actions
+}
+
+/// The commands of the screen followed by the global ones, a global command being left out when the screen has an
+/// action of the same name
+fn available_commands(
+    keymap: &Keymap,
+    screen: AppScreen,
+    side_pane_mode: Option<SidePaneMode>,
+    mode: ClientMode,
+    in_fleet: bool,
+) -> Vec<Command> {
+    let bindings: HashMap<_, _> = keymap.bindings().into_iter().collect();
+    let mut commands: Vec<_> = screen_actions(screen, side_pane_mode)
+        .into_iter()
+        .map(|(path, action)| Command {
+            name: action_name(path),
+            binding: bindings.get(path).cloned().unwrap_or_default(),
+            action,
+        })
+        .collect();
+    for (name, action) in global_actions(screen, mode, in_fleet) {
+        if commands.iter().all(|c| c.name != name) {
+            commands.push(Command {
+                name: name.to_owned(),
+                binding: Binding::default(),
+                action,
+            });
+        }
+    }
+    commands
+}
+
+/// Takes the key presses and mouse gestures from the screens while the palette is open, and opens it when its key
+/// is pressed
+pub(crate) fn read_input(
+    palette: Res<Palette>,
+    mut presses: ResMut<Events<KeyPress>>,
+    mut gestures: ResMut<Events<MouseGesture>>,
+    keymap: Res<Keymap>,
+    mut internal_event: EventWriter<PaletteEvent>,
+) {
+    use Direction2::*;
+    use PaletteEvent::*;
+    let open_key = &keymap.global.open_palette;
+    if !palette.open {
+        if presses
+            .iter_current_update_events()
+            .any(|press| open_key.matches(press))
+        {
+            presses.clear();
+            internal_event.send(Open);
+        }
+        return;
+    }
+    let keymap = &keymap.palette;
+    for press in presses.drain() {
+        internal_event.send(match &press {
+            e if open_key.matches(e) || keymap.close.matches(e) => Close,
+            e if keymap.select_next.matches(e) => Select(Down),
+            e if keymap.select_previous.matches(e) => Select(Up),
+            e if keymap.validate.matches(e) => Validate,
+            e if keymap.delete_char.matches(e) => DeleteChar,
+            KeyPress {
+                event:
+                    crossterm::event::KeyEvent {
+                        code: crossterm::event::KeyCode::Char(c),
+                        ..
+                    },
+                ..
+            } => WriteChar(*c),
+            _ => continue,
+        });
+    }
+    for gesture in gestures.drain() {
+        internal_event.send(match gesture {
+            MouseGesture::Click { column, row } => SelectAt(column, row),
+            MouseGesture::Scroll { direction, .. } => Select(direction),
+            MouseGesture::Drag { .. } => continue,
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_events(
+    mut events: EventReader<PaletteEvent>,
+    mut palette: ResMut<Palette>,
+    keymap: Res<Keymap>,
+    screen: Res<State<AppScreen>>,
+    mode: Option<Res<State<ClientMode>>>,
+    explorer: Option<Res<ExplorerContext>>,
+    fleet: Option<Res<FleetContext>>,
+    matcher: Res<SearchMatcher>,
+    mut actions: EventWriter<PaletteAction>,
+) {
+    for event in events.read() {
+        match event {
+            PaletteEvent::Open => {
+                palette.commands = available_commands(
+                    &keymap,
+                    *screen.get(),
+                    explorer.as_ref().map(|e| e.side_pane_mode),
+                    mode.as_ref().map_or(ClientMode::None, |m| *m.get()),
+                    fleet.is_some(),
+                );
+                palette.input.clear();
+                palette.message = None;
+                palette.open = true;
+                palette.update_matches(&matcher.0);
+            }
+            PaletteEvent::Close => palette.open = false,
+            PaletteEvent::Select(d) => palette.select_adjacent(*d),
+            PaletteEvent::SelectAt(column, row) => {
+                palette.select_at(*column, *row);
+            }
+            PaletteEvent::WriteChar(c) => {
+                palette.input.push(*c);
+                palette.update_matches(&matcher.0);
+            }
+            PaletteEvent::DeleteChar => {
+                palette.input.pop();
+                palette.update_matches(&matcher.0);
+            }
+            PaletteEvent::Validate => {
+                let Some(command) = palette.selected_command() else {
+                    continue;
+                };
+                let action = command.action;
+                // The outcome of saving is shown in the palette
+                palette.open = matches!(action, PaletteAction::SaveKeymap);
+                actions.send(action);
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_actions(
+    mut actions: EventReader<PaletteAction>,
+    mut palette: ResMut<Palette>,
+    mut explorer_events: EventWriter<ExplorerEvent>,
+    mut fleet_events: EventWriter<FleetScreenEvent>,
+    mut editor_events: EventWriter<EditorEvents>,
+    mut horizon_events: EventWriter<ChangePredictionsHorizon>,
+    mut start_menu_events: EventWriter<StartMenuEvent>,
+    mut settings_events: EventWriter<SettingsEvent>,
+    mut time_events: EventWriter<TimeEvent>,
+    mut console_events: EventWriter<ConsoleEvent>,
+    mut next_screen: ResMut<NextState<AppScreen>>,
+    mut next_mode: ResMut<NextState<ClientMode>>,
+    keymap: Res<Keymap>,
+    path: Res<KeymapPath>,
+    mut quit: EventWriter<AppExit>,
+) {
+    for action in actions.read() {
+        match *action {
+            PaletteAction::Explorer(event) => {
+                explorer_events.send(event());
+            }
+            PaletteAction::Fleet(event) => {
+                fleet_events.send(event());
+            }
+            PaletteAction::Editor(event) => {
+                editor_events.send(event());
+            }
+            PaletteAction::Horizon(event) => {
+                horizon_events.send(event);
+            }
+            PaletteAction::StartMenu(event) => {
+                start_menu_events.send(event());
+            }
+            PaletteAction::Settings(event) => {
+                settings_events.send(event());
+            }
+            PaletteAction::Time(event) => {
+                time_events.send(event);
+            }
+            PaletteAction::Screen(screen) => next_screen.set(screen),
+            PaletteAction::Console => {
+                console_events.send(ConsoleEvent::Toggle);
+            }
+            PaletteAction::LeaveGame => next_mode.set(ClientMode::None),
+            PaletteAction::SaveKeymap => palette.message = Some(save_keymap(&keymap, &path)),
+            PaletteAction::Quit => {
+                quit.send_default();
+            }
+        }
+    }
+}
+
+pub struct PaletteWidget;
+
+impl StatefulWidget for PaletteWidget {
+    type State = Palette;
+
+    fn render(
+        self,
+        area: ratatui::prelude::Rect,
+        buf: &mut ratatui::prelude::Buffer,
+        state: &mut Self::State,
+    ) {
+        let popup = centered_rect(60, 60, area);
+        Clear.render(popup, buf);
+        let chunks = Layout::vertical([Constraint::Length(3), Constraint::Fill(1)]).split(popup);
+        Paragraph::new(&state.input[..])
+            .block(Block::bordered().title_top("Command palette".bold()))
+            .render(chunks[0], buf);
+
+        let width = state
+            .matches
+            .iter()
+            .map(|&i| state.commands[i].name.len())
+            .max()
+            .unwrap_or_default();
+        let entries: Vec<_> = state
+            .matches
+            .iter()
+            .map(|&i| {
+                let command = &state.commands[i];
+                format!("{:width$}  {}", command.name, command.binding)
+            })
+            .collect();
+        let mut block = Block::bordered();
+        if let Some(message) = &state.message {
+            block = block.title_bottom(message.clone());
+        }
+        state.list_area = block.inner(chunks[1]);
+        let list = List::new(entries).highlight_symbol("> ").block(block);
+        <List as StatefulWidget>::render(list, chunks[1], buf, &mut state.list_state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::app::App;
+    use bevy_ratatui::event::KeyEvent;
+
+    use crate::{prelude::*, ui::screen::explorer::ExplorerContext};
+
+    use super::{Palette, PaletteEvent};
+
+    #[test]
+    fn test_palette() {
+        let mut app = App::new();
+        app.add_plugins((
+            ClientPlugin::testing().in_mode(ClientMode::Explorer),
+            TuiPlugin::testing(),
+        ));
+        app.update();
+        app.update();
+        app.update();
+
+        // The key opening the palette is not seen by the explorer
+        app.world_mut()
+            .send_event(KeyEvent(Key::from_str_unchecked("C p").into()));
+        app.update();
+        assert!(app.world().resource::<Palette>().is_open());
+        let palette = app.world().resource::<Palette>();
+        assert!(palette.commands.iter().any(|c| c.name == "Zoom in"));
+        assert!(palette.commands.iter().any(|c| c.name == "Open settings"));
+        assert_eq!(
+            palette
+                .commands
+                .iter()
+                .filter(|c| c.name == "Toggle time")
+                .count(),
+            1
+        );
+
+        // Keys typed in the palette search the commands instead of triggering the explorer actions
+        app.world_mut().send_event_batch(
+            "tglinfo"
+                .chars()
+                .map(|c| KeyEvent(Key::from_str_unchecked(&c.to_string()).into())),
+        );
+        app.update();
+        assert!(!app.world().resource::<ExplorerContext>().info_toggle);
+        app.world_mut().send_event(PaletteEvent::Validate);
+        app.update();
+        app.update();
+        assert!(!app.world().resource::<Palette>().is_open());
+        assert!(app.world().resource::<ExplorerContext>().info_toggle);
+    }
+}
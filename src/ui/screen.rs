@@ -3,19 +3,34 @@ use bevy_ratatui::{event::KeyEvent, terminal::RatatuiContext};
 use editor::{EditorContext, EditorScreen};
 use explorer::{ExplorerContext, ExplorerScreen};
 use fleet::{FleetContext, FleetScreen};
+use ratatui::layout::{Constraint, Layout, Rect};
+use settings::{SettingsContext, SettingsScreen};
 use start::{StartMenu, StartMenuContext};
 
 use crate::{
     client::ClientMode,
-    objects::ships::ShipID,
+    input::{
+        binding::KeyHistory,
+        prelude::{KeyPress, MouseGesture},
+    },
+    objects::{
+        ships::{ShipEvent, ShipID},
+        ObjectsUpdate,
+    },
     prelude::{exit_on_error_if_app, Loaded},
 };
 
-use super::{widget::space_map::SpaceMap, InputReading, RenderSet};
+use super::{
+    console::{Console, ConsoleWidget, CONSOLE_HEIGHT},
+    palette::{Palette, PaletteWidget},
+    widget::space_map::{unfocus_removed_ships, SpaceMap},
+    EventHandling, InputReading, RenderSet,
+};
 
 pub mod editor;
 pub mod explorer;
 pub mod fleet;
+pub mod settings;
 pub mod start;
 pub mod schedule_screen;
 
@@ -30,6 +45,7 @@ pub enum AppScreen {
     Fleet,
     Editor(ShipID),
     Scheduler(ShipID),
+    Settings,
 }
 
 #[derive(Resource, Default, Debug)]
@@ -41,6 +57,7 @@ pub fn plugin(app: &mut App) {
         explorer::plugin,
         fleet::plugin,
         editor::plugin,
+        settings::plugin,
     ))
     .init_state::<AppScreen>()
     .init_resource::<PreviousScreen>()
@@ -50,10 +67,17 @@ pub fn plugin(app: &mut App) {
     )
     .add_systems(
         Update,
-        clear_key_events
+        clear_input_events
             .before(InputReading)
             .run_if(state_changed::<AppScreen>),
     )
+    .add_systems(
+        Update,
+        unfocus_removed_ships
+            .after(EventHandling)
+            .before(ObjectsUpdate)
+            .run_if(resource_exists::<SpaceMap>.and_then(on_event::<ShipEvent>())),
+    )
     .add_systems(
         OnEnter(ClientMode::Explorer),
         move |mut next_screen: ResMut<NextState<AppScreen>>| next_screen.set(AppScreen::Explorer),
@@ -81,10 +105,19 @@ fn update_previous_screen(
     }
 }
 
-fn clear_key_events(mut events: ResMut<Events<KeyEvent>>) {
+fn clear_input_events(
+    mut events: ResMut<Events<KeyEvent>>,
+    mut presses: ResMut<Events<KeyPress>>,
+    mut history: ResMut<KeyHistory>,
+    mut gestures: ResMut<Events<MouseGesture>>,
+) {
     events.clear();
+    presses.clear();
+    history.0.clear();
+    gestures.clear();
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render(
     mut ctx: ResMut<RatatuiContext>,
     screen: Res<State<AppScreen>>,
@@ -92,30 +125,59 @@ fn render(
     explorer: Option<ResMut<ExplorerContext>>,
     fleet: Option<ResMut<FleetContext>>,
     editor: Option<ResMut<EditorContext>>,
+    settings: Option<ResMut<SettingsContext>>,
     space_map: Option<ResMut<SpaceMap>>,
+    mut palette: ResMut<Palette>,
+    mut console: ResMut<Console>,
 ) -> color_eyre::Result<()> {
-    ctx.draw(|f| match screen.get() {
-        AppScreen::StartMenu => {
-            f.render_stateful_widget(StartMenu, f.size(), start_menu.unwrap().as_mut())
-        }
-        AppScreen::Explorer => {
-            if let Some(mut explorer) = explorer {
-                f.render_stateful_widget(
-                    ExplorerScreen {
-                        map: space_map.unwrap().as_mut(),
-                    },
-                    f.size(),
-                    explorer.as_mut(),
-                )
+    ctx.draw(|f| {
+        // The console takes the bottom of the terminal, the screen being drawn above it
+        let [area, console_area] = if console.is_open() {
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(CONSOLE_HEIGHT)])
+                .areas(f.size())
+        } else {
+            [f.size(), Rect::default()]
+        };
+        match screen.get() {
+            AppScreen::StartMenu => {
+                f.render_stateful_widget(StartMenu, area, start_menu.unwrap().as_mut())
+            }
+            AppScreen::Explorer => {
+                if let Some(mut explorer) = explorer {
+                    f.render_stateful_widget(
+                        ExplorerScreen {
+                            map: space_map.unwrap().as_mut(),
+                        },
+                        area,
+                        explorer.as_mut(),
+                    )
+                }
+            }
+            AppScreen::Fleet => {
+                f.render_stateful_widget(FleetScreen, area, fleet.unwrap().as_mut())
+            }
+            AppScreen::Editor(_) => {
+                if let (Some(mut editor), Some(mut map)) = (editor, space_map) {
+                    f.render_stateful_widget(
+                        EditorScreen { map: map.as_mut() },
+                        area,
+                        editor.as_mut(),
+                    )
+                }
+            }
+            AppScreen::Scheduler(_) => {} // A REMPLIR UNE FOIS L'UI FAITE
+            AppScreen::Settings => {
+                if let Some(mut settings) = settings {
+                    f.render_stateful_widget(SettingsScreen, area, settings.as_mut())
+                }
             }
         }
-        AppScreen::Fleet => {
-            f.render_stateful_widget(FleetScreen, f.size(), fleet.unwrap().as_mut())
+        if console.is_open() {
+            f.render_stateful_widget(ConsoleWidget, console_area, console.as_mut());
         }
-        AppScreen::Editor(_) => {
-            f.render_stateful_widget(EditorScreen, f.size(), editor.unwrap().as_mut())
+        if palette.is_open() {
+            f.render_stateful_widget(PaletteWidget, f.size(), palette.as_mut());
         }
-        AppScreen::Scheduler(_) => {} // A REMPLIR UNE FOIS L'UI FAITE
     })?;
     Ok(())
 }
@@ -1,11 +1,14 @@
 use std::collections::BTreeMap;
 
 use bevy::{math::DVec3, prelude::*};
-use bevy_ratatui::event::KeyEvent;
-use crossterm::event::KeyEventKind;
+use crossterm::event::KeyCode;
 use ratatui::{
-    layout::{Constraint, Layout},
-    widgets::{Block, List, ListState, Paragraph, StatefulWidget, Widget},
+    layout::{Constraint, Layout, Rect},
+    style::Color,
+    widgets::{
+        Block, Clear, List, ListState, Paragraph, Sparkline, StatefulWidget, StatefulWidgetRef,
+        Widget,
+    },
 };
 
 use crate::{
@@ -14,16 +17,34 @@ use crate::{
             // DisableShipOrbitCheck, HostBody
             }
 }, 
+    ephemeris::format_epoch,
     physics::
     {
         // influence::HillRadius, leapfrog::get_acceleration,
-        time::SIMTICKS_PER_TICK}, 
+        predictions::{aligned, closest_approach, Prediction},
+        time::{GAMETIME_PER_SIMTICK, SIMTICKS_PER_TICK}}, 
     prelude::*,
+    ui::{widget::space_map::SpaceMapWidget, UiUpdate},
+    utils::{list::OptionsList, ui::area_contains},
+};
+
+use self::{
+    editor_backend::{ChangePredictionsHorizon, ReloadPredictions, RunningPredictions},
+    history::EditHistory,
+    node_form::NodeForm,
 };
 
 use super::AppScreen;
 
 pub mod editor_backend;
+pub mod history;
+pub mod node_form;
+
+/// Δv added to the selected node by the thrust keys, which cycle through them (in km/d)
+const THRUST_STEPS: [f64; 5] = [1., 10., 100., 1e3, 1e4];
+const DEFAULT_THRUST_STEP: usize = 2;
+/// Ticks between the selected node and the one created after it with the keyboard
+const NEW_NODE_TICKS: u64 = 10;
 
 pub fn plugin(app: &mut App) {
     app.add_plugins(editor_backend::plugin)
@@ -32,13 +53,11 @@ pub fn plugin(app: &mut App) {
         .add_systems(
             Update,
             (
-                read_input.in_set(InputReading),
-                ((
-                    handle_select_prediction.run_if(resource_exists::<Events<SelectObjectEvent>>),
-                    handle_editor_events,
-                )
-                    .chain(),)
-                    .in_set(EventHandling),
+                (read_input, read_mouse).in_set(InputReading),
+                handle_editor_events.in_set(EventHandling),
+                update_map
+                    .in_set(UiUpdate)
+                    .run_if(resource_exists::<SpaceMap>),
             )
                 .run_if(in_state(InEditor))
                 .run_if(resource_exists::<EditorContext>),
@@ -69,16 +88,35 @@ pub struct EditorContext {
     pub speed: DVec3,
     pub simtick: u64,
     list_state: ListState,
-    /// Each maneuver node is stored here along with the associated tick, and corresponds to a prediction.
-    /// Since there is a prediction for each tick, the index of the prediction is simply the number of ticks
-    /// that separate the start from the maneuver node
+    /// Each maneuver node is stored here along with the associated tick, and corresponds to the prediction kept
+    /// at the simtick starting this tick
     nodes: BTreeMap<u64, ManeuverNode>,
-    predictions: Vec<Entity>,
-    /// These predictions start from a maneuver node that is currently being edited. At the end of edition,
-    /// the true predictions after the node are replaced by these temporary ones
-    temp_predictions: Vec<Entity>,
+    /// Number of simticks following the start covered by the predictions
+    horizon: usize,
+    /// Coordinates of the ship at the sampled simticks following the start, with the current nodes
+    predictions: Vec<Prediction>,
+    /// These predictions include the thrust that is currently being edited, or are the predictions being
+    /// received when the nodes changed
+    temp_predictions: Vec<Prediction>,
+    /// Ship whose path is predicted along with this one, to plan a rendezvous
+    target: Option<ShipID>,
+    /// Coordinates of the target at the same simticks as the predictions of the ship
+    target_predictions: Vec<Prediction>,
+    /// Predictions of the nodes being computed, which replace the current ones once received
+    running: Option<RunningPredictions>,
+    /// Predictions with the thrust being edited, computed without interrupting the ones of the nodes
+    running_temp: Option<RunningPredictions>,
     /// This field stores the thrust that will be added to a node when we are editing one
     editing_data: Option<DVec3>,
+    history: EditHistory,
+    /// Form editing the fields of the selected node, if open
+    form: Option<NodeForm>,
+    /// Index in [THRUST_STEPS] of the Δv added by the thrust keys
+    thrust_step: usize,
+    space_map: SpaceMapWidget,
+    /// Areas of the terminal in which the node list and the map were last drawn
+    list_area: Rect,
+    map_area: Rect,
 }
 
 impl EditorContext {
@@ -97,9 +135,20 @@ impl EditorContext {
             simtick: tick,
             list_state: ListState::default(),
             nodes: BTreeMap::new(),
+            horizon: 0,
             predictions: Vec::new(),
             temp_predictions: Vec::new(),
+            target: None,
+            target_predictions: Vec::new(),
+            running: None,
+            running_temp: None,
             editing_data: None,
+            history: EditHistory::default(),
+            form: None,
+            thrust_step: DEFAULT_THRUST_STEP,
+            space_map: SpaceMapWidget::default(),
+            list_area: Rect::default(),
+            map_area: Rect::default(),
         }
     }
 
@@ -114,6 +163,17 @@ impl EditorContext {
         self.selected_entry().map(|(t, _)| *t)
     }
 
+    /// First tick at which a node can be inserted, after the current tick
+    pub fn first_node_tick(&self) -> u64 {
+        self.simtick.div_ceil(SIMTICKS_PER_TICK) + 1
+    }
+
+    /// Tick at which a new node is inserted, a few ticks after the selected node or after the current tick
+    pub fn new_node_tick(&self) -> u64 {
+        self.selected_tick()
+            .map_or(self.first_node_tick(), |t| t + NEW_NODE_TICKS)
+    }
+
     /// Attempts to select the node at the provided tick, returning the index if successful
     pub fn select_tick(&mut self, tick: u64) -> Option<usize> {
         self.index_of_tick(tick)
@@ -136,19 +196,42 @@ impl EditorContext {
             .and_then(|i| self.nodes.iter_mut().nth(i))
     }
 
-    fn index_of_prediction_at_simtick(&self, simtick: u64) -> usize {
-        (simtick - self.simtick).max(self.simtick) as usize
+    pub fn horizon(&self) -> usize {
+        self.horizon
+    }
+
+    pub fn predictions(&self) -> &[Prediction] {
+        &self.predictions
+    }
+
+    pub fn temp_predictions(&self) -> &[Prediction] {
+        &self.temp_predictions
+    }
+
+    pub fn target(&self) -> Option<ShipID> {
+        self.target
+    }
+
+    pub fn target_predictions(&self) -> &[Prediction] {
+        &self.target_predictions
+    }
+
+    /// Simtick at which the ship comes the closest to the target, along with their distance
+    pub fn closest_approach(&self) -> Option<(u64, f64)> {
+        closest_approach(&self.predictions, &self.target_predictions)
     }
 
-    fn prediction_at_simtick(&self, tick: u64) -> Option<Entity> {
-        self.predictions
-            .get(self.index_of_prediction_at_simtick(tick))
-            .cloned()
+    /// Prediction kept at the given simtick, or the last one before it
+    fn prediction_at_simtick(&self, simtick: u64) -> Option<&Prediction> {
+        let i = self.predictions.partition_point(|p| p.simtick <= simtick);
+        i.checked_sub(1).map(|i| &self.predictions[i])
     }
 
-    pub fn selected_prediction_entity(&self) -> Option<Entity> {
+    /// Predicted coordinates of the ship at the selected node
+    pub fn selected_prediction(&self) -> Option<(DVec3, DVec3)> {
         self.selected_tick()
             .and_then(|t| self.prediction_at_simtick(SIMTICKS_PER_TICK * t))
+            .map(|p| (p.pos, p.speed))
     }
 
     pub fn get_node(&self, tick: u64) -> Option<&ManeuverNode> {
@@ -160,6 +243,12 @@ impl EditorContext {
         self.select_tick(tick);
     }
 
+    /// Records the current nodes in the history, before they are edited
+    fn record_edit(&mut self) {
+        let selected = self.list_state.selected();
+        self.history.record(&self.nodes, selected);
+    }
+
     pub fn change_tick(&mut self, tick: u64, newtick: u64) {
         self.nodes
             .remove(&tick)
@@ -174,9 +263,15 @@ impl ClampedList for EditorContext {
     fn len(&self) -> usize {
         self.nodes.len()
     }
+
+    fn list_area(&self) -> Rect {
+        self.list_area
+    }
 }
 
-pub struct EditorScreen;
+pub struct EditorScreen<'a> {
+    pub map: &'a mut SpaceMap,
+}
 
 #[allow(clippy::too_many_arguments)]
 fn create_screen(
@@ -256,22 +351,87 @@ fn clear_screen(
 }
 
 fn read_input(
-    mut key_event: EventReader<KeyEvent>,
+    mut context: ResMut<EditorContext>,
+    mut presses: EventReader<KeyPress>,
     keymap: Res<Keymap>,
     mut internal_event: EventWriter<EditorEvents>,
+    mut horizon_event: EventWriter<ChangePredictionsHorizon>,
     mut next_screen: ResMut<NextState<AppScreen>>,
 ) {
     use Direction2::*;
     use EditorEvents::*;
     let keymap = &keymap.editor;
-    for event in key_event.read() {
-        if event.kind == KeyEventKind::Release {
-            return;
-        }
-        if keymap.select_next.matches(event) {
+    for event in presses.read() {
+        if let Some(form) = &mut context.form {
+            match event {
+                e if keymap.cycle_options.matches(e) => form.select_next(),
+                e if keymap.cycle_options_back.matches(e) => form.select_previous(),
+                e if keymap.back.matches(e) => context.form = None,
+                e if keymap.validate_node.matches(e) => {
+                    internal_event.send(SubmitForm);
+                }
+                e if keymap.delete_char.matches(e) => {
+                    form.selected_field().pop();
+                }
+                KeyPress {
+                    event:
+                        crossterm::event::KeyEvent {
+                            code: KeyCode::Char(c),
+                            ..
+                        },
+                    ..
+                } => form.selected_field().push(*c),
+                _ => {}
+            }
+        } else if keymap.edit_node.matches(event) {
+            internal_event.send(EditNode);
+        } else if keymap.select_next.matches(event) {
             internal_event.send(SelectAdjacent(Down));
         } else if keymap.select_previous.matches(event) {
             internal_event.send(SelectAdjacent(Up));
+        } else if keymap.delete_node.matches(event) {
+            internal_event.send(DeleteSelected);
+        } else if keymap.undo.matches(event) {
+            internal_event.send(Undo);
+        } else if keymap.redo.matches(event) {
+            internal_event.send(Redo);
+        } else if keymap.cycle_target.matches(event) {
+            internal_event.send(CycleTarget);
+        } else if keymap.longer_horizon.matches(event) {
+            horizon_event.send(ChangePredictionsHorizon::Scroll {
+                is_step: true,
+                amount: 1.,
+            });
+        } else if keymap.shorter_horizon.matches(event) {
+            horizon_event.send(ChangePredictionsHorizon::Scroll {
+                is_step: true,
+                amount: -1.,
+            });
+        } else if keymap.cycle_horizon_unit.matches(event) {
+            horizon_event.send(ChangePredictionsHorizon::CycleUnit);
+        } else if keymap.new_node.matches(event) {
+            internal_event.send(NewNode);
+        } else if let Some(direction) = [
+            (&keymap.thrust_prograde, DVec3::X),
+            (&keymap.thrust_retrograde, DVec3::NEG_X),
+            (&keymap.thrust_radial, DVec3::Y),
+            (&keymap.thrust_antiradial, DVec3::NEG_Y),
+            // The normal component of the thrust is along the opposite of the third axis of the node
+            (&keymap.thrust_normal, DVec3::NEG_Z),
+            (&keymap.thrust_antinormal, DVec3::Z),
+        ]
+        .into_iter()
+        .find_map(|(key, direction)| key.matches(event).then_some(direction))
+        {
+            internal_event.send(NudgeThrust(direction));
+        } else if keymap.cycle_thrust_step.matches(event) {
+            internal_event.send(CycleThrustStep);
+        } else if keymap.zoom_in.matches(event) {
+            internal_event.send(Zoom(Up));
+        } else if keymap.zoom_out.matches(event) {
+            internal_event.send(Zoom(Down));
+        } else if keymap.cycle_focus.matches(event) {
+            internal_event.send(CycleFocus);
         } else if keymap.open_scheduler.matches(event) {
             // next_screen.set(AppScreen::Scheduler(context.ship_info.id));
         } else if keymap.back.matches(event) {
@@ -280,29 +440,98 @@ fn read_input(
     }
 }
 
+/// Whether the node form is open, in which case it takes every key press, the global keys included
+pub(crate) fn form_focused(context: Option<Res<EditorContext>>) -> bool {
+    context.is_some_and(|context| context.form.is_some())
+}
+
+fn read_mouse(
+    context: Res<EditorContext>,
+    mut gestures: EventReader<MouseGesture>,
+    mut internal_event: EventWriter<EditorEvents>,
+) {
+    use EditorEvents::*;
+    // The node form is only filled from the keyboard
+    if context.form.is_some() {
+        gestures.clear();
+        return;
+    }
+    for gesture in gestures.read() {
+        let on_map = |column, row| area_contains(context.map_area, column, row);
+        internal_event.send(match *gesture {
+            MouseGesture::Click { column, row } => SelectAt(column, row),
+            MouseGesture::Scroll {
+                column,
+                row,
+                direction,
+            } if on_map(column, row) => Zoom(direction),
+            MouseGesture::Scroll { direction, .. } => SelectAdjacent(direction),
+            MouseGesture::Drag { column, row, delta } if on_map(column, row) => DragMap(delta),
+            MouseGesture::Drag { .. } => continue,
+        });
+    }
+}
+
 #[derive(Event, Clone, Copy)]
 pub enum EditorEvents {
     SelectAdjacent(Direction2),
+    /// Selects the node drawn at the given column and row of the terminal
+    SelectAt(u16, u16),
     SelectNearestOrInsert(u64),
+    /// Inserts a node a few ticks after the selected one, or after the current tick
+    NewNode,
+    /// Opens the form of the selected node
+    EditNode,
+    DeleteSelected,
+    Undo,
+    Redo,
+    SubmitForm,
+    CycleTarget,
     CreateSchedule(ShipID),
+    /// Adds the current thrust step to the selected node, in the given direction of its frame
+    NudgeThrust(DVec3),
+    CycleThrustStep,
+    Zoom(Direction2),
+    /// Moves the map by the given number of columns and rows, as dragged by the mouse
+    DragMap((i32, i32)),
+    /// Focuses the map on the next body influencing the ship, which the predictions are then relative to
+    CycleFocus,
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_editor_events(
     mut context: ResMut<EditorContext>,
     mut events: EventReader<EditorEvents>,
     bodies: Query<&BodyInfo>,
     primary: Query<&BodyInfo, With<PrimaryBody>>,
-    space_map: Res<SpaceMap>,
+    mapping: Res<BodiesMapping>,
+    ships: Res<ShipsMapping>,
+    mut space_map: ResMut<SpaceMap>,
+    influence: Query<&Influenced>,
+    mut reload: EventWriter<ReloadPredictions>,
 ) {
     for event in events.read() {
         match *event {
             EditorEvents::SelectAdjacent(d) => context.select_adjacent(d),
-            EditorEvents::SelectNearestOrInsert(simtick) => {
+            EditorEvents::SelectAt(column, row) => {
+                context.select_at(column, row);
+            }
+            EditorEvents::SelectNearestOrInsert(_) | EditorEvents::NewNode => {
+                // Nodes are applied at the start of their tick, so the one of a prediction is the next tick
+                let tick = match *event {
+                    EditorEvents::SelectNearestOrInsert(simtick) => simtick
+                        .div_ceil(SIMTICKS_PER_TICK)
+                        .max(context.first_node_tick()),
+                    _ => context.new_node_tick(),
+                };
                 let origin = space_map
                     .focus_body
                     .map_or(primary.single().0.id, |e| bodies.get(e).unwrap().0.id);
+                if context.get_node(tick).is_none() {
+                    context.record_edit();
+                }
                 context.select_or_insert(
-                    simtick / SIMTICKS_PER_TICK,
+                    tick,
                     ManeuverNode {
                         name: "Node".into(),
                         thrust: DVec3::ZERO,
@@ -310,24 +539,126 @@ fn handle_editor_events(
                     },
                 );
             }
-            _=> return,
+            EditorEvents::EditNode => {
+                context.form = context
+                    .selected_entry()
+                    .map(|(&tick, node)| NodeForm::new(tick, node));
+            }
+            EditorEvents::DeleteSelected => {
+                if let Some(tick) = context.selected_tick() {
+                    context.record_edit();
+                    context.nodes.remove(&tick);
+                    let last = context.len().checked_sub(1);
+                    let selected = context.list_state.selected();
+                    context
+                        .list_state
+                        .select(last.map(|last| selected.unwrap_or_default().min(last)));
+                    reload.send_default();
+                }
+            }
+            EditorEvents::CycleTarget => {
+                let mut ids: Vec<_> = ships
+                    .0
+                    .keys()
+                    .filter(|id| **id != context.ship_info.id)
+                    .copied()
+                    .collect();
+                ids.sort();
+                context.target = match context
+                    .target
+                    .and_then(|t| ids.iter().position(|id| *id == t))
+                {
+                    Some(i) => ids.get(i + 1).copied(),
+                    None => ids.first().copied(),
+                };
+                reload.send_default();
+            }
+            EditorEvents::NudgeThrust(direction) => {
+                if context.selected_entry().is_none() {
+                    continue;
+                }
+                context.record_edit();
+                let step = THRUST_STEPS[context.thrust_step];
+                if let Some(node) = context.selected_node_mut() {
+                    node.thrust += step * direction;
+                }
+                reload.send_default();
+            }
+            EditorEvents::CycleThrustStep => {
+                context.thrust_step = (context.thrust_step + 1) % THRUST_STEPS.len();
+            }
+            EditorEvents::Zoom(d) => space_map.zoom(d),
+            EditorEvents::DragMap(delta) => space_map.drag(context.map_area, delta),
+            EditorEvents::CycleFocus => {
+                let Ok(influence) = influence.get(context.ship) else {
+                    continue;
+                };
+                let bodies = &influence.influencers;
+                space_map.focus_body = match space_map
+                    .focus_body
+                    .and_then(|f| bodies.iter().position(|b| *b == f))
+                {
+                    Some(i) => bodies.get((i + 1) % bodies.len()).copied(),
+                    None => bodies.first().copied(),
+                };
+                reload.send_default();
+            }
+            EditorEvents::SubmitForm => {
+                let Some(form) = &context.form else {
+                    continue;
+                };
+                let current_tick = context.simtick.div_ceil(SIMTICKS_PER_TICK);
+                match form.to_node(&mapping.0, &context.nodes, current_tick) {
+                    Ok((tick, node)) => {
+                        let old_tick = form.tick;
+                        context.record_edit();
+                        context.nodes.remove(&old_tick);
+                        context.nodes.insert(tick, node);
+                        context.select_tick(tick);
+                        context.form = None;
+                        reload.send_default();
+                    }
+                    Err(err) => {
+                        if let Some(form) = &mut context.form {
+                            form.error = Some(err.to_string());
+                        }
+                    }
+                }
+            }
+            _ => {}
         }
     }
 }
 
-fn handle_select_prediction(
-    mut select_events: EventReader<SelectObjectEvent>,
-    mut editor_events: EventWriter<EditorEvents>,
-    predictions: Query<&Prediction>,
+/// Draws the predictions of the ship and of its target on the map, along with the selected node
+fn update_map(
+    mut context: ResMut<EditorContext>,
+    space_map: Res<SpaceMap>,
+    bodies: Query<(Entity, &Position, &BodyInfo)>,
+    ships: Query<(Entity, &Position), With<ShipInfo>>,
+    orbits: Query<(Entity, &EllipticalOrbit, &Position, Option<&BodyInfo>)>,
 ) {
-    for event in select_events.read() {
-        if let Ok(p) = predictions.get(event.entity) {
-            editor_events.send(EditorEvents::SelectNearestOrInsert(p.simtick));
-        }
-    }
+    let node = context
+        .selected_prediction()
+        .map(|(pos, _)| (pos, Color::LightRed));
+    let context = context.as_mut();
+    let map = &mut context.space_map;
+    map.update_map(&space_map, &bodies, &ships, &orbits);
+    let path = |predictions: &[Prediction]| -> Vec<DVec3> {
+        predictions.iter().map(|p| p.pos).collect()
+    };
+    map.set_paths(
+        &space_map,
+        [
+            (path(&context.predictions), Color::White),
+            (path(&context.temp_predictions), Color::Yellow),
+            (path(&context.target_predictions), Color::Cyan),
+        ],
+    );
+    map.set_markers(&space_map, node);
 }
 
-impl StatefulWidget for EditorScreen {
+impl StatefulWidget for EditorScreen<'_> {
     type State = EditorContext;
 
     fn render(
@@ -338,17 +669,98 @@ impl StatefulWidget for EditorScreen {
     ) {
         let chunks =
             Layout::horizontal([Constraint::Percentage(30), Constraint::Fill(1)]).split(area);
+        let block = Block::bordered()
+            .title_top("Maneuver nodes")
+            .title_bottom(format!(
+                "{} undo / {} redo",
+                state.history.undo_len(),
+                state.history.redo_len()
+            ));
+        state.list_area = block.inner(chunks[0]);
         let list = List::new(state.nodes.values().map(|n| &n.name[..]))
             .highlight_symbol(">")
-            .block(Block::bordered().title_top("Maneuver nodes"));
+            .block(block);
         StatefulWidget::render(list, chunks[0], buf, &mut state.list_state);
 
+        let info = if let Some(target) = state.target {
+            let info = Layout::vertical([Constraint::Fill(1), Constraint::Length(8)]).split(chunks[1]);
+            // Closest distance to the target over the simticks of each column, the predictions being unevenly
+            // spaced
+            let width = info[1].width.saturating_sub(2).max(1) as usize;
+            let mut data = vec![u64::MAX; width];
+            for (a, b) in aligned(&state.predictions, &state.target_predictions) {
+                let column = ((a.simtick - state.simtick - 1) as usize * width
+                    / state.horizon.max(1))
+                .min(width - 1);
+                data[column] = data[column].min(a.pos.distance(b.pos) as u64);
+            }
+            // Columns between two predictions keep the distance of the previous one
+            let mut last = 0;
+            for d in &mut data {
+                if *d == u64::MAX {
+                    *d = last;
+                } else {
+                    last = *d;
+                }
+            }
+            Sparkline::default()
+                .data(&data)
+                .block(Block::bordered().title_top(format!("Distance to {} (km)", target)))
+                .render(info[1], buf);
+            info[0]
+        } else {
+            chunks[1]
+        };
+        let mut text = vec![format!(
+            "Horizon: {:.2} days ({} predictions)\nThrust step: {} km/d",
+            state.horizon as f64 * GAMETIME_PER_SIMTICK,
+            state.predictions.len(),
+            THRUST_STEPS[state.thrust_step]
+        )];
         if let Some((tick, node)) = state.selected_entry() {
-            Paragraph::new(format!(
+            text.push(format!(
                 "Tick: {}\nThrust: {}\nOrigin: {}",
                 tick, node.thrust, node.origin
-            ))
-            .render(chunks[1], buf);
+            ));
+        }
+        if let Some(target) = state.target {
+            text.push(format!("Target: {}", target));
+            if let Some((simtick, distance)) = state.closest_approach() {
+                text.push(format!(
+                    "Closest approach: {:.0} km at tick {} ({})",
+                    distance,
+                    simtick / SIMTICKS_PER_TICK,
+                    format_epoch(simtick)
+                ));
+            }
+        }
+        let text = text.join("\n");
+        let info = Layout::vertical([
+            Constraint::Fill(1),
+            Constraint::Length(text.lines().count() as u16),
+        ])
+        .split(info);
+        state.map_area = info[0];
+        state.space_map.render_ref(info[0], buf, self.map);
+        Paragraph::new(text).render(info[1], buf);
+
+        // Node edition form
+        if let Some(form) = &mut state.form {
+            Clear.render(chunks[1], buf);
+            let mut constraints = [Constraint::Length(3)].repeat(6);
+            constraints.push(Constraint::Fill(1));
+            let fields = Layout::vertical(constraints).split(chunks[1]);
+            for i in 0..6 {
+                form.paragraph(i).render(fields[i], buf);
+            }
+            let text = [form.date(), form.error.clone()]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join("\n");
+            Paragraph::new(text)
+                .block(Block::bordered().title_top("Edit node"))
+                .render(fields[6], buf);
         }
     }
 }
@@ -1,44 +1,41 @@
 use std::time::Duration;
 
 use crate::{
-    game::GameFiles,
     objects::{
-        ships::trajectory::{read_ship_trajectory, Trajectory, TrajectoryEvent},
+        ships::trajectory::{Trajectory, TrajectoryEvent, TrajectoryStore},
         orbiting_obj::OrbitingObjects,
     },
     physics::{
         influence::HillRadius,
-        predictions::{Prediction, PredictionStart},
+        predictions::{
+            BodiesSnapshot, FleetMember, PredictionHorizon, PredictionStart, PredictionTask,
+            Sampling,
+        },
     },
     prelude::*,
-    ui::gui::SelectionRadius,
 };
-use bevy::{math::DVec3, prelude::*};
+use bevy::{ecs::system::SystemParam, math::DVec3, prelude::*};
 
-use super::{ClearOnEditorExit, EditorContext};
+use super::{EditorContext, EditorEvents};
 
-pub const PREDICTIONS_NUMBER: usize = 10_000;
 const PREDICTION_DELAY: Duration = Duration::from_millis(100);
-const PREDICTIONS_ADD_STEP: isize = 100;
+/// Number of pixels to scroll to change the horizon by one step
+const HORIZON_PIXELS_PER_STEP: f64 = 100.;
 const TICK_ADD_STEP: isize = 1;
 
 pub fn plugin(app: &mut App) {
     app.add_event::<UpdateThrust>()
         .add_event::<ConfirmThrust>()
         .add_event::<PredictionDelayEvent>()
-        .add_event::<ChangePredictionsNumber>()
+        .add_event::<ChangePredictionsHorizon>()
         .add_event::<ChangeNodeTick>()
         .add_event::<ReloadPredictions>()
+        .add_event::<PredictionsReady>()
         .init_resource::<PredictionDelay>()
-        .init_resource::<NumberOfPredictions>()
+        .init_resource::<PredictionSettings>()
         .add_systems(
             OnEnter(super::InEditor),
-            (
-                read_nodes.pipe(exit_on_error_if_app),
-                create_predictions,
-                update_temp_predictions,
-                copy_predictions,
-            )
+            (read_nodes.pipe(exit_on_error_if_app), start_predictions)
                 .chain()
                 .after(super::create_screen),
         )
@@ -50,47 +47,44 @@ pub fn plugin(app: &mut App) {
             Update,
             (
                 (
-                    handle_change_predictions_number.run_if(on_event::<ChangePredictionsNumber>()),
+                    handle_change_predictions_horizon
+                        .run_if(on_event::<ChangePredictionsHorizon>()),
                     handle_change_node_tick.run_if(on_event::<ChangeNodeTick>()),
                     handle_change_focus.run_if(
                         resource_exists::<Events<SelectObjectEvent>>
                             .and_then(on_event::<SelectObjectEvent>()),
                     ),
-                    (
-                        explicitly_clear_predictions,
-                        create_predictions,
-                        update_temp_predictions,
-                        copy_predictions,
-                    )
-                        .chain()
-                        .run_if(on_event::<ReloadPredictions>()),
+                    start_predictions.run_if(on_event::<ReloadPredictions>()),
                 )
                     .chain(),
                 handle_update_thrust.run_if(on_event::<UpdateThrust>()),
                 (
                     tick_prediction_delay,
-                    update_temp_predictions.run_if(on_event::<PredictionDelayEvent>()),
+                    start_temp_predictions.run_if(on_event::<PredictionDelayEvent>()),
                 )
                     .chain(),
-                (
-                    handle_confirm_thrust,
-                    update_temp_predictions,
-                    copy_predictions,
-                )
+                (handle_confirm_thrust, start_predictions)
                     .chain()
                     .run_if(on_event::<ConfirmThrust>()),
+                handle_undo_redo.run_if(on_event::<EditorEvents>()),
             )
                 .run_if(resource_exists::<EditorContext>)
                 .in_set(EventHandling),
+        )
+        .add_systems(
+            Update,
+            receive_predictions
+                .after(EventHandling)
+                .run_if(resource_exists::<EditorContext>),
         );
 }
 
 fn read_nodes(
     mut context: ResMut<EditorContext>,
-    gamefiles: Res<GameFiles>,
+    store: Res<TrajectoryStore>,
 ) -> color_eyre::Result<()> {
-    if let Ok(traj) = read_ship_trajectory(&gamefiles.trajectories, context.ship_info.id) {
-        context.nodes = traj.nodes;
+    if let Some(traj) = store.get(&context.ship_info.id) {
+        context.nodes = traj.nodes.clone();
     }
     Ok(())
 }
@@ -108,99 +102,222 @@ fn save_trajectory(mut traj_event: EventWriter<TrajectoryEvent>, ctx: Res<Editor
     ]);
 }
 
-#[derive(Bundle, Clone)]
-struct PredictionBundle {
-    prediction: Prediction,
-    pos: Position,
-    speed: Velocity,
-    transform: TransformBundle,
-    clear: ClearOnEditorExit,
+/// Predictions being computed in the background
+pub(super) struct RunningPredictions {
+    task: PredictionTask,
+    /// Whether the predictions received are also shown as the temporary ones, which stops when predictions of an
+    /// edited thrust are started
+    shown: bool,
+    /// Focus of the map when the predictions were started
+    focus: Option<Entity>,
+}
+
+#[derive(SystemParam)]
+struct PredictionParams<'w, 's> {
+    settings: Res<'w, PredictionSettings>,
+    ships: Query<
+        'w,
+        's,
+        (
+            &'static Position,
+            &'static Velocity,
+            &'static Acceleration,
+            &'static Influenced,
+            Option<&'static ForceModels>,
+        ),
+    >,
+    ships_mapping: Res<'w, ShipsMapping>,
+    store: Res<'w, TrajectoryStore>,
+    bodies: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static EllipticalOrbit,
+            &'static BodyInfo,
+            &'static HillRadius,
+        ),
+    >,
+    orbiting: Query<'w, 's, &'static OrbitingObjects>,
+    oblate_bodies: Query<'w, 's, (&'static ZonalHarmonics, &'static BodyRotation)>,
+    mapping: Res<'w, BodiesMapping>,
+    space_map: Res<'w, SpaceMap>,
+    time: Res<'w, GameTime>,
 }
-impl PredictionBundle {
-    fn from_prediction(prediction: Prediction) -> Self {
-        Self {
-            prediction,
-            transform: TransformBundle::from_transform(Transform::from_xyz(0., 0., -3.)),
-            pos: Position::default(),
-            speed: Velocity::default(),
-            clear: ClearOnEditorExit,
+
+impl PredictionParams<'_, '_> {
+    /// Starts computing the predictions of the ship, with the thrust being edited added to the selected node,
+    /// along with the ones of the target unless only the thrust changed. Committed predictions cancel every
+    /// computation still running, while temporary ones only cancel the previous temporary ones and let the
+    /// predictions of the nodes be received in the background
+    fn spawn(&self, ctx: &mut EditorContext, commit: bool) {
+        // The ship may have been removed while it was edited, the previous predictions are then kept
+        let Ok((_, _, &Acceleration { current: acc, .. }, influence, forces)) =
+            self.ships.get(ctx.ship)
+        else {
+            warn!("Ship {} not found, its predictions are not updated", ctx.ship_info.id);
+            return;
+        };
+        let start = PredictionStart {
+            pos: ctx.pos,
+            speed: ctx.speed,
+            simtick: ctx.simtick,
+            acc,
+        };
+        let mut nodes = ctx.nodes.clone();
+        if let (Some(tick), Some(thrust)) = (ctx.selected_tick(), ctx.editing_data) {
+            nodes.get_mut(&tick).unwrap().thrust += thrust;
+        }
+        let bodies = BodiesSnapshot::new(
+            &self.bodies,
+            &self.orbiting,
+            &self.oblate_bodies,
+            &self.mapping.0,
+        );
+        ctx.horizon = self.settings.horizon.simticks(&start, influence, &bodies);
+        let reference = self.space_map.focus_body.or(influence.main_influencer);
+        let mut members = vec![FleetMember {
+            start,
+            influence: influence.clone(),
+            forces: forces.cloned(),
+            nodes,
+        }];
+        let target = ctx
+            .target
+            .filter(|_| commit)
+            .and_then(|id| Some((id, self.ships.get(*self.ships_mapping.0.get(&id)?).ok()?)));
+        // The target is at its live state, which is ahead of the ship if time advanced since the editor was
+        // opened: it is then only predicted from the current simtick
+        if let Some((id, (&Position(pos), &Velocity(speed), acc, influence, forces))) = target {
+            members.push(FleetMember {
+                start: PredictionStart {
+                    pos,
+                    speed,
+                    acc: acc.current,
+                    simtick: self.time.simtick.max(ctx.simtick),
+                },
+                influence: influence.clone(),
+                forces: forces.cloned(),
+                nodes: self
+                    .store
+                    .get(&id)
+                    .map(|t| t.nodes.clone())
+                    .unwrap_or_default(),
+            });
+        }
+        let focus = self.space_map.focus_body;
+        let task = PredictionTask::spawn(
+            members,
+            ctx.horizon,
+            reference,
+            bodies,
+            self.settings.sampling,
+        );
+        let running = Some(RunningPredictions {
+            task,
+            shown: true,
+            focus,
+        });
+        ctx.temp_predictions.clear();
+        if commit {
+            ctx.predictions.clear();
+            ctx.target_predictions.clear();
+            // The predictions of the current nodes are unknown until the task is finished
+            ctx.history.set_predictions(Vec::new(), focus);
+            ctx.running = running;
+            ctx.running_temp = None;
+        } else {
+            if let Some(running) = &mut ctx.running {
+                running.shown = false;
+            }
+            ctx.running_temp = running;
         }
     }
 }
-#[derive(Component)]
-pub struct TempPrediction;
 
-fn create_predictions(
-    mut commands: Commands,
-    mut ctx: ResMut<EditorContext>,
-    predictions_number: Res<NumberOfPredictions>,
-) {
-    let (ship, tick) = (ctx.ship, ctx.simtick);
-    (0..predictions_number.0).for_each(|i| {
-        let pred = PredictionBundle::from_prediction(Prediction {
-            ship,
-            index: i,
-            simtick: tick + i as u64,
-        });
-        ctx.predictions.push(
-            commands
-                .spawn((
-                    pred.clone(),
-                    SelectionRadius {
-                        min_radius: MAX_HEIGHT / 100.,
-                        actual_radius: 0.,
-                    },
-                ))
-                .id(),
-        );
-        ctx.temp_predictions
-            .push(commands.spawn((pred, TempPrediction)).id())
-    });
+fn start_predictions(mut ctx: ResMut<EditorContext>, params: PredictionParams) {
+    params.spawn(&mut ctx, true);
 }
 
-fn explicitly_clear_predictions(mut commands: Commands, mut context: ResMut<EditorContext>) {
+fn start_temp_predictions(mut ctx: ResMut<EditorContext>, params: PredictionParams) {
+    params.spawn(&mut ctx, false);
+}
+
+/// Sent once the predictions of the nodes are all computed
+#[derive(Event, Default)]
+pub struct PredictionsReady;
+
+fn receive_predictions(mut ctx: ResMut<EditorContext>, mut ready: EventWriter<PredictionsReady>) {
+    if ctx.running.is_none() && ctx.running_temp.is_none() {
+        return;
+    }
     let EditorContext {
         predictions,
         temp_predictions,
+        target_predictions,
+        history,
+        running,
+        running_temp,
         ..
-    } = context.as_mut();
-    predictions
-        .drain(0..)
-        .chain(temp_predictions.drain(0..))
-        .for_each(|e| commands.entity(e).despawn());
+    } = ctx.as_mut();
+    if let Some(RunningPredictions { task, shown, focus }) = running {
+        let mut received = [Vec::new(), Vec::new()];
+        let finished = task.receive(&mut received);
+        let [own, target] = received;
+        if *shown {
+            temp_predictions.extend_from_slice(&own);
+        }
+        predictions.extend(own);
+        target_predictions.extend(target);
+        if finished {
+            history.set_predictions(predictions.clone(), *focus);
+            ready.send_default();
+            *running = None;
+        }
+    }
+    if let Some(RunningPredictions { task, .. }) = running_temp {
+        let mut received = [Vec::new()];
+        let finished = task.receive(&mut received);
+        let [own] = received;
+        temp_predictions.extend(own);
+        if finished {
+            *running_temp = None;
+        }
+    }
 }
 
-#[derive(Resource, Debug, Clone, Copy)]
-pub struct NumberOfPredictions(pub usize);
-
-#[derive(Event, Clone, Copy)]
-pub struct ChangePredictionsNumber {
-    pub is_step: bool,
-    pub amount: f32,
+/// How far the predictions go and how densely they are kept
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct PredictionSettings {
+    pub horizon: PredictionHorizon,
+    pub sampling: Sampling,
 }
 
-impl Default for NumberOfPredictions {
-    fn default() -> Self {
-        Self(PREDICTIONS_NUMBER)
-    }
+#[derive(Event, Clone, Copy)]
+pub enum ChangePredictionsHorizon {
+    Scroll { is_step: bool, amount: f32 },
+    CycleUnit,
 }
 
 #[derive(Event, Default)]
 pub struct ReloadPredictions;
 
-fn handle_change_predictions_number(
-    mut events: EventReader<ChangePredictionsNumber>,
-    mut number: ResMut<NumberOfPredictions>,
+fn handle_change_predictions_horizon(
+    mut events: EventReader<ChangePredictionsHorizon>,
+    mut settings: ResMut<PredictionSettings>,
     mut reload: EventWriter<ReloadPredictions>,
 ) {
     for event in events.read() {
-        number.0 = number.0.saturating_add_signed(
-            (if event.is_step {
-                PREDICTIONS_ADD_STEP as f32
-            } else {
-                1.
-            } * event.amount) as isize,
-        );
+        match *event {
+            ChangePredictionsHorizon::Scroll { is_step, amount } => {
+                settings.horizon.add_steps(if is_step {
+                    amount as f64
+                } else {
+                    amount as f64 / HORIZON_PIXELS_PER_STEP
+                })
+            }
+            ChangePredictionsHorizon::CycleUnit => settings.horizon.cycle_unit(),
+        }
         reload.send_default();
     }
 }
@@ -225,7 +342,10 @@ fn handle_change_node_tick(
                     1.
                 } * event.amount) as i64,
             );
-            ctx.change_tick(tick, newtick);
+            if newtick != tick {
+                ctx.record_edit();
+                ctx.change_tick(tick, newtick);
+            }
         }
         reload.send_default();
     }
@@ -258,6 +378,9 @@ fn handle_confirm_thrust(
 ) {
     if let Some(thrust) = context.editing_data {
         let ship = context.ship_info.id;
+        if context.selected_entry().is_some() {
+            context.record_edit();
+        }
         if let Some((&tick, node)) = context.selected_entry_mut() {
             node.thrust += thrust;
             traj_event.send(TrajectoryEvent::AddNode {
@@ -305,57 +428,184 @@ fn tick_prediction_delay(
     }
 }
 
-#[allow(clippy::too_many_arguments)]
-fn update_temp_predictions(
-    ctx: Res<EditorContext>,
-    predictions_number: Res<NumberOfPredictions>,
-    query: Query<(&Acceleration, &Influenced)>,
-    mut bodies: Query<(&EllipticalOrbit, &BodyInfo, &HillRadius)>,
-    orbiting: Query<&OrbitingObjects>,
-    bodies_mapping: Res<BodiesMapping>,
-    mut coords: Query<(&mut Position, &mut Velocity), With<TempPrediction>>,
+/// Restores the nodes from the history, along with their predictions unless they were computed with another
+/// horizon or relative to another body
+fn handle_undo_redo(
+    mut events: EventReader<EditorEvents>,
+    mut ctx: ResMut<EditorContext>,
     space_map: Res<SpaceMap>,
+    mut reload: EventWriter<ReloadPredictions>,
 ) {
-    let (&Acceleration { current: acc, .. }, influence) = query.get(ctx.ship).unwrap();
-    let start = PredictionStart {
-        pos: ctx.pos,
-        speed: ctx.speed,
-        simtick: ctx.simtick,
-        acc,
+    for event in events.read() {
+        let EditorContext {
+            nodes,
+            list_state,
+            history,
+            ..
+        } = ctx.as_mut();
+        let snapshot = match event {
+            EditorEvents::Undo => history.undo(nodes, list_state.selected()),
+            EditorEvents::Redo => history.redo(nodes, list_state.selected()),
+            _ => None,
+        };
+        let Some(snapshot) = snapshot else {
+            continue;
+        };
+        ctx.nodes = snapshot.nodes;
+        ctx.list_state.select(snapshot.selected);
+        // The predictions of the target may not have been received yet
+        if snapshot.reference != space_map.focus_body
+            || snapshot.predictions.last().map(|p| p.simtick)
+                != Some(ctx.simtick + ctx.horizon as u64)
+            || ctx.running.is_some()
+        {
+            reload.send_default();
+            continue;
+        }
+        ctx.running_temp = None;
+        ctx.predictions = snapshot.predictions.to_vec();
+        ctx.temp_predictions = snapshot.predictions.to_vec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{math::DVec3, prelude::*};
+    use bevy_ratatui::event::KeyEvent;
+    use crossterm::event::{KeyCode, KeyEvent as CKeyEvent, KeyModifiers};
+
+    use crate::{
+        prelude::*,
+        ui::{console::Console, palette::Palette, screen::editor::EditorContext},
+        utils::testing::spawn_test_ship,
     };
-    let thrust = ctx.editing_data.unwrap_or_default();
-    let mut nodes = ctx.nodes.clone();
-    if let Some(tick) = ctx.selected_tick() {
-        nodes.get_mut(&tick).unwrap().thrust += thrust;
+
+    use super::{PredictionDelayEvent, PredictionSettings, UpdateThrust};
+    use crate::{
+        physics::{predictions::PredictionHorizon, time::SIMTICKS_PER_TICK},
+        ui::screen::editor::EditorEvents,
+    };
+
+    /// An app in the editor of a ship orbiting the sun, with the given prediction horizon
+    fn editor_app(horizon: Option<PredictionHorizon>) -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            ClientPlugin::testing().in_mode(ClientMode::Singleplayer),
+            TuiPlugin::testing(),
+        ));
+        // The settings are read when the editor is entered
+        if let Some(horizon) = horizon {
+            app.insert_resource(PredictionSettings {
+                horizon,
+                ..Default::default()
+            });
+        }
+        app.update();
+        app.update();
+        let id = id_from("s");
+        spawn_test_ship(&mut app, id);
+        app.update();
+        app.update();
+        app.world_mut()
+            .resource_mut::<NextState<AppScreen>>()
+            .set(AppScreen::Editor(id));
+        app.update();
+        app
     }
-    let reference = space_map.focus_body.or(influence.main_influencer);
-    let predictions = start.compute_predictions(
-        predictions_number.0,
-        influence,
-        reference,
-        &mut bodies.as_query_lens(),
-        &orbiting,
-        &bodies_mapping.0,
-        &nodes,
-    );
-    let mut i = 0;
-    let mut iter = coords.iter_many_mut(&ctx.temp_predictions);
-    while let Some((mut pos, mut speed)) = iter.fetch_next() {
-        (pos.0, speed.0) = predictions[i];
-        i += 1;
+
+    #[test]
+    fn test_background_predictions() {
+        let mut app = editor_app(Some(PredictionHorizon::Days(1.)));
+        // The predictions are streamed in over several frames
+        let mut updates = 0;
+        let finished = |ctx: &EditorContext| {
+            ctx.predictions()
+                .last()
+                .is_some_and(|p| p.simtick == ctx.simtick + 1000)
+        };
+        while !finished(app.world().resource::<EditorContext>()) {
+            app.update();
+            updates += 1;
+            assert!(updates < 10_000);
+        }
+        let ctx = app.world().resource::<EditorContext>();
+        assert_eq!(ctx.temp_predictions(), ctx.predictions());
+        assert_eq!(ctx.horizon(), 1000);
     }
-}
 
-fn copy_predictions(
-    ctx: Res<EditorContext>,
-    new_coords: Query<(&Position, &Velocity), With<TempPrediction>>,
-    mut coords: Query<(&mut Position, &mut Velocity), Without<TempPrediction>>,
-) {
-    let mut new_coords = new_coords.iter_many(&ctx.temp_predictions);
-    let mut iter = coords.iter_many_mut(&ctx.predictions);
-    while let (Some((mut pos, mut speed)), Some((new_pos, new_speed))) =
-        (iter.fetch_next(), new_coords.next())
-    {
-        (pos.0, speed.0) = (new_pos.0, new_speed.0);
+    #[test]
+    fn test_temp_predictions_keep_commit() {
+        let mut app = editor_app(Some(PredictionHorizon::Days(10.)));
+        app.world_mut()
+            .send_event(KeyEvent(CKeyEvent::new(KeyCode::Char('n'), KeyModifiers::NONE)));
+        app.update();
+        // Dragging the thrust while the predictions of the new node are streamed in
+        app.world_mut()
+            .send_event(UpdateThrust(DVec3::new(1e4, 0., 0.)));
+        app.world_mut().send_event(PredictionDelayEvent);
+        app.update();
+        let mut updates = 0;
+        let finished = |ctx: &EditorContext| ctx.running.is_none() && ctx.running_temp.is_none();
+        while !finished(app.world().resource::<EditorContext>()) {
+            app.update();
+            updates += 1;
+            assert!(updates < 10_000);
+        }
+        let ctx = app.world().resource::<EditorContext>();
+        let (last, temp_last) = (ctx.predictions().last(), ctx.temp_predictions().last());
+        assert_eq!(last.map(|p| p.simtick), Some(ctx.simtick + 10_000));
+        assert_eq!(temp_last.map(|p| p.simtick), Some(ctx.simtick + 10_000));
+        assert_ne!(last.unwrap().pos, temp_last.unwrap().pos);
+    }
+
+    #[test]
+    fn test_keyboard_thrust() {
+        let mut app = editor_app(None);
+        let press = |c| KeyEvent(CKeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        app.world_mut().send_event(press('n'));
+        app.update();
+        app.world_mut()
+            .send_event_batch([press('i'), press('i'), press('u')]);
+        app.update();
+        let ctx = app.world().resource::<EditorContext>();
+        let thrust = ctx.selected_node().unwrap().thrust;
+        assert_eq!(thrust, DVec3::new(200., 0., -100.));
+    }
+
+    #[test]
+    fn test_form_takes_global_keys() {
+        let mut app = editor_app(None);
+        let mut press = |key| {
+            app.world_mut()
+                .send_event(KeyEvent(Key::from_str_unchecked(key).into()));
+            app.update();
+        };
+        press("n");
+        press("e");
+        // The keys opening the console and the palette are typed in the form instead
+        press("`");
+        press("C p");
+        let world = app.world();
+        assert!(world.resource::<EditorContext>().form.is_some());
+        assert!(!world.resource::<Console>().is_open());
+        assert!(!world.resource::<Palette>().is_open());
+    }
+
+    #[test]
+    fn test_select_nearest_or_insert() {
+        let mut app = editor_app(None);
+        let simtick = app.world().resource::<EditorContext>().simtick;
+        let mut insert = |simtick| {
+            app.world_mut()
+                .send_event(EditorEvents::SelectNearestOrInsert(simtick));
+            app.update();
+            app.world().resource::<EditorContext>().selected_tick()
+        };
+        // The node of a prediction is at the start of the next tick, and never before the first node tick
+        let first = simtick.div_ceil(SIMTICKS_PER_TICK) + 1;
+        assert_eq!(insert(simtick + 1), Some(first));
+        assert_eq!(insert((first + 4) * SIMTICKS_PER_TICK + 1), Some(first + 5));
+        assert_eq!(insert((first + 5) * SIMTICKS_PER_TICK), Some(first + 5));
+        assert_eq!(app.world().resource::<EditorContext>().nodes.len(), 2);
     }
 }
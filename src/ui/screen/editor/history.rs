@@ -0,0 +1,153 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use bevy::prelude::Entity;
+
+use crate::{objects::ships::trajectory::ManeuverNode, physics::predictions::Prediction};
+
+/// Maximum number of edits that can be undone
+const MAX_HISTORY: usize = 100;
+
+/// State of the editor that undo and redo switch between
+#[derive(Clone, Debug, PartialEq)]
+pub struct EditorSnapshot {
+    pub nodes: BTreeMap<u64, ManeuverNode>,
+    pub selected: Option<usize>,
+    /// Coordinates of the predictions computed for these nodes, shared between the snapshots whose edits
+    /// didn't change them
+    pub predictions: Arc<Vec<Prediction>>,
+    /// Body the predictions were computed relative to
+    pub reference: Option<Entity>,
+}
+
+/// Undo and redo stacks of the edits of the maneuver nodes
+#[derive(Default, Debug)]
+pub struct EditHistory {
+    undo: Vec<EditorSnapshot>,
+    redo: Vec<EditorSnapshot>,
+    /// Predictions of the current state of the editor
+    predictions: Arc<Vec<Prediction>>,
+    reference: Option<Entity>,
+}
+
+impl EditHistory {
+    /// Stores the predictions computed for the current nodes
+    pub fn set_predictions(&mut self, predictions: Vec<Prediction>, reference: Option<Entity>) {
+        self.predictions = Arc::new(predictions);
+        self.reference = reference;
+    }
+
+    fn snapshot(
+        &self,
+        nodes: &BTreeMap<u64, ManeuverNode>,
+        selected: Option<usize>,
+    ) -> EditorSnapshot {
+        EditorSnapshot {
+            nodes: nodes.clone(),
+            selected,
+            predictions: self.predictions.clone(),
+            reference: self.reference,
+        }
+    }
+
+    /// Records the state before an edit, which discards the undone edits
+    pub fn record(&mut self, nodes: &BTreeMap<u64, ManeuverNode>, selected: Option<usize>) {
+        if self.undo.len() == MAX_HISTORY {
+            self.undo.remove(0);
+        }
+        self.undo.push(self.snapshot(nodes, selected));
+        self.redo.clear();
+    }
+
+    /// Returns the state before the last edit, the current one being given so that it can be redone
+    pub fn undo(
+        &mut self,
+        nodes: &BTreeMap<u64, ManeuverNode>,
+        selected: Option<usize>,
+    ) -> Option<EditorSnapshot> {
+        let snapshot = self.undo.pop()?;
+        self.redo.push(self.snapshot(nodes, selected));
+        self.restore(snapshot)
+    }
+
+    /// Returns the state after the last undone edit, the current one being given so that it can be undone again
+    pub fn redo(
+        &mut self,
+        nodes: &BTreeMap<u64, ManeuverNode>,
+        selected: Option<usize>,
+    ) -> Option<EditorSnapshot> {
+        let snapshot = self.redo.pop()?;
+        self.undo.push(self.snapshot(nodes, selected));
+        self.restore(snapshot)
+    }
+
+    fn restore(&mut self, snapshot: EditorSnapshot) -> Option<EditorSnapshot> {
+        self.predictions = snapshot.predictions.clone();
+        self.reference = snapshot.reference;
+        Some(snapshot)
+    }
+
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec3;
+
+    use crate::prelude::id_from;
+
+    use super::*;
+
+    fn nodes(ticks: &[u64]) -> BTreeMap<u64, ManeuverNode> {
+        ticks
+            .iter()
+            .map(|&t| {
+                (
+                    t,
+                    ManeuverNode {
+                        name: t.to_string(),
+                        thrust: DVec3::X,
+                        origin: id_from("terre"),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = EditHistory::default();
+        history.set_predictions(vec![Prediction::default()], None);
+        history.record(&nodes(&[]), None);
+        history.set_predictions(
+            vec![Prediction {
+                pos: DVec3::X,
+                ..Default::default()
+            }],
+            None,
+        );
+        history.record(&nodes(&[1]), Some(0));
+
+        let s = history.undo(&nodes(&[1, 2]), Some(1)).unwrap();
+        assert_eq!(s.nodes, nodes(&[1]));
+        assert_eq!(s.predictions[0].pos, DVec3::X);
+        let s = history.undo(&s.nodes, s.selected).unwrap();
+        assert_eq!(s.nodes, nodes(&[]));
+        assert_eq!(s.predictions[0].pos, DVec3::ZERO);
+        assert!(history.undo(&s.nodes, s.selected).is_none());
+
+        let s = history.redo(&s.nodes, s.selected).unwrap();
+        assert_eq!(s.nodes, nodes(&[1]));
+        assert_eq!(s.selected, Some(0));
+        assert_eq!((history.undo_len(), history.redo_len()), (1, 1));
+
+        // A new edit discards the undone ones
+        history.record(&s.nodes, s.selected);
+        assert_eq!((history.undo_len(), history.redo_len()), (2, 0));
+    }
+}
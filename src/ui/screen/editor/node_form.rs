@@ -0,0 +1,222 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    num::{ParseFloatError, ParseIntError},
+};
+
+use bevy::{math::DVec3, prelude::Entity, utils::HashMap};
+
+use crate::{
+    ephemeris::{format_epoch, parse_epoch},
+    objects::ships::trajectory::ManeuverNode,
+    physics::time::SIMTICKS_PER_TICK,
+    prelude::BodyID,
+    utils::list::OptionsList,
+};
+
+/// Form editing the selected maneuver node. The Δv components are expressed in the axes of
+/// [relative_axes](crate::utils::algebra::relative_axes): prograde, the "right" axis orthogonal to it in the
+/// orbital plane, and anti-normal. The right axis is only radial for near-circular orbits, otherwise it is tilted
+/// from the radial direction by the flight path angle, and so is the Δv of the radial field
+#[derive(Default, Clone, Debug)]
+pub struct NodeForm {
+    /// Tick of the node being edited
+    pub tick: u64,
+    name: String,
+    /// Either a tick or an ISO 8601 date
+    date: String,
+    origin: String,
+    prograde: String,
+    normal: String,
+    radial: String,
+    selected: usize,
+    /// Why the last submission was rejected
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeFormError {
+    ParseFloat(&'static str, ParseFloatError),
+    ParseTick(ParseIntError),
+    InvalidDate(String),
+    EmptyName,
+    UnknownOrigin(String),
+    PastTick { tick: u64, current: u64 },
+    TickTaken(u64),
+}
+
+impl Error for NodeFormError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NodeFormError::ParseFloat(_, e) => Some(e),
+            NodeFormError::ParseTick(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for NodeFormError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeFormError::ParseFloat(field, e) => write!(f, "Invalid {} Δv: {}", field, e),
+            NodeFormError::ParseTick(e) => write!(f, "Invalid tick: {}", e),
+            NodeFormError::InvalidDate(e) => write!(f, "{}", e),
+            NodeFormError::EmptyName => write!(f, "The node must have a name"),
+            NodeFormError::UnknownOrigin(id) => write!(f, "Unknown origin body \"{}\"", id),
+            NodeFormError::PastTick { tick, current } => {
+                write!(f, "Tick {} is before the current tick {}", tick, current)
+            }
+            NodeFormError::TickTaken(tick) => write!(f, "There is already a node at tick {}", tick),
+        }
+    }
+}
+
+impl OptionsList<6> for NodeForm {
+    fn current_index(&mut self) -> &mut usize {
+        &mut self.selected
+    }
+
+    fn fields_list(&mut self) -> [(&mut String, String); 6] {
+        [
+            (&mut self.name, "Name".into()),
+            (&mut self.date, "Tick or date".into()),
+            (&mut self.origin, "Origin body id".into()),
+            (&mut self.prograde, "Prograde Δv (km/d)".into()),
+            (&mut self.normal, "Normal Δv (km/d)".into()),
+            (&mut self.radial, "Radial Δv (km/d)".into()),
+        ]
+    }
+}
+
+impl NodeForm {
+    pub fn new(tick: u64, node: &ManeuverNode) -> Self {
+        Self {
+            tick,
+            name: node.name.clone(),
+            date: tick.to_string(),
+            origin: node.origin.to_string(),
+            prograde: node.thrust.x.to_string(),
+            normal: (-node.thrust.z).to_string(),
+            radial: node.thrust.y.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Calendar date of the node, if the tick field holds a valid tick
+    pub fn date(&self) -> Option<String> {
+        self.date
+            .parse::<u64>()
+            .ok()
+            .map(|tick| format_epoch(tick * SIMTICKS_PER_TICK))
+    }
+
+    fn parse_tick(&self) -> Result<u64, NodeFormError> {
+        if self.date.contains('T') {
+            parse_epoch(&self.date)
+                .map(|simtick| simtick / SIMTICKS_PER_TICK)
+                .map_err(|e| NodeFormError::InvalidDate(e.to_string()))
+        } else {
+            self.date.trim().parse().map_err(NodeFormError::ParseTick)
+        }
+    }
+
+    /// Validates the fields, returning the new tick of the node along with the node itself. The node cannot be
+    /// moved before the current tick nor onto another node
+    pub fn to_node(
+        &self,
+        bodies: &HashMap<BodyID, Entity>,
+        nodes: &BTreeMap<u64, ManeuverNode>,
+        current_tick: u64,
+    ) -> Result<(u64, ManeuverNode), NodeFormError> {
+        let parse = |field: &'static str, s: &str| {
+            s.trim()
+                .parse::<f64>()
+                .map_err(|e| NodeFormError::ParseFloat(field, e))
+        };
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err(NodeFormError::EmptyName);
+        }
+        let tick = self.parse_tick()?;
+        if tick < current_tick {
+            return Err(NodeFormError::PastTick {
+                tick,
+                current: current_tick,
+            });
+        }
+        if tick != self.tick && nodes.contains_key(&tick) {
+            return Err(NodeFormError::TickTaken(tick));
+        }
+        let origin = BodyID::from(self.origin.trim())
+            .ok()
+            .filter(|id| bodies.contains_key(id))
+            .ok_or_else(|| NodeFormError::UnknownOrigin(self.origin.clone()))?;
+        let thrust = DVec3::new(
+            parse("prograde", &self.prograde)?,
+            parse("radial", &self.radial)?,
+            -parse("normal", &self.normal)?,
+        );
+        Ok((
+            tick,
+            ManeuverNode {
+                name: name.into(),
+                thrust,
+                origin,
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::id_from;
+
+    use super::*;
+
+    #[test]
+    fn test_node_form() {
+        let node = ManeuverNode {
+            name: "Burn".into(),
+            thrust: DVec3::new(1., 2., 3.),
+            origin: id_from("terre"),
+        };
+        let bodies = HashMap::from([(id_from("terre"), Entity::PLACEHOLDER)]);
+        let nodes = BTreeMap::from([(10, node.clone()), (20, node.clone())]);
+        let mut form = NodeForm::new(10, &node);
+        assert_eq!(form.to_node(&bodies, &nodes, 5), Ok((10, node.clone())));
+        assert!(matches!(
+            form.to_node(&bodies, &nodes, 11),
+            Err(NodeFormError::PastTick { .. })
+        ));
+
+        form.date = "20".into();
+        assert_eq!(
+            form.to_node(&bodies, &nodes, 0),
+            Err(NodeFormError::TickTaken(20))
+        );
+        form.date = "2000-01-01T12:00:00.4Z".into();
+        assert_eq!(form.to_node(&bodies, &nodes, 0).unwrap().0, 0);
+        form.date = "2000-01-01T12:00".into();
+        assert!(matches!(
+            form.to_node(&bodies, &nodes, 0),
+            Err(NodeFormError::InvalidDate(_))
+        ));
+
+        form.date = "15".into();
+        form.prograde = "1e-3".into();
+        form.normal = "x".into();
+        assert!(matches!(
+            form.to_node(&bodies, &nodes, 0),
+            Err(NodeFormError::ParseFloat("normal", _))
+        ));
+        form.normal = " -2".into();
+        let (tick, edited) = form.to_node(&bodies, &nodes, 0).unwrap();
+        assert_eq!((tick, edited.thrust), (15, DVec3::new(1e-3, 2., 2.)));
+
+        form.origin = "lune".into();
+        assert_eq!(
+            form.to_node(&bodies, &nodes, 0),
+            Err(NodeFormError::UnknownOrigin("lune".into()))
+        );
+    }
+}
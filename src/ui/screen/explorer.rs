@@ -1,30 +1,47 @@
-use bevy::{prelude::*, utils::HashMap};
-use bevy_ratatui::event::KeyEvent;
-use crossterm::event::{KeyCode, KeyEvent as CKeyEvent, KeyEventKind};
+use bevy::prelude::*;
+use crossterm::event::{KeyCode, KeyEvent as CKeyEvent};
 use ratatui::{
-    layout::{Constraint, Layout},
+    layout::{Constraint, Layout, Rect},
+    style::Color,
     widgets::{StatefulWidget, StatefulWidgetRef, WidgetRef},
 };
 
 use crate::{
     client::ClientMode,
     game::GameStage,
-    physics::{orbit::SystemSize, time::TimeEvent},
+    physics::{
+        forces::ForceModels,
+        harmonics::ZonalHarmonics,
+        influence::{HillRadius, Influenced},
+        leapfrog::Acceleration,
+        orbit::{EllipticalOrbit, OsculatingElements, SystemSize},
+        predictions::{
+            BodiesSnapshot, FleetMember, Prediction, PredictionHorizon, PredictionStart,
+            PredictionTask, Sampling,
+        },
+        rotation::BodyRotation,
+        time::{GameTime, TimeEvent, GAMETIME_PER_SIMTICK, SIMTICKS_PER_TICK},
+        Mass, G, SECONDS_PER_DAY,
+    },
     ui::{
         gui::SelectObjectEvent,
         widget::{
-            info::InfoWidget,
+            info::{InfoWidget, ObjectDetails, OrbitInfo},
             search::{SearchPlugin, SearchState, SearchWidget},
             space_map::{SpaceMap, SpaceMapWidget},
             tree::{TreeState, TreeWidget},
         },
         UiUpdate,
     },
-    utils::list::ClampedList,
+    utils::{list::ClampedList, ui::area_contains},
 };
-use crate::{input::prelude::Keymap, objects::prelude::*};
 use crate::{
-    physics::Position,
+    input::prelude::{KeyPress, Keymap, MouseGesture},
+    objects::prelude::*,
+};
+use crate::{
+    objects::ships::{trajectory::TrajectoryStore, HostBody},
+    physics::{Position, Velocity},
     ui::{
         prelude::*,
         widget::{
@@ -34,7 +51,7 @@ use crate::{
         },
     },
 };
-use crate::objects::orbiting_obj::OrbitingObjects;
+use crate::objects::orbiting_obj::{OrbitalObjID, OrbitingObjects};
 
 use super::PreviousScreen;
 
@@ -44,7 +61,7 @@ pub fn plugin(app: &mut App) {
         .add_systems(
             Update,
             (
-                read_input.in_set(InputReading),
+                (read_input, read_mouse).in_set(InputReading),
                 (
                     handle_explorer_events,
                     focus_on_select_body.run_if(
@@ -53,7 +70,12 @@ pub fn plugin(app: &mut App) {
                     ),
                 )
                     .in_set(EventHandling),
-                update_space_map.in_set(UiUpdate),
+                (
+                    update_tree_ships,
+                    (update_space_map, update_ship_path).chain(),
+                    update_info.run_if(|ctx: Res<ExplorerContext>| ctx.info_toggle),
+                )
+                    .in_set(UiUpdate),
             )
                 .run_if(in_loaded_screen::<ExplorerContext>(AppScreen::Explorer)),
         )
@@ -68,13 +90,51 @@ fn create_screen(
     mut commands: Commands,
     primary: Query<Entity, With<PrimaryBody>>,
     bodies: Query<(&BodyInfo, &OrbitingObjects)>,
+    ships: ShipHosts,
     system_size: Res<SystemSize>,
     mapping: Res<BodiesMapping>,
     query: Query<&OrbitingObjects>
 ) {
     let primary = primary.single();
     commands.insert_resource(SpaceMap::new(system_size.0, Some(primary), Some(primary)));
-    commands.insert_resource(ExplorerContext::new(primary, &bodies,mapping, query));
+    commands.insert_resource(ExplorerContext::new(primary, &bodies, &ships, mapping, query));
+}
+
+/// Ships along with what determines their main body: their main influencer when they move freely, or the
+/// host of their orbit
+type ShipHosts<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static ShipInfo,
+        Option<&'static Influenced>,
+        Option<&'static HostBody>,
+    ),
+>;
+
+fn ship_host(
+    influence: Option<&Influenced>,
+    host: Option<&HostBody>,
+    mapping: &BodiesMapping,
+) -> Option<Entity> {
+    influence
+        .and_then(|i| i.main_influencer)
+        .or_else(|| host.and_then(|h| mapping.0.get(&h.0).copied()))
+}
+
+/// Ships along with the body they are listed under in the tree, which is their main body
+fn ships_parents<'a>(
+    ships: &'a ShipHosts<'_, '_>,
+    bodies: &'a Query<(&BodyInfo, &OrbitingObjects)>,
+    mapping: &'a BodiesMapping,
+    primary: BodyID,
+) -> impl Iterator<Item = (ShipID, BodyID)> + 'a {
+    ships.iter().map(move |(info, influence, host)| {
+        let parent = ship_host(influence, host, mapping)
+            .and_then(|e| bodies.get(e).ok())
+            .map_or(primary, |(body, _)| body.0.id);
+        (info.id, parent)
+    })
 }
 
 fn clear_screen(mut commands: Commands) {
@@ -89,6 +149,20 @@ pub enum SidePaneMode {
     Search,
 }
 
+/// Simticks after which the predicted path of the selected ship is computed again
+const PATH_REFRESH_SIMTICKS: u64 = 10 * SIMTICKS_PER_TICK;
+
+/// Predicted path of the ship selected in the explorer
+pub struct ShipPath {
+    ship: Entity,
+    /// Focus of the map when the predictions were started
+    focus: Option<Entity>,
+    simtick: u64,
+    /// Predictions being computed, and the ones received so far
+    task: Option<(PredictionTask, Vec<Vec<Prediction>>)>,
+    predictions: Vec<Prediction>,
+}
+
 #[derive(Resource)]
 pub struct ExplorerContext {
     pub side_pane_mode: SidePaneMode,
@@ -97,29 +171,39 @@ pub struct ExplorerContext {
     pub search_state: SearchState,
     pub info: InfoWidget,
     pub space_map: SpaceMapWidget,
+    pub path: Option<ShipPath>,
+    /// Area of the terminal in which the map was last drawn
+    map_area: Rect,
 }
 
 impl ExplorerContext {
-    pub fn new(primary: Entity, bodies: &Query<(&BodyInfo, &OrbitingObjects)>, mapping: Res<BodiesMapping>, query: Query<&OrbitingObjects>) -> ExplorerContext {
-        let (primary_info, primary_orbiting) = bodies.get(primary).unwrap();
+    pub(crate) fn new(
+        primary: Entity,
+        bodies: &Query<(&BodyInfo, &OrbitingObjects)>,
+        ships: &ShipHosts,
+        mapping: Res<BodiesMapping>,
+        query: Query<&OrbitingObjects>,
+    ) -> ExplorerContext {
+        let (primary_info, _) = bodies.get(primary).unwrap();
         let primary_data = primary_info.clone().0;
         let infos: Vec<_> = bodies.iter().map(|(i,_)| &i.0).collect();
+        let ships_parents: Vec<_> = ships_parents(ships, bodies, &mapping, primary_data.id).collect();
         ExplorerContext {
             side_pane_mode: SidePaneMode::default(),
             info_toggle: false,
-            tree_state: TreeState::new(&primary_data, Some(&primary_data), infos.clone().into_iter(), mapping, query),
-            search_state: SearchState::new(infos.into_iter()),
-            info: InfoWidget {
-                body_info: primary_data,
-                orbiting_obj: primary_orbiting.clone(),
-            },
+            tree_state: TreeState::new(
+                &primary_data,
+                Some(&primary_data),
+                infos.clone().into_iter(),
+                ships_parents.into_iter(),
+                mapping,
+                query,
+            ),
+            search_state: SearchState::new(infos.into_iter(), ships.iter().map(|(info, _, _)| info)),
+            info: InfoWidget::default(),
             space_map: SpaceMapWidget::default(),
-        }
-    }
-    fn update_info(&mut self, mapping: &HashMap<BodyID, Entity>, bodies: &Query<&BodyInfo>) {
-        let id = self.tree_state.selected_body_id();
-        if let Ok(body_info) = bodies.get(mapping[&id]) {
-            self.info.body_info = body_info.0.clone();
+            path: None,
+            map_area: Rect::default(),
         }
     }
 }
@@ -134,13 +218,13 @@ pub(crate) enum ExplorerEvent {
 }
 
 impl ExplorerContext {
-    pub fn selected_body(&self) -> BodyID {
-        let tree_selection = self.tree_state.selected_body_id();
+    pub fn selected_object(&self) -> OrbitalObjID {
+        let tree_selection = self.tree_state.selected_id();
         match self.side_pane_mode {
             SidePaneMode::Tree => tree_selection,
             SidePaneMode::Search => self
                 .search_state
-                .selected_body_id()
+                .selected_id()
                 .unwrap_or(tree_selection),
         }
     }
@@ -155,17 +239,14 @@ pub enum ViewEvent {
 
 fn read_input(
     context: Res<ExplorerContext>,
-    mut key_event: EventReader<KeyEvent>,
+    mut presses: EventReader<KeyPress>,
     keymap: Res<Keymap>,
     mut internal_event: EventWriter<ExplorerEvent>,
 ) {
     use crate::prelude::Direction2::*;
     use ExplorerEvent::*;
     use ViewEvent::*;
-    for KeyEvent(event) in key_event.read() {
-        if event.kind == KeyEventKind::Release {
-            return;
-        }
+    for press in presses.read() {
         let keymap = &keymap.explorer;
         internal_event.send(match context.side_pane_mode {
             SidePaneMode::Tree => {
@@ -174,7 +255,7 @@ fn read_input(
                 use SpaceMapEvent::*;
                 use TimeEvent::*;
                 use TreeEvent::*;
-                match event {
+                match press {
                     e if codes.select_next.matches(e) => Tree(Select(Down)),
                     e if codes.select_previous.matches(e) => Tree(Select(Up)),
                     e if codes.toggle_expand.matches(e) => Tree(ToggleTreeExpansion),
@@ -201,7 +282,7 @@ fn read_input(
             SidePaneMode::Search => {
                 use SearchEvent::*;
                 let codes = &keymap.search;
-                match event {
+                match press {
                     e if codes.delete_char.matches(e) => Search(DeleteChar),
                     e if codes.validate_search.matches(e) => Search(ValidateSearch),
                     e if codes.select_next.matches(e) => Search(Select(Down)),
@@ -209,8 +290,12 @@ fn read_input(
                     e if codes.leave_search.matches(e) => {
                         View(ChangeSidePaneMode(SidePaneMode::Tree))
                     }
-                    CKeyEvent {
-                        code: KeyCode::Char(char),
+                    KeyPress {
+                        event:
+                            CKeyEvent {
+                                code: KeyCode::Char(char),
+                                ..
+                            },
                         ..
                     } => Search(WriteChar(*char)),
                     _ => return,
@@ -220,6 +305,40 @@ fn read_input(
     }
 }
 
+fn read_mouse(
+    context: Res<ExplorerContext>,
+    mut gestures: EventReader<MouseGesture>,
+    mut internal_event: EventWriter<ExplorerEvent>,
+) {
+    use ExplorerEvent::*;
+    use SpaceMapEvent::*;
+    for gesture in gestures.read() {
+        let on_map = |column, row| area_contains(context.map_area, column, row);
+        internal_event.send(match *gesture {
+            MouseGesture::Click { column, row } if on_map(column, row) => {
+                SpaceMap(SelectAt(column, row))
+            }
+            MouseGesture::Click { column, row } => match context.side_pane_mode {
+                SidePaneMode::Tree => Tree(TreeEvent::SelectAt(column, row)),
+                SidePaneMode::Search => Search(SearchEvent::SelectAt(column, row)),
+            },
+            MouseGesture::Scroll {
+                column,
+                row,
+                direction,
+            } if on_map(column, row) => SpaceMap(Zoom(direction)),
+            MouseGesture::Scroll { direction, .. } => match context.side_pane_mode {
+                SidePaneMode::Tree => Tree(TreeEvent::Select(direction)),
+                SidePaneMode::Search => Search(SearchEvent::Select(direction)),
+            },
+            MouseGesture::Drag { column, row, delta } if on_map(column, row) => {
+                SpaceMap(Drag(delta))
+            }
+            MouseGesture::Drag { .. } => continue,
+        });
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 fn handle_explorer_events(
     mut ctx: ResMut<ExplorerContext>,
@@ -236,7 +355,9 @@ fn handle_explorer_events(
 
     mut events: EventReader<ExplorerEvent>,
     mapping: Res<BodiesMapping>,
+    ships_mapping: Res<ShipsMapping>,
     bodies: Query<&BodyInfo>,
+    ships: Query<&ShipInfo>,
     bodies_orbiting: Query<(&BodyInfo, &OrbitingObjects)>,
     mut time_events: ResMut<Events<TimeEvent>>,
     fuzzy_matcher: Res<SearchMatcher>,
@@ -248,7 +369,9 @@ fn handle_explorer_events(
                 match event {
                     Select(d) => {
                         ctx.tree_state.select_adjacent(*d);
-                        ctx.update_info(&mapping.0, &bodies);
+                    }
+                    SelectAt(column, row) => {
+                        ctx.tree_state.select_at(*column, *row);
                     }
                     ToggleTreeExpansion => ctx.tree_state.toggle_selection_expansion(),
                 }
@@ -258,21 +381,29 @@ fn handle_explorer_events(
                 match event {
                     DeleteChar => {
                         ctx.search_state.delete_char();
-                        ctx.search_state
-                            .update_search_entries(bodies.iter(), &fuzzy_matcher.0);
+                        ctx.search_state.update_search_entries(
+                            bodies.iter(),
+                            ships.iter(),
+                            &fuzzy_matcher.0,
+                        );
                     }
                     Select(d) => ctx.search_state.select_adjacent(*d),
+                    SelectAt(column, row) => {
+                        ctx.search_state.select_at(*column, *row);
+                    }
                     ValidateSearch => {
-                        if let Some(id) = ctx.search_state.selected_body_id() {
-                            ctx.tree_state.select_body(id);
-                            ctx.update_info(&mapping.0, &bodies);
-                        }
+                        if let Some(id) = ctx.search_state.selected_id() {
+                            ctx.tree_state.select(id);
+                            }
                         ctx.side_pane_mode = SidePaneMode::Tree;
                     }
                     WriteChar(char) => {
                         ctx.search_state.enter_char(*char);
-                        ctx.search_state
-                            .update_search_entries(bodies.iter(), &fuzzy_matcher.0);
+                        ctx.search_state.update_search_entries(
+                            bodies.iter(),
+                            ships.iter(),
+                            &fuzzy_matcher.0,
+                        );
                     }
                 }
             }
@@ -281,13 +412,34 @@ fn handle_explorer_events(
                 match event {
                     Zoom(d) => space_map.zoom(*d),
                     MapOffset(d) => space_map.offset(*d),
+                    Drag(delta) => space_map.drag(ctx.map_area, *delta),
                     MapOffsetReset => space_map.reset_offset(),
                     FocusBody => {
-                        if let Some(entity) = mapping.0.get(&ctx.tree_state.selected_body_id()) {
-                            space_map.focus(*entity);
-                            ctx.tree_state.focus_body(bodies.get(*entity).unwrap().0.id)
+                        let id = ctx.tree_state.selected_id();
+                        if let Some(entity) = id.entity(&mapping.0, &ships_mapping.0) {
+                            space_map.focus(entity);
+                            ctx.tree_state.focus(id)
                         }
                     }
+                    SelectAt(column, row) => {
+                        let Some(entity) =
+                            ctx.space_map
+                                .object_at(&space_map, ctx.map_area, *column, *row)
+                        else {
+                            continue;
+                        };
+                        let id = if let Ok(info) = bodies.get(entity) {
+                            OrbitalObjID::Body(info.0.id)
+                        } else if let Ok(info) = ships.get(entity) {
+                            OrbitalObjID::Ship(info.id)
+                        } else {
+                            continue;
+                        };
+                        // The search selection would otherwise take precedence over the tree one
+                        ctx.search_state.reset_search();
+                        ctx.side_pane_mode = SidePaneMode::Tree;
+                        ctx.tree_state.select(id);
+                    }
                     Autoscale => space_map.autoscale(&mapping.0, &bodies_orbiting),
                 }
             }
@@ -327,27 +479,221 @@ fn handle_explorer_events(
     }
 }
 
+/// Moves the ships in the tree under their current main influencer
+fn update_tree_ships(
+    mut ctx: ResMut<ExplorerContext>,
+    ships: ShipHosts,
+    bodies: Query<(&BodyInfo, &OrbitingObjects)>,
+    mapping: Res<BodiesMapping>,
+    primary: Query<&BodyInfo, With<PrimaryBody>>,
+) {
+    ctx.tree_state
+        .set_ships(ships_parents(&ships, &bodies, &mapping, primary.single().0.id));
+}
+
+/// Refreshes the live information about the selected object
+#[allow(clippy::too_many_arguments)]
+fn update_info(
+    mut ctx: ResMut<ExplorerContext>,
+    mapping: Res<BodiesMapping>,
+    ships_mapping: Res<ShipsMapping>,
+    states: Query<(&Position, &Velocity, Option<&Mass>)>,
+    bodies: Query<(&BodyInfo, &OrbitingObjects, Option<&HillRadius>)>,
+    ships: ShipHosts,
+    primary: Query<Entity, With<PrimaryBody>>,
+    store: Res<TrajectoryStore>,
+    time: Res<GameTime>,
+) {
+    let id = ctx.selected_object();
+    let Some(entity) = id.entity(&mapping.0, &ships_mapping.0) else {
+        return;
+    };
+    let (name, host, details) = match id {
+        OrbitalObjID::Body(_) => {
+            let Ok((BodyInfo(data), OrbitingObjects(orbiting), hill_radius)) = bodies.get(entity)
+            else {
+                return;
+            };
+            let details = ObjectDetails::Body {
+                body_type: data.body_type,
+                orbiting: orbiting.len(),
+                radius: data.radius,
+                mass: data.mass,
+                hill_radius: hill_radius.map(|r| r.0),
+                surface_gravity: G * data.mass / (data.radius * data.radius)
+                    / (SECONDS_PER_DAY * SECONDS_PER_DAY)
+                    * 1e3,
+            };
+            let host = data.host_body.and_then(|h| mapping.0.get(&h)).copied();
+            (data.name.clone(), host, details)
+        }
+        OrbitalObjID::Ship(ship_id) => {
+            let Ok((_, influence, host)) = ships.get(entity) else {
+                return;
+            };
+            let tick = time.tick();
+            let next_node = store.get(&ship_id).and_then(|trajectory| {
+                let (node_tick, node) = trajectory.nodes.range(tick..).next()?;
+                let days = (node_tick * SIMTICKS_PER_TICK).saturating_sub(time.simtick) as f64
+                    * GAMETIME_PER_SIMTICK;
+                Some((node.name.clone(), days))
+            });
+            (
+                ship_id.to_string(),
+                ship_host(influence, host, &mapping),
+                ObjectDetails::Ship { next_node },
+            )
+        }
+    };
+    let orbit = host.and_then(|host| {
+        let (BodyInfo(host_data), _, _) = bodies.get(host).ok()?;
+        let (pos, speed, mass) = states.get(entity).ok()?;
+        let (host_pos, host_speed, _) = states.get(host).ok()?;
+        let (primary_pos, _, _) = states.get(primary.single()).ok()?;
+        let (rel_pos, rel_speed) = (pos.0 - host_pos.0, speed.0 - host_speed.0);
+        let mu = G * (host_data.mass + mass.map_or(0., |m| m.0));
+        Some(OrbitInfo {
+            elements: OsculatingElements::from_state(rel_pos, rel_speed, mu),
+            soi: host_data.name.clone(),
+            host_radius: host_data.radius,
+            speed: rel_speed.length(),
+            primary_distance: (pos.0 - primary_pos.0).length(),
+        })
+    });
+    ctx.info = InfoWidget {
+        name,
+        orbit,
+        details,
+    };
+}
+
 fn update_space_map(
     mut ctx: ResMut<ExplorerContext>,
     mut space_map: ResMut<SpaceMap>,
     query: Query<(Entity, &Position, &BodyInfo)>,
+    ships: Query<(Entity, &Position), With<ShipInfo>>,
+    orbits: Query<(Entity, &EllipticalOrbit, &Position, Option<&BodyInfo>)>,
+    mapping: Res<BodiesMapping>,
+    ships_mapping: Res<ShipsMapping>,
+) {
+    space_map.selected = ctx
+        .selected_object()
+        .entity(&mapping.0, &ships_mapping.0);
+    ctx.space_map
+        .update_map(space_map.as_ref(), &query, &ships, &orbits);
+}
+
+/// Predicts the path of the selected ship over one of its orbits, and draws it on the space map once computed
+#[allow(clippy::too_many_arguments)]
+fn update_ship_path(
+    mut ctx: ResMut<ExplorerContext>,
+    space_map: Res<SpaceMap>,
+    time: Res<GameTime>,
+    ships: Query<(
+        &Position,
+        &Velocity,
+        &Acceleration,
+        &Influenced,
+        Option<&ForceModels>,
+    )>,
+    store: Res<TrajectoryStore>,
+    bodies: Query<(Entity, &EllipticalOrbit, &BodyInfo, &HillRadius)>,
+    orbiting: Query<&OrbitingObjects>,
+    oblate_bodies: Query<(&ZonalHarmonics, &BodyRotation)>,
     mapping: Res<BodiesMapping>,
+    ships_mapping: Res<ShipsMapping>,
 ) {
-    space_map.selected = mapping.0.get(&ctx.selected_body()).cloned();
-    ctx.space_map.update_map(space_map.as_ref(), &query);
+    // Ships on rails have their orbit drawn instead
+    let selected = match ctx.selected_object() {
+        OrbitalObjID::Ship(id) => ships_mapping
+            .0
+            .get(&id)
+            .and_then(|&e| Some((e, id, ships.get(e).ok()?))),
+        OrbitalObjID::Body(_) => None,
+    };
+    let Some((ship, id, (&Position(pos), &Velocity(speed), acc, influence, forces))) = selected
+    else {
+        ctx.path = None;
+        ctx.space_map.set_paths(&space_map, []);
+        return;
+    };
+    // A path being computed for the same ship and focus is kept rather than restarted
+    let outdated = ctx.path.as_ref().is_none_or(|path| {
+        path.ship != ship
+            || path.focus != space_map.focus_body
+            || (path.task.is_none() && time.simtick >= path.simtick + PATH_REFRESH_SIMTICKS)
+    });
+    if outdated {
+        let start = PredictionStart {
+            pos,
+            speed,
+            acc: acc.current,
+            simtick: time.simtick,
+        };
+        let snapshot = BodiesSnapshot::new(&bodies, &orbiting, &oblate_bodies, &mapping.0);
+        let horizon = PredictionHorizon::Orbits(1.).simticks(&start, influence, &snapshot);
+        let reference = space_map
+            .focus_body
+            .filter(|&f| bodies.contains(f))
+            .or(influence.main_influencer);
+        let task = PredictionTask::spawn(
+            vec![FleetMember {
+                start,
+                influence: influence.clone(),
+                forces: forces.cloned(),
+                nodes: store.get(&id).map(|t| t.nodes.clone()).unwrap_or_default(),
+            }],
+            horizon,
+            reference,
+            snapshot,
+            Sampling::default(),
+        );
+        // The previous path is kept until the new one is computed if it belongs to the same ship
+        let predictions = ctx
+            .path
+            .take()
+            .filter(|path| path.ship == ship)
+            .map(|path| path.predictions)
+            .unwrap_or_default();
+        ctx.path = Some(ShipPath {
+            ship,
+            focus: space_map.focus_body,
+            simtick: time.simtick,
+            task: Some((task, vec![Vec::new()])),
+            predictions,
+        });
+    }
+    let Some(path) = ctx.path.as_mut() else {
+        return;
+    };
+    if let Some((task, received)) = &mut path.task {
+        if task.receive(received) {
+            path.predictions = received.pop().unwrap_or_default();
+            path.task = None;
+        }
+    }
+    let positions = path.predictions.iter().map(|p| p.pos).collect();
+    ctx.space_map
+        .set_paths(&space_map, [(positions, Color::LightCyan)]);
 }
 
 fn focus_on_select_body(
     mut events: EventReader<SelectObjectEvent>,
-    info: Query<&BodyInfo>,
+    bodies: Query<&BodyInfo>,
+    ships: Query<&ShipInfo>,
     mut space_map: ResMut<SpaceMap>,
     mut ctx: ResMut<ExplorerContext>,
 ) {
     for event in events.read() {
-        if let Ok(info) = info.get(event.entity) {
-            space_map.focus(event.entity);
-            ctx.tree_state.focus_body(info.0.id)
-        }
+        let id = if let Ok(info) = bodies.get(event.entity) {
+            OrbitalObjID::Body(info.0.id)
+        } else if let Ok(info) = ships.get(event.entity) {
+            OrbitalObjID::Ship(info.id)
+        } else {
+            continue;
+        };
+        space_map.focus(event.entity);
+        ctx.tree_state.focus(id)
     }
 }
 pub struct ExplorerScreen<'a> {
@@ -375,6 +721,7 @@ impl StatefulWidget for ExplorerScreen<'_> {
                 SearchWidget.render(chunks[0], buf, &mut state.search_state);
             }
         }
+        state.map_area = chunks[1];
         state.space_map.render_ref(chunks[1], buf, self.map);
         if state.info_toggle {
             state.info.render_ref(chunks[2], buf);
@@ -2,30 +2,43 @@ use std::{error::Error, num::ParseFloatError};
 
 use arrayvec::CapacityError;
 use bevy::prelude::*;
-use bevy_ratatui::event::KeyEvent;
-use crossterm::event::{KeyCode, KeyEventKind};
+use crossterm::event::KeyCode;
 use ratatui::{
-    layout::{Alignment, Constraint, Layout},
+    layout::{Alignment, Constraint, Layout, Rect},
     style::Stylize,
     widgets::{Block, Clear, List, ListState, Paragraph, StatefulWidget, Widget},
 };
 
 use crate::{
-    objects::id::MAX_ID_LENGTH,
+    objects::{
+        id::MAX_ID_LENGTH,
+        ships::{autopilot::AutopilotDiagnostics, trajectory::TrajectoryDiagnostics},
+    },
+    physics::forces::{ForceModelEvent, ForceModelKind, SailAttitude, SolarSail},
     prelude::*,
     ui::UiUpdate,
     utils::{algebra::circular_orbit_around_body, list::OptionsList, ui::centered_rect},
 };
 
+/// Reflectivity of the sails of the ships created from the fleet screen
+const DEFAULT_SAIL_REFLECTIVITY: f64 = 0.9;
+
 pub fn plugin(app: &mut App) {
     app.add_event::<FleetScreenEvent>()
         .add_systems(
             Update,
             (
-                read_input.in_set(InputReading),
+                (read_input, read_mouse).in_set(InputReading),
                 handle_fleet_events
                     .pipe(exit_on_error_if_app)
                     .in_set(EventHandling),
+                (
+                    update_ground_position,
+                    update_force_models,
+                    update_trajectory_diagnostic,
+                    update_autopilot_diagnostic,
+                )
+                    .in_set(UiUpdate),
             )
                 .run_if(in_loaded_screen::<FleetContext>(AppScreen::Fleet)),
         )
@@ -61,17 +74,31 @@ fn clear_screen(mut commands: Commands) {
 #[derive(Resource, Default)]
 pub struct FleetContext {
     list_state: ListState,
+    list_area: Rect,
     ships: Vec<ShipInfo>,
     popup_context: Option<CreateShipContext>,
     stage: GameStage,
+    /// Position of the selected ship above the surface of its main influencer
+    ground_position: Option<(String, SurfaceCoordinates)>,
+    /// Descriptions of the force models of the selected ship
+    force_models: Vec<String>,
+    /// Why the trajectory of the selected ship was rejected
+    trajectory_diagnostic: Option<String>,
+    /// Why the autopilot of the selected ship was stopped
+    autopilot_diagnostic: Option<String>,
 }
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Event, Clone)]
 pub enum FleetScreenEvent {
     Select(Direction2),
+    /// Selects the ship drawn at the given column and row of the terminal
+    SelectAt(u16, u16),
+    /// Opens the ship creation popup
+    NewShip,
     TryNewShip(CreateShipContext),
     EditTrajectory,
+    CycleSailAttitude,
     EnterExplorer,
     Back,
 }
@@ -132,6 +159,10 @@ impl ClampedList for FleetContext {
     fn len(&self) -> usize {
         self.ships.len()
     }
+
+    fn list_area(&self) -> Rect {
+        self.list_area
+    }
 }
 
 #[derive(Default, Clone)]
@@ -139,6 +170,10 @@ pub struct CreateShipContext {
     id_text: String,
     host_body: String,
     altitude: String,
+    latitude: String,
+    longitude: String,
+    sail_area: String,
+    mass: String,
     pos_x: String,
     pos_y: String,
     pos_z: String,
@@ -148,17 +183,21 @@ pub struct CreateShipContext {
     selected: usize,
 }
 
-impl OptionsList<9> for CreateShipContext {
+impl OptionsList<13> for CreateShipContext {
     fn current_index(&mut self) -> &mut usize {
         &mut self.selected
     }
 
-    fn fields_list(&mut self) -> [(&mut String, String); 9] {
+    fn fields_list(&mut self) -> [(&mut String, String); 13] {
         [
             (&mut self.id_text, "Ship ID".into()),
             // TODO: add search or tree widget instead of plain id
             (&mut self.host_body, "Host body id".into()),
             (&mut self.altitude, "Spawn Altitude".into()),
+            (&mut self.latitude, "Launch site latitude".into()),
+            (&mut self.longitude, "Launch site longitude".into()),
+            (&mut self.sail_area, "Solar sail area (m²)".into()),
+            (&mut self.mass, "Ship mass (kg)".into()),
             (&mut self.pos_x, "Spawn x".into()),
             (&mut self.pos_y, "Spawn y".into()),
             (&mut self.pos_z, "Spawn z".into()),
@@ -173,13 +212,15 @@ impl CreateShipContext {
     fn to_info<'a>(
         &self,
         mut ships: impl Iterator<Item = &'a ShipInfo>,
-        bodies: &Query<(&Mass, &Position, &Velocity)>,
+        bodies: &Query<(&Mass, &Position, &Velocity, &BodyInfo, &BodyRotation)>,
         mapping: &BodiesMapping,
     ) -> Result<ShipInfo, ShipCreationError> {
         let CreateShipContext {
             id_text,
             host_body,
             altitude,
+            latitude,
+            longitude,
             pos_x,
             pos_y,
             pos_z,
@@ -188,16 +229,35 @@ impl CreateShipContext {
             speed_z,
             ..
         } = self;
-        let (spawn_pos, spawn_speed) =
-            if let Some(body) = BodyID::from(host_body).ok().and_then(|i| mapping.0.get(&i)) {
-                let (Mass(m), Position(p), Velocity(v)) = bodies.get(*body).unwrap();
+        let (spawn_pos, spawn_speed) = if let Some((id, body)) = BodyID::from(host_body)
+            .ok()
+            .and_then(|i| mapping.0.get(&i).map(|e| (i, e)))
+        {
+            let (Mass(m), Position(p), Velocity(v), BodyInfo(data), rotation) =
+                bodies.get(*body).unwrap();
+            if latitude.is_empty() && longitude.is_empty() {
                 circular_orbit_around_body(altitude.parse()?, *m, *p, *v)
             } else {
-                (
-                    (pos_x.parse()?, pos_y.parse()?, pos_z.parse()?).into(),
-                    (speed_x.parse()?, speed_y.parse()?, speed_z.parse()?).into(),
-                )
-            };
+                LaunchSite {
+                    body: id,
+                    coords: SurfaceCoordinates {
+                        latitude: latitude.parse()?,
+                        longitude: longitude.parse()?,
+                        altitude: if altitude.is_empty() {
+                            0.
+                        } else {
+                            altitude.parse()?
+                        },
+                    },
+                }
+                .spawn_coordinates(rotation, data.radius, *p, *v)
+            }
+        } else {
+            (
+                (pos_x.parse()?, pos_y.parse()?, pos_z.parse()?).into(),
+                (speed_x.parse()?, speed_y.parse()?, speed_z.parse()?).into(),
+            )
+        };
         let id = ShipID::from(id_text).map_err(CapacityError::simplify)?;
         if ships.any(|s| s.id == id) {
             Err(ShipCreationError::ShipAlreadyExists(id))
@@ -211,6 +271,21 @@ impl CreateShipContext {
     }
 }
 
+impl CreateShipContext {
+    /// Solar sail of the new ship, if a sail area was given
+    fn to_sail(&self) -> Result<Option<SolarSail>, ShipCreationError> {
+        if self.sail_area.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(SolarSail {
+            area: self.sail_area.parse()?,
+            mass: self.mass.parse()?,
+            reflectivity: DEFAULT_SAIL_REFLECTIVITY,
+            attitude: SailAttitude::FaceSun,
+        }))
+    }
+}
+
 impl FleetContext {
     pub fn new(ships: impl Iterator<Item = ShipInfo>) -> Self {
         Self {
@@ -227,19 +302,16 @@ pub struct FleetScreen;
 
 fn read_input(
     mut context: ResMut<FleetContext>,
-    mut key_event: EventReader<KeyEvent>,
+    mut presses: EventReader<KeyPress>,
     keymap: Res<Keymap>,
     mut internal_event: EventWriter<FleetScreenEvent>,
 ) {
     use Direction2::*;
     use FleetScreenEvent::*;
     let keymap = &keymap.fleet_screen;
-    for KeyEvent(event) in key_event.read() {
-        if event.kind == KeyEventKind::Release {
-            return;
-        }
+    for press in presses.read() {
         match &mut context.popup_context {
-            None => match event {
+            None => match press {
                 e if keymap.select_next.matches(e) => {
                     internal_event.send(Select(Down));
                 }
@@ -250,7 +322,7 @@ fn read_input(
                     internal_event.send(EditTrajectory);
                 }
                 e if keymap.new_ship.matches(e) => {
-                    context.popup_context = Some(CreateShipContext::default())
+                    internal_event.send(NewShip);
                 }
                 e if keymap.back.matches(e) => {
                     internal_event.send(Back);
@@ -258,9 +330,12 @@ fn read_input(
                 e if keymap.enter_explorer.matches(e) => {
                     internal_event.send(EnterExplorer);
                 }
+                e if keymap.cycle_sail_attitude.matches(e) => {
+                    internal_event.send(CycleSailAttitude);
+                }
                 _ => {}
             },
-            Some(ctx) => match event {
+            Some(ctx) => match press {
                 e if keymap.cycle_options.matches(e) => ctx.select_next(),
                 e if keymap.cycle_options_back.matches(e) => ctx.select_previous(),
                 e if keymap.back.matches(e) => context.popup_context = None,
@@ -270,8 +345,12 @@ fn read_input(
                 e if keymap.delete_char.matches(e) => {
                     ctx.selected_field().pop();
                 }
-                crossterm::event::KeyEvent {
-                    code: KeyCode::Char(c),
+                KeyPress {
+                    event:
+                        crossterm::event::KeyEvent {
+                            code: KeyCode::Char(c),
+                            ..
+                        },
                     ..
                 } => ctx.selected_field().push(*c),
                 _ => {}
@@ -280,22 +359,54 @@ fn read_input(
     }
 }
 
+fn read_mouse(
+    context: Res<FleetContext>,
+    mut gestures: EventReader<MouseGesture>,
+    mut internal_event: EventWriter<FleetScreenEvent>,
+) {
+    // The ship creation popup is only filled from the keyboard
+    if context.popup_context.is_some() {
+        gestures.clear();
+        return;
+    }
+    for gesture in gestures.read() {
+        internal_event.send(match *gesture {
+            MouseGesture::Click { column, row } => FleetScreenEvent::SelectAt(column, row),
+            MouseGesture::Scroll { direction, .. } => FleetScreenEvent::Select(direction),
+            MouseGesture::Drag { .. } => continue,
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn handle_fleet_events(
     mut context: ResMut<FleetContext>,
     mut next_screen: ResMut<NextState<AppScreen>>,
     mut next_mode: ResMut<NextState<ClientMode>>,
     mut events: EventReader<FleetScreenEvent>,
     mut ship_events: EventWriter<ShipEvent>,
-    bodies: Query<(&Mass, &Position, &Velocity)>,
+    mut force_events: EventWriter<ForceModelEvent>,
+    bodies: Query<(&Mass, &Position, &Velocity, &BodyInfo, &BodyRotation)>,
     mapping: Res<BodiesMapping>,
 ) -> color_eyre::eyre::Result<()> {
     for event in events.read() {
         match event {
             FleetScreenEvent::Select(d) => context.select_adjacent(*d),
+            FleetScreenEvent::SelectAt(column, row) => {
+                context.select_at(*column, *row);
+            }
+            FleetScreenEvent::NewShip => context.popup_context = Some(CreateShipContext::default()),
             FleetScreenEvent::TryNewShip(ctx) => {
                 let info = ctx.to_info(context.ships.iter(), &bodies, mapping.as_ref())?;
+                let sail = ctx.to_sail()?;
                 context.ships.push(info.clone());
                 ship_events.send(ShipEvent::Create(info.clone()));
+                if let Some(sail) = sail {
+                    force_events.send(ForceModelEvent::Attach {
+                        ship: info.id,
+                        model: ForceModelKind::SolarSail(sail),
+                    });
+                }
                 context.popup_context = None;
             }
             FleetScreenEvent::EditTrajectory => {
@@ -303,6 +414,11 @@ fn handle_fleet_events(
                     next_screen.set(AppScreen::Editor(ship.id));
                 }
             }
+            FleetScreenEvent::CycleSailAttitude => {
+                if let Some(ship) = context.selected_ship() {
+                    force_events.send(ForceModelEvent::CycleSailAttitude(ship.id));
+                }
+            }
             FleetScreenEvent::Back => next_mode.set(ClientMode::None),
             FleetScreenEvent::EnterExplorer => next_screen.set(AppScreen::Explorer),
         }
@@ -324,6 +440,62 @@ fn update_fleet_context(
     ctx.ships.extend(diff);
 }
 
+fn update_ground_position(
+    mut ctx: ResMut<FleetContext>,
+    mapping: Res<ShipsMapping>,
+    ships: Query<(&Position, &Influenced)>,
+    bodies: Query<(&Position, &BodyInfo, &BodyRotation)>,
+) {
+    ctx.ground_position = ctx
+        .selected_ship()
+        .and_then(|info| mapping.0.get(&info.id))
+        .and_then(|e| ships.get(*e).ok())
+        .and_then(|(Position(pos), influence)| {
+            influence
+                .main_influencer
+                .and_then(|e| bodies.get(e).ok())
+                .map(|(Position(body_pos), BodyInfo(data), rotation)| {
+                    (
+                        data.name.clone(),
+                        rotation.local_to_surface(*pos - *body_pos, data.radius),
+                    )
+                })
+        });
+}
+
+fn update_force_models(
+    mut ctx: ResMut<FleetContext>,
+    mapping: Res<ShipsMapping>,
+    ships: Query<&ForceModels>,
+) {
+    ctx.force_models = ctx
+        .selected_ship()
+        .and_then(|info| mapping.0.get(&info.id))
+        .and_then(|e| ships.get(*e).ok())
+        .map(|models| models.0.iter().map(|m| m.describe()).collect())
+        .unwrap_or_default();
+}
+
+fn update_trajectory_diagnostic(
+    mut ctx: ResMut<FleetContext>,
+    diagnostics: Res<TrajectoryDiagnostics>,
+) {
+    ctx.trajectory_diagnostic = ctx
+        .selected_ship()
+        .and_then(|info| diagnostics.0.get(&info.id))
+        .cloned();
+}
+
+fn update_autopilot_diagnostic(
+    mut ctx: ResMut<FleetContext>,
+    diagnostics: Res<AutopilotDiagnostics>,
+) {
+    ctx.autopilot_diagnostic = ctx
+        .selected_ship()
+        .and_then(|info| diagnostics.0.get(&info.id))
+        .cloned();
+}
+
 impl StatefulWidget for FleetScreen {
     type State = FleetContext;
 
@@ -338,21 +510,37 @@ impl StatefulWidget for FleetScreen {
 
         // Ship list
         let entries = state.ships.iter().map(|s| s.id.to_string());
-        let list = List::new(entries).highlight_symbol(">").block(
-            Block::bordered()
-                .title_top("Ships")
-                .title_bottom(format!("Current stage: {}", state.stage)),
-        );
+        let block = Block::bordered()
+            .title_top("Ships")
+            .title_bottom(format!("Current stage: {}", state.stage));
+        state.list_area = block.inner(chunks[0]);
+        let list = List::new(entries).highlight_symbol(">").block(block);
         <List as StatefulWidget>::render(list, chunks[0], buf, &mut state.list_state);
 
         // Ship info
         if let Some(info) = state.selected_ship() {
-            Paragraph::new(format!(
+            let mut text = format!(
                 "ID: {}\nSpawn position: {}\nSpawn velocity: {}",
                 info.id, info.spawn_pos, info.spawn_speed
-            ))
-            .block(Block::bordered().title_top("Ship info"))
-            .render(chunks[1], buf);
+            );
+            if let Some((name, coords)) = &state.ground_position {
+                text.push_str(&format!(
+                    "\nGround position: {:.2}°, {:.2}° ({:.0} km above {})",
+                    coords.latitude, coords.longitude, coords.altitude, name
+                ));
+            }
+            for model in &state.force_models {
+                text.push_str(&format!("\n{}", model));
+            }
+            if let Some(diagnostic) = &state.trajectory_diagnostic {
+                text.push_str(&format!("\n{}", diagnostic));
+            }
+            if let Some(diagnostic) = &state.autopilot_diagnostic {
+                text.push_str(&format!("\n{}", diagnostic));
+            }
+            Paragraph::new(text)
+                .block(Block::bordered().title_top("Ship info"))
+                .render(chunks[1], buf);
         }
 
         // Ship creation popup
@@ -371,10 +559,10 @@ impl StatefulWidget for FleetScreen {
                 .split(chunks[1]);
 
             // Left side of options
-            let mut constraints = [Constraint::Percentage(100 / 3)].repeat(3);
+            let mut constraints = [Constraint::Percentage(100 / 7)].repeat(7);
             constraints.push(Constraint::Fill(1));
             let left = Layout::vertical(constraints).split(body[0]);
-            for i in 0..3 {
+            for i in 0..7 {
                 ctx.paragraph(i).render(left[i], buf);
             }
 
@@ -382,8 +570,8 @@ impl StatefulWidget for FleetScreen {
             let mut constraints = [Constraint::Percentage(100 / 6)].repeat(6);
             constraints.push(Constraint::Fill(1));
             let coords = Layout::vertical(constraints).split(body[1]);
-            for i in 3..9 {
-                ctx.paragraph(i).render(coords[i - 3], buf);
+            for i in 7..13 {
+                ctx.paragraph(i).render(coords[i - 7], buf);
             }
         }
     }
@@ -424,6 +612,38 @@ mod tests {
         assert_eq!(app.world().resource::<ShipsMapping>().0.len(), 1)
     }
 
+    #[test]
+    fn test_create_ship_from_launch_site() {
+        let mut app = new_app();
+        let popup = CreateShipContext {
+            id_text: "s".into(),
+            host_body: "terre".into(),
+            latitude: "45".into(),
+            longitude: "10".into(),
+            ..Default::default()
+        };
+        app.world_mut()
+            .send_event(FleetScreenEvent::TryNewShip(popup));
+        app.update();
+        app.update();
+        let world = app.world_mut();
+        let earth = world.resource::<BodiesMapping>().0[&id_from("terre")];
+        let ship = world.resource::<ShipsMapping>().0[&id_from("s")];
+        let (&Position(earth_pos), BodyInfo(data), &rotation) = world
+            .query::<(&Position, &BodyInfo, &BodyRotation)>()
+            .get(world, earth)
+            .unwrap();
+        let radius = data.radius;
+        let spawn_pos = world
+            .query::<&ShipInfo>()
+            .get(world, ship)
+            .unwrap()
+            .spawn_pos;
+        let coords = rotation.local_to_surface(spawn_pos - earth_pos, radius);
+        assert!((coords.latitude - 45.).abs() < 1e-6);
+        assert!(coords.altitude.abs() < 1e-6);
+    }
+
     #[test]
     fn test_update_context() {
         let mut app = new_app();
@@ -0,0 +1,369 @@
+use std::path::PathBuf;
+
+use bevy::{prelude::*, utils::HashMap};
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::Stylize,
+    text::Line,
+    widgets::{Block, List, ListState, Paragraph, StatefulWidget, Widget, Wrap},
+};
+
+use crate::{
+    input::{binding::MAX_CHORD_LENGTH, validation::KeymapIssue},
+    prelude::*,
+};
+
+use super::{AppScreen, PreviousScreen};
+
+/// File the keymap is written to from the settings screen
+#[derive(Resource, Clone, Debug)]
+pub struct KeymapPath(pub PathBuf);
+
+impl Default for KeymapPath {
+    fn default() -> Self {
+        Self("keymap.toml".into())
+    }
+}
+
+pub fn plugin(app: &mut App) {
+    app.add_event::<SettingsEvent>()
+        .init_resource::<KeymapPath>()
+        .add_systems(
+            Update,
+            (
+                (read_input, read_mouse).in_set(InputReading),
+                handle_events.in_set(EventHandling),
+            )
+                .run_if(in_state(AppScreen::Settings))
+                .run_if(resource_exists::<SettingsContext>),
+        )
+        .add_systems(OnEnter(AppScreen::Settings), create_screen)
+        .add_systems(OnExit(AppScreen::Settings), clear_screen);
+}
+
+/// What the next keys pressed are used for
+#[derive(Clone, Debug, PartialEq)]
+pub enum Capture {
+    /// The next key replaces the binding of the selected action
+    Replace,
+    /// The next key is added to the keys triggering the selected action
+    AddKey,
+    /// The next keys are added as a chord triggering the selected action, the ones already pressed being given
+    AddChord(Vec<Key>),
+}
+
+#[derive(Event, Clone, Debug)]
+pub enum SettingsEvent {
+    Select(Direction2),
+    /// Selects the action drawn at the given column and row of the terminal
+    SelectAt(u16, u16),
+    StartCapture(Capture),
+    Capture(Key),
+    Reset,
+    Save,
+    Back,
+}
+
+#[derive(Resource)]
+pub struct SettingsContext {
+    list_state: ListState,
+    list_area: Rect,
+    bindings: Vec<(String, Binding)>,
+    capture: Option<Capture>,
+    issues: Vec<KeymapIssue>,
+    message: Option<String>,
+}
+
+impl SettingsContext {
+    pub fn new(keymap: &Keymap) -> Self {
+        let mut context = Self {
+            list_state: ListState::default().with_selected(Some(0)),
+            list_area: Rect::default(),
+            bindings: Vec::new(),
+            capture: None,
+            issues: Vec::new(),
+            message: None,
+        };
+        context.update(keymap);
+        context
+    }
+
+    fn update(&mut self, keymap: &Keymap) {
+        self.bindings = keymap.bindings();
+        self.issues = keymap.issues(&HashMap::new());
+    }
+
+    fn selected_action(&self) -> Option<&(String, Binding)> {
+        self.list_state
+            .selected()
+            .and_then(|i| self.bindings.get(i))
+    }
+
+    fn in_conflict(&self, action: &str) -> bool {
+        self.issues.iter().any(|issue| match issue {
+            KeymapIssue::Conflict { first, second, .. } => first == action || second == action,
+            KeymapIssue::ChordTooLong { action: a, .. }
+            | KeymapIssue::UnknownKey { action: a, .. } => a == action,
+        })
+    }
+}
+
+impl ClampedList for SettingsContext {
+    fn list_state(&mut self) -> &mut ListState {
+        &mut self.list_state
+    }
+
+    fn len(&self) -> usize {
+        self.bindings.len()
+    }
+
+    fn list_area(&self) -> Rect {
+        self.list_area
+    }
+}
+
+fn create_screen(mut commands: Commands, keymap: Res<Keymap>) {
+    commands.insert_resource(SettingsContext::new(&keymap));
+}
+
+fn clear_screen(mut commands: Commands) {
+    commands.remove_resource::<SettingsContext>();
+}
+
+fn read_input(
+    context: Res<SettingsContext>,
+    mut presses: EventReader<KeyPress>,
+    keymap: Res<Keymap>,
+    mut internal_event: EventWriter<SettingsEvent>,
+) {
+    use Direction2::*;
+    use SettingsEvent::*;
+    let keymap = &keymap.settings;
+    for press in presses.read() {
+        // While capturing, every key is bound, including the ones of the settings screen
+        if context.capture.is_some() {
+            internal_event.send(SettingsEvent::Capture(Key::from(&press.event)));
+            continue;
+        }
+        internal_event.send(match press {
+            e if keymap.select_next.matches(e) => Select(Down),
+            e if keymap.select_previous.matches(e) => Select(Up),
+            e if keymap.back.matches(e) => Back,
+            e if keymap.rebind.matches(e) => StartCapture(Capture::Replace),
+            e if keymap.add_key.matches(e) => StartCapture(Capture::AddKey),
+            e if keymap.add_chord.matches(e) => StartCapture(Capture::AddChord(Vec::new())),
+            e if keymap.reset.matches(e) => Reset,
+            e if keymap.save.matches(e) => Save,
+            _ => continue,
+        });
+    }
+}
+
+fn read_mouse(
+    context: Res<SettingsContext>,
+    mut gestures: EventReader<MouseGesture>,
+    mut internal_event: EventWriter<SettingsEvent>,
+) {
+    if context.capture.is_some() {
+        return;
+    }
+    for gesture in gestures.read() {
+        internal_event.send(match *gesture {
+            MouseGesture::Click { column, row } => SettingsEvent::SelectAt(column, row),
+            MouseGesture::Scroll { direction, .. } => SettingsEvent::Select(direction),
+            MouseGesture::Drag { .. } => continue,
+        });
+    }
+}
+
+pub fn handle_events(
+    mut events: EventReader<SettingsEvent>,
+    mut context: ResMut<SettingsContext>,
+    mut keymap: ResMut<Keymap>,
+    path: Res<KeymapPath>,
+    previous: Res<PreviousScreen>,
+    mut next_screen: ResMut<NextState<AppScreen>>,
+) {
+    for event in events.read() {
+        match event {
+            SettingsEvent::Select(d) => context.select_adjacent(*d),
+            SettingsEvent::SelectAt(column, row) => {
+                context.select_at(*column, *row);
+            }
+            SettingsEvent::StartCapture(capture) => {
+                context.message = None;
+                context.capture = Some(capture.clone());
+            }
+            SettingsEvent::Capture(key) => {
+                let Some((action, binding)) = context.selected_action().cloned() else {
+                    continue;
+                };
+                let binding = match context.capture.take() {
+                    Some(Capture::Replace) => Binding::from(*key),
+                    Some(Capture::AddKey) => {
+                        let mut binding = binding;
+                        binding.0.push(vec![*key]);
+                        binding
+                    }
+                    Some(Capture::AddChord(mut keys)) => {
+                        keys.push(*key);
+                        if keys.len() < MAX_CHORD_LENGTH {
+                            context.capture = Some(Capture::AddChord(keys));
+                            continue;
+                        }
+                        let mut binding = binding;
+                        binding.0.push(keys);
+                        binding
+                    }
+                    None => continue,
+                };
+                if let Err(e) = keymap.set_binding(&action, binding) {
+                    context.message = Some(e.to_string());
+                }
+                context.update(&keymap);
+            }
+            SettingsEvent::Reset => {
+                let Some((action, _)) = context.selected_action().cloned() else {
+                    continue;
+                };
+                if let Some(binding) = Keymap::default().binding(&action) {
+                    if let Err(e) = keymap.set_binding(&action, binding) {
+                        context.message = Some(e.to_string());
+                    }
+                }
+                context.update(&keymap);
+            }
+            SettingsEvent::Save => context.message = Some(save_keymap(&keymap, &path)),
+            SettingsEvent::Back => next_screen.set(previous.0),
+        }
+    }
+}
+
+/// Writes the keymap to its file unless some of its actions conflict, and describes the outcome
+pub fn save_keymap(keymap: &Keymap, path: &KeymapPath) -> String {
+    if !keymap.issues(&HashMap::new()).is_empty() {
+        return "Resolve the conflicts before saving".to_owned();
+    }
+    match keymap.write_to_file(&path.0, true) {
+        Ok(()) => format!("Keymap saved to {}", path.0.display()),
+        Err(e) => format!("Could not save the keymap: {e}"),
+    }
+}
+
+pub struct SettingsScreen;
+
+impl StatefulWidget for SettingsScreen {
+    type State = SettingsContext;
+
+    fn render(
+        self,
+        area: ratatui::prelude::Rect,
+        buf: &mut ratatui::prelude::Buffer,
+        state: &mut Self::State,
+    ) {
+        let [list_area, info_area] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(6)]).areas(area);
+
+        let width = state
+            .bindings
+            .iter()
+            .map(|(action, _)| action.len())
+            .max()
+            .unwrap_or_default();
+        let entries: Vec<_> = state
+            .bindings
+            .iter()
+            .map(|(action, binding)| {
+                let line = Line::from(format!("{action:width$}  {binding}"));
+                if state.in_conflict(action) {
+                    line.red()
+                } else {
+                    line
+                }
+            })
+            .collect();
+        let block = Block::bordered().title_top("Key bindings");
+        state.list_area = block.inner(list_area);
+        let list = List::new(entries).highlight_symbol(">").block(block);
+        <List as StatefulWidget>::render(list, list_area, buf, &mut state.list_state);
+
+        let text = match (&state.capture, state.selected_action()) {
+            (Some(Capture::AddChord(keys)), Some((action, _))) => format!(
+                "Press the keys of the new chord of {action} ({}/{MAX_CHORD_LENGTH})",
+                keys.len()
+            ),
+            (Some(_), Some((action, _))) => format!("Press the new key of {action}"),
+            _ => state
+                .message
+                .iter()
+                .cloned()
+                .chain(state.issues.iter().map(ToString::to_string))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+        Paragraph::new(text)
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title_top("Settings"))
+            .render(info_area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{app::App, state::state::NextState};
+
+    use crate::prelude::*;
+
+    use super::{Capture, SettingsContext, SettingsEvent};
+
+    fn new_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((ClientPlugin::testing(), TuiPlugin::testing()));
+        // One update to enter the start menu
+        app.update();
+        app.world_mut()
+            .resource_mut::<NextState<AppScreen>>()
+            .set(AppScreen::Settings);
+        // One update to enter the settings screen
+        app.update();
+        app
+    }
+
+    fn select(app: &mut App, action: &str) {
+        let mut context = app.world_mut().resource_mut::<SettingsContext>();
+        let index = context.bindings.iter().position(|(a, _)| a == action);
+        context.list_state.select(index);
+    }
+
+    #[test]
+    fn test_rebind_key() {
+        let mut app = new_app();
+        select(&mut app, "editor.back");
+        app.world_mut().send_event_batch([
+            SettingsEvent::StartCapture(Capture::AddChord(Vec::new())),
+            SettingsEvent::Capture(Key::from_str_unchecked("q")),
+            SettingsEvent::Capture(Key::from_str_unchecked("q")),
+        ]);
+        app.update();
+        let binding = app.world().resource::<Keymap>().binding("editor.back");
+        let (esc, q) = (Key::from_str_unchecked("esc"), Key::from_str_unchecked("q"));
+        assert_eq!(binding, Some(Binding(vec![vec![esc], vec![q, q]])));
+        assert!(app.world().resource::<SettingsContext>().issues.is_empty());
+
+        // Binding an action to the key of another action of the same screen is reported
+        app.world_mut().send_event_batch([
+            SettingsEvent::StartCapture(Capture::Replace),
+            SettingsEvent::Capture(Key::from_str_unchecked("f")),
+        ]);
+        app.update();
+        assert_eq!(app.world().resource::<SettingsContext>().issues.len(), 1);
+
+        app.world_mut().send_event(SettingsEvent::Reset);
+        app.update();
+        assert!(app.world().resource::<SettingsContext>().issues.is_empty());
+        assert_eq!(
+            app.world().resource::<Keymap>().binding("editor.back"),
+            Some(Binding::from_str_unchecked("esc"))
+        );
+    }
+}
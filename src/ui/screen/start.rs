@@ -1,8 +1,6 @@
 use bevy::prelude::*;
-use bevy_ratatui::event::KeyEvent;
-use crossterm::event::KeyEventKind;
 use ratatui::{
-    layout::{Constraint, Flex, Layout},
+    layout::{Constraint, Flex, Layout, Rect},
     text::Line,
     widgets::{List, ListState, Paragraph, StatefulWidget, Widget},
 };
@@ -11,10 +9,18 @@ use crate::prelude::*;
 
 use super::AppScreen;
 
-const SCREENS: [(ClientMode, &str); 3] = [
-    (ClientMode::Singleplayer, "Singleplayer"),
-    (ClientMode::Multiplayer, "Multiplayer"),
-    (ClientMode::Explorer, "Explore"),
+/// What an entry of the start menu leads to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MenuEntry {
+    Mode(ClientMode),
+    Settings,
+}
+
+const SCREENS: [(MenuEntry, &str); 4] = [
+    (MenuEntry::Mode(ClientMode::Singleplayer), "Singleplayer"),
+    (MenuEntry::Mode(ClientMode::Multiplayer), "Multiplayer"),
+    (MenuEntry::Mode(ClientMode::Explorer), "Explore"),
+    (MenuEntry::Settings, "Settings"),
 ];
 
 pub struct StartMenuPlugin;
@@ -23,12 +29,15 @@ pub struct StartMenuPlugin;
 pub enum StartMenuEvent {
     Quit,
     Select(Direction2),
+    /// Selects the entry drawn at the given column and row of the terminal
+    SelectAt(u16, u16),
     Validate,
 }
 
 #[derive(Resource)]
 pub struct StartMenuContext {
     list_state: ListState,
+    list_area: Rect,
 }
 
 pub struct StartMenu;
@@ -38,7 +47,7 @@ pub fn plugin(app: &mut App) {
         .add_systems(
             Update,
             (
-                read_input.in_set(InputReading),
+                (read_input, read_mouse).in_set(InputReading),
                 handle_events.in_set(EventHandling),
             )
                 .run_if(in_state(AppScreen::StartMenu)),
@@ -51,19 +60,16 @@ fn create_screen(mut next_screen: ResMut<NextState<AppScreen>>) {
 }
 
 fn read_input(
-    mut key_event: EventReader<KeyEvent>,
+    mut presses: EventReader<KeyPress>,
     keymap: Res<Keymap>,
     mut internal_event: EventWriter<StartMenuEvent>,
 ) {
-    for KeyEvent(event) in key_event.read() {
-        if event.kind == KeyEventKind::Release {
-            return;
-        }
+    for press in presses.read() {
         use Direction2::*;
         use StartMenuEvent::*;
 
         let keymap = &keymap.start_menu;
-        internal_event.send(match event {
+        internal_event.send(match press {
             e if keymap.select_next.matches(e) => Select(Down),
             e if keymap.select_previous.matches(e) => Select(Up),
             e if keymap.quit.matches(e) => Quit,
@@ -73,8 +79,21 @@ fn read_input(
     }
 }
 
+fn read_mouse(
+    mut gestures: EventReader<MouseGesture>,
+    mut internal_event: EventWriter<StartMenuEvent>,
+) {
+    for gesture in gestures.read() {
+        internal_event.send(match *gesture {
+            MouseGesture::Click { column, row } => StartMenuEvent::SelectAt(column, row),
+            MouseGesture::Scroll { direction, .. } => StartMenuEvent::Select(direction),
+            MouseGesture::Drag { .. } => continue,
+        });
+    }
+}
+
 impl StartMenuContext {
-    fn get_next_mode(&self) -> ClientMode {
+    fn get_next_entry(&self) -> MenuEntry {
         match self.list_state.selected().unwrap() {
             i if i < SCREENS.len() => SCREENS[i].0,
             _ => unreachable!(),
@@ -86,6 +105,7 @@ impl Default for StartMenuContext {
     fn default() -> Self {
         Self {
             list_state: ListState::default().with_selected(Some(0)),
+            list_area: Rect::default(),
         }
     }
 }
@@ -98,10 +118,15 @@ impl ClampedList for StartMenuContext {
     fn len(&self) -> usize {
         SCREENS.len()
     }
+
+    fn list_area(&self) -> Rect {
+        self.list_area
+    }
 }
 
 pub fn handle_events(
     mut next_mode: ResMut<NextState<ClientMode>>,
+    mut next_screen: ResMut<NextState<AppScreen>>,
     mut context: ResMut<StartMenuContext>,
     mut events: EventReader<StartMenuEvent>,
     mut quit: EventWriter<AppExit>,
@@ -112,7 +137,13 @@ pub fn handle_events(
                 quit.send_default();
             }
             StartMenuEvent::Select(d) => context.select_adjacent(*d),
-            StartMenuEvent::Validate => next_mode.set(context.get_next_mode()),
+            StartMenuEvent::SelectAt(column, row) => {
+                context.select_at(*column, *row);
+            }
+            StartMenuEvent::Validate => match context.get_next_entry() {
+                MenuEntry::Mode(mode) => next_mode.set(mode),
+                MenuEntry::Settings => next_screen.set(AppScreen::Settings),
+            },
         }
     }
 }
@@ -149,6 +180,7 @@ impl StatefulWidget for StartMenu {
         let [list_area] = Layout::horizontal([Constraint::Length(list_width as u16 + 1)])
             .flex(Flex::Center)
             .areas(chunks[2]);
+        state.list_area = list_area;
         StatefulWidget::render(list, list_area, buf, &mut state.list_state);
     }
 }
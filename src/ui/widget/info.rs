@@ -3,35 +3,186 @@ use ratatui::{
     widgets::{Block, Borders, Paragraph, WidgetRef},
 };
 
-use crate::objects::{
-    prelude::BodyData,
-    orbiting_obj::OrbitingObjects
-};
+use crate::{objects::prelude::BodyType, physics::orbit::OsculatingElements};
 
+/// Information about the object selected in the explorer, refreshed every frame while displayed
+#[derive(Default)]
 pub struct InfoWidget {
-    pub body_info: BodyData,
-    pub orbiting_obj: OrbitingObjects,
+    pub name: String,
+    pub orbit: Option<OrbitInfo>,
+    pub details: ObjectDetails,
+}
+
+/// The state of an object relative to its main influencer
+pub struct OrbitInfo {
+    pub elements: OsculatingElements,
+    /// Name of the body whose sphere of influence contains the object
+    pub soi: String,
+    /// Radius of the main influencer, from which the altitudes are measured
+    pub host_radius: f64,
+    /// Speed relative to the main influencer, in km/day
+    pub speed: f64,
+    pub primary_distance: f64,
+}
+
+pub enum ObjectDetails {
+    Body {
+        body_type: BodyType,
+        orbiting: usize,
+        radius: f64,
+        mass: f64,
+        hill_radius: Option<f64>,
+        /// In m/s²
+        surface_gravity: f64,
+    },
+    Ship {
+        /// The name of the next maneuver node along with the time left before it, in days
+        next_node: Option<(String, f64)>,
+    },
+}
+
+impl Default for ObjectDetails {
+    fn default() -> Self {
+        Self::Ship { next_node: None }
+    }
+}
+
+impl InfoWidget {
+    fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        match &self.details {
+            ObjectDetails::Body {
+                body_type,
+                orbiting,
+                radius,
+                mass,
+                hill_radius,
+                surface_gravity,
+            } => {
+                lines.push(format!("Body type: {body_type}"));
+                lines.push(format!("N of orbiting objects: {orbiting}"));
+                lines.push(format!("Radius: {radius} km"));
+                lines.push(format!("Mass: {mass:.3e} kg"));
+                if let Some(hill_radius) = hill_radius {
+                    lines.push(format!("Hill radius: {hill_radius:.0} km"));
+                }
+                lines.push(format!("Surface gravity: {surface_gravity:.2} m/s²"));
+            }
+            ObjectDetails::Ship { next_node } => lines.push(match next_node {
+                Some((name, days)) => format!("Next node: {name} in {days:.2} days"),
+                None => "No maneuver node".into(),
+            }),
+        }
+        if let Some(orbit) = &self.orbit {
+            let OrbitInfo {
+                elements: e,
+                soi,
+                host_radius,
+                speed,
+                primary_distance,
+            } = orbit;
+            lines.push(String::new());
+            lines.push(format!("SOI: {soi}"));
+            lines.push(format!("Speed: {:.3} km/s", speed / 86400.));
+            lines.push(format!("Distance to primary: {primary_distance:.0} km"));
+            lines.push(format!("a: {:.0} km", e.semimajor_axis));
+            lines.push(format!("e: {:.4}", e.eccentricity));
+            lines.push(format!("i: {:.2}°", e.inclination));
+            lines.push(format!("Ω: {:.2}°", e.long_asc_node));
+            lines.push(format!("ω: {:.2}°", e.arg_periapsis));
+            lines.push(format!("True anomaly: {:.2}°", e.true_anomaly));
+            lines.push(format!(
+                "Periapsis altitude: {:.0} km",
+                e.periapsis - host_radius
+            ));
+            lines.push(match e.apoapsis {
+                Some(apoapsis) => format!("Apoapsis altitude: {:.0} km", apoapsis - host_radius),
+                None => "Apoapsis altitude: escape".into(),
+            });
+            lines.push(match e.period {
+                Some(period) => format!("Period: {period:.2} days"),
+                None => "Period: escape".into(),
+            });
+        }
+        lines
+    }
 }
 
 impl WidgetRef for InfoWidget {
     fn render_ref(&self, area: ratatui::layout::Rect, buf: &mut Buffer) {
-        let body_info = &self.body_info;
-        let orbiting_obj = &self.orbiting_obj;
-        let info = Paragraph::new(format!(
-            "Body type: {}\n\
-            N of orbiting bodies: {}\n\
-            Radius: {} km\n\
-            Revolution period: {} earth days",
-            body_info.body_type,
-            orbiting_obj.0.len(),
-            body_info.radius,
-            body_info.revolution_period,
-        ))
-        .block(
-            Block::default()
-                .title(&body_info.name[..])
-                .borders(Borders::ALL),
-        );
+        let info = Paragraph::new(self.lines().join("\n"))
+            .block(Block::default().title(&self.name[..]).borders(Borders::ALL));
         info.render_ref(area, buf);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::app::App;
+
+    use super::*;
+    use crate::{
+        objects::orbiting_obj::OrbitalObjID,
+        prelude::*,
+        ui::screen::explorer::{ExplorerContext, ExplorerEvent, ViewEvent},
+        utils::testing::spawn_test_ship,
+    };
+
+    fn new_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            ClientPlugin::testing().in_mode(ClientMode::Explorer),
+            TuiPlugin::testing(),
+        ));
+        app.update();
+        app.update();
+        app.update();
+        app.world_mut()
+            .send_event(ExplorerEvent::View(ViewEvent::ToggleInfo));
+        app
+    }
+
+    #[test]
+    fn test_body_info() {
+        let mut app = new_app();
+        let mut ctx = app.world_mut().resource_mut::<ExplorerContext>();
+        ctx.tree_state.select(OrbitalObjID::Body(id_from("terre")));
+        app.update();
+        let info = &app.world().resource::<ExplorerContext>().info;
+        assert_eq!(info.name, "Earth");
+        let ObjectDetails::Body {
+            surface_gravity, ..
+        } = info.details
+        else {
+            panic!("Earth should have body details")
+        };
+        assert!((surface_gravity - 9.8).abs() < 0.1);
+        let orbit = info.orbit.as_ref().unwrap();
+        assert_eq!(orbit.soi, "Sun");
+        assert!((orbit.elements.period.unwrap() - 365.25).abs() < 1.);
+    }
+
+    #[test]
+    fn test_ship_info() {
+        let mut app = new_app();
+        let id = id_from("s");
+        spawn_test_ship(&mut app, id);
+        app.update();
+        app.update();
+        let mut ctx = app.world_mut().resource_mut::<ExplorerContext>();
+        ctx.tree_state.select(OrbitalObjID::Ship(id));
+        app.update();
+        let info = &app.world().resource::<ExplorerContext>().info;
+        assert_eq!(info.name, "s");
+        assert!(matches!(
+            info.details,
+            ObjectDetails::Ship { next_node: None }
+        ));
+        let orbit = info.orbit.as_ref().unwrap();
+        assert!(orbit.primary_distance > 1e8);
+        assert_eq!(
+            orbit.elements.period.is_some(),
+            orbit.elements.eccentricity < 1.
+        );
+    }
+}
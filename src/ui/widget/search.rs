@@ -1,17 +1,19 @@
 use bevy::prelude::*;
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
 use ratatui::{
-    layout::{Alignment, Constraint, Layout},
+    layout::{Alignment, Constraint, Layout, Rect},
     style::{Style, Stylize},
     text::Text,
     widgets::{block::Title, Block, List, ListState, Paragraph, StatefulWidget, Widget},
 };
 
-use crate::prelude::*;
+use crate::{objects::orbiting_obj::OrbitalObjID, prelude::*};
 
 #[derive(Debug)]
 pub enum SearchEvent {
     Select(Direction2),
+    /// Selects the entry drawn at the given column and row of the terminal
+    SelectAt(u16, u16),
     ValidateSearch,
     WriteChar(char),
     DeleteChar,
@@ -31,23 +33,33 @@ pub struct SearchMatcher(pub SkimMatcherV2);
 pub struct SearchState {
     search_entries: Vec<SearchEntry>,
     list_state: ListState,
+    list_area: Rect,
     search_input: String,
 }
 
 struct SearchEntry {
-    id: BodyID,
+    id: OrbitalObjID,
     name: String,
 }
 
 impl From<&BodyData> for SearchEntry {
     fn from(value: &BodyData) -> Self {
         Self {
-            id: value.id,
+            id: OrbitalObjID::Body(value.id),
             name: value.name.clone(),
         }
     }
 }
 
+impl From<&ShipInfo> for SearchEntry {
+    fn from(value: &ShipInfo) -> Self {
+        Self {
+            id: OrbitalObjID::Ship(value.id),
+            name: value.id.to_string(),
+        }
+    }
+}
+
 pub struct SearchWidget;
 
 impl StatefulWidget for SearchWidget {
@@ -69,13 +81,11 @@ impl StatefulWidget for SearchWidget {
             .map(|s| Text::styled(s, Style::default()))
             .collect();
         let search_bar = Paragraph::new(&state.search_input[..]).block(Block::bordered());
-        let list = List::new(texts)
-            .block(
-                Block::bordered()
-                    .title(Title::from("Search view".bold()).alignment(Alignment::Center)),
-            )
-            .highlight_symbol("> ");
+        let block =
+            Block::bordered().title(Title::from("Search view".bold()).alignment(Alignment::Center));
         let chunks = Layout::vertical([Constraint::Length(3), Constraint::Fill(1)]).split(area);
+        state.list_area = block.inner(chunks[1]);
+        let list = List::new(texts).block(block).highlight_symbol("> ");
         search_bar.render(chunks[0], buf);
 
         <List as StatefulWidget>::render(list, chunks[1], buf, &mut state.list_state);
@@ -90,15 +100,26 @@ impl ClampedList for SearchState {
     fn len(&self) -> usize {
         self.search_entries.len()
     }
+
+    fn list_area(&self) -> Rect {
+        self.list_area
+    }
 }
 
 impl SearchState {
-    pub fn new<'a>(bodies: impl Iterator<Item = &'a BodyData>) -> SearchState {
-        let search_entries: Vec<_> = bodies.map(|data| data.into()).collect();
+    pub fn new<'a>(
+        bodies: impl Iterator<Item = &'a BodyData>,
+        ships: impl Iterator<Item = &'a ShipInfo>,
+    ) -> SearchState {
+        let search_entries: Vec<_> = bodies
+            .map(|data| data.into())
+            .chain(ships.map(|info| info.into()))
+            .collect();
         SearchState {
             search_entries,
             search_input: String::new(),
             list_state: ListState::default(),
+            list_area: Rect::default(),
         }
     }
 
@@ -110,7 +131,7 @@ impl SearchState {
         self.search_input.pop();
     }
 
-    pub fn selected_body_id(&self) -> Option<BodyID> {
+    pub fn selected_id(&self) -> Option<OrbitalObjID> {
         self.list_state
             .selected()
             .and_then(|i| self.search_entries.get(i))
@@ -125,18 +146,21 @@ impl SearchState {
     pub fn update_search_entries<'a>(
         &mut self,
         bodies: impl Iterator<Item = &'a BodyInfo>,
+        ships: impl Iterator<Item = &'a ShipInfo>,
         fuzzy_matcher: &SkimMatcherV2,
     ) {
         let mut ids_score: Vec<_> = bodies
-            .filter_map(|BodyInfo(body)| {
+            .map(|BodyInfo(body)| SearchEntry::from(body))
+            .chain(ships.map(SearchEntry::from))
+            .filter_map(|entry| {
                 fuzzy_matcher
-                    .fuzzy_match(&body.name, &self.search_input)
-                    .map(|score| (body, score))
+                    .fuzzy_match(&entry.name, &self.search_input)
+                    .map(|score| (entry, score))
             })
             .collect();
         ids_score.sort_by(|a, b| a.0.name.cmp(&b.0.name));
         ids_score.sort_by(|a, b| a.1.cmp(&b.1).reverse());
-        self.search_entries = ids_score.into_iter().map(|(data, _)| data.into()).collect();
+        self.search_entries = ids_score.into_iter().map(|(entry, _)| entry).collect();
         if self.list_state.selected().is_none() && !self.search_entries.is_empty() {
             self.list_state.select(Some(0));
         }
@@ -148,7 +172,10 @@ mod tests {
     use bevy::app::App;
 
     use super::*;
-    use crate::ui::screen::explorer::{ExplorerContext, ExplorerEvent};
+    use crate::{
+        ui::screen::explorer::{ExplorerContext, ExplorerEvent},
+        utils::testing::spawn_test_ship,
+    };
 
     #[test]
     fn test_search() {
@@ -173,7 +200,30 @@ mod tests {
             .send_event(ExplorerEvent::Search(SearchEvent::ValidateSearch));
         app.update();
         let ctx = app.world_mut().resource_mut::<ExplorerContext>();
-        let id = ctx.tree_state.selected_body_id();
-        assert_eq!(id, id_from("lune"))
+        let id = ctx.tree_state.selected_id();
+        assert_eq!(id, OrbitalObjID::Body(id_from("lune")))
+    }
+
+    #[test]
+    fn test_search_ship() {
+        let mut app = App::new();
+        app.add_plugins((
+            ClientPlugin::testing().in_mode(ClientMode::Explorer),
+            TuiPlugin::testing(),
+        ));
+        app.update();
+        app.update();
+        let id = id_from("explorer-ship");
+        spawn_test_ship(&mut app, id);
+        app.update();
+        use SearchEvent::*;
+        app.world_mut()
+            .send_event_batch("xplr".chars().map(|c| ExplorerEvent::Search(WriteChar(c))));
+        app.update();
+        app.world_mut()
+            .send_event(ExplorerEvent::Search(SearchEvent::ValidateSearch));
+        app.update();
+        let ctx = app.world().resource::<ExplorerContext>();
+        assert_eq!(ctx.tree_state.selected_id(), OrbitalObjID::Ship(id))
     }
 }
@@ -7,16 +7,20 @@ use ratatui::{
     buffer::Buffer,
     layout::{Alignment, Rect},
     style::{Color, Stylize},
+    text::Span,
     widgets::{
         block::Title,
-        canvas::{Canvas, Circle},
+        canvas::{Canvas, Circle, Line, Painter, Shape},
         Block, StatefulWidgetRef, WidgetRef,
     },
 };
 use crate::objects::orbiting_obj::{OrbitingObjects, OrbitalObjID};
 
 
-use crate::{prelude::*, utils::algebra::project_onto_plane};
+use crate::{
+    prelude::*,
+    utils::algebra::{center_to_periapsis_direction, ellipse_half_sizes, project_onto_plane, rotate},
+};
 
 pub const OFFSET_STEP: f64 = 1e8;
 pub const ZOOM_STEP: f64 = 1.5;
@@ -25,8 +29,12 @@ pub const ZOOM_STEP: f64 = 1.5;
 pub enum SpaceMapEvent {
     Zoom(Direction2),
     MapOffset(Direction4),
+    /// Moves the map by the given number of columns and rows, as dragged by the mouse
+    Drag((i32, i32)),
     MapOffsetReset,
     FocusBody,
+    /// Selects the object drawn at the given column and row of the terminal
+    SelectAt(u16, u16),
     Autoscale,
 }
 
@@ -87,7 +95,12 @@ impl SpaceMap {
         id_mapping: &HashMap<BodyID, Entity>, //Il faut etre sur que ça me donne bien soit le ShipsMapping, soit le BosyMapping dans les deux cas
         bodies: &Query<(&BodyInfo, &OrbitingObjects)>
     ) {
-        if let Some(focus_orbiting_obj) = self.focus_body.map(|f| &bodies.get(f).unwrap().1.0) {
+        // Ships have no orbiting objects to scale the map to
+        if let Some(focus_orbiting_obj) = self
+            .focus_body
+            .and_then(|f| bodies.get(f).ok())
+            .map(|(_, orbiting)| &orbiting.0)
+        {
             if let Some(max_dist) = focus_orbiting_obj
                 .iter()
                 .filter_map(|obj_id| {
@@ -111,11 +124,168 @@ impl SpaceMap {
         self.reset_offset();
         self.focus_body = Some(entity);
     }
+
+    /// Half the width and height of the space covered by a map drawn in the given area
+    fn half_extent(&self, area: Rect) -> DVec2 {
+        let (width, height) = (area.width as f64, area.height as f64);
+        let scale = self.system_size / (width.min(height) * self.zoom_level);
+        // Cells being about twice as high as they are wide, they cover twice the distance vertically
+        DVec2::new(width * scale / 2., height * scale)
+    }
+
+    /// Distance covered by a cell of a map drawn in the given area, horizontally and vertically
+    pub fn cell_size(&self, area: Rect) -> DVec2 {
+        let inner = Block::bordered().inner(area);
+        2. * self.half_extent(area) / DVec2::new(inner.width.max(1) as f64, inner.height.max(1) as f64)
+    }
+
+    /// Point of a map drawn in the given area at the center of the given cell, relative to the center of the map
+    pub fn cell_to_point(&self, area: Rect, column: u16, row: u16) -> DVec2 {
+        let inner = Block::bordered().inner(area);
+        let cell = DVec2::new(
+            (column as f64 - inner.x as f64) + 0.5 - inner.width as f64 / 2.,
+            inner.height as f64 / 2. - (row as f64 - inner.y as f64) - 0.5,
+        );
+        cell * self.cell_size(area)
+    }
+
+    /// Moves the map along with the cursor dragging it by the given number of columns and rows
+    pub fn drag(&mut self, area: Rect, (columns, rows): (i32, i32)) {
+        self.offset_amount -= DVec2::new(columns as f64, -rows as f64) * self.cell_size(area);
+    }
+}
+
+/// Moves the focus and the selection away from the ships being removed, to their main influencer or the primary
+pub fn unfocus_removed_ships(
+    mut reader: EventReader<ShipEvent>,
+    mut space_map: ResMut<SpaceMap>,
+    mapping: Res<ShipsMapping>,
+    ships: Query<&Influenced>,
+    primary: Query<Entity, With<PrimaryBody>>,
+) {
+    for event in reader.read() {
+        let ShipEvent::Remove(id) = event else {
+            continue;
+        };
+        let Some(&ship) = mapping.0.get(id) else {
+            continue;
+        };
+        let host = ships
+            .get(ship)
+            .ok()
+            .and_then(|i| i.main_influencer)
+            .or_else(|| primary.get_single().ok());
+        if space_map.focus_body == Some(ship) {
+            space_map.focus_body = host;
+            space_map.reset_offset();
+        }
+        if space_map.selected == Some(ship) {
+            space_map.selected = host;
+        }
+    }
+}
+
+/// Glyph of the ships on the map
+const SHIP_GLYPH: &str = "▲";
+/// Glyph of the points marked on the map, such as maneuver nodes
+const MARKER_GLYPH: &str = "◆";
+/// Objects further than this number of cells from a click on the map are not selected by it
+const MAX_CLICK_CELLS: f64 = 2.;
+/// Orbits spanning fewer cells of the map than this are not drawn
+const MIN_ORBIT_CELLS: f64 = 2.;
+const MIN_ORBIT_SEGMENTS: usize = 12;
+const MAX_ORBIT_SEGMENTS: usize = 256;
+/// Maximum number of orbits of asteroids and comets drawn at once, the largest ones on the map being kept so that
+/// they don't clutter it
+const MAX_SMALL_BODY_ORBITS: usize = 100;
+
+/// An orbit projected onto the map, relative to the focus
+struct OrbitEllipse {
+    center: DVec2,
+    /// Projections of the semimajor and semiminor axes of the ellipse
+    axes: (DVec2, DVec2),
+    semimajor_axis: f64,
+    color: Color,
+    /// Whether the orbit is the one of an asteroid or a comet
+    small_body: bool,
+}
+
+impl OrbitEllipse {
+    #[allow(non_snake_case)]
+    fn new(orbit: &EllipticalOrbit, host_pos: DVec3, color: Color, small_body: bool) -> Self {
+        let (o, O, I) = (
+            orbit.arg_periapsis.to_radians(),
+            orbit.long_asc_node.to_radians(),
+            orbit.inclination.to_radians(),
+        );
+        let (a, e) = (orbit.semimajor_axis, orbit.eccentricity);
+        let periapsis_dir = center_to_periapsis_direction(o, O, I).normalize();
+        let half_sizes = ellipse_half_sizes(a, e);
+        let minor_dir = rotate(DVec2::Y, o, O, I);
+        let plane = (DVec3::X, DVec3::Y);
+        Self {
+            center: project_onto_plane(host_pos - a * e * periapsis_dir, plane),
+            axes: (
+                project_onto_plane(half_sizes.x * periapsis_dir, plane),
+                project_onto_plane(half_sizes.y * minor_dir, plane),
+            ),
+            semimajor_axis: a,
+            color,
+            small_body,
+        }
+    }
+
+    /// Whether the orbit can't be seen in the rectangle centered on the origin with the given half sizes
+    fn is_outside(&self, half_width: f64, half_height: f64) -> bool {
+        self.center.x.abs() - self.semimajor_axis > half_width
+            || self.center.y.abs() - self.semimajor_axis > half_height
+    }
+
+    fn points(&self, segments: usize) -> Vec<DVec2> {
+        (0..=segments)
+            .map(|i| {
+                let angle = std::f64::consts::TAU * i as f64 / segments as f64;
+                self.center + angle.cos() * self.axes.0 + angle.sin() * self.axes.1
+            })
+            .collect()
+    }
+}
+
+/// Successive line segments through points of the map
+struct Polyline<'a> {
+    points: &'a [DVec2],
+    color: Color,
+}
+
+impl Shape for Polyline<'_> {
+    fn draw(&self, painter: &mut Painter) {
+        for segment in self.points.windows(2) {
+            Line {
+                x1: segment[0].x,
+                y1: segment[0].y,
+                x2: segment[1].x,
+                y2: segment[1].y,
+                color: self.color,
+            }
+            .draw(painter);
+        }
+    }
 }
 
 #[derive(Default)]
 pub struct SpaceMapWidget {
     circles: Vec<Circle>,
+    /// Coordinates and color of the ships
+    ships: Vec<(f64, f64, Color)>,
+    orbits: Vec<OrbitEllipse>,
+    /// Predicted paths of ships, with the coordinates of their points on the map
+    paths: Vec<(Vec<DVec2>, Color)>,
+    /// Points marked on the map, such as maneuver nodes
+    markers: Vec<(DVec2, Color)>,
+    /// Position of the focus when the map was last updated
+    focus_pos: DVec3,
+    /// Coordinates of the bodies and ships on the map, to find the one under the cursor
+    objects: Vec<(Entity, DVec2)>,
 }
 
 impl SpaceMapWidget {
@@ -123,11 +293,18 @@ impl SpaceMapWidget {
         &mut self,
         space_map: &SpaceMap,
         query: &Query<(Entity, &Position, &BodyInfo)>,
+        ships: &Query<(Entity, &Position), With<ShipInfo>>,
+        orbits: &Query<(Entity, &EllipticalOrbit, &Position, Option<&BodyInfo>)>,
     ) {
         let mut circles = Vec::new();
-        let &Position(focus_pos) = space_map
-            .focus_body
-            .map_or(&Position::default(), |f| query.get(f).unwrap().1);
+        let mut objects = Vec::new();
+        let focus_pos = space_map.focus_body.map_or(DVec3::ZERO, |f| {
+            query
+                .get(f)
+                .map(|(_, pos, _)| pos.0)
+                .or_else(|_| ships.get(f).map(|(_, pos)| pos.0))
+                .unwrap_or_default()
+        });
         for (entity, &Position(pos), BodyInfo(data)) in query.iter() {
             let proj =
                 project_onto_plane(pos - focus_pos, (DVec3::X, DVec3::Y)) - space_map.offset_amount;
@@ -138,6 +315,7 @@ impl SpaceMapWidget {
                 _ => Color::DarkGray,
             };
             let radius = data.radius;
+            objects.push((entity, proj));
             circles.push(Circle {
                 x: proj.x,
                 y: proj.y,
@@ -146,6 +324,127 @@ impl SpaceMapWidget {
             });
         }
         self.circles = circles;
+        self.ships = ships
+            .iter()
+            .map(|(entity, &Position(pos))| {
+                let proj = project_onto_plane(pos - focus_pos, (DVec3::X, DVec3::Y))
+                    - space_map.offset_amount;
+                let color = if Some(entity) == space_map.selected {
+                    Color::Red
+                } else {
+                    Color::Cyan
+                };
+                objects.push((entity, proj));
+                (proj.x, proj.y, color)
+            })
+            .collect();
+        self.orbits = orbits
+            .iter()
+            .map(|(entity, orbit, &Position(pos), info)| {
+                let body_type = info.map(|i| i.0.body_type);
+                let color = match body_type {
+                    _ if Some(entity) == space_map.selected => Color::LightRed,
+                    None => Color::Cyan,
+                    Some(BodyType::Planet) => Color::Blue,
+                    Some(BodyType::Moon | BodyType::DwarfPlanet) => Color::Gray,
+                    _ => Color::DarkGray,
+                };
+                // The host is where the orbit of the object puts it relative to its own position
+                let host_pos = pos - orbit.local_pos - focus_pos;
+                let mut ellipse = OrbitEllipse::new(
+                    orbit,
+                    host_pos,
+                    color,
+                    matches!(body_type, Some(BodyType::Asteroid | BodyType::Comet)),
+                );
+                ellipse.center -= space_map.offset_amount;
+                ellipse
+            })
+            .collect();
+        self.focus_pos = focus_pos;
+        self.objects = objects;
+    }
+
+    /// The body or ship closest to the given cell of the map drawn in the given area, if it is close enough
+    pub fn object_at(
+        &self,
+        space_map: &SpaceMap,
+        area: Rect,
+        column: u16,
+        row: u16,
+    ) -> Option<Entity> {
+        let point = space_map.cell_to_point(area, column, row);
+        let cell_size = space_map.cell_size(area);
+        self.objects
+            .iter()
+            .map(|&(entity, pos)| (entity, ((pos - point) / cell_size).length()))
+            .filter(|&(_, cells)| cells <= MAX_CLICK_CELLS)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(entity, _)| entity)
+    }
+
+    /// Replaces the predicted paths drawn on the map, which must be updated beforehand
+    pub fn set_paths(
+        &mut self,
+        space_map: &SpaceMap,
+        paths: impl IntoIterator<Item = (Vec<DVec3>, Color)>,
+    ) {
+        self.paths = paths
+            .into_iter()
+            .map(|(positions, color)| {
+                let points = positions
+                    .into_iter()
+                    .map(|pos| {
+                        project_onto_plane(pos - self.focus_pos, (DVec3::X, DVec3::Y))
+                            - space_map.offset_amount
+                    })
+                    .collect();
+                (points, color)
+            })
+            .collect();
+    }
+
+    /// Replaces the points marked on the map, which must be updated beforehand
+    pub fn set_markers(
+        &mut self,
+        space_map: &SpaceMap,
+        markers: impl IntoIterator<Item = (DVec3, Color)>,
+    ) {
+        self.markers = markers
+            .into_iter()
+            .map(|(pos, color)| {
+                let point = project_onto_plane(pos - self.focus_pos, (DVec3::X, DVec3::Y))
+                    - space_map.offset_amount;
+                (point, color)
+            })
+            .collect();
+    }
+
+    /// Orbits worth drawing on a map whose cells span the given distance, along with their number of segments.
+    /// Orbits too small or out of the map are skipped, as are the smallest ones of asteroids and comets
+    fn visible_orbits(
+        &self,
+        half_width: f64,
+        half_height: f64,
+        cell_size: f64,
+    ) -> Vec<(&OrbitEllipse, usize)> {
+        let cells = |orbit: &OrbitEllipse| 2. * orbit.semimajor_axis / cell_size;
+        let (mut small, mut visible): (Vec<_>, Vec<_>) = self
+            .orbits
+            .iter()
+            .filter(|o| cells(o) >= MIN_ORBIT_CELLS && !o.is_outside(half_width, half_height))
+            .partition(|o| o.small_body && o.color != Color::LightRed);
+        small.sort_by(|a, b| b.semimajor_axis.total_cmp(&a.semimajor_axis));
+        small.truncate(MAX_SMALL_BODY_ORBITS);
+        visible.extend(small);
+        visible
+            .into_iter()
+            .map(|o| {
+                // About one segment every two cells along the ellipse
+                let segments = (std::f64::consts::PI * cells(o) / 2.) as usize;
+                (o, segments.clamp(MIN_ORBIT_SEGMENTS, MAX_ORBIT_SEGMENTS))
+            })
+            .collect()
     }
 }
 
@@ -154,20 +453,38 @@ impl StatefulWidgetRef for SpaceMapWidget {
     where
         Self: Sized,
     {
-        let (width, height) = (area.width as f64, area.height as f64);
-        let scale = state.system_size / (width.min(height) * state.zoom_level);
-        let (width, height) = (width * scale, height * scale);
+        let half = state.half_extent(area);
+        let scale = state.system_size / (area.width.min(area.height) as f64 * state.zoom_level);
         Canvas::default()
             .block(
                 Block::bordered()
                     .title(Title::from("Space map".bold()).alignment(Alignment::Center)),
             )
-            .x_bounds([-width / 2., width / 2.])
-            .y_bounds([-height, height])
+            .x_bounds([-half.x, half.x])
+            .y_bounds([-half.y, half.y])
             .paint(|ctx| {
+                for (orbit, segments) in self.visible_orbits(half.x, half.y, scale) {
+                    ctx.draw(&Polyline {
+                        points: &orbit.points(segments),
+                        color: orbit.color,
+                    });
+                }
+                for (points, color) in &self.paths {
+                    ctx.draw(&Polyline {
+                        points,
+                        color: *color,
+                    });
+                }
+                ctx.layer();
                 for circle in &self.circles {
                     ctx.draw(circle);
                 }
+                for &(x, y, color) in &self.ships {
+                    ctx.print(x, y, Span::styled(SHIP_GLYPH, color));
+                }
+                for &(point, color) in &self.markers {
+                    ctx.print(point.x, point.y, Span::styled(MARKER_GLYPH, color));
+                }
             })
             .render_ref(area, buf)
     }
@@ -180,7 +497,10 @@ mod tests {
     use bevy::app::App;
 
     use super::*;
-    use crate::ui::screen::explorer::{ExplorerContext, ExplorerEvent};
+    use crate::{
+        ui::screen::explorer::{ExplorerContext, ExplorerEvent},
+        utils::testing::spawn_test_ship,
+    };
 
     fn new_app() -> App {
         let mut app = App::new();
@@ -205,12 +525,40 @@ mod tests {
         assert!(map.system_size < 4537039826.);
     }
 
+    #[test]
+    fn test_orbits() {
+        let mut app = new_app();
+        let ctx = app.world().resource::<ExplorerContext>();
+        assert_eq!(ctx.space_map.orbits.len(), 8);
+        let orbits: Vec<_> = ctx
+            .space_map
+            .orbits
+            .iter()
+            .map(|o| o.points(4096))
+            .collect();
+        // The map is focused on the sun, so the orbit of the earth goes through its position
+        let world = app.world_mut();
+        let (&Position(earth), _) = world
+            .query::<(&Position, &BodyInfo)>()
+            .iter(world)
+            .find(|(_, info)| info.0.id == id_from("terre"))
+            .unwrap();
+        let earth = project_onto_plane(earth, (DVec3::X, DVec3::Y));
+        let distance = orbits
+            .iter()
+            .flatten()
+            .map(|p| p.distance(earth))
+            .min_by(|a, b| a.total_cmp(b))
+            .unwrap();
+        assert!(distance < 2e5, "{distance}");
+    }
+
     #[test]
     fn test_change_focus_body() {
         let mut app = new_app();
         let earth = id_from("terre");
         let mut ctx = app.world_mut().resource_mut::<ExplorerContext>();
-        ctx.tree_state.select_body(earth);
+        ctx.tree_state.select(OrbitalObjID::Body(earth));
 
         app.update();
 
@@ -227,4 +575,55 @@ mod tests {
             earth
         );
     }
+
+    #[test]
+    fn test_focus_ship() {
+        let mut app = new_app();
+        let id = id_from("s");
+        spawn_test_ship(&mut app, id);
+        app.update();
+        app.update();
+        let mut ctx = app.world_mut().resource_mut::<ExplorerContext>();
+        assert!(ctx.tree_state.select(OrbitalObjID::Ship(id)));
+        app.world_mut()
+            .send_event(ExplorerEvent::SpaceMap(SpaceMapEvent::FocusBody));
+        app.update();
+        let ship = app.world().resource::<ShipsMapping>().0[&id];
+        assert_eq!(app.world().resource::<SpaceMap>().focus_body, Some(ship));
+        let ctx = app.world().resource::<ExplorerContext>();
+        assert_eq!(ctx.space_map.ships.len(), 1);
+
+        // Removing the ship moves the focus to its main influencer
+        let host = app.world().get::<Influenced>(ship).unwrap().main_influencer;
+        assert!(host.is_some());
+        app.world_mut().send_event(ShipEvent::Remove(id));
+        app.update();
+        app.update();
+        assert!(app.world().get_entity(ship).is_none());
+        assert_eq!(app.world().resource::<SpaceMap>().focus_body, host);
+    }
+
+    #[test]
+    fn test_mouse_on_map() {
+        let mut app = new_app();
+        let area = Rect::new(0, 0, 102, 52);
+        let (column, row) = (51, 26);
+        let mut map = app.world_mut().resource_mut::<SpaceMap>();
+        map.zoom_level = 1e4;
+        // The point under the cursor follows it when dragging the map
+        let before = map.cell_to_point(area, column, row) + map.offset_amount;
+        map.drag(area, (3, -2));
+        let after = map.cell_to_point(area, column + 3, row - 2) + map.offset_amount;
+        assert!(before.distance(after) < 1e-6 * map.cell_size(area).x);
+        map.reset_offset();
+        app.update();
+        let world = app.world_mut();
+        let sun = world
+            .query_filtered::<Entity, With<PrimaryBody>>()
+            .single(world);
+        let ctx = app.world().resource::<ExplorerContext>();
+        let map = app.world().resource::<SpaceMap>();
+        assert_eq!(ctx.space_map.object_at(map, area, column, row), Some(sun));
+        assert_eq!(ctx.space_map.object_at(map, area, 0, 0), None);
+    }
 }
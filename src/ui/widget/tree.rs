@@ -1,6 +1,9 @@
-use bevy::{prelude::*, utils::HashMap};
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
 use ratatui::{
-    layout::Alignment,
+    layout::{Alignment, Rect},
     style::{Style, Stylize},
     text::{Line, Span},
     widgets::{block::Title, Block, List, ListState, StatefulWidget, StatefulWidgetRef},
@@ -17,12 +20,14 @@ use crate::{
 #[derive(Debug, Event)]
 pub enum TreeEvent {
     Select(Direction2),
+    /// Selects the entry drawn at the given column and row of the terminal
+    SelectAt(u16, u16),
     ToggleTreeExpansion,
 }
 
 #[derive(Debug, Clone)]
 struct TreeEntry {
-    id: BodyID,
+    id: OrbitalObjID,
     name: String,
     is_last_child: bool,
     index_of_parent: Option<usize>,
@@ -34,8 +39,14 @@ pub struct TreeState {
     /// Indices of the entries in the system tree, and whether they are expanded or not
     visible_tree_entries: Vec<usize>,
     system_tree: Vec<TreeEntry>,
-    focus_body: Option<BodyID>,
+    /// Names of the bodies along with their children sorted by semimajor axis, from which the tree is built
+    bodies: HashMap<BodyID, (String, Vec<BodyID>)>,
+    primary: BodyID,
+    /// Ships listed in the tree, along with the body they are listed under
+    ships: Vec<(ShipID, BodyID)>,
+    focus: Option<OrbitalObjID>,
     list_state: ListState,
+    list_area: Rect,
 }
 
 impl ClampedList for TreeState {
@@ -46,6 +57,10 @@ impl ClampedList for TreeState {
     fn len(&self) -> usize {
         self.visible_tree_entries.len()
     }
+
+    fn list_area(&self) -> Rect {
+        self.list_area
+    }
 }
 
 pub struct TreeWidget;
@@ -65,10 +80,10 @@ impl StatefulWidget for TreeWidget {
             .iter()
             .map(|&index_in_tree| {
                 let entry = &state.system_tree[index_in_tree];
-                let style = if Some(entry.id) == state.focus_body {
-                    Style::default().bold()
-                } else {
-                    Style::default()
+                let style = match entry.id {
+                    id if Some(id) == state.focus => Style::default().bold(),
+                    OrbitalObjID::Ship(_) => Style::default().cyan(),
+                    OrbitalObjID::Body(_) => Style::default(),
                 };
                 vec![
                     Span::from(state.build_deepness_prefix(index_in_tree)),
@@ -77,12 +92,10 @@ impl StatefulWidget for TreeWidget {
                 .into()
             })
             .collect();
-        let list = List::new(texts)
-            .block(
-                Block::bordered()
-                    .title(Title::from("Tree view".bold()).alignment(Alignment::Center)),
-            )
-            .highlight_symbol("> ");
+        let block = Block::bordered()
+            .title(Title::from("Tree view".bold()).alignment(Alignment::Center));
+        state.list_area = block.inner(area);
+        let list = List::new(texts).block(block).highlight_symbol("> ");
         <List as StatefulWidgetRef>::render_ref(&list, area, buf, &mut state.list_state)
     }
 }
@@ -108,66 +121,136 @@ impl TreeState {
         primary: &'a BodyData,
         focus_body: Option<&'a BodyData>,
         bodies: impl Iterator<Item = &'a BodyData>,
+        ships: impl Iterator<Item = (ShipID, BodyID)>,
         bodies_mapping: Res<BodiesMapping>,
         query: Query<&OrbitingObjects>,
     ) -> TreeState {
-        #[derive(Clone)]
-        struct Temp {
-            children: Vec<BodyID>,
-            semimajor_axis: f64,
-            name: String,
-        }
-        let mut info: HashMap<BodyID, Temp> = bodies
-            .map(|data| {
-                let id = data.id;
-                (
-                    id,
-                    Temp {
-                        children: get_orbiting_bodies(&id, &bodies_mapping, &query),
-                        semimajor_axis: data.semimajor_axis,
-                        name: data.name.clone(),
-                    },
-                )
+        let info: HashMap<BodyID, (f64, String)> = bodies
+            .map(|data| (data.id, (data.semimajor_axis, data.name.clone())))
+            .collect();
+        let bodies = info
+            .iter()
+            .map(|(&id, (_, name))| {
+                let mut children = get_orbiting_bodies(&id, &bodies_mapping, &query);
+                children.retain(|b| info.contains_key(b));
+                children.sort_by(|a, b| info[a].0.total_cmp(&info[b].0));
+                (id, (name.clone(), children))
             })
             .collect();
-        let info_bis = info.clone();
-        for entry in info.values_mut() {
-            entry.children.retain(|b| info_bis.get(b).is_some());
-            entry.children.sort_by(|a, b| {
-                info_bis[a]
-                    .semimajor_axis
-                    .total_cmp(&info_bis[b].semimajor_axis)
-            });
+        let mut ships: Vec<_> = ships.collect();
+        ships.sort();
+        let mut state = TreeState {
+            system_tree: Vec::new(),
+            visible_tree_entries: Vec::new(),
+            bodies,
+            primary: primary.id,
+            ships,
+            focus: focus_body.map(|r| OrbitalObjID::Body(r.id)),
+            list_state: ListState::default().with_selected(Some(0)),
+            list_area: Rect::default(),
+        };
+        state.build();
+        state
+    }
+
+    /// Lists the ships under the given bodies, rebuilding the tree if they changed
+    pub fn set_ships(&mut self, ships: impl Iterator<Item = (ShipID, BodyID)>) {
+        let mut ships: Vec<_> = ships.collect();
+        ships.sort();
+        if ships != self.ships {
+            self.ships = ships;
+            self.build();
         }
+    }
+
+    /// Builds the tree from the bodies and the ships, keeping the entries that were expanded and the selection
+    fn build(&mut self) {
         fn fill_tree_rec(
             tree: &mut Vec<TreeEntry>,
-            info: &HashMap<BodyID, Temp>,
-            id: BodyID,
+            bodies: &HashMap<BodyID, (String, Vec<BodyID>)>,
+            ships: &HashMap<BodyID, Vec<ShipID>>,
+            id: OrbitalObjID,
             index_of_parent: Option<usize>,
             is_last_child: bool,
         ) {
-            let Temp { children, name, .. } = &info[&id];
+            let name = match id {
+                OrbitalObjID::Body(body) => bodies[&body].0.clone(),
+                OrbitalObjID::Ship(ship) => ship.to_string(),
+            };
             tree.push(TreeEntry {
                 id,
-                name: name.clone(),
+                name,
                 is_last_child,
                 index_of_parent,
                 is_expanded: false,
             });
+            let OrbitalObjID::Body(body) = id else {
+                return;
+            };
             let index_of_parent = tree.len() - 1;
+            let children: Vec<_> = bodies[&body]
+                .1
+                .iter()
+                .map(|&b| OrbitalObjID::Body(b))
+                .chain(
+                    ships
+                        .get(&body)
+                        .into_iter()
+                        .flatten()
+                        .map(|&s| OrbitalObjID::Ship(s)),
+                )
+                .collect();
             let size = children.len();
-            for (i, &child) in children.iter().enumerate() {
-                fill_tree_rec(tree, info, child, Some(index_of_parent), i == size - 1);
+            for (i, child) in children.into_iter().enumerate() {
+                fill_tree_rec(tree, bodies, ships, child, Some(index_of_parent), i == size - 1);
             }
         }
+        let expanded: HashSet<OrbitalObjID> = self
+            .system_tree
+            .iter()
+            .filter(|entry| entry.is_expanded)
+            .map(|entry| entry.id)
+            .collect();
+        let selected = self
+            .list_state
+            .selected()
+            .and_then(|i| self.nth_visible_entry(i))
+            .map(|entry| entry.id);
+
+        // Ships whose main influencer isn't in the tree are listed under the primary body
+        let mut ships: HashMap<BodyID, Vec<ShipID>> = HashMap::new();
+        for &(ship, parent) in &self.ships {
+            let parent = if self.bodies.contains_key(&parent) {
+                parent
+            } else {
+                self.primary
+            };
+            ships.entry(parent).or_default().push(ship);
+        }
         let mut system_tree = Vec::new();
-        fill_tree_rec(&mut system_tree, &info, primary.id, None, true);
-
-        TreeState {
-            system_tree,
-            visible_tree_entries: vec![0],
-            focus_body: focus_body.map(|r| r.id),
-            list_state: ListState::default().with_selected(Some(0)),
+        fill_tree_rec(
+            &mut system_tree,
+            &self.bodies,
+            &ships,
+            OrbitalObjID::Body(self.primary),
+            None,
+            true,
+        );
+        for entry in &mut system_tree {
+            entry.is_expanded = expanded.contains(&entry.id);
+        }
+        // Parents come before their children, so the visibility of an entry follows from its parent's
+        let mut visible: Vec<bool> = Vec::with_capacity(system_tree.len());
+        for entry in &system_tree {
+            let is_visible = entry
+                .index_of_parent
+                .is_none_or(|p| visible[p] && system_tree[p].is_expanded);
+            visible.push(is_visible);
+        }
+        self.visible_tree_entries = (0..system_tree.len()).filter(|&i| visible[i]).collect();
+        self.system_tree = system_tree;
+        if !selected.is_some_and(|id| self.select(id)) {
+            self.list_state.select(Some(0));
         }
     }
 }
@@ -209,9 +292,9 @@ impl TreeState {
         prefix
     }
 
-    /// Returns the index of the specified body in the system tree,
-    /// or None if the body is not present
-    pub fn index_of(&self, id: BodyID) -> Option<usize> {
+    /// Returns the index of the specified object in the system tree,
+    /// or None if the object is not present
+    pub fn index_of(&self, id: OrbitalObjID) -> Option<usize> {
         self.system_tree.iter().position(|entry| entry.id == id)
     }
 
@@ -232,7 +315,7 @@ impl TreeState {
         }
     }
 
-    pub fn expand_visible_entry_by_id(&mut self, id: BodyID) {
+    pub fn expand_visible_entry_by_id(&mut self, id: OrbitalObjID) {
         if let Some(i) = self.index_of(id) {
             self.try_expand_visible_entry(i);
         }
@@ -293,7 +376,7 @@ impl TreeState {
         true
     }
 
-    pub fn selected_body_id(&self) -> BodyID {
+    pub fn selected_id(&self) -> OrbitalObjID {
         self.nth_visible_entry(self.list_state.selected().unwrap())
             .unwrap()
             .id
@@ -318,7 +401,7 @@ impl TreeState {
         }
     }
 
-    pub fn select_body(&mut self, id: BodyID) -> bool {
+    pub fn select(&mut self, id: OrbitalObjID) -> bool {
         if let Some(index) = self.index_of(id) {
             self.try_expand_entry(index);
             if let Some(index) = self
@@ -333,18 +416,20 @@ impl TreeState {
         false
     }
 
-    pub fn focus_body(&mut self, id: BodyID) {
+    pub fn focus(&mut self, id: OrbitalObjID) {
         if let Some(index) = self.index_of(id) {
             self.try_expand_entry(index);
-            self.focus_body = Some(id);
+            self.focus = Some(id);
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{prelude::*, ui::screen::explorer::ExplorerContext};
-    use bevy::app::App;
+    use crate::{
+        objects::orbiting_obj::OrbitalObjID, prelude::*, ui::screen::explorer::ExplorerContext,
+    };
+    use bevy::{app::App, math::DVec3};
 
     fn new_app(moons: bool) -> App {
         let mut app = App::new();
@@ -382,8 +467,31 @@ mod tests {
         let mut ctx = world.resource_mut::<ExplorerContext>();
         let tree = &mut ctx.tree_state;
         let earth = id_from("terre");
-        tree.select_body(earth);
-        assert_eq!(tree.selected_body_id(), earth)
+        tree.select(OrbitalObjID::Body(earth));
+        assert_eq!(tree.selected_id(), OrbitalObjID::Body(earth))
+    }
+
+    #[test]
+    fn test_ships_in_tree() {
+        let mut app = new_app(false);
+        let world = app.world_mut();
+        let earth = id_from("terre");
+        let earth_entity = world.resource::<BodiesMapping>().0[&earth];
+        let earth_pos = world.get::<Position>(earth_entity).unwrap().0;
+        let id = id_from("s");
+        world.send_event(ShipEvent::Create(ShipInfo {
+            id,
+            spawn_pos: earth_pos + DVec3::new(1e4, 0., 0.),
+            spawn_speed: DVec3::ZERO,
+        }));
+        app.update();
+        app.update();
+        let tree = &app.world().resource::<ExplorerContext>().tree_state;
+        assert_eq!(tree.system_tree.len(), 10);
+        // The ship is listed under its main influencer
+        let index = tree.index_of(OrbitalObjID::Ship(id)).unwrap();
+        let parent = tree.system_tree[index].index_of_parent.unwrap();
+        assert_eq!(tree.system_tree[parent].id, OrbitalObjID::Body(earth));
     }
 
     #[test]
@@ -414,7 +522,7 @@ mod tests {
         let tree = &ctx.tree_state;
 
         assert_eq!(
-            tree.compute_deepness_map(tree.index_of(id_from("lune")).unwrap()),
+            tree.compute_deepness_map(tree.index_of(OrbitalObjID::Body(id_from("lune"))).unwrap()),
             vec![true, false, true]
         );
     }
@@ -1,12 +1,16 @@
+use serde::{Deserialize, Serialize};
+
 pub mod algebra;
 pub mod args;
 pub mod de;
 pub mod ecs;
 pub mod hash;
 pub mod list;
+#[cfg(test)]
+pub mod testing;
 pub mod ui;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Direction2 {
     Up,
     Down,
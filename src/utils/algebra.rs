@@ -94,10 +94,12 @@ pub fn circular_orbit_around_body(
 }
 
 #[allow(non_snake_case)]
+/// Direction from the center of an orbit towards its periapsis, given its argument of periapsis, longitude of the
+/// ascending node and inclination in radians
 pub fn center_to_periapsis_direction(o: f64, O: f64, I: f64) -> DVec3 {
     DVec3::new(
         O.cos() * o.cos() - O.sin() * I.cos() * o.sin(),
-        O.cos() * o.cos() + O.cos() * I.cos() * o.sin(),
+        O.sin() * o.cos() + O.cos() * I.cos() * o.sin(),
         I.sin() * o.sin(),
     )
 }
@@ -105,3 +107,23 @@ pub fn center_to_periapsis_direction(o: f64, O: f64, I: f64) -> DVec3 {
 pub fn ellipse_half_sizes(a: f64, e: f64) -> DVec2 {
     DVec2::new(1., (1. - e * e).sqrt()) * a
 }
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_PI_2;
+
+    use super::*;
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn test_center_to_periapsis_direction() {
+        // The periapsis is at the ascending node when the argument of periapsis is zero
+        let dir = center_to_periapsis_direction(0., FRAC_PI_2, 0.);
+        assert!((dir - DVec3::Y).length() < 1e-12);
+        for (o, O, I) in [(0.3, 1.2, 0.5), (2., 4., 1.), (5., 0.1, 3.)] {
+            let dir = center_to_periapsis_direction(o, O, I);
+            assert!((dir - rotate(DVec2::X, o, O, I)).length() < 1e-12);
+            assert!((dir.length() - 1.).abs() < 1e-12);
+        }
+    }
+}
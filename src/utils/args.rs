@@ -1,12 +1,14 @@
-use std::{env::Args, error::Error};
+use std::{env::Args, error::Error, path::PathBuf};
 
 use crate::input::prelude::Keymap;
 
+/// The keymap read from the file given after `-k`, after writing the default one to the file given after
+/// `--writekeymap`
 pub fn get_keymap(mut args: Args) -> Result<Keymap, Box<dyn Error>> {
     let mut keymap = Keymap::default();
 
-    if let Some(command) = args.nth(1) {
-        match &command[..] {
+    while let Some(arg) = args.next() {
+        match &arg[..] {
             "--writekeymap" => {
                 Keymap::default()
                     .write_to_file(args.next().ok_or("Expected output file path")?, false)?;
@@ -19,3 +21,43 @@ pub fn get_keymap(mut args: Args) -> Result<Keymap, Box<dyn Error>> {
     }
     Ok(keymap)
 }
+
+/// The keymap file given after `-k`, which the settings screen saves the keymap to
+pub fn get_keymap_path(mut args: Args) -> Option<PathBuf> {
+    args.find(|arg| arg == "-k")?;
+    args.next().map(PathBuf::from)
+}
+
+/// OEM files to import as ghost ships, each given after a `--ghost` flag
+pub fn get_ghosts(mut args: Args) -> Vec<PathBuf> {
+    let mut ghosts = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--ghost" {
+            ghosts.extend(args.next().map(PathBuf::from));
+        }
+    }
+    ghosts
+}
+
+/// File of console commands given after `--script`, run once the game is loaded
+pub fn get_script(mut args: Args) -> Option<PathBuf> {
+    args.find(|arg| arg == "--script")?;
+    args.next().map(PathBuf::from)
+}
+
+/// File the inputs of the session are recorded to, given after `--record`
+pub fn get_record(mut args: Args) -> Option<PathBuf> {
+    args.find(|arg| arg == "--record")?;
+    args.next().map(PathBuf::from)
+}
+
+/// Journal of a recorded session to replay, given after `--replay`
+pub fn get_replay(mut args: Args) -> Option<PathBuf> {
+    args.find(|arg| arg == "--replay")?;
+    args.next().map(PathBuf::from)
+}
+
+/// Whether the game should run in the terminal only, without opening the Bevy window
+pub fn is_windowless(mut args: Args) -> bool {
+    args.any(|arg| arg == "--no-gui")
+}
@@ -2,13 +2,14 @@ use bevy::{
     app::AppExit,
     ecs::{
         event::EventWriter,
-        system::{In, Res},
+        query::{QueryData, QueryFilter, QueryItem},
+        system::{In, Query, Res},
     },
 };
 use bevy_ratatui::error::exit_on_error;
 use color_eyre::eyre::Result;
 
-use crate::client::Testing;
+use crate::{client::Testing, replay::Deterministic};
 pub fn exit_on_error_if_app(
     input: In<Result<()>>,
     app_exit: EventWriter<AppExit>,
@@ -20,3 +21,17 @@ pub fn exit_on_error_if_app(
         exit_on_error(input, app_exit);
     }
 }
+
+/// Runs `f` on every item of the query, in parallel unless the simulation is deterministic,
+/// in which case the items are visited in the query's order
+pub fn for_each_mut<D: QueryData, F: QueryFilter>(
+    query: &mut Query<D, F>,
+    deterministic: &Deterministic,
+    f: impl Fn(QueryItem<'_, D>) + Send + Sync + Clone,
+) {
+    if deterministic.0 {
+        query.iter_mut().for_each(f);
+    } else {
+        query.par_iter_mut().for_each(f);
+    }
+}
@@ -1,9 +1,13 @@
 use ratatui::{
+    layout::Rect,
     style::{Style, Stylize},
     widgets::{Block, ListState, Paragraph},
 };
 
-use super::{ui::cycle_add, Direction2};
+use super::{
+    ui::{area_contains, cycle_add},
+    Direction2,
+};
 
 pub fn select_next_clamp(list_state: &mut ListState, max: usize) {
     list_state.select(match list_state.selected() {
@@ -51,6 +55,23 @@ pub trait ClampedList {
         let len = self.len();
         self.list_state().select(len.checked_sub(1));
     }
+
+    /// Area of the terminal in which the entries were last drawn, borders excluded
+    fn list_area(&self) -> Rect;
+
+    /// Selects the entry drawn at the given cell of the terminal, and returns whether there is one
+    fn select_at(&mut self, column: u16, row: u16) -> bool {
+        let area = self.list_area();
+        if !area_contains(area, column, row) {
+            return false;
+        }
+        let index = self.list_state().offset() + (row - area.y) as usize;
+        let found = index < self.len();
+        if found {
+            self.list_state().select(Some(index));
+        }
+        found
+    }
 }
 pub trait OptionsList<const SIZE: usize> {
     fn current_index(&mut self) -> &mut usize;
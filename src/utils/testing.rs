@@ -0,0 +1,12 @@
+use bevy::{app::App, math::DVec3};
+
+use crate::prelude::*;
+
+/// Sends the creation of a ship orbiting the sun near the Earth, which is spawned at the next update
+pub fn spawn_test_ship(app: &mut App, id: ShipID) {
+    app.world_mut().send_event(ShipEvent::Create(ShipInfo {
+        id,
+        spawn_pos: DVec3::new(1.5e8, 0., 0.),
+        spawn_speed: DVec3::new(0., 2.5e6, 0.),
+    }));
+}
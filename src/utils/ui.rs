@@ -32,8 +32,12 @@ pub fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         .split(popup_layout[1])[1] // Return the middle chunk
 }
 
+pub fn area_contains(area: Rect, column: u16, row: u16) -> bool {
+    (area.left()..area.right()).contains(&column) && (area.top()..area.bottom()).contains(&row)
+}
+
 pub fn cycle_add(i: &mut usize, size: usize, value: isize) {
-    *i = ((*i as isize + value) % size as isize) as usize
+    *i = (*i as isize + value).rem_euclid(size as isize) as usize
 }
 
 pub fn viewable_radius(camera: &Camera) -> Option<f32> {